@@ -250,6 +250,7 @@ fn diff(
                 xdiff::DiffOpts {
                     context: 3,
                     copy_info: xdiff::CopyInfo::None,
+                    ..Default::default()
                 },
             );
             String::from_utf8_lossy(&diff).into_owned()
@@ -416,6 +416,7 @@ pub async fn unified_diff(
     let opts = xdiff::DiffOpts {
         context: context_lines,
         copy_info,
+        ..Default::default()
     };
     let raw_diff = xdiff::diff_unified(old_diff_file, new_diff_file, opts);
     Ok(UnifiedDiff {
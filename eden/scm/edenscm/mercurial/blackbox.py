@@ -9,6 +9,7 @@ from bindings import blackbox as _blackbox
 events = _blackbox.events
 init = _blackbox.init
 log = _blackbox.log
+query = _blackbox.query
 sessions = _blackbox.sessions
 sync = _blackbox.sync
 
@@ -1793,6 +1793,12 @@ def _docommit(ui, repo, *pats, **opts):
         ("e", "edit", None, _("edit user config")),
         ("l", "local", None, _("edit repository config")),
         ("g", "global", None, _("edit global config")),
+        (
+            "",
+            "explain",
+            None,
+            _("show where config items are set and which one is effective"),
+        ),
     ]
     + formatteropts,
     _("[-u] [NAME]..."),
@@ -1817,12 +1823,28 @@ def config(ui, repo, *values, **opts):
     With --debug, the source (filename and line number) is printed
     for each config item.
 
+    With --explain, print every place that set the given config items,
+    in the order they were loaded. The last one is the effective value,
+    the others are overridden.
+
     See :hg:`help config` for more information about config files.
 
     Returns 0 on success, 1 if NAME does not exist.
 
     """
 
+    if opts.get("explain"):
+        if (
+            opts.get("edit")
+            or opts.get("local")
+            or opts.get("global")
+            or opts.get("template")
+        ):
+            raise error.Abort(_("--explain cannot be used with other options"))
+        if not values:
+            raise error.Abort(_("--explain requires a config name"))
+        return _explainconfig(ui, values)
+
     if opts.get("edit") or opts.get("local") or opts.get("global"):
         if opts.get("local") and opts.get("global"):
             raise error.Abort(_("can't use --local and --global together"))
@@ -1900,6 +1922,42 @@ def config(ui, repo, *values, **opts):
     return 1
 
 
+def _explainconfig(ui, values):
+    """print where config items are set, like the native 'config --explain'"""
+
+    def describe(filesource, source):
+        if filesource and filesource[0] != "<builtin>":
+            return "%s:%s (%s)" % (filesource[0], filesource[3], source)
+        return source
+
+    found = False
+    for v in values:
+        if "." in v:
+            section, name = v.split(".", 1)
+            names = [(section, name)] if ui.configsources(section, name) else []
+        else:
+            names = [(s, n) for s, n, _v in ui.walkconfig() if s == v]
+        for section, name in names:
+            sources = ui.configsources(section, name)
+            effective = sources[-1][0]
+            ui.write(
+                "%s.%s=%s\n"
+                % (section, name, "%unset" if effective is None else effective)
+            )
+            for i, (value, filesource, source) in enumerate(sources):
+                state = "effective" if i == len(sources) - 1 else "overridden"
+                ui.write(
+                    "    %s: %s (%s)\n"
+                    % (
+                        describe(filesource, source),
+                        "%unset" if value is None else value,
+                        state,
+                    )
+                )
+            found = True
+    return 0 if found else 1
+
+
 @command("continue|cont")
 def continuecmd(ui, repo):
     """resume operation after resolving conflicts"""
@@ -2597,6 +2655,88 @@ def _dograft(ui, repo, *revs, **opts):
     return 0
 
 
+def _grepctx(ui, repo, pattern, pats, opts):
+    """search the files of a revision, with the same output as the native grep"""
+    ctx = scmutil.revsingle(repo, opts.get("rev"))
+
+    def contextlength(value):
+        try:
+            return int(value or 0)
+        except ValueError:
+            raise error.Abort(_("%s: invalid context length argument") % value)
+
+    before = after = contextlength(opts.get("context"))
+    if opts.get("before_context"):
+        before = contextlength(opts.get("before_context"))
+    if opts.get("after_context"):
+        after = contextlength(opts.get("after_context"))
+
+    if opts.get("fixed_strings"):
+        pattern = re.escape(pattern)
+    if opts.get("word_regexp"):
+        pattern = r"\b(?:%s)\b" % pattern
+    flags = re.MULTILINE
+    if opts.get("ignore_case"):
+        flags |= re.IGNORECASE
+    try:
+        regex = re.compile(pycompat.encodeutf8(pattern), flags)
+    except re.error as ex:
+        raise error.Abort(_("invalid pattern: %s") % ex)
+
+    invert = opts.get("invert_match")
+    fileswithmatches = opts.get("files_with_matches")
+    hascontext = not fileswithmatches and (before or after)
+    matchopts = {k: opts.get(k) for k in ("include", "exclude") if k in opts}
+    m = scmutil.match(ctx, pats or ["."], matchopts)
+
+    found = False
+    for f in sorted(ctx.walk(m)):
+        fctx = ctx[f]
+        if fctx.islink():
+            continue
+        data = fctx.data()
+        # Like grep --binary-files=without-match.
+        if not data or b"\0" in data or (not invert and not regex.search(data)):
+            continue
+        if data.endswith(b"\n"):
+            data = data[:-1]
+        lines = data.split(b"\n")
+        path = pycompat.encodeutf8(m.rel(f))
+
+        matching = [bool(regex.search(line)) != bool(invert) for line in lines]
+        if fileswithmatches:
+            if any(matching):
+                ui.writebytes(path + b"\n")
+                found = True
+            continue
+
+        # True for matching lines, False for context lines.
+        marks = [None] * len(lines)
+        for i in range(len(lines)):
+            if not matching[i]:
+                continue
+            marks[i] = True
+            for j in range(max(i - before, 0), min(i + after, len(lines) - 1) + 1):
+                if marks[j] is None:
+                    marks[j] = False
+
+        last = None
+        for i, mark in enumerate(marks):
+            if mark is None:
+                continue
+            if found and hascontext and (last is None or last + 1 != i):
+                ui.writebytes(b"--\n")
+            separator = b":" if mark else b"-"
+            out = path + separator
+            if opts.get("line_number"):
+                out += pycompat.encodeutf8("%d" % (i + 1)) + separator
+            ui.writebytes(out + lines[i] + b"\n")
+            last = i
+            found = True
+
+    return 0 if found else 1
+
+
 @command(
     "grep|gre",
     [
@@ -2611,6 +2751,7 @@ def _dograft(ui, repo, *revs, **opts):
         ("E", "extended-regexp", None, "use POSIX extended regexps"),
         ("F", "fixed-strings", None, "interpret pattern as fixed string"),
         ("P", "perl-regexp", None, "use Perl-compatible regexps"),
+        ("r", "rev", "", _("search the repository as it is in REV"), _("REV")),
         (
             "I",
             "include",
@@ -2635,7 +2776,15 @@ def grep(ui, repo, pattern, *pats, **opts):
     The default regexp style is POSIX basic regexps. If no FILE parameters are
     passed in, the current directory and its subdirectories will be searched.
 
+    With --rev, files are searched as they are in REV, without checking it
+    out. The pattern then uses the syntax of Rust regular expressions, which
+    is close to -E.
+
     For the old 'hg grep', which searches through history, see 'histgrep'."""
+    if opts.get("rev"):
+        # Usually handled by the native grep, which falls back here for
+        # repositories and revisions it cannot read.
+        return _grepctx(ui, repo, pattern, pats, opts)
     # XXX: The current implementation heavily depends on external programs like
     # grep, xargs and biggrep.  Command-line flag support is a bit messy.  It
     # does not look like a source control command (ex. no --rev support).
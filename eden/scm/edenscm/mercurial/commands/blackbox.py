@@ -5,7 +5,7 @@
 
 import time
 
-from .. import blackbox as blackboxmod
+from .. import blackbox as blackboxmod, error
 from ..i18n import _
 from .cmdtable import command
 
@@ -18,6 +18,27 @@ from .cmdtable import command
         ("p", "pattern", "", _("JSON pattern to match (ADVANCED)")),
         ("", "timestamp", True, _("show timestamp (ADVANCED)")),
         ("", "sid", True, _("show session id (ADVANCED)")),
+        (
+            "",
+            "session-id",
+            0,
+            _("only show the given session (overrides --start and --end)"),
+        ),
+        ("t", "type", [], _("only show events of the given type"), _("TYPE")),
+        ("", "command", "", _("only show sessions running the given command")),
+        (
+            "",
+            "exit-code",
+            -1,
+            _("only show sessions exiting with the given code (-1: any)"),
+        ),
+        (
+            "",
+            "min-duration",
+            0,
+            _("only show sessions taking at least the given milliseconds"),
+        ),
+        ("f", "format", "text", _("output format (text, json, stats)")),
     ],
 )
 def blackbox(ui, repo, **opts):
@@ -26,6 +47,13 @@ def blackbox(ui, repo, **opts):
     By default, show events in the last 15 minutes. Use '--start 60' to get
     events in the past hour.
 
+    Sessions can be selected by '--session-id', '--command', '--exit-code'
+    and '--min-duration'. Use '--type' to only show events of the given
+    types, like 'start', 'finish' or 'network'.
+
+    Use '--format json' to output one JSON object per event, or
+    '--format stats' to show duration percentiles per command.
+
     Use '--debug' to see raw JSON values instead of human-readable messages.
 
     Use '--pattern' to filter events by JSON patterns. Examples::
@@ -52,21 +80,40 @@ def blackbox(ui, repo, **opts):
     end = opts.get("end", 0)
     showtimestamp = opts.get("timestamp", True)
     showsid = opts.get("sid", True)
+    exitcode = opts.get("exit_code", -1)
+    if not -1 <= exitcode <= 255:
+        raise error.Abort(_("exit code must be between 0 and 255"))
+    fmt = opts.get("format") or "text"
+    if fmt not in ("text", "json", "stats"):
+        raise error.Abort(_("unknown format: %s") % fmt)
+
     now = time.time()
-    timepattern = '{"start": {"timestamp_ms": ["range", %d, %d]}}' % (
-        (now - start * 60) * 1000,
-        (now - end * 60 + 1) * 1000,
+    events, stats = blackboxmod.query(
+        start=max(int((now - start * 60) * 1000), 0),
+        end=max(int((now - end * 60 + 1) * 1000), 0),
+        session_id=opts.get("session_id") or None,
+        types=opts.get("type") or [],
+        command=opts.get("command") or None,
+        exit_code=exitcode if exitcode >= 0 else None,
+        min_duration=max(opts.get("min_duration") or 0, 0) or None,
+        pattern=opts.get("pattern") or None,
     )
 
-    sessions = blackboxmod.sessions(timepattern)
-    events = blackboxmod.events(sessions, opts.get("pattern") or "{}")
-
     ui.pager("blackbox")
+    if fmt == "json":
+        for _sid, _ts, _msg, _json, entryjson in events:
+            ui.write("%s\n" % entryjson)
+        return
+    if fmt == "stats":
+        row = "%-20s %6s %8s %8s %8s %8s\n"
+        ui.write(row % ("COMMAND", "COUNT", "FAILED", "P50 MS", "P95 MS", "MAX MS"))
+        for stat in stats:
+            ui.write(row % stat)
+        return
+
     sidcolor = {}
     debugflag = ui.debugflag
-    # sort by timestamp
-    events = sorted(events, key=lambda t: t[1])
-    for sid, ts, msg, json in events:
+    for sid, ts, msg, json, _entryjson in events:
         if showtimestamp:
             localtime = time.localtime(ts)
             timestr = time.strftime("%Y/%m/%d %H:%M:%S", localtime) + (
@@ -28,6 +28,8 @@ import sys
 import tempfile
 import time
 
+import bindings
+
 from .. import (
     bookmarks,
     bundle2,
@@ -84,7 +86,7 @@ from .. import (
 from ..i18n import _, _x
 from ..node import bin, hex, nullhex, nullid, nullrev, short
 from ..pycompat import decodeutf8, range
-from .cmdtable import command
+from .cmdtable import command, table
 
 
 release = lockmod.release
@@ -483,6 +485,73 @@ def debugcheckstate(ui, repo):
         raise error.Abort(msg)
 
 
+@command(
+    "debugcompletion",
+    [
+        ("", "shell", "", _("shell to generate the script for (bash, zsh or fish)")),
+        ("", "commands", "", _("JSON file with more command definitions")),
+    ],
+    optionalrepo=True,
+)
+def debugcompletion(ui, repo, **opts):
+    """generate a shell completion script
+
+    The script completes command names, aliases from the ``[alias]``
+    config section, flags, bookmarks and file paths.
+
+    --shell selects the shell: 'bash', 'zsh' or 'fish'. By default, the
+    shell is picked from the SHELL environment variable.
+
+    Commands defined in Python and extensions are exported by Python to a
+    JSON file passed with --commands. It maps names like "log|history" to
+    objects with a "doc" string and a "flags" list of
+    ``[short, long, default, description]``.
+
+    Examples:
+
+    - enable completion in bash::
+
+        source <(hg debugcompletion --shell bash)
+
+    - install completion for fish::
+
+        hg debugcompletion --shell fish > ~/.config/fish/completions/hg.fish
+
+    Returns 0 on success.
+    """
+    # The script is generated by Rust. Pass it the merged command table,
+    # which includes commands defined in Python and extensions.
+    commands = {}
+    for names, entry in table.items():
+        if callable(entry[0]):
+            doc = entry[0].__doc__ or ""
+        else:
+            doc = entry[0]
+        flags = []
+        for flag in entry[1]:
+            short, long, default, desc = flag[:4]
+            if not isinstance(default, (bool, int, str, list)):
+                # Flags without values default to None.
+                default = False
+            flags.append([short, long, default, desc])
+        commands[names] = {"doc": doc, "flags": flags}
+    if opts.get("commands"):
+        commands.update(json.loads(util.readfile(opts.get("commands"))))
+
+    fd, path = tempfile.mkstemp(prefix="hg-completion-", suffix=".json")
+    try:
+        with util.fdopen(fd, "w") as f:
+            f.write(json.dumps(commands))
+        args = ["hg", "debugcompletion", "--commands", path]
+        if opts.get("shell"):
+            args += ["--shell", opts.get("shell")]
+        if repo is not None:
+            args += ["-R", repo.root]
+        return bindings.commands.run(args, ui.fin, ui.fout, ui.ferr)
+    finally:
+        util.tryunlink(path)
+
+
 @command(
     "debugcolor",
     [("", "style", None, _("show all configured styles"))],
@@ -8,16 +8,60 @@ from __future__ import absolute_import
 import collections
 from typing import Set, Tuple
 
-from .. import cmdutil, graphmod, phases, pycompat, util
+from .. import cmdutil, error, graphmod, mdiff, phases, pycompat, util
 from ..i18n import _
 from ..node import bin, hex, short
 from .cmdtable import command
 
 
-@command("debugmetalog", [("t", "time-range", [], _("select time range"), _("TIME"))])
+@command(
+    "debugmetalog",
+    [
+        ("t", "time-range", [], _("select time range"), _("TIME")),
+        ("", "history", None, _("list roots stored in metalog")),
+        ("", "diff", None, _("show keys changed since the selected root")),
+        (
+            "",
+            "restore",
+            [],
+            _("restore the given key from the selected root"),
+            _("KEY"),
+        ),
+        ("r", "root", "", _("select a root by index or hex prefix"), _("ROOT")),
+        (
+            "",
+            "before",
+            "",
+            _("select the last root before the given time"),
+            _("DATE"),
+        ),
+    ]
+    + cmdutil.formatteropts,
+)
 def debugmetalog(ui, repo, **opts):
     # type: (...) -> None
-    """show changes in commit graph over time"""
+    """show changes in commit graph over time
+
+    With --history, list roots stored in metalog, the newest first.
+
+    With --diff, show keys changed since the root selected by --root or
+    --before.
+
+    With --restore, copy the given keys (ex. bookmarks, visibleheads) from
+    the root selected by --root or --before, and write them as a new root.
+
+    --root accepts a root index as shown by --history, or a hex prefix of
+    a root id.
+
+    With --history and -T/--template, the output can be 'json', 'jsonl', or
+    a template string. Template keywords: ``index``, ``date`` (seconds
+    since epoch), ``id`` and ``message``.
+    """
+
+    if opts.get("history"):
+        return _history(ui, repo, opts)
+    if opts.get("diff") or opts.get("restore"):
+        return _diffrestore(ui, repo, opts)
 
     matchdatefuncs = []
     for timerange in opts.get("time_range") or []:
@@ -68,6 +112,101 @@ def debugmetalog(ui, repo, **opts):
     cmdutil.rustdisplaygraph(ui, repo, revdag, displayer(nodenamesdict))
 
 
+def _history(ui, repo, opts):
+    matchdatefuncs = [util.matchdate(t) for t in opts.get("time_range") or []]
+    metalog = repo.svfs.metalog
+    metalogpath = repo.svfs.join("metalog")
+    roots = metalog.listroots(metalogpath)
+    ui.pager("debugmetalog")
+    fm = ui.formatter("debugmetalog", opts)
+    for i, root in reversed(list(enumerate(roots))):
+        meta = metalog.__class__(metalogpath, root)
+        timestamp = meta.timestamp()
+        if matchdatefuncs and not any(m(timestamp) for m in matchdatefuncs):
+            continue
+        desc = meta.message()
+        fm.startitem()
+        fm.write(
+            "index datestr id shortmessage",
+            "%5s %s %s %s\n",
+            i,
+            util.datestr((timestamp, 0), "%Y-%m-%d %H:%M:%S"),
+            hex(root),
+            (desc.splitlines() or [""])[0],
+        )
+        fm.data(date=timestamp, message=desc)
+    fm.end()
+    return 0
+
+
+def _diffrestore(ui, repo, opts):
+    metalog = repo.svfs.metalog
+    metalogpath = repo.svfs.join("metalog")
+    root = _selectroot(metalog, metalogpath, opts)
+    old = metalog.__class__(metalogpath, root)
+
+    if opts.get("diff"):
+        current = metalog.__class__(metalogpath)
+        keys = sorted(set(old.keys()) | set(current.keys()))
+        for key in map(pycompat.decodeutf8, keys):
+            oldvalue, newvalue = old.get(key), current.get(key)
+            if oldvalue == newvalue:
+                continue
+            if oldvalue is None:
+                ui.write(_("%s: added\n") % key)
+            elif newvalue is None:
+                ui.write(_("%s: removed\n") % key)
+            else:
+                ui.write(_("%s: changed\n") % key)
+                diffopts = mdiff.diffopts(text=True, nodates=True)
+                _headers, hunks = mdiff.unidiff(
+                    oldvalue, "", newvalue, "", key, key, opts=diffopts
+                )
+                for _range, lines in hunks:
+                    ui.writebytes(b"".join(lines))
+
+    keys = opts.get("restore")
+    if keys:
+        with repo.lock(), repo.transaction("debugmetalog"):
+            current = repo.svfs.metalog
+            for key in keys:
+                value = old.get(key)
+                if value is None:
+                    current.remove(key)
+                else:
+                    current.set(key, value)
+        newroot = metalog.listroots(metalogpath)[-1]
+        ui.write(
+            _("restore %s from root %s (new root %s)\n")
+            % (", ".join(keys), hex(root), hex(newroot))
+        )
+    return 0
+
+
+def _selectroot(metalog, metalogpath, opts):
+    """find the root selected by --root or --before"""
+    roots = metalog.listroots(metalogpath)
+    before = opts.get("before")
+    if before:
+        timestamp = util.parsedate(before)[0]
+        result = roots[0]
+        for root in roots[1:]:
+            if metalog.__class__(metalogpath, root).timestamp() <= timestamp:
+                result = root
+        return result
+    rootarg = opts.get("root")
+    if not rootarg:
+        raise error.Abort(_("--root or --before is required"))
+    if rootarg.isdigit() and int(rootarg) < len(roots):
+        return roots[int(rootarg)]
+    matched = [r for r in roots if hex(r).startswith(rootarg)]
+    if len(matched) == 1:
+        return matched[0]
+    if not matched:
+        raise error.Abort(_("unknown root: %s") % rootarg)
+    raise error.Abort(_("ambiguous root: %s") % rootarg)
+
+
 def parsenodenames(meta):
     # type: (...) -> Set[Tuple[bytes, str]]
     """Parse a metalog entry.  Return nodes and their names."""
@@ -169,6 +169,7 @@ coreconfigitem("configs", "generationtime", default=-1)
 coreconfigitem("configs", "loaddynamicconfig", default=False)
 coreconfigitem("configs", "mismatchsampling", default=10000)
 coreconfigitem("configs", "mismatchwarn", default=False)
+coreconfigitem("configs", "rulespath", default=None)
 coreconfigitem("configs", "validatedynamicconfig", default=False)
 coreconfigitem("convert", "git.committeractions", default=lambda: ["messagedifferent"])
 coreconfigitem("convert", "git.extrakeys", default=list)
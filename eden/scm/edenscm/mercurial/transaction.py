@@ -590,11 +590,17 @@ class transaction(util.transactional):
                     "config", pycompat.encodeutf8(self.uiconfig.configtostring())
                 )
 
+            # merge strategies for concurrent writers, ex. "bookmarks = by-name"
+            resolve = None
+            if self.uiconfig:
+                resolve = self.uiconfig.configitems("metalog-merge") or None
+
             metalog.commit(
                 encoding.unifromlocal(
                     " ".join(map(util.shellquote, pycompat.sysargv[1:]))
                 ),
                 int(util.timer()),
+                resolve=resolve,
             )
             # Discard metalog state when exiting transaction.
             del svfs.__dict__["metalog"]
@@ -302,6 +302,9 @@ class ui(object):
     def configsource(self, section, name, untrusted=False):
         return self._uiconfig.configsource(section, name, untrusted)
 
+    def configsources(self, section, name):
+        return self._uiconfig.configsources(section, name)
+
     def config(self, section, name, default=_unset, untrusted=False):
         """return the plain string version of a config"""
         return self._uiconfig.config(section, name, default, untrusted)
@@ -233,6 +233,13 @@ class uiconfig(object):
                     return strsource
         return ""
 
+    def configsources(self, section, name):
+        """return [(value, filesource, source)] of places that set a config,
+        in the order they were loaded"""
+        return [
+            s for s in self._rcfg.sources(section, name) if s[2] != "ui.fixconfig"
+        ]
+
     def config(self, section, name, default=_unset, untrusted=False):
         """return the plain string version of a config"""
         value = self._config(section, name, default=default, untrusted=untrusted)
@@ -681,11 +688,13 @@ def validatedynamicconfig(ui):
 
 def applydynamicconfig(ui, reponame):
     if ui.configbool("configs", "loaddynamicconfig"):
-        dynamicconfig.applydynamicconfig(ui._uiconfig._rcfg._rcfg, reponame)
+        rulespath = ui.configpath("configs", "rulespath")
+        dynamicconfig.applydynamicconfig(ui._uiconfig._rcfg._rcfg, reponame, rulespath)
 
         validatedynamicconfig(ui)
 
 
 def generatedynamicconfig(ui, reponame, sharedpath):
     if ui.configbool("configs", "loaddynamicconfig"):
-        dynamicconfig.generatedynamicconfig(reponame, sharedpath)
+        rulespath = ui.configpath("configs", "rulespath")
+        dynamicconfig.generatedynamicconfig(reponame, sharedpath, rulespath)
@@ -7,7 +7,10 @@
 
 //! Python bindings for native blackbox logging.
 
-use blackbox::{self, event::Event, init, log, serde_json, BlackboxOptions, SessionId, ToValue};
+use blackbox::{
+    self, command_stats, event::Event, init, log, serde_json, BlackboxOptions, Query, SessionId,
+    ToValue,
+};
 use cpython::*;
 use cpython_ext::{PyNone, PyPath, ResultPyErrExt};
 use std::ops::Deref;
@@ -38,6 +41,23 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
             events_by_session_ids(session_ids: Vec<u64>, pattern: &str)
         ),
     )?;
+    m.add(
+        py,
+        "query",
+        py_fn!(
+            py,
+            query(
+                start: u64 = 0,
+                end: u64 = u64::MAX,
+                session_id: Option<u64> = None,
+                types: Vec<String> = Vec::new(),
+                command: Option<String> = None,
+                exit_code: Option<u8> = None,
+                min_duration: Option<u64> = None,
+                pattern: Option<String> = None
+            )
+        ),
+    )?;
 
     // _logjson takes a JSON string. Make it easier to use by
     // exposing a 'log' function that takes a Python object.
@@ -125,3 +145,67 @@ fn events_by_session_ids(
     }
     Ok(result)
 }
+
+/// Query sessions like `Blackbox::query`. Times are in milliseconds.
+/// Return `([(session_id, timestamp, message, json, entry_json)], stats)`,
+/// where `stats` is `[(command, count, failures, p50, p95, max)]`.
+fn query(
+    py: Python,
+    start: u64,
+    end: u64,
+    session_id: Option<u64>,
+    types: Vec<String>,
+    command: Option<String>,
+    exit_code: Option<u8>,
+    min_duration: Option<u64>,
+    pattern: Option<String>,
+) -> PyResult<(
+    Vec<(u64, f64, String, String, String)>,
+    Vec<(String, usize, usize, u64, u64, u64)>,
+)> {
+    let mut query = Query::new();
+    match session_id {
+        Some(session_id) => query = query.session_id(SessionId(session_id)),
+        None => query = query.start_time_range(start, end),
+    }
+    for name in types {
+        query = query.event_type(name);
+    }
+    if let Some(command) = command {
+        query = query.command(command);
+    }
+    if let Some(exit_code) = exit_code {
+        query = query.exit_code(exit_code);
+    }
+    if let Some(min_duration) = min_duration {
+        query = query.min_duration_ms(min_duration);
+    }
+    if let Some(pattern) = pattern {
+        query = query.pattern(serde_json::from_str(&pattern).map_pyerr(py)?);
+    }
+
+    let sessions = blackbox::SINGLETON.lock().query(&query);
+    let mut entries: Vec<_> = sessions.iter().flat_map(|s| s.entries.iter()).collect();
+    entries.sort_by_key(|e| e.timestamp);
+    let events = entries
+        .into_iter()
+        .map(|entry| {
+            let json = match &entry.data {
+                &Event::TracingData { serialized: _ } => "{}".to_string(),
+                _ => serde_json::to_string(&entry.data.to_value()).unwrap(),
+            };
+            (
+                entry.session_id,
+                (entry.timestamp as f64) / 1000.0,
+                format!("{}", entry.data),
+                json,
+                serde_json::to_string(&entry.to_json()).unwrap(),
+            )
+        })
+        .collect();
+    let stats = command_stats(&sessions)
+        .into_iter()
+        .map(|s| (s.command, s.count, s.failures, s.p50_ms, s.p95_ms, s.max_ms))
+        .collect();
+    Ok((events, stats))
+}
@@ -12,7 +12,7 @@ use std::fs;
 use cpython::*;
 use cpython_ext::{error::ResultPyErrExt, PyNone, PyPathBuf};
 
-use dynamicconfig::Generator;
+use dynamicconfig::{Generator, Rules};
 use pyconfigparser::config;
 
 pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
@@ -21,22 +21,33 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
     m.add(
         py,
         "applydynamicconfig",
-        py_fn!(py, applydynamicconfig(config: config, repo_name: String)),
+        py_fn!(
+            py,
+            applydynamicconfig(config: config, repo_name: String, rules_path: Option<PyPathBuf>)
+        ),
     )?;
     m.add(
         py,
         "generatedynamicconfig",
         py_fn!(
             py,
-            generatedynamicconfig(repo_name: String, shared_path: PyPathBuf)
+            generatedynamicconfig(
+                repo_name: String,
+                shared_path: PyPathBuf,
+                rules_path: Option<PyPathBuf>
+            )
         ),
     )?;
     Ok(m)
 }
 
-fn applydynamicconfig(py: Python, config: config, repo_name: String) -> PyResult<PyNone> {
-    let dyn_cfg = Generator::new(repo_name)
-        .map_pyerr(py)?
+fn applydynamicconfig(
+    py: Python,
+    config: config,
+    repo_name: String,
+    rules_path: Option<PyPathBuf>,
+) -> PyResult<PyNone> {
+    let dyn_cfg = generator(py, repo_name, rules_path)?
         .execute()
         .map_pyerr(py)?;
     for section in dyn_cfg.sections() {
@@ -60,9 +71,9 @@ fn generatedynamicconfig(
     py: Python,
     repo_name: String,
     shared_path: PyPathBuf,
+    rules_path: Option<PyPathBuf>,
 ) -> PyResult<PyNone> {
-    let config = Generator::new(repo_name)
-        .map_pyerr(py)?
+    let config = generator(py, repo_name, rules_path)?
         .execute()
         .map_pyerr(py)?;
     let config_str = config.to_string();
@@ -75,3 +86,12 @@ fn generatedynamicconfig(
     fs::write(shared_path.as_path().join("hgrc.dynamic"), config_str).map_pyerr(py)?;
     Ok(PyNone)
 }
+
+/// Create a generator with the rules at `rules_path`, if any.
+fn generator(py: Python, repo_name: String, rules_path: Option<PyPathBuf>) -> PyResult<Generator> {
+    let mut generator = Generator::new(repo_name).map_pyerr(py)?;
+    if let Some(rules_path) = rules_path {
+        generator = generator.with_rules(Rules::load(rules_path.as_path()).map_pyerr(py)?);
+    }
+    Ok(generator)
+}
@@ -7,7 +7,7 @@
 
 #![allow(non_camel_case_types)]
 
-use ::metalog::{CommitOptions, Id20, MetaLog, Repair};
+use ::metalog::{resolver::Strategies, CommitOptions, Id20, MetaLog, Repair};
 use cpython::*;
 use cpython_ext::{Bytes, PyNone, ResultPyErrExt, Str};
 use std::cell::RefCell;
@@ -81,9 +81,22 @@ py_class!(class metalog |py| {
     }
 
     /// Write pending data to disk. Raise if race condition is detected.
-    def commit(&self, message: &str, time: Option<u64> = None, pending: bool = false) -> PyResult<Bytes> {
+    ///
+    /// `resolve` is a list of `(key, strategy)` used to merge changes from
+    /// concurrent writers. Strategies are 'fail', 'prefer-this',
+    /// 'prefer-other', 'union' and 'by-name'. Key '*' sets the default.
+    def commit(
+        &self,
+        message: &str,
+        time: Option<u64> = None,
+        pending: bool = false,
+        resolve: Option<Vec<(String, String)>> = None
+    ) -> PyResult<Bytes> {
         let mut opts = CommitOptions::default();
         opts.detached = pending;
+        if let Some(resolve) = resolve {
+            opts.resolver = Some(Strategies::from_names(resolve).map_pyerr(py)?.into_resolver());
+        }
         opts.timestamp = time.unwrap_or_else(|| {
             SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
@@ -139,4 +152,10 @@ py_class!(class metalog |py| {
     def repair(path: &str) -> PyResult<Str> {
         py.allow_threads(|| MetaLog::repair(path)).map_pyerr(py).map(Into::into)
     }
+
+    @staticmethod
+    def gc(path: &str) -> PyResult<(usize, usize, u64, u64)> {
+        let stats = py.allow_threads(|| MetaLog::gc(path)).map_pyerr(py)?;
+        Ok((stats.objects_before, stats.objects_after, stats.bytes_before, stats.bytes_after))
+    }
 });
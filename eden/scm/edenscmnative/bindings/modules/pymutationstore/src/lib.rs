@@ -15,7 +15,7 @@ use cpython::*;
 use cpython_ext::{PyNone, PyPath, ResultPyErrExt, Str};
 use thiserror::Error;
 
-use ::mutationstore::{MutationStore, Repair};
+use ::mutationstore::{MutationKind, MutationStore, Repair};
 use pydag::memnamedag;
 use types::mutation::MutationEntry;
 use types::node::Node;
@@ -153,6 +153,11 @@ py_class!(class mutationentry |py| {
         Ok(self.entry(py).op.clone().into())
     }
 
+    /// Classified operation: amend, rebase, fold, split, land, or other.
+    def kind(&self) -> PyResult<Str> {
+        Ok(MutationKind::from_entry(self.entry(py)).as_str().to_string().into())
+    }
+
     def user(&self) -> PyResult<Str> {
         Ok(self.entry(py).user.clone().into())
     }
@@ -236,6 +241,40 @@ py_class!(class mutationstore |py| {
         Ok(pyssets)
     }
 
+    /// Latest versions of the node, following successors recursively.
+    def getfinalsuccessors(&self, node: &PyBytes) -> PyResult<Vec<PyBytes>> {
+        let node = Node::from_slice(node.data(py)).map_pyerr(py)?;
+        let ms = self.mut_store(py).borrow();
+        let nodes = ms.get_final_successors(node).map_pyerr(py)?;
+        Ok(nodes.into_iter().map(|n| PyBytes::new(py, n.as_ref())).collect())
+    }
+
+    /// All predecessors of the node, the closest first.
+    def getallpredecessors(&self, node: &PyBytes) -> PyResult<Vec<PyBytes>> {
+        let node = Node::from_slice(node.data(py)).map_pyerr(py)?;
+        let ms = self.mut_store(py).borrow();
+        let nodes = ms.get_all_predecessors(node).map_pyerr(py)?;
+        Ok(nodes.into_iter().map(|n| PyBytes::new(py, n.as_ref())).collect())
+    }
+
+    /// Divergent commits among the node and its successors, as a list of
+    /// (node, [final successors for each rewrite]).
+    def getdivergence(&self, node: &PyBytes) -> PyResult<Vec<(PyBytes, Vec<Vec<PyBytes>>)>> {
+        let node = Node::from_slice(node.data(py)).map_pyerr(py)?;
+        let ms = self.mut_store(py).borrow();
+        let divergence = ms.get_divergence(node).map_pyerr(py)?;
+        Ok(divergence
+            .into_iter()
+            .map(|(node, sets)| {
+                let sets = sets
+                    .into_iter()
+                    .map(|set| set.into_iter().map(|n| PyBytes::new(py, n.as_ref())).collect())
+                    .collect();
+                (PyBytes::new(py, node.as_ref()), sets)
+            })
+            .collect())
+    }
+
     /// Figure out connected components related to specified nodes.
     /// The returned graph supports DAG related calculations like
     /// ancestors, heads, roots, etc.
@@ -0,0 +1,47 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use clidispatch::errors;
+use clidispatch::io::IO;
+use clidispatch::server::{run_client, Request, SERVER_ENV};
+use std::path::Path;
+
+/// Run the command using `hg debugcommandserver` if `HGCOMMANDSERVER` is
+/// set to its socket path.
+/// Note that this function terminates the process if the server ran the
+/// command.
+pub fn maybe_call_command_server(args: &[String]) {
+    let path = match std::env::var_os(SERVER_ENV) {
+        Some(path) => path,
+        None => return,
+    };
+
+    // `/dev/fd/x` resolves to a different fd in the server.
+    if args
+        .iter()
+        .any(|a| a.starts_with("/dev/fd/") || a.starts_with("/proc/self/"))
+    {
+        return;
+    }
+
+    let request = match Request::from_current_process(args.iter().skip(1).cloned().collect()) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+    let mut io = IO::stdio();
+    let code = match run_client(Path::new(&path), &request, &mut io) {
+        Ok(None) => return,
+        Ok(Some(code)) => code,
+        Err(err) => {
+            errors::print_error(&err, &mut io);
+            255
+        }
+    };
+    let _ = io.flush();
+    drop(io);
+    std::process::exit(code);
+}
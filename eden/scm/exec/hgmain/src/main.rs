@@ -8,6 +8,10 @@
 use clidispatch::dispatch;
 
 mod buildinfo;
+#[cfg(unix)]
+mod commandserver;
+#[cfg(unix)]
+use commandserver::maybe_call_command_server;
 #[cfg(feature = "with_chg")]
 mod chg;
 #[cfg(feature = "with_chg")]
@@ -58,6 +62,9 @@ fn main() {
         _ => (),
     }
 
+    #[cfg(unix)]
+    maybe_call_command_server(&full_args);
+
     #[cfg(feature = "with_chg")]
     maybe_call_chg(&full_args);
 
@@ -14,4 +14,8 @@ use thiserror::Error;
 pub enum ErrorKind {
     #[error("unexpected error {0}")]
     ScmDaemonUnexpectedError(String),
+    #[error("config error: {0}")]
+    ScmDaemonConfigError(String),
+    #[error("job error: {0}")]
+    ScmDaemonJobError(String),
 }
@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Result;
+use log::{error, info};
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod defaults {
+    pub fn min_interval_sec() -> u64 {
+        60 * 5
+    }
+}
+
+/// A job hosted by the daemon
+/// Jobs run periodically, or when triggered by the `scm_daemon::run_job`
+/// command of the tcp receiver
+/// Jobs are run one at a time by the scheduler
+pub trait Job: Send {
+    /// Name of the job, which is also the name of its config section
+    fn name(&self) -> &'static str;
+
+    /// Run the job once
+    fn run(&mut self, log: &mut JobLog) -> Result<()>;
+}
+
+/// Settings shared by all jobs, decoded from the job's TOML section.
+/// Each field has default implementation, meaning that it doesn't have to be present in TOML.
+
+#[derive(Debug, Default, Deserialize)]
+pub struct JobConfig {
+    /// Jobs are disabled unless enabled explicitly
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Run the job every `interval_sec` seconds
+    /// 0 means the job only runs when triggered
+    #[serde(default)]
+    pub interval_sec: u64,
+
+    /// Minimal time between two runs in sec, including triggered runs
+    #[serde(default = "defaults::min_interval_sec")]
+    pub min_interval_sec: u64,
+
+    /// Repo roots to run the job in
+    #[serde(default)]
+    pub repos: Vec<PathBuf>,
+
+    /// File to append the job's log to, in addition to the daemon log
+    #[serde(default)]
+    pub log_path: Option<PathBuf>,
+}
+
+/// Log of a single job
+/// Messages go to the daemon log prefixed by the job name, and to the
+/// job's log file if it is configured
+pub struct JobLog {
+    name: &'static str,
+    file: Option<File>,
+}
+
+impl JobLog {
+    pub fn new(name: &'static str, path: Option<&Path>) -> Result<JobLog> {
+        let file = match path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(JobLog { name, file })
+    }
+
+    pub fn info(&mut self, message: impl AsRef<str>) {
+        info!("[{}] {}", self.name, message.as_ref());
+        self.write("INFO", message.as_ref());
+    }
+
+    pub fn error(&mut self, message: impl AsRef<str>) {
+        error!("[{}] {}", self.name, message.as_ref());
+        self.write("ERROR", message.as_ref());
+    }
+
+    fn write(&mut self, level: &str, message: &str) {
+        if let Some(ref mut file) = self.file {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            // a broken log file should not fail the job
+            let _ = writeln!(file, "{} {} {}", now, level, message);
+        }
+    }
+}
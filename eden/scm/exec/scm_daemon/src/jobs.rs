@@ -0,0 +1,370 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::error::ErrorKind;
+use crate::job::{Job, JobConfig, JobLog};
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use treestate::dirstate::Dirstate;
+use treestate::treestate::CompactionPolicy;
+
+/// [prefetch] and [repack] sections
+#[derive(Debug, Deserialize)]
+pub struct HgJobConfig {
+    #[serde(flatten)]
+    pub job: JobConfig,
+
+    /// Arguments of `hg` to run instead of the default ones of the job
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+}
+
+/// [treestate] section
+#[derive(Debug, Deserialize)]
+pub struct TreestateJobConfig {
+    #[serde(flatten)]
+    pub hg: HgJobConfig,
+
+    /// Minimal number of dead bytes in the treestate file to compact it
+    #[serde(default)]
+    pub min_dead_size: Option<u64>,
+
+    /// Minimal ratio of dead bytes to the treestate file size to compact it
+    #[serde(default)]
+    pub min_dead_ratio: Option<f64>,
+}
+
+/// [blackbox] section
+#[derive(Debug, Deserialize)]
+pub struct BlackboxJobConfig {
+    #[serde(flatten)]
+    pub job: JobConfig,
+
+    /// Command that reads blackbox events from stdin and uploads them
+    /// Example: ["scribe_cat", "hg_blackbox"]
+    #[serde(default)]
+    pub upload_command: Vec<String>,
+}
+
+/// Job running an `hg` command in every configured repo
+/// Used for background prefetch and repack
+pub struct HgCommandJob {
+    name: &'static str,
+    args: Vec<String>,
+    repos: Vec<PathBuf>,
+}
+
+impl HgCommandJob {
+    pub fn new(name: &'static str, config: &HgJobConfig, default_args: &[&str]) -> HgCommandJob {
+        HgCommandJob {
+            name,
+            args: config
+                .args
+                .clone()
+                .unwrap_or_else(|| default_args.iter().map(|s| s.to_string()).collect()),
+            repos: config.job.repos.clone(),
+        }
+    }
+
+    /// Run the command in `repo_root` and log its output
+    fn run_in(&self, repo_root: &Path, log: &mut JobLog) -> Result<()> {
+        let stdout = run_hg(repo_root, &self.args, log)?;
+        let stdout = String::from_utf8_lossy(&stdout);
+        if !stdout.trim().is_empty() {
+            log.info(format!("stdout: \n{}", stdout.trim()));
+        }
+        Ok(())
+    }
+}
+
+impl Job for HgCommandJob {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&mut self, log: &mut JobLog) -> Result<()> {
+        let mut failed = 0;
+        for repo_root in self.repos.iter() {
+            if let Err(e) = self.run_in(repo_root, log) {
+                log.error(format!("'{}': {}", repo_root.display(), e));
+                failed += 1;
+            }
+        }
+        check_failures(failed, self.repos.len())
+    }
+}
+
+/// Job compacting the treestate of every configured repo
+/// `hg debugtreestate repack` only runs in repos whose treestate file has
+/// enough dead bytes according to the compaction policy
+pub struct TreestateJob {
+    command: HgCommandJob,
+    policy: CompactionPolicy,
+}
+
+impl TreestateJob {
+    pub fn new(config: &TreestateJobConfig) -> TreestateJob {
+        let default = CompactionPolicy::default();
+        TreestateJob {
+            command: HgCommandJob::new("treestate", &config.hg, &["debugtreestate", "repack"]),
+            policy: CompactionPolicy {
+                min_dead_size: config.min_dead_size.unwrap_or(default.min_dead_size),
+                min_dead_ratio: config.min_dead_ratio.unwrap_or(default.min_dead_ratio),
+            },
+        }
+    }
+
+    /// Test if the treestate file of `repo_root` needs compaction
+    fn needs_compaction(&self, repo_root: &Path) -> Result<bool> {
+        let dot_hg = repo_root.join(".hg");
+        let dirstate = Dirstate::read(&dot_hg)?;
+        let mut tree = dirstate.open_tree(&dot_hg)?;
+        tree.needs_compaction(&self.policy)
+    }
+}
+
+impl Job for TreestateJob {
+    fn name(&self) -> &'static str {
+        self.command.name
+    }
+
+    fn run(&mut self, log: &mut JobLog) -> Result<()> {
+        let mut failed = 0;
+        for repo_root in self.command.repos.iter() {
+            let result = self.needs_compaction(repo_root).and_then(|needed| {
+                if needed {
+                    self.command.run_in(repo_root, log)
+                } else {
+                    log.info(format!(
+                        "'{}': compaction is not needed",
+                        repo_root.display()
+                    ));
+                    Ok(())
+                }
+            });
+            if let Err(e) = result {
+                log.error(format!("'{}': {}", repo_root.display(), e));
+                failed += 1;
+            }
+        }
+        check_failures(failed, self.command.repos.len())
+    }
+}
+
+/// Job uploading recent blackbox events of every configured repo
+/// Events newer than the last uploaded one are read by
+/// `hg blackbox --format json` and piped into the upload command, one JSON
+/// object per line
+/// If the job has a log file, the last uploaded events are remembered in
+/// `<log file>.state` so a restarted daemon does not upload them again
+pub struct BlackboxUploadJob {
+    upload_command: Vec<String>,
+    start_minutes: u64,
+    repos: Vec<PathBuf>,
+    /// Timestamp in milliseconds of the last uploaded event per repo
+    last_uploaded: HashMap<PathBuf, u64>,
+    /// File to persist `last_uploaded` in
+    state_path: Option<PathBuf>,
+}
+
+impl BlackboxUploadJob {
+    pub fn new(config: &BlackboxJobConfig) -> Result<BlackboxUploadJob> {
+        if config.upload_command.is_empty() {
+            return Err(ErrorKind::ScmDaemonConfigError(
+                "undefined 'upload_command' for blackbox".into(),
+            )
+            .into());
+        }
+        // `hg blackbox` takes minutes. Round up so no event is skipped.
+        let start_minutes = match config.job.interval_sec {
+            0 => 15,
+            interval_sec => (interval_sec + 59) / 60,
+        };
+        let state_path = config.job.log_path.as_ref().map(|log_path| {
+            let mut name = log_path.file_name().unwrap_or_default().to_owned();
+            name.push(".state");
+            log_path.with_file_name(name)
+        });
+        // A missing or broken state only causes events to be uploaded again
+        let last_uploaded = state_path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Ok(BlackboxUploadJob {
+            upload_command: config.upload_command.clone(),
+            start_minutes,
+            repos: config.job.repos.clone(),
+            last_uploaded,
+            state_path,
+        })
+    }
+
+    /// Write `last_uploaded` to the state file
+    fn save_state(&self) -> Result<()> {
+        if let Some(ref path) = self.state_path {
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, serde_json::to_vec(&self.last_uploaded)?)?;
+            fs::rename(&tmp_path, path)?;
+        }
+        Ok(())
+    }
+
+    /// Read events of `repo_root` newer than the last uploaded one
+    /// Return the events and the timestamp of the newest one
+    fn read_events(&self, repo_root: &Path, log: &mut JobLog) -> Result<(Vec<u8>, Option<u64>)> {
+        let last_uploaded = self.last_uploaded.get(repo_root).cloned();
+        // `hg blackbox` selects sessions by their start time. Also include
+        // sessions started an interval before the last upload, since they
+        // can have newer events.
+        let start_minutes = match last_uploaded {
+            Some(last_uploaded) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis() as u64)
+                    .unwrap_or(0);
+                now.saturating_sub(last_uploaded) / 60_000 + 1 + self.start_minutes
+            }
+            None => self.start_minutes,
+        };
+        let args = vec![
+            "blackbox".to_owned(),
+            "--start".to_owned(),
+            start_minutes.to_string(),
+            "--format".to_owned(),
+            "json".to_owned(),
+        ];
+        let output = run_hg(repo_root, &args, log)?;
+
+        let mut events = Vec::new();
+        let mut newest = last_uploaded;
+        for line in output.split(|&b| b == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let timestamp = serde_json::from_slice::<serde_json::Value>(line)?["timestamp_ms"]
+                .as_u64()
+                .ok_or_else(|| {
+                    ErrorKind::ScmDaemonJobError("blackbox event without timestamp".into())
+                })?;
+            if last_uploaded >= Some(timestamp) {
+                continue;
+            }
+            events.extend_from_slice(line);
+            events.push(b'\n');
+            newest = newest.max(Some(timestamp));
+        }
+        Ok((events, newest))
+    }
+
+    fn upload(&self, repo_root: &Path, events: Vec<u8>, log: &mut JobLog) -> Result<()> {
+        let mut child = Command::new(&self.upload_command[0])
+            .args(&self.upload_command[1..])
+            .current_dir(repo_root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        // write from another thread so a chatty command cannot block us
+        let writer = thread::spawn(move || stdin.write_all(&events));
+        let output = child.wait_with_output()?;
+        let _ = writer.join();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim().is_empty() {
+            log.info(format!("upload stderr: \n{}", stderr.trim()));
+        }
+        if !output.status.success() {
+            return Err(ErrorKind::ScmDaemonJobError(format!(
+                "upload exited with: {}",
+                output.status
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Job for BlackboxUploadJob {
+    fn name(&self) -> &'static str {
+        "blackbox"
+    }
+
+    fn run(&mut self, log: &mut JobLog) -> Result<()> {
+        let mut failed = 0;
+        for repo_root in self.repos.clone() {
+            let result = self
+                .read_events(&repo_root, log)
+                .and_then(|(events, newest)| {
+                    if !events.is_empty() {
+                        self.upload(&repo_root, events, log)?;
+                    }
+                    if let Some(newest) = newest {
+                        self.last_uploaded.insert(repo_root.clone(), newest);
+                    }
+                    Ok(())
+                });
+            if let Err(e) = result {
+                log.error(format!("'{}': {}", repo_root.display(), e));
+                failed += 1;
+            }
+        }
+        if let Err(e) = self.save_state() {
+            log.error(format!("cannot save state: {}", e));
+        }
+        check_failures(failed, self.repos.len())
+    }
+}
+
+/// Run `hg` with `args` in `repo_root` and return its stdout
+fn run_hg(repo_root: &Path, args: &[String], log: &mut JobLog) -> Result<Vec<u8>> {
+    let now = Instant::now();
+    let output = Command::new("hg")
+        .current_dir(repo_root)
+        .env("HGPLAIN", "1")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        log.info(format!("stderr: \n{}", stderr.trim()));
+    }
+    log.info(format!(
+        "`hg {}` in '{}' took {} ms",
+        args.join(" "),
+        repo_root.display(),
+        now.elapsed().as_millis()
+    ));
+    if !output.status.success() {
+        return Err(ErrorKind::ScmDaemonJobError(format!(
+            "process exited with: {}",
+            output.status
+        ))
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+fn check_failures(failed: usize, total: usize) -> Result<()> {
+    if failed > 0 {
+        return Err(ErrorKind::ScmDaemonJobError(format!(
+            "failed in {} of {} repos",
+            failed, total
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -6,13 +6,21 @@
  */
 
 pub mod error;
+pub mod job;
+pub mod jobs;
+pub mod scheduler;
 
 use anyhow::{bail, Result};
 use clap::{App, Arg};
 use commitcloudsubscriber::{
     CommitCloudConfig, CommitCloudTcpReceiverService, CommitCloudWorkspaceSubscriberService,
 };
+use jobs::{
+    BlackboxJobConfig, BlackboxUploadJob, HgCommandJob, HgJobConfig, TreestateJob,
+    TreestateJobConfig,
+};
 use log::info;
+use scheduler::Scheduler;
 use serde::Deserialize;
 use std::fs::File;
 use std::io::Read;
@@ -27,6 +35,14 @@ pub struct Config {
     pub title: Option<String>,
     /// [commitcloud] section: commitcloudlib provides description of it
     pub commitcloud: Option<CommitCloudConfig>,
+    /// [prefetch] section: periodic background prefetch, see job::JobConfig
+    pub prefetch: Option<HgJobConfig>,
+    /// [repack] section: periodic incremental repack
+    pub repack: Option<HgJobConfig>,
+    /// [treestate] section: periodic treestate compaction
+    pub treestate: Option<TreestateJobConfig>,
+    /// [blackbox] section: periodic blackbox upload
+    pub blackbox: Option<BlackboxJobConfig>,
 }
 
 // To support older than Rust 1.26 on dev servers
@@ -78,35 +94,81 @@ fn run() -> Result<()> {
         content
     })?;
 
+    // jobs part of the configuration
+    let scheduler = scheduler_from_config(&config)?;
+
     // commit cloud part of the configuration
-    let commitcloudconfref = &config
-        .commitcloud
-        .unwrap_or_else(|| toml::from_str::<CommitCloudConfig>("").unwrap());
+    // the workspace subscriber only runs if there is a [commitcloud] section
+    let default_commitcloudconf;
+    let commitcloudconfref = match config.commitcloud {
+        Some(ref commitcloudconf) => commitcloudconf,
+        None => {
+            default_commitcloudconf = toml::from_str::<CommitCloudConfig>("").unwrap();
+            &default_commitcloudconf
+        }
+    };
 
-    let commitcloud_workspacesubscriber =
-        CommitCloudWorkspaceSubscriberService::new(commitcloudconfref)?;
-    let commitcloud_tcpreceiver =
+    let commitcloud_workspacesubscriber = match config.commitcloud {
+        Some(_) => Some(CommitCloudWorkspaceSubscriberService::new(
+            commitcloudconfref,
+        )?),
+        None => None,
+    };
+    let mut commitcloud_tcpreceiver =
         CommitCloudTcpReceiverService::new(commitcloudconfref.tcp_receiver_port)
-            .with_actions(commitcloud_workspacesubscriber.actions());
+            .with_handlers(scheduler.handlers());
+    if let Some(ref commitcloud_workspacesubscriber) = commitcloud_workspacesubscriber {
+        commitcloud_tcpreceiver =
+            commitcloud_tcpreceiver.with_actions(commitcloud_workspacesubscriber.actions());
+    }
 
     // start services
-    let commitcloud_tcpreceiver_handler = commitcloud_tcpreceiver.serve()?;
-    let commitcloud_workspacesubscriber_handler = commitcloud_workspacesubscriber.serve()?;
+    let mut handlers = vec![("commitcloud tcpreceiver", commitcloud_tcpreceiver.serve()?)];
+    if let Some(commitcloud_workspacesubscriber) = commitcloud_workspacesubscriber {
+        handlers.push((
+            "commitcloud workspace subscriber",
+            commitcloud_workspacesubscriber.serve()?,
+        ));
+    }
+    if !scheduler.is_empty() {
+        handlers.push(("scheduler", scheduler.serve()?));
+    }
 
     // join running services, this will block
-    match commitcloud_tcpreceiver_handler.join() {
-        Ok(result) => result?,
-        Err(_) => bail!("commitcloud tcpreceiver panicked"),
-    };
-
-    match commitcloud_workspacesubscriber_handler.join() {
-        Ok(result) => result?,
-        Err(_) => bail!("commitcloud workspace subscriber panicked"),
-    };
+    for (name, handler) in handlers {
+        match handler.join() {
+            Ok(result) => result?,
+            Err(_) => bail!("{} panicked", name),
+        };
+    }
 
     Ok(())
 }
 
+/// Create the scheduler with the jobs enabled in the config
+fn scheduler_from_config(config: &Config) -> Result<Scheduler> {
+    let mut scheduler = Scheduler::new();
+    if let Some(ref prefetch) = config.prefetch {
+        let job = HgCommandJob::new("prefetch", prefetch, &["prefetch"]);
+        scheduler.add(Box::new(job), &prefetch.job)?;
+    }
+    if let Some(ref repack) = config.repack {
+        let job = HgCommandJob::new("repack", repack, &["repack", "--incremental"]);
+        scheduler.add(Box::new(job), &repack.job)?;
+    }
+    if let Some(ref treestate) = config.treestate {
+        let job = TreestateJob::new(treestate);
+        scheduler.add(Box::new(job), &treestate.hg.job)?;
+    }
+    if let Some(ref blackbox) = config.blackbox {
+        if blackbox.job.enabled {
+            let job = BlackboxUploadJob::new(blackbox)?;
+            scheduler.add(Box::new(job), &blackbox.job)?;
+        }
+    }
+    Ok(scheduler)
+}
+
 /// Refuse to run if nice is too high (i.e. process has low priority)
 ///
 /// This is because the hg processes spawned by this daemon inherit the
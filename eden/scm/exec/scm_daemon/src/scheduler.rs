@@ -0,0 +1,229 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::error::ErrorKind;
+use crate::job::{Job, JobConfig, JobLog};
+use anyhow::{bail, Result};
+use commitcloudsubscriber::receiver::{CommandName, Handler};
+use commitcloudsubscriber::ThrottlingExecutor;
+use log::{error, info};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Health of a job, reported by the `scm_daemon::health` command
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct JobStatus {
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    pub throttled: u64,
+    /// Unix time in sec of the last start
+    pub last_start: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+struct ScheduledJob {
+    job: Box<dyn Job>,
+    interval: Option<Duration>,
+    next_run: Option<Instant>,
+    throttler: ThrottlingExecutor,
+    log: JobLog,
+}
+
+/// Scheduler runs the enabled jobs one at a time
+/// The workflow is simple:
+/// * jobs with an interval run every interval, starting one interval
+///   after the daemon starts
+/// * any job runs when the `scm_daemon::run_job` command names it,
+///   example: ["scm_daemon::run_job", {"job": "repack"}]
+/// * a job does not run again within its `min_interval_sec`,
+///   neither periodically nor when triggered
+/// * `scm_daemon::health` replies with the status of all jobs
+///
+/// The serve function starts the service
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+    status: Arc<Mutex<BTreeMap<&'static str, JobStatus>>>,
+    channel: (mpsc::Sender<String>, mpsc::Receiver<String>),
+    started: SystemTime,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            jobs: Vec::new(),
+            status: Arc::new(Mutex::new(BTreeMap::new())),
+            channel: mpsc::channel(),
+            started: SystemTime::now(),
+        }
+    }
+
+    /// Add a job if it is enabled in its config
+    pub fn add(&mut self, job: Box<dyn Job>, config: &JobConfig) -> Result<()> {
+        let name = job.name();
+        if !config.enabled {
+            info!("Job {} is disabled", name);
+            return Ok(());
+        }
+        if config.repos.is_empty() {
+            return Err(
+                ErrorKind::ScmDaemonConfigError(format!("undefined 'repos' for {}", name)).into(),
+            );
+        }
+        let interval = match config.interval_sec {
+            0 => None,
+            sec => Some(Duration::from_secs(sec)),
+        };
+        info!(
+            "Job {} is enabled, interval: {} sec, min interval: {} sec",
+            name, config.interval_sec, config.min_interval_sec
+        );
+        self.jobs.push(ScheduledJob {
+            job,
+            interval,
+            next_run: interval.map(|interval| Instant::now() + interval),
+            throttler: ThrottlingExecutor::new(Duration::from_secs(config.min_interval_sec)),
+            log: JobLog::new(name, config.log_path.as_deref())?,
+        });
+        self.status
+            .lock()
+            .unwrap()
+            .insert(name, JobStatus::default());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Callbacks for the TcpReceiver commands
+    pub fn handlers(&self) -> HashMap<CommandName, Handler> {
+        let mut handlers: HashMap<CommandName, Handler> = HashMap::new();
+        handlers.insert(CommandName::ScmDaemonHealth, {
+            let status = self.status.clone();
+            let started = self.started;
+            Box::new(move |_| {
+                let uptime_sec = started.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+                let jobs = status.lock().unwrap().clone();
+                json!({ "uptime_sec": uptime_sec, "jobs": jobs })
+            })
+        });
+        handlers.insert(CommandName::ScmDaemonRunJob, {
+            let status = self.status.clone();
+            let sender = self.channel.0.clone();
+            Box::new(move |data| {
+                let name = match data.extra.get("job").and_then(|name| name.as_str()) {
+                    Some(name) => name.to_string(),
+                    None => return json!({ "error": "undefined 'job'" }),
+                };
+                if !status.lock().unwrap().contains_key(name.as_str()) {
+                    return json!({ "error": format!("job {} is not enabled", name) });
+                }
+                match sender.send(name) {
+                    Ok(_) => json!({ "queued": true }),
+                    Err(err) => {
+                        error!("Send job name via mpsc::channel failed with {}", err);
+                        json!({ "error": err.to_string() })
+                    }
+                }
+            })
+        });
+        handlers
+    }
+
+    pub fn serve(mut self) -> Result<thread::JoinHandle<Result<()>>> {
+        Ok(thread::spawn(move || {
+            info!("Starting Scheduler with {} jobs", self.jobs.len());
+            loop {
+                let now = Instant::now();
+                let timeout = self
+                    .jobs
+                    .iter()
+                    .filter_map(|job| job.next_run)
+                    .min()
+                    .map(|next_run| next_run.saturating_duration_since(now))
+                    .unwrap_or_else(|| Duration::from_secs(60));
+                match self.channel.1.recv_timeout(timeout) {
+                    Ok(name) => {
+                        if let Some(job) = self.jobs.iter_mut().find(|job| job.job.name() == name) {
+                            info!("Job {} is triggered", name);
+                            run_job(job, &self.status);
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        let now = Instant::now();
+                        for job in self.jobs.iter_mut() {
+                            if job.next_run.map_or(false, |next_run| next_run <= now) {
+                                run_job(job, &self.status);
+                                job.next_run =
+                                    job.interval.map(|interval| Instant::now() + interval);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Receive from mpsc::channel failed with {}", e);
+                        bail!("Receive and wait on mpsc::channel failed with {}", e);
+                    }
+                }
+            }
+        }))
+    }
+}
+
+/// Run a job unless it ran within its min interval, and record its status
+fn run_job(job: &mut ScheduledJob, status: &Mutex<BTreeMap<&'static str, JobStatus>>) {
+    let name = job.job.name();
+    let set_status = |f: &dyn Fn(&mut JobStatus)| {
+        if let Some(status) = status.lock().unwrap().get_mut(name) {
+            f(status);
+        }
+    };
+    let ScheduledJob {
+        job: inner,
+        throttler,
+        log,
+        ..
+    } = job;
+    let executed = throttler.execute(|| {
+        let start = SystemTime::now();
+        set_status(&|status| {
+            status.running = true;
+            status.last_start = start.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+        });
+        log.info("Job started");
+        let result = inner.run(log);
+        let duration_ms = start.elapsed().map(|d| d.as_millis() as u64).unwrap_or(0);
+        match &result {
+            Ok(_) => log.info(format!("Job finished in {} ms", duration_ms)),
+            Err(e) => log.error(format!("Job failed in {} ms: {}", duration_ms, e)),
+        }
+        set_status(&|status| {
+            status.running = false;
+            status.runs += 1;
+            status.last_duration_ms = Some(duration_ms);
+            status.last_error = result.as_ref().err().map(|e| e.to_string());
+            if result.is_err() {
+                status.failures += 1;
+            }
+        });
+    });
+    if !executed {
+        log.info("Job skipped because it ran recently");
+        set_status(&|status| status.throttled += 1);
+    }
+}
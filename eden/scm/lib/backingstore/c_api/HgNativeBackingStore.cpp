@@ -9,6 +9,7 @@
 
 #include <folly/Range.h>
 #include <folly/String.h>
+#include <folly/futures/Promise.h>
 #include <folly/io/IOBuf.h>
 #include <folly/logging/xlog.h>
 #include <cstddef>
@@ -58,11 +59,86 @@ void getBlobBatchCallback(
         (*static_cast<Fn*>(fn))(index, result);
       });
 }
+
+/**
+ * Same as `getBlobBatchCallback`, for trees.
+ */
+template <typename Fn>
+void getTreeBatchCallback(
+    RustBackingStore* store,
+    RustRequest* request,
+    uintptr_t size,
+    bool local,
+    Fn&& fn) {
+  rust_backingstore_get_tree_batch(
+      store,
+      request,
+      size,
+      local,
+      // We need to take address of the function, not to forward it.
+      // @lint-ignore HOWTOEVEN MissingStdForward
+      &fn,
+      [](void* fn, size_t index, RustCFallibleBase result) {
+        (*static_cast<Fn*>(fn))(index, result);
+      });
+}
+
+std::vector<RustRequest> toRustRequests(
+    const std::vector<std::pair<folly::ByteRange, folly::ByteRange>>&
+        requests) {
+  std::vector<RustRequest> raw_requests;
+  raw_requests.reserve(requests.size());
+
+  for (auto& request : requests) {
+    auto& name = request.first;
+    auto& node = request.second;
+
+    raw_requests.emplace_back(RustRequest{
+        name.data(),
+        name.size(),
+        node.data(),
+    });
+
+    XLOGF(
+        DBG9,
+        "Processing path=\"{}\" ({}) node={} ({:p})",
+        name.data(),
+        name.size(),
+        folly::hexlify(node),
+        node.data());
+  }
+
+  return raw_requests;
+}
+
+/**
+ * The promise of a queued request. The Rust fetch queue holds a heap allocated
+ * copy of the pointer until the request is resolved, so the promise outlives
+ * both the resolution and the caller.
+ */
+template <typename T>
+using PromisePtr = std::shared_ptr<folly::Promise<T>>;
+
+/**
+ * Returns the future of a queued request, which cancels the request through
+ * `handle` when interrupted.
+ */
+template <typename T>
+folly::SemiFuture<T> withCancellation(
+    PromisePtr<T> promise,
+    RustCancelHandle* handle) {
+  std::shared_ptr<RustCancelHandle> cancel(handle, rust_cancel_handle_free);
+  promise->setInterruptHandler([cancel](const folly::exception_wrapper&) {
+    rust_cancel_handle_cancel(cancel.get());
+  });
+  return promise->getSemiFuture();
+}
 } // namespace
 
 HgNativeBackingStore::HgNativeBackingStore(
     folly::StringPiece repository,
-    bool useEdenApi) {
+    bool useEdenApi,
+    size_t fetchWorkers) {
   RustCFallible<RustBackingStore> store(
       rust_backingstore_new(repository.data(), repository.size(), useEdenApi),
       rust_backingstore_free);
@@ -72,6 +148,15 @@ HgNativeBackingStore::HgNativeBackingStore(
   }
 
   store_ = store.unwrap();
+
+  RustCFallible<RustFetchQueue> queue(
+      rust_fetchqueue_new(store_.get(), fetchWorkers), rust_fetchqueue_free);
+
+  if (queue.isError()) {
+    throw std::runtime_error(queue.getError());
+  }
+
+  queue_ = queue.unwrap();
 }
 
 std::unique_ptr<folly::IOBuf> HgNativeBackingStore::getBlob(
@@ -108,27 +193,7 @@ void HgNativeBackingStore::getBlobBatch(
 
   XLOG(DBG7) << "Import blobs with size:" << count;
 
-  std::vector<RustRequest> raw_requests;
-  raw_requests.reserve(count);
-
-  for (auto& request : requests) {
-    auto& name = request.first;
-    auto& node = request.second;
-
-    raw_requests.emplace_back(RustRequest{
-        name.data(),
-        name.size(),
-        node.data(),
-    });
-
-    XLOGF(
-        DBG9,
-        "Processing path=\"{}\" ({}) node={} ({:p})",
-        name.data(),
-        name.size(),
-        folly::hexlify(node),
-        node.data());
-  }
+  auto raw_requests = toRustRequests(requests);
 
   getBlobBatchCallback(
       store_.get(),
@@ -182,6 +247,112 @@ std::shared_ptr<RustTree> HgNativeBackingStore::getTree(folly::ByteRange node) {
   return manifest.unwrap();
 }
 
+void HgNativeBackingStore::getTreeBatch(
+    const std::vector<std::pair<folly::ByteRange, folly::ByteRange>>& requests,
+    bool local,
+    std::function<void(size_t, std::shared_ptr<RustTree>)>&& resolve) {
+  size_t count = requests.size();
+
+  XLOG(DBG7) << "Import trees with size:" << count;
+
+  auto raw_requests = toRustRequests(requests);
+
+  getTreeBatchCallback(
+      store_.get(),
+      raw_requests.data(),
+      count,
+      local,
+      [resolve, requests, count](size_t index, RustCFallibleBase raw_result) {
+        RustCFallible<RustTree> result(std::move(raw_result), rust_tree_free);
+
+        if (result.isError()) {
+          XLOGF(
+              DBG6,
+              "Failed to import tree path=\"{}\" node={} (batch {}/{}): {}",
+              folly::StringPiece{requests[index].first},
+              folly::hexlify(requests[index].second),
+              index,
+              count,
+              result.getError());
+        } else {
+          XLOGF(
+              DBG6,
+              "Imported tree path=\"{}\" node={} (batch: {}/{})",
+              folly::StringPiece{requests[index].first},
+              folly::hexlify(requests[index].second),
+              index,
+              count);
+          resolve(index, result.unwrap());
+        }
+      });
+}
+
+folly::SemiFuture<std::unique_ptr<folly::IOBuf>>
+HgNativeBackingStore::getBlobAsync(
+    folly::ByteRange name,
+    folly::ByteRange node,
+    RustPriority priority) {
+  XLOG(DBG7) << "Queueing blob name=" << name.data()
+             << " node=" << folly::hexlify(node);
+
+  using Promise = PromisePtr<std::unique_ptr<folly::IOBuf>>;
+  auto promise = std::make_shared<Promise::element_type>();
+  RustRequest request{name.data(), name.size(), node.data()};
+
+  auto handle = rust_fetchqueue_enqueue_blob(
+      queue_.get(),
+      &request,
+      priority,
+      new Promise(promise),
+      [](void* data, RustCFallibleBase raw_result) {
+        std::unique_ptr<Promise> promise(static_cast<Promise*>(data));
+        RustCFallible<RustCBytes> result(
+            std::move(raw_result), rust_cbytes_free);
+
+        if (result.isError()) {
+          (*promise)->setException(std::runtime_error(result.getError()));
+        } else {
+          (*promise)->setValue(bytesToIOBuf(result.unwrap().release()));
+        }
+      });
+
+  return withCancellation(std::move(promise), handle);
+}
+
+folly::SemiFuture<std::shared_ptr<RustTree>> HgNativeBackingStore::getTreeAsync(
+    folly::ByteRange path,
+    folly::ByteRange node,
+    RustPriority priority) {
+  XLOG(DBG7) << "Queueing tree path=" << path.data()
+             << " node=" << folly::hexlify(node);
+
+  using Promise = PromisePtr<std::shared_ptr<RustTree>>;
+  auto promise = std::make_shared<Promise::element_type>();
+  RustRequest request{path.data(), path.size(), node.data()};
+
+  auto handle = rust_fetchqueue_enqueue_tree(
+      queue_.get(),
+      &request,
+      priority,
+      new Promise(promise),
+      [](void* data, RustCFallibleBase raw_result) {
+        std::unique_ptr<Promise> promise(static_cast<Promise*>(data));
+        RustCFallible<RustTree> result(std::move(raw_result), rust_tree_free);
+
+        if (result.isError()) {
+          (*promise)->setException(std::runtime_error(result.getError()));
+        } else {
+          (*promise)->setValue(result.unwrap());
+        }
+      });
+
+  return withCancellation(std::move(promise), handle);
+}
+
+RustBackingStoreStats HgNativeBackingStore::getStats() {
+  return rust_backingstore_get_stats(store_.get());
+}
+
 void HgNativeBackingStore::refresh() {
   XLOG(DBG7) << "Refreshing backing store";
 
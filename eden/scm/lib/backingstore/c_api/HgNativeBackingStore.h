@@ -7,6 +7,7 @@
 #pragma once
 
 #include <folly/Range.h>
+#include <folly/futures/Future.h>
 #include <memory>
 
 #include "eden/scm/lib/backingstore/c_api/RustBackingStore.h"
@@ -20,7 +21,14 @@ namespace eden {
 
 class HgNativeBackingStore {
  public:
-  HgNativeBackingStore(folly::StringPiece repository, bool useEdenApi);
+  /**
+   * `fetchWorkers` is the number of threads serving `getBlobAsync` and
+   * `getTreeAsync` requests.
+   */
+  HgNativeBackingStore(
+      folly::StringPiece repository,
+      bool useEdenApi,
+      size_t fetchWorkers = 4);
 
   std::unique_ptr<folly::IOBuf>
   getBlob(folly::ByteRange name, folly::ByteRange node, bool local);
@@ -44,11 +52,47 @@ class HgNativeBackingStore {
 
   std::shared_ptr<RustTree> getTree(folly::ByteRange node);
 
+  /**
+   * Imports a list of trees, like `getBlobBatch`. Each request is the path
+   * and the node of a tree. Trees missing locally are fetched together in one
+   * request to the server.
+   */
+  void getTreeBatch(
+      const std::vector<std::pair<folly::ByteRange, folly::ByteRange>>&
+          requests,
+      bool local,
+      std::function<void(size_t, std::shared_ptr<RustTree>)>&& resolve);
+
+  /**
+   * Queues the import of a file. Queued requests are fetched in batches by
+   * background threads, and foreground requests are served before prefetch
+   * requests.
+   *
+   * Interrupting the returned future cancels the request if it is still
+   * queued, and the future then completes with an error.
+   */
+  folly::SemiFuture<std::unique_ptr<folly::IOBuf>> getBlobAsync(
+      folly::ByteRange name,
+      folly::ByteRange node,
+      RustPriority priority);
+
+  /**
+   * Queues the import of a tree. Works like `getBlobAsync`.
+   */
+  folly::SemiFuture<std::shared_ptr<RustTree>> getTreeAsync(
+      folly::ByteRange path,
+      folly::ByteRange node,
+      RustPriority priority);
+
+  RustBackingStoreStats getStats();
+
   void refresh();
 
  private:
   std::unique_ptr<RustBackingStore, std::function<void(RustBackingStore*)>>
       store_;
+  // Declared after `store_` so it is destroyed first.
+  std::unique_ptr<RustFetchQueue, std::function<void(RustFetchQueue*)>> queue_;
 };
 } // namespace eden
 } // namespace facebook
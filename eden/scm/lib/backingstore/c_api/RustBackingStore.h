@@ -76,6 +76,13 @@ public:
 #include <cstdlib>
 #include <new>
 
+enum class RustPriority : uint8_t {
+  /// Something is waiting for the result, like a file system operation.
+  Foreground,
+  /// Nothing is waiting for the result yet. Only served when there is no foreground request.
+  Prefetch,
+};
+
 enum class RustTreeEntryType : uint8_t {
   Tree,
   RegularFile,
@@ -85,9 +92,29 @@ enum class RustTreeEntryType : uint8_t {
 
 struct RustBackingStore;
 
+/// Cancels a queued request. A request is resolved with `Cancelled` if it is cancelled before a
+/// worker picks it up. Cancelling a request that is already being fetched has no effect.
+struct RustCancelHandle;
+
+struct RustFetchQueue;
+
 template<typename T>
 struct RustVec;
 
+struct RustBackingStoreStats {
+  uint64_t blob_local_hits;
+  uint64_t blob_remote_fetches;
+  uint64_t tree_local_hits;
+  uint64_t tree_remote_fetches;
+  /// Round trips to the server. A batch fetch counts once.
+  uint64_t remote_batches;
+  /// Total time spent in round trips to the server, in microseconds.
+  uint64_t remote_fetch_micros;
+  uint64_t errors;
+  /// Queued requests that were cancelled before being fetched.
+  uint64_t cancelled;
+};
+
 struct RustCBytes {
   uint8_t *ptr;
   size_t len;
@@ -141,20 +168,55 @@ void rust_backingstore_get_blob_batch(RustBackingStore *store,
                                       void *data,
                                       void (*resolve)(void*, uintptr_t, RustCFallibleBase));
 
+RustBackingStoreStats rust_backingstore_get_stats(RustBackingStore *store);
+
 RustCFallibleBase rust_backingstore_get_tree(RustBackingStore *store,
                                                        const uint8_t *node,
                                                        uintptr_t node_len);
 
+void rust_backingstore_get_tree_batch(RustBackingStore *store,
+                                      const RustRequest *requests,
+                                      uintptr_t size,
+                                      bool local,
+                                      void *data,
+                                      void (*resolve)(void*, uintptr_t, RustCFallibleBase));
+
 RustCFallibleBase rust_backingstore_new(const char *repository,
                                                           size_t repository_len,
                                                           bool use_edenapi);
 
 void rust_backingstore_refresh(RustBackingStore *store);
 
+void rust_cancel_handle_cancel(RustCancelHandle *handle);
+
+/// Freeing the handle does not cancel the request.
+void rust_cancel_handle_free(RustCancelHandle *handle);
+
 void rust_cbytes_free(RustCBytes *vec);
 
 void rust_cfallible_free_error(char *ptr);
 
+/// Queues a blob request. `resolve` is called exactly once, with the blob content or an error.
+/// The returned handle must be freed with `rust_cancel_handle_free`.
+RustCancelHandle *rust_fetchqueue_enqueue_blob(RustFetchQueue *queue,
+                                               const RustRequest *request,
+                                               RustPriority priority,
+                                               void *data,
+                                               void (*resolve)(void*, RustCFallibleBase));
+
+/// Queues a tree request. `resolve` is called exactly once, with the tree or an error. The
+/// returned handle must be freed with `rust_cancel_handle_free`.
+RustCancelHandle *rust_fetchqueue_enqueue_tree(RustFetchQueue *queue,
+                                               const RustRequest *request,
+                                               RustPriority priority,
+                                               void *data,
+                                               void (*resolve)(void*, RustCFallibleBase));
+
+/// Cancels the requests left in the queue, and waits for the requests being fetched.
+void rust_fetchqueue_free(RustFetchQueue *queue);
+
+RustCFallibleBase rust_fetchqueue_new(RustBackingStore *store, uintptr_t workers);
+
 RustCBytes rust_test_cbytes();
 
 /// Returns a `CFallible` with error message "failure!". This function is intended to be called
@@ -6,6 +6,7 @@
  */
 
 use crate::remotestore::FakeRemoteStore;
+use crate::stats::{Stats, StatsSnapshot};
 use crate::treecontentstore::TreeContentStore;
 use crate::utils::key_from_path_node_slice;
 use anyhow::Result;
@@ -21,11 +22,15 @@ use revisionstore::{
 };
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 use types::{Key, Node, RepoPath};
 
+/// Cloning a `BackingStore` is cheap, and the clones share the underlying stores and counters.
+#[derive(Clone)]
 pub struct BackingStore {
-    blobstore: ContentStore,
+    blobstore: Arc<ContentStore>,
     treestore: Arc<TreeContentStore>,
+    stats: Arc<Stats>,
 }
 
 impl BackingStore {
@@ -69,8 +74,9 @@ impl BackingStore {
         };
 
         Ok(Self {
-            blobstore,
+            blobstore: Arc::new(blobstore),
             treestore: Arc::new(TreeContentStore::new(treestore)),
+            stats: Arc::new(Stats::default()),
         })
     }
 
@@ -95,11 +101,24 @@ impl BackingStore {
         let key = key_from_path_node_slice(path, node)?;
 
         // check if the blob present on disk
-        if local_only && !self.blobstore.contains(&StoreKey::from(&key))? {
+        let contains = match self.blobstore.contains(&StoreKey::from(&key)) {
+            Ok(contains) => contains,
+            Err(e) if local_only => return Err(e),
+            Err(_) => false,
+        };
+        if local_only && !contains {
             return Ok(None);
         }
 
-        self.get_blob_impl(key)
+        if contains {
+            self.stats.blob_local_hits(1);
+            self.count_error(self.get_blob_impl(key))
+        } else {
+            let start = Instant::now();
+            let result = self.get_blob_impl(key);
+            self.stats.blob_remote_fetch(1, start.elapsed());
+            self.count_error(result)
+        }
     }
 
     /// Fetch file contents in batch. Whenever a blob is fetched, the supplied `resolve` function is
@@ -133,7 +152,8 @@ impl BackingStore {
             let store_key = StoreKey::from(&key);
             // Assuming a blob do not exist if `.contains` call fails
             if self.blobstore.contains(&store_key).unwrap_or(false) {
-                resolve(index, self.get_blob_impl(key))
+                self.stats.blob_local_hits(1);
+                resolve(index, self.count_error(self.get_blob_impl(key)))
             } else if !local_only {
                 missing.push(store_key);
                 missing_requests.push((index, key));
@@ -145,19 +165,103 @@ impl BackingStore {
             return;
         }
 
-        let _ = self.blobstore.prefetch(&missing);
+        if !missing.is_empty() {
+            let start = Instant::now();
+            let _ = self.blobstore.prefetch(&missing);
+            self.stats.blob_remote_fetch(missing.len(), start.elapsed());
+        }
         for (index, key) in missing_requests {
-            resolve(index, self.get_blob_impl(key))
+            resolve(index, self.count_error(self.get_blob_impl(key)))
         }
     }
 
-    pub fn get_tree(&self, node: &[u8]) -> Result<List> {
-        let node = Node::from_slice(node)?;
+    fn get_tree_impl(&self, node: Node) -> Result<List> {
         let manifest = TreeManifest::durable(self.treestore.clone(), node);
 
         manifest.list(RepoPath::empty())
     }
 
+    pub fn get_tree(&self, node: &[u8]) -> Result<List> {
+        let node = Node::from_slice(node)?;
+        let key = Key::new(RepoPath::empty().to_owned(), node);
+
+        if self
+            .treestore
+            .as_content_store()
+            .contains(&StoreKey::from(&key))
+            .unwrap_or(false)
+        {
+            self.stats.tree_local_hits(1);
+            self.count_error(self.get_tree_impl(node))
+        } else {
+            let start = Instant::now();
+            let result = self.get_tree_impl(node);
+            self.stats.tree_remote_fetch(1, start.elapsed());
+            self.count_error(result)
+        }
+    }
+
+    /// Fetch trees in batch. Works like `get_blob_batch`: `resolve` is called with the index of
+    /// the tree in the request array and its entries, or an error message. Trees missing locally
+    /// are fetched together in one request to the server, which goes through `EdenApi::get_trees`
+    /// when EdenAPI is enabled. When `local_only` is enabled, trees missing locally are not
+    /// resolved.
+    pub fn get_tree_batch<F>(&self, keys: Vec<Result<Key>>, local_only: bool, resolve: F)
+    where
+        F: Fn(usize, Result<List>) -> (),
+    {
+        let store = self.treestore.as_content_store();
+        let mut missing = Vec::new();
+        let mut missing_requests = Vec::new();
+
+        for (index, key) in keys.into_iter().enumerate() {
+            let key = match key {
+                Ok(key) => key,
+                Err(e) => {
+                    resolve(index, Err(e));
+                    continue;
+                }
+            };
+            let store_key = StoreKey::from(&key);
+            // Assuming a tree do not exist if `.contains` call fails
+            if store.contains(&store_key).unwrap_or(false) {
+                self.stats.tree_local_hits(1);
+                resolve(index, self.count_error(self.get_tree_impl(key.hgid)))
+            } else if !local_only {
+                missing.push(store_key);
+                missing_requests.push((index, key));
+            }
+        }
+
+        if local_only {
+            return;
+        }
+
+        if !missing.is_empty() {
+            let start = Instant::now();
+            let _ = store.prefetch(&missing);
+            self.stats.tree_remote_fetch(missing.len(), start.elapsed());
+        }
+        for (index, key) in missing_requests {
+            resolve(index, self.count_error(self.get_tree_impl(key.hgid)))
+        }
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    pub(crate) fn record_cancelled(&self) {
+        self.stats.cancelled();
+    }
+
+    fn count_error<T>(&self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.stats.error();
+        }
+        result
+    }
+
     /// forces backing store to rescan pack files
     pub fn refresh(&self) {
         self.blobstore.get_missing(&[]).ok();
@@ -0,0 +1,489 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A queue of blob and tree requests served by a pool of worker threads.
+//!
+//! Requests are resolved asynchronously, through a callback or a `Pending` result that can be
+//! waited for. Workers take requests of the same kind in batches, so a checkout touching many
+//! directories makes a few round trips to the server instead of one per tree. Foreground requests
+//! are always served before prefetch requests, and requests can be cancelled until a worker picks
+//! them up.
+
+use anyhow::{format_err, Result};
+use log::warn;
+use manifest::List;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use types::Key;
+
+use crate::backingstore::BackingStore;
+
+/// Maximum number of requests fetched together by a worker.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// Fetches batches of blobs and trees for the queue. Each worker has its own clone.
+pub trait BatchFetch: Clone + Send + 'static {
+    /// Fetches blobs. `resolve` is called with the index of a key and its result.
+    fn fetch_blobs(&self, keys: Vec<Key>, resolve: &dyn Fn(usize, Result<Option<Vec<u8>>>));
+
+    /// Fetches trees. `resolve` is called with the index of a key and its result.
+    fn fetch_trees(&self, keys: Vec<Key>, resolve: &dyn Fn(usize, Result<List>));
+
+    /// Called for each request cancelled before being fetched.
+    fn record_cancelled(&self) {}
+}
+
+impl BatchFetch for BackingStore {
+    fn fetch_blobs(&self, keys: Vec<Key>, resolve: &dyn Fn(usize, Result<Option<Vec<u8>>>)) {
+        self.get_blob_batch(keys.into_iter().map(Ok).collect(), false, resolve)
+    }
+
+    fn fetch_trees(&self, keys: Vec<Key>, resolve: &dyn Fn(usize, Result<List>)) {
+        self.get_tree_batch(keys.into_iter().map(Ok).collect(), false, resolve)
+    }
+
+    fn record_cancelled(&self) {
+        BackingStore::record_cancelled(self)
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Something is waiting for the result, like a file system operation.
+    Foreground,
+    /// Nothing is waiting for the result yet. Only served when there is no foreground request.
+    Prefetch,
+}
+
+/// The error a request is resolved with when it gets cancelled.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Cancels a queued request. A request is resolved with `Cancelled` if it is cancelled before a
+/// worker picks it up. Cancelling a request that is already being fetched has no effect.
+#[derive(Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Result of a queued request.
+pub struct Pending<T> {
+    receiver: mpsc::Receiver<Result<T>>,
+    handle: CancelHandle,
+}
+
+impl<T> Pending<T> {
+    /// Blocks until the request is resolved.
+    pub fn wait(self) -> Result<T> {
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(format_err!("fetch queue is shut down")))
+    }
+
+    /// Returns the result if the request is resolved, without blocking.
+    pub fn try_wait(&self) -> Option<Result<T>> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn cancel(&self) {
+        self.handle.cancel()
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.handle.clone()
+    }
+}
+
+pub type BlobCallback = Box<dyn FnOnce(Result<Option<Vec<u8>>>) + Send>;
+pub type TreeCallback = Box<dyn FnOnce(Result<List>) + Send>;
+
+enum Resolve {
+    Blob(BlobCallback),
+    Tree(TreeCallback),
+}
+
+impl Resolve {
+    fn is_blob(&self) -> bool {
+        match self {
+            Resolve::Blob(_) => true,
+            Resolve::Tree(_) => false,
+        }
+    }
+
+    fn error(self, error: anyhow::Error) {
+        match self {
+            Resolve::Blob(resolve) => resolve(Err(error)),
+            Resolve::Tree(resolve) => resolve(Err(error)),
+        }
+    }
+}
+
+struct QueuedRequest {
+    key: Key,
+    handle: CancelHandle,
+    resolve: Resolve,
+}
+
+#[derive(Default)]
+struct State {
+    foreground: VecDeque<QueuedRequest>,
+    prefetch: VecDeque<QueuedRequest>,
+    shutdown: bool,
+}
+
+impl State {
+    /// Takes the oldest request of the highest priority, and more requests of the same priority
+    /// and kind, up to `MAX_BATCH_SIZE`.
+    fn take_batch(&mut self) -> Vec<QueuedRequest> {
+        let queue = if self.foreground.is_empty() {
+            &mut self.prefetch
+        } else {
+            &mut self.foreground
+        };
+        let is_blob = match queue.front() {
+            Some(request) => request.resolve.is_blob(),
+            None => return Vec::new(),
+        };
+
+        let mut batch = Vec::new();
+        let mut rest = VecDeque::with_capacity(queue.len());
+        for request in queue.drain(..) {
+            if batch.len() < MAX_BATCH_SIZE && request.resolve.is_blob() == is_blob {
+                batch.push(request);
+            } else {
+                rest.push_back(request);
+            }
+        }
+        *queue = rest;
+        batch
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+pub struct FetchQueue {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl FetchQueue {
+    /// Starts `workers` threads serving requests from `store`.
+    pub fn new<S: BatchFetch>(store: S, workers: usize) -> Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let workers = (0..workers.max(1))
+            .map(|index| {
+                let shared = shared.clone();
+                let store = store.clone();
+                thread::Builder::new()
+                    .name(format!("backingstore-fetch-{}", index))
+                    .spawn(move || work(&shared, store))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(FetchQueue { shared, workers })
+    }
+
+    fn enqueue(&self, key: Key, priority: Priority, resolve: Resolve) -> CancelHandle {
+        let handle = CancelHandle::default();
+        let request = QueuedRequest {
+            key,
+            handle: handle.clone(),
+            resolve,
+        };
+
+        let mut state = self.shared.state.lock().unwrap();
+        match priority {
+            Priority::Foreground => state.foreground.push_back(request),
+            Priority::Prefetch => state.prefetch.push_back(request),
+        }
+        drop(state);
+        self.shared.condvar.notify_one();
+
+        handle
+    }
+
+    /// Queues a blob request. `resolve` is called from a worker thread with the file content.
+    pub fn enqueue_blob<F>(&self, key: Key, priority: Priority, resolve: F) -> CancelHandle
+    where
+        F: FnOnce(Result<Option<Vec<u8>>>) + Send + 'static,
+    {
+        self.enqueue(key, priority, Resolve::Blob(Box::new(resolve)))
+    }
+
+    /// Queues a tree request. `resolve` is called from a worker thread with the tree entries.
+    pub fn enqueue_tree<F>(&self, key: Key, priority: Priority, resolve: F) -> CancelHandle
+    where
+        F: FnOnce(Result<List>) + Send + 'static,
+    {
+        self.enqueue(key, priority, Resolve::Tree(Box::new(resolve)))
+    }
+
+    pub fn fetch_blob(&self, key: Key, priority: Priority) -> Pending<Option<Vec<u8>>> {
+        let (sender, receiver) = mpsc::channel();
+        let handle = self.enqueue_blob(key, priority, move |result| {
+            let _ = sender.send(result);
+        });
+        Pending { receiver, handle }
+    }
+
+    pub fn fetch_tree(&self, key: Key, priority: Priority) -> Pending<List> {
+        let (sender, receiver) = mpsc::channel();
+        let handle = self.enqueue_tree(key, priority, move |result| {
+            let _ = sender.send(result);
+        });
+        Pending { receiver, handle }
+    }
+}
+
+impl Drop for FetchQueue {
+    /// Waits for the batches being fetched, and cancels the requests left in the queue.
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.condvar.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                warn!("backingstore fetch worker panicked");
+            }
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        let state = &mut *state;
+        for request in state.foreground.drain(..).chain(state.prefetch.drain(..)) {
+            request.resolve.error(Cancelled.into());
+        }
+    }
+}
+
+fn work(shared: &Shared, store: impl BatchFetch) {
+    loop {
+        let batch = {
+            let mut state = shared.state.lock().unwrap();
+            while !state.shutdown && state.foreground.is_empty() && state.prefetch.is_empty() {
+                state = shared.condvar.wait(state).unwrap();
+            }
+            if state.shutdown {
+                return;
+            }
+            state.take_batch()
+        };
+        fetch_batch(&store, batch);
+    }
+}
+
+fn fetch_batch(store: &impl BatchFetch, batch: Vec<QueuedRequest>) {
+    let mut keys = Vec::new();
+    let mut blobs = Vec::new();
+    let mut trees = Vec::new();
+
+    for request in batch {
+        if request.handle.is_cancelled() {
+            store.record_cancelled();
+            request.resolve.error(Cancelled.into());
+            continue;
+        }
+        keys.push(request.key);
+        match request.resolve {
+            Resolve::Blob(resolve) => blobs.push(Some(resolve)),
+            Resolve::Tree(resolve) => trees.push(Some(resolve)),
+        }
+    }
+
+    if !blobs.is_empty() {
+        let blobs = RefCell::new(blobs);
+        store.fetch_blobs(keys, &|index, result| {
+            let resolve = blobs.borrow_mut()[index].take();
+            if let Some(resolve) = resolve {
+                resolve(result);
+            }
+        });
+        for resolve in blobs.into_inner().into_iter().flatten() {
+            resolve(Err(format_err!("no blob found")));
+        }
+    } else if !trees.is_empty() {
+        let trees = RefCell::new(trees);
+        store.fetch_trees(keys, &|index, result| {
+            let resolve = trees.borrow_mut()[index].take();
+            if let Some(resolve) = resolve {
+                resolve(result);
+            }
+        });
+        for resolve in trees.into_inner().into_iter().flatten() {
+            resolve(Err(format_err!("no tree found")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::MutexGuard;
+    use std::time::Duration;
+    use types::testutil::key;
+
+    /// Kind and paths of a fetched batch.
+    type Batch = (&'static str, Vec<String>);
+
+    /// Records fetched batches. Fetches wait while `gate` is locked by the test.
+    #[derive(Clone, Default)]
+    struct FakeStore {
+        batches: Arc<Mutex<Vec<Batch>>>,
+        gate: Arc<Mutex<()>>,
+        cancelled: Arc<Mutex<usize>>,
+    }
+
+    impl FakeStore {
+        fn record(&self, kind: &'static str, keys: &[Key]) {
+            let _gate = self.gate.lock().unwrap();
+            let paths = keys.iter().map(|k| k.path.to_string()).collect();
+            self.batches.lock().unwrap().push((kind, paths));
+        }
+
+        fn batches(&self) -> Vec<Batch> {
+            self.batches.lock().unwrap().clone()
+        }
+    }
+
+    impl BatchFetch for FakeStore {
+        fn fetch_blobs(&self, keys: Vec<Key>, resolve: &dyn Fn(usize, Result<Option<Vec<u8>>>)) {
+            self.record("blob", &keys);
+            for (index, key) in keys.iter().enumerate() {
+                resolve(index, Ok(Some(key.path.to_string().into_bytes())));
+            }
+        }
+
+        fn fetch_trees(&self, keys: Vec<Key>, resolve: &dyn Fn(usize, Result<List>)) {
+            self.record("tree", &keys);
+            for index in 0..keys.len() {
+                resolve(index, Ok(List::NotFound));
+            }
+        }
+
+        fn record_cancelled(&self) {
+            *self.cancelled.lock().unwrap() += 1;
+        }
+    }
+
+    /// Starts a queue with one worker, busy fetching a blob until the returned guard is dropped.
+    fn busy_queue(store: &FakeStore) -> (FetchQueue, Pending<Option<Vec<u8>>>, MutexGuard<'_, ()>) {
+        let gate = store.gate.lock().unwrap();
+        let queue = FetchQueue::new(store.clone(), 1).unwrap();
+        let busy = queue.fetch_blob(key("busy", "1"), Priority::Foreground);
+        wait_until(|| queue.shared.state.lock().unwrap().foreground.is_empty());
+        (queue, busy, gate)
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        while !condition() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn batch(kind: &'static str, paths: &[&str]) -> Batch {
+        (kind, paths.iter().map(|p| p.to_string()).collect())
+    }
+
+    #[test]
+    fn test_priority_and_batching() {
+        let store = FakeStore::default();
+        let (queue, busy, gate) = busy_queue(&store);
+
+        let p1 = queue.fetch_blob(key("p1", "2"), Priority::Prefetch);
+        let t1 = queue.fetch_tree(key("t1", "3"), Priority::Foreground);
+        let b1 = queue.fetch_blob(key("b1", "4"), Priority::Foreground);
+        let t2 = queue.fetch_tree(key("t2", "5"), Priority::Foreground);
+        let p2 = queue.fetch_tree(key("p2", "6"), Priority::Prefetch);
+        drop(gate);
+
+        assert_eq!(busy.wait().unwrap(), Some(b"busy".to_vec()));
+        assert_eq!(b1.wait().unwrap(), Some(b"b1".to_vec()));
+        assert_eq!(p1.wait().unwrap(), Some(b"p1".to_vec()));
+        assert_eq!(t1.wait().unwrap(), List::NotFound);
+        assert_eq!(t2.wait().unwrap(), List::NotFound);
+        assert_eq!(p2.wait().unwrap(), List::NotFound);
+        assert_eq!(
+            store.batches(),
+            vec![
+                batch("blob", &["busy"]),
+                batch("tree", &["t1", "t2"]),
+                batch("blob", &["b1"]),
+                batch("blob", &["p1"]),
+                batch("tree", &["p2"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let store = FakeStore::default();
+        let (queue, busy, gate) = busy_queue(&store);
+
+        let a = queue.fetch_blob(key("a", "2"), Priority::Foreground);
+        let b = queue.fetch_blob(key("b", "3"), Priority::Foreground);
+        a.cancel();
+        drop(gate);
+
+        assert!(busy.wait().is_ok());
+        assert!(a.wait().unwrap_err().is::<Cancelled>());
+        assert_eq!(b.wait().unwrap(), Some(b"b".to_vec()));
+        assert_eq!(
+            store.batches(),
+            vec![batch("blob", &["busy"]), batch("blob", &["b"])]
+        );
+        assert_eq!(*store.cancelled.lock().unwrap(), 1);
+
+        // Cancelling a fetched request has no effect.
+        let c = queue.fetch_blob(key("c", "4"), Priority::Foreground);
+        let handle = c.cancel_handle();
+        assert!(c.wait().is_ok());
+        handle.cancel();
+        assert_eq!(*store.cancelled.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_drop() {
+        let store = FakeStore::default();
+        let (queue, busy, gate) = busy_queue(&store);
+
+        let a = queue.fetch_tree(key("a", "2"), Priority::Prefetch);
+        let shared = queue.shared.clone();
+        let dropper = thread::spawn(move || drop(queue));
+        wait_until(|| shared.state.lock().unwrap().shutdown);
+        drop(gate);
+        dropper.join().unwrap();
+
+        // The batch being fetched completes. Queued requests are cancelled.
+        assert!(busy.wait().is_ok());
+        assert!(a.wait().unwrap_err().is::<Cancelled>());
+        assert_eq!(store.batches(), vec![batch("blob", &["busy"])]);
+    }
+}
@@ -14,9 +14,13 @@
 //! regular C++ classes.
 
 mod backingstore;
+mod fetchqueue;
 mod raw;
 mod remotestore;
+mod stats;
 mod treecontentstore;
 mod utils;
 
 pub use crate::backingstore::BackingStore;
+pub use crate::fetchqueue::{BatchFetch, CancelHandle, Cancelled, FetchQueue, Pending, Priority};
+pub use crate::stats::StatsSnapshot;
@@ -15,7 +15,7 @@ use std::{slice, str};
 use types::Key;
 
 use crate::backingstore::BackingStore;
-use crate::raw::{CBytes, CFallible, Request, Tree};
+use crate::raw::{BackingStoreStats, CBytes, CFallible, Request, Tree};
 
 fn stringpiece_to_slice<'a, T, U>(ptr: *const T, length: size_t) -> Result<&'a [U]> {
     ensure!(!ptr.is_null(), "string ptr is null");
@@ -131,6 +131,28 @@ pub extern "C" fn rust_backingstore_get_tree(
     backingstore_get_tree(store, node, node_len).into()
 }
 
+#[no_mangle]
+pub extern "C" fn rust_backingstore_get_tree_batch(
+    store: *mut BackingStore,
+    requests: *const Request,
+    size: usize,
+    local: bool,
+    data: *mut c_void,
+    resolve: unsafe extern "C" fn(*mut c_void, usize, CFallible<Tree>),
+) {
+    assert!(!store.is_null());
+    let store = unsafe { &*store };
+    let requests: &[Request] = unsafe { slice::from_raw_parts(requests, size) };
+    let keys: Vec<Result<Key>> = requests.iter().map(|req| req.try_into_key()).collect();
+
+    store.get_tree_batch(keys, local, |idx, result| {
+        let result = result
+            .and_then(|list| list.try_into())
+            .map(|result| Box::into_raw(Box::new(result)));
+        unsafe { resolve(data, idx, result.into()) };
+    });
+}
+
 #[no_mangle]
 pub extern "C" fn rust_tree_free(tree: *mut Tree) {
     assert!(!tree.is_null());
@@ -145,3 +167,11 @@ pub extern "C" fn rust_backingstore_refresh(store: *mut BackingStore) {
 
     store.refresh();
 }
+
+#[no_mangle]
+pub extern "C" fn rust_backingstore_get_stats(store: *mut BackingStore) -> BackingStoreStats {
+    assert!(!store.is_null());
+    let store = unsafe { &*store };
+
+    store.stats().into()
+}
@@ -0,0 +1,128 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Provides the c-bindings for `crate::fetchqueue`.
+//!
+//! The `resolve` callbacks are called from the worker threads of the queue, so `data` must be
+//! safe to use from any thread.
+
+use anyhow::{ensure, Error, Result};
+use libc::c_void;
+use std::convert::TryInto;
+
+use crate::backingstore::BackingStore;
+use crate::fetchqueue::{CancelHandle, FetchQueue, Priority};
+use crate::raw::{CBytes, CFallible, Request, Tree};
+
+/// Carries the opaque `data` pointer of the caller to the worker threads.
+struct SendPtr(*mut c_void);
+
+unsafe impl Send for SendPtr {}
+
+fn fetchqueue_new(store: *mut BackingStore, workers: usize) -> Result<*mut FetchQueue> {
+    assert!(!store.is_null());
+    ensure!(workers > 0, "fetch queue needs at least one worker");
+    let store = unsafe { &*store };
+    let queue = Box::new(FetchQueue::new(store.clone(), workers)?);
+
+    Ok(Box::into_raw(queue))
+}
+
+#[no_mangle]
+pub extern "C" fn rust_fetchqueue_new(
+    store: *mut BackingStore,
+    workers: usize,
+) -> CFallible<FetchQueue> {
+    fetchqueue_new(store, workers).into()
+}
+
+/// Cancels the requests left in the queue, and waits for the requests being fetched.
+#[no_mangle]
+pub extern "C" fn rust_fetchqueue_free(queue: *mut FetchQueue) {
+    assert!(!queue.is_null());
+    let queue = unsafe { Box::from_raw(queue) };
+    drop(queue);
+}
+
+/// Queues a blob request. `resolve` is called exactly once, with the blob content or an error.
+/// The returned handle must be freed with `rust_cancel_handle_free`.
+#[no_mangle]
+pub extern "C" fn rust_fetchqueue_enqueue_blob(
+    queue: *mut FetchQueue,
+    request: *const Request,
+    priority: Priority,
+    data: *mut c_void,
+    resolve: unsafe extern "C" fn(*mut c_void, CFallible<CBytes>),
+) -> *mut CancelHandle {
+    assert!(!queue.is_null());
+    assert!(!request.is_null());
+    let queue = unsafe { &*queue };
+    let data = SendPtr(data);
+    let resolve = move |result: Result<Option<Vec<u8>>>| {
+        let result = result
+            .and_then(|opt| opt.ok_or_else(|| Error::msg("no blob found")))
+            .map(CBytes::from_vec)
+            .map(|result| Box::into_raw(Box::new(result)));
+        unsafe { resolve(data.0, result.into()) };
+    };
+
+    let handle = match unsafe { &*request }.try_into_key() {
+        Ok(key) => queue.enqueue_blob(key, priority, resolve),
+        Err(e) => {
+            resolve(Err(e));
+            CancelHandle::default()
+        }
+    };
+    Box::into_raw(Box::new(handle))
+}
+
+/// Queues a tree request. `resolve` is called exactly once, with the tree or an error. The
+/// returned handle must be freed with `rust_cancel_handle_free`.
+#[no_mangle]
+pub extern "C" fn rust_fetchqueue_enqueue_tree(
+    queue: *mut FetchQueue,
+    request: *const Request,
+    priority: Priority,
+    data: *mut c_void,
+    resolve: unsafe extern "C" fn(*mut c_void, CFallible<Tree>),
+) -> *mut CancelHandle {
+    assert!(!queue.is_null());
+    assert!(!request.is_null());
+    let queue = unsafe { &*queue };
+    let data = SendPtr(data);
+    let resolve = move |result: Result<manifest::List>| {
+        let result = result
+            .and_then(|list| list.try_into())
+            .map(|result| Box::into_raw(Box::new(result)));
+        unsafe { resolve(data.0, result.into()) };
+    };
+
+    let handle = match unsafe { &*request }.try_into_key() {
+        Ok(key) => queue.enqueue_tree(key, priority, resolve),
+        Err(e) => {
+            resolve(Err(e));
+            CancelHandle::default()
+        }
+    };
+    Box::into_raw(Box::new(handle))
+}
+
+#[no_mangle]
+pub extern "C" fn rust_cancel_handle_cancel(handle: *mut CancelHandle) {
+    assert!(!handle.is_null());
+    let handle = unsafe { &*handle };
+
+    handle.cancel();
+}
+
+/// Freeing the handle does not cancel the request.
+#[no_mangle]
+pub extern "C" fn rust_cancel_handle_free(handle: *mut CancelHandle) {
+    assert!(!handle.is_null());
+    let handle = unsafe { Box::from_raw(handle) };
+    drop(handle);
+}
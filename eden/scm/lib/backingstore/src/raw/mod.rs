@@ -13,12 +13,15 @@
 mod backingstore;
 mod cbytes;
 mod cfallible;
+mod fetchqueue;
 mod init;
 mod request;
+mod stats;
 mod tests;
 mod tree;
 
 pub use cbytes::CBytes;
 pub use cfallible::CFallible;
 pub use request::Request;
+pub use stats::BackingStoreStats;
 pub use tree::Tree;
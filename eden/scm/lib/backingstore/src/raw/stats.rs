@@ -0,0 +1,40 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Representation of `crate::stats::StatsSnapshot` in C++.
+
+use crate::stats::StatsSnapshot;
+
+#[repr(C)]
+pub struct BackingStoreStats {
+    blob_local_hits: u64,
+    blob_remote_fetches: u64,
+    tree_local_hits: u64,
+    tree_remote_fetches: u64,
+    /// Round trips to the server. A batch fetch counts once.
+    remote_batches: u64,
+    /// Total time spent in round trips to the server, in microseconds.
+    remote_fetch_micros: u64,
+    errors: u64,
+    /// Queued requests that were cancelled before being fetched.
+    cancelled: u64,
+}
+
+impl From<StatsSnapshot> for BackingStoreStats {
+    fn from(stats: StatsSnapshot) -> Self {
+        BackingStoreStats {
+            blob_local_hits: stats.blob_local_hits,
+            blob_remote_fetches: stats.blob_remote_fetches,
+            tree_local_hits: stats.tree_local_hits,
+            tree_remote_fetches: stats.tree_remote_fetches,
+            remote_batches: stats.remote_batches,
+            remote_fetch_micros: stats.remote_fetch_micros,
+            errors: stats.errors,
+            cancelled: stats.cancelled,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Counters of the backing store, so EdenFS can tell how many objects are served from the local
+//! stores and how long fetching the rest from the server takes.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Default)]
+pub(crate) struct Stats {
+    blob_local_hits: AtomicU64,
+    blob_remote_fetches: AtomicU64,
+    tree_local_hits: AtomicU64,
+    tree_remote_fetches: AtomicU64,
+    remote_batches: AtomicU64,
+    remote_fetch_micros: AtomicU64,
+    errors: AtomicU64,
+    cancelled: AtomicU64,
+}
+
+/// A point-in-time copy of the counters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsSnapshot {
+    /// Blobs found in the local stores.
+    pub blob_local_hits: u64,
+    /// Blobs that had to be fetched from the server.
+    pub blob_remote_fetches: u64,
+    /// Trees found in the local stores.
+    pub tree_local_hits: u64,
+    /// Trees that had to be fetched from the server.
+    pub tree_remote_fetches: u64,
+    /// Round trips to the server. A batch fetch counts once.
+    pub remote_batches: u64,
+    /// Total time spent in round trips to the server, in microseconds.
+    pub remote_fetch_micros: u64,
+    /// Requests that failed.
+    pub errors: u64,
+    /// Queued requests that were cancelled before being fetched.
+    pub cancelled: u64,
+}
+
+impl Stats {
+    pub fn blob_local_hits(&self, count: usize) {
+        self.blob_local_hits
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn tree_local_hits(&self, count: usize) {
+        self.tree_local_hits
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn blob_remote_fetch(&self, count: usize, elapsed: Duration) {
+        self.blob_remote_fetches
+            .fetch_add(count as u64, Ordering::Relaxed);
+        self.remote_batch(elapsed);
+    }
+
+    pub fn tree_remote_fetch(&self, count: usize, elapsed: Duration) {
+        self.tree_remote_fetches
+            .fetch_add(count as u64, Ordering::Relaxed);
+        self.remote_batch(elapsed);
+    }
+
+    fn remote_batch(&self, elapsed: Duration) {
+        self.remote_batches.fetch_add(1, Ordering::Relaxed);
+        self.remote_fetch_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn cancelled(&self) {
+        self.cancelled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            blob_local_hits: self.blob_local_hits.load(Ordering::Relaxed),
+            blob_remote_fetches: self.blob_remote_fetches.load(Ordering::Relaxed),
+            tree_local_hits: self.tree_local_hits.load(Ordering::Relaxed),
+            tree_remote_fetches: self.tree_remote_fetches.load(Ordering::Relaxed),
+            remote_batches: self.remote_batches.load(Ordering::Relaxed),
+            remote_fetch_micros: self.remote_fetch_micros.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let stats = Stats::default();
+        assert_eq!(stats.snapshot(), StatsSnapshot::default());
+
+        stats.blob_local_hits(3);
+        stats.tree_local_hits(2);
+        stats.blob_remote_fetch(4, Duration::from_micros(100));
+        stats.tree_remote_fetch(1, Duration::from_micros(20));
+        stats.blob_remote_fetch(2, Duration::from_micros(5));
+        stats.error();
+        stats.cancelled();
+        stats.cancelled();
+
+        assert_eq!(
+            stats.snapshot(),
+            StatsSnapshot {
+                blob_local_hits: 3,
+                blob_remote_fetches: 6,
+                tree_local_hits: 2,
+                tree_remote_fetches: 1,
+                remote_batches: 3,
+                remote_fetch_micros: 125,
+                errors: 1,
+                cancelled: 2,
+            }
+        );
+    }
+}
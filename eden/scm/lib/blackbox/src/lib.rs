@@ -17,11 +17,13 @@
 
 mod blackbox;
 mod match_pattern;
+mod query;
 mod singleton;
 
 pub use self::blackbox::{Blackbox, BlackboxOptions, Entry, SessionId, ToValue};
 pub use self::singleton::{init, log, sync, SINGLETON};
 pub use match_pattern::{capture_pattern, match_pattern};
+pub use query::{command_stats, CommandStats, Query, Session};
 pub use serde_json::{self, json, Value};
 
 pub mod event;
@@ -0,0 +1,447 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Structured queries on top of [`Blackbox::session_ids_by_pattern`].
+
+use crate::blackbox::{Blackbox, Entry, SessionId, ToValue};
+use crate::event::Event;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Filters to select sessions and their entries.
+///
+/// Conditions are combined with "and". Unset conditions match everything.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    start_time_range: Option<(u64, u64)>,
+    session_ids: Vec<SessionId>,
+    event_types: Vec<String>,
+    command: Option<String>,
+    exit_code: Option<u8>,
+    min_duration_ms: Option<u64>,
+    pattern: Option<Value>,
+}
+
+/// Summary and matched entries of a session.
+#[derive(Debug)]
+pub struct Session {
+    pub session_id: SessionId,
+
+    /// Command line arguments from [`Event::Start`].
+    pub args: Vec<String>,
+
+    /// Start time in milliseconds from [`Event::Start`].
+    pub start_timestamp_ms: Option<u64>,
+
+    /// Exit code from [`Event::Finish`].
+    pub exit_code: Option<u8>,
+
+    /// Duration from [`Event::Finish`].
+    pub duration_ms: Option<u64>,
+
+    /// Entries matching the event filters.
+    pub entries: Vec<Entry>,
+}
+
+/// Duration statistics of a command.
+#[derive(Debug, PartialEq)]
+pub struct CommandStats {
+    pub command: String,
+
+    /// Number of finished sessions.
+    pub count: usize,
+
+    /// Number of finished sessions with a non-zero exit code.
+    pub failures: usize,
+
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Select sessions started between `start` and `end` milliseconds since
+    /// epoch (inclusive).
+    pub fn start_time_range(mut self, start: u64, end: u64) -> Self {
+        self.start_time_range = Some((start, end));
+        self
+    }
+
+    /// Select the given session. Can be called multiple times.
+    pub fn session_id(mut self, session_id: SessionId) -> Self {
+        self.session_ids.push(session_id);
+        self
+    }
+
+    /// Only show entries of the given event type, like "finish" or
+    /// "network". Can be called multiple times.
+    pub fn event_type(mut self, name: impl ToString) -> Self {
+        self.event_types.push(name.to_string());
+        self
+    }
+
+    /// Select sessions running the given command (ex. "status").
+    pub fn command(mut self, name: impl ToString) -> Self {
+        self.command = Some(name.to_string());
+        self
+    }
+
+    /// Select sessions that exited with the given code.
+    pub fn exit_code(mut self, code: u8) -> Self {
+        self.exit_code = Some(code);
+        self
+    }
+
+    /// Select sessions that took at least the given milliseconds.
+    pub fn min_duration_ms(mut self, duration_ms: u64) -> Self {
+        self.min_duration_ms = Some(duration_ms);
+        self
+    }
+
+    /// Only show entries matching the given pattern.
+    /// See `match_pattern.rs` for the syntax.
+    pub fn pattern(mut self, pattern: Value) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// Pattern used to find candidate sessions. It matches a single entry,
+    /// and is picked so [`Blackbox::session_ids_by_pattern`] can use an index.
+    fn index_pattern(&self) -> Value {
+        if let Some((start, end)) = self.start_time_range {
+            json!({"start": {"timestamp_ms": ["range", start, end]}})
+        } else if let Some(min) = self.min_duration_ms {
+            json!({"finish": {"duration_ms": ["range", min, u64::MAX]}})
+        } else {
+            json!("_")
+        }
+    }
+
+    fn match_session(&self, session: &Session) -> bool {
+        if let Some((start, end)) = self.start_time_range {
+            match session.start_timestamp_ms {
+                Some(t) if t >= start && t <= end => {}
+                _ => return false,
+            }
+        }
+        if let Some(command) = &self.command {
+            if session.command() != Some(command.as_str()) {
+                return false;
+            }
+        }
+        if self.exit_code.is_some() && session.exit_code != self.exit_code {
+            return false;
+        }
+        if let Some(min) = self.min_duration_ms {
+            if session.duration_ms.unwrap_or(0) < min {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn match_entry(&self, entry: &Entry) -> bool {
+        if !self.event_types.is_empty()
+            && !self.event_types.iter().any(|t| t == entry.data.type_name())
+        {
+            return false;
+        }
+        match &self.pattern {
+            Some(pattern) => entry.match_pattern(pattern),
+            None => true,
+        }
+    }
+}
+
+impl Blackbox {
+    /// Find sessions matching `query`, the oldest first.
+    ///
+    /// Sessions are selected by their [`Event::Start`] and [`Event::Finish`]
+    /// events. Their entries are then filtered by event types and pattern.
+    /// Sessions without matched entries are skipped.
+    pub fn query(&self, query: &Query) -> Vec<Session> {
+        let session_ids: Vec<SessionId> = if query.session_ids.is_empty() {
+            self.session_ids_by_pattern(&query.index_pattern())
+                .into_iter()
+                .collect()
+        } else {
+            query.session_ids.clone()
+        };
+
+        let mut result = Vec::new();
+        for session_id in session_ids {
+            let mut session =
+                Session::from_entries(session_id, self.entries_by_session_id(session_id));
+            if !query.match_session(&session) {
+                continue;
+            }
+            session.entries.retain(|e| query.match_entry(e));
+            if !session.entries.is_empty() {
+                result.push(session);
+            }
+        }
+        result.sort_by_key(|s| (s.start_timestamp_ms, s.session_id));
+        result
+    }
+}
+
+impl Session {
+    fn from_entries(session_id: SessionId, entries: Vec<Entry>) -> Self {
+        let mut session = Session {
+            session_id,
+            args: Vec::new(),
+            start_timestamp_ms: None,
+            exit_code: None,
+            duration_ms: None,
+            entries,
+        };
+        for entry in session.entries.iter() {
+            match &entry.data {
+                Event::Start {
+                    args, timestamp_ms, ..
+                } => {
+                    session.args = args.clone();
+                    session.start_timestamp_ms = Some(*timestamp_ms);
+                }
+                Event::Finish {
+                    exit_code,
+                    duration_ms,
+                    ..
+                } => {
+                    session.exit_code = Some(*exit_code);
+                    session.duration_ms = Some(*duration_ms);
+                }
+                _ => {}
+            }
+        }
+        session
+    }
+
+    /// The command name. That is the first argument that is not a global
+    /// flag or its value.
+    pub fn command(&self) -> Option<&str> {
+        // Global flags that take a value.
+        const FLAGS_WITH_VALUE: &[&str] = &[
+            "-R",
+            "--repository",
+            "--repo",
+            "--cwd",
+            "--config",
+            "--configfile",
+            "--encoding",
+            "--encodingmode",
+            "--color",
+            "--pager",
+        ];
+        let mut args = self.args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            if FLAGS_WITH_VALUE.contains(&arg.as_str()) {
+                args.next();
+            } else if !arg.starts_with('-') {
+                return Some(arg);
+            }
+        }
+        None
+    }
+}
+
+/// Calculate duration statistics per command for finished sessions.
+/// The result is sorted by command name.
+pub fn command_stats(sessions: &[Session]) -> Vec<CommandStats> {
+    let mut durations: BTreeMap<&str, (Vec<u64>, usize)> = BTreeMap::new();
+    for session in sessions {
+        if let (Some(command), Some(duration_ms)) = (session.command(), session.duration_ms) {
+            let (list, failures) = durations.entry(command).or_default();
+            list.push(duration_ms);
+            if session.exit_code != Some(0) {
+                *failures += 1;
+            }
+        }
+    }
+    durations
+        .into_iter()
+        .map(|(command, (mut list, failures))| {
+            list.sort_unstable();
+            // Nearest-rank percentile.
+            let percentile = |p: usize| list[(list.len() * p - 1) / 100];
+            CommandStats {
+                command: command.to_string(),
+                count: list.len(),
+                failures,
+                p50_ms: percentile(50),
+                p95_ms: percentile(95),
+                max_ms: list[list.len() - 1],
+            }
+        })
+        .collect()
+}
+
+impl Event {
+    /// The human-friendly name of the event type, like "finish".
+    pub fn type_name(&self) -> &'static str {
+        use Event::*;
+        match self {
+            Alias { .. } => "alias",
+            Blocked { .. } => "blocked",
+            CommitCloudSync { .. } => "commit_cloud_sync",
+            Config { .. } => "config",
+            ClientTelemetry { .. } => "clienttelemetry",
+            Debug { .. } => "debug",
+            EdenApi { .. } => "edenapi",
+            Exception { .. } => "exception",
+            Finish { .. } => "finish",
+            FsmonitorQuery { .. } => "fsmonitor",
+            LegacyLog { .. } => "legacy_log",
+            Network { .. } => "network",
+            PerfTrace { .. } => "perftrace",
+            ProcessTree { .. } => "process_tree",
+            Profile { .. } => "profile",
+            Repo { .. } => "repo",
+            Start { .. } => "start",
+            Tags { .. } => "tags",
+            TracingData { .. } => "tracing_data",
+            Watchman { .. } => "watchman",
+        }
+    }
+}
+
+impl Entry {
+    /// Human-friendly JSON form of the entry, as used by the `json` output of
+    /// the blackbox command.
+    pub fn to_json(&self) -> Value {
+        let data = match &self.data {
+            // Skip converting TracingData to JSON.
+            Event::TracingData { .. } => json!({"tracing_data": {}}),
+            data => data.to_value(),
+        };
+        json!({
+            "timestamp_ms": self.timestamp,
+            "session_id": self.session_id,
+            "data": data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blackbox::BlackboxOptions;
+
+    fn start(args: &[&str], timestamp_ms: u64) -> Event {
+        Event::Start {
+            pid: 0,
+            uid: 0,
+            nice: 0,
+            args: args.iter().map(|s| s.to_string()).collect(),
+            timestamp_ms,
+        }
+    }
+
+    fn finish(exit_code: u8, duration_ms: u64) -> Event {
+        Event::Finish {
+            exit_code,
+            max_rss: 0,
+            duration_ms,
+            timestamp_ms: 0,
+        }
+    }
+
+    fn alias() -> Event {
+        Event::Alias {
+            from: "a".to_string(),
+            to: "b".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let mut blackbox = BlackboxOptions::new().create_in_memory().unwrap();
+        let sessions = [
+            (start(&["hg", "status"], 1000), finish(0, 100)),
+            (start(&["hg", "-R", "x", "log"], 2000), finish(1, 500)),
+            (start(&["hg", "--verbose", "status"], 3000), finish(0, 300)),
+        ];
+        for (start, finish) in sessions.iter() {
+            blackbox.refresh_session_id();
+            blackbox.log(start);
+            blackbox.log(&alias());
+            blackbox.log(finish);
+        }
+
+        let query = |q: Query| -> Vec<String> {
+            blackbox
+                .query(&q)
+                .into_iter()
+                .map(|s| {
+                    let types: Vec<_> = s.entries.iter().map(|e| e.data.type_name()).collect();
+                    format!(
+                        "{} {} {}",
+                        s.start_timestamp_ms.unwrap(),
+                        s.command().unwrap(),
+                        types.join(",")
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            query(Query::new()),
+            [
+                "1000 status start,alias,finish",
+                "2000 log start,alias,finish",
+                "3000 status start,alias,finish"
+            ]
+        );
+        assert_eq!(
+            query(
+                Query::new()
+                    .start_time_range(1500, 3000)
+                    .event_type("finish")
+            ),
+            ["2000 log finish", "3000 status finish"]
+        );
+        assert_eq!(
+            query(Query::new().command("status").min_duration_ms(200)),
+            ["3000 status start,alias,finish"]
+        );
+        assert_eq!(
+            query(Query::new().exit_code(1).pattern(json!({"alias": "_"}))),
+            ["2000 log alias"]
+        );
+        assert_eq!(
+            query(Query::new().min_duration_ms(1000)),
+            Vec::<String>::new()
+        );
+
+        let stats = command_stats(&blackbox.query(&Query::new()));
+        assert_eq!(
+            stats,
+            [
+                CommandStats {
+                    command: "log".to_string(),
+                    count: 1,
+                    failures: 1,
+                    p50_ms: 500,
+                    p95_ms: 500,
+                    max_ms: 500,
+                },
+                CommandStats {
+                    command: "status".to_string(),
+                    count: 2,
+                    failures: 0,
+                    p50_ms: 100,
+                    p95_ms: 300,
+                    max_ms: 300,
+                },
+            ]
+        );
+    }
+}
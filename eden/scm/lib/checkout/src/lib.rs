@@ -0,0 +1,21 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Update the working copy from one manifest to another.
+//!
+//! [`CheckoutPlan`] diffs two tree manifests and turns the differences into
+//! [`vfs::Action`]s. File contents are fetched in chunks through a
+//! [`FileStore`], and the changes are applied by a [`vfs::BatchWriter`] so an
+//! interrupted checkout can be rolled back.
+
+mod plan;
+mod status;
+mod store;
+
+pub use crate::plan::{CheckoutPlan, CheckoutStats};
+pub use crate::status::{find_changed, FileStatus};
+pub use crate::store::FileStore;
@@ -0,0 +1,434 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use bytes::Bytes;
+use manifest::{DiffType, FileMetadata, FileType, Manifest};
+use manifest_tree::{Diff, TreeManifest};
+use pathmatcher::Matcher;
+use treestate::filestate::{FileStateV2, StateFlags};
+use treestate::treestate::TreeState;
+use types::{Key, RepoPath, RepoPathBuf};
+use vfs::{Action, BatchWriter, UpdateFlag, VFS};
+
+use crate::status::{find_changed, mtime, FileStatus};
+use crate::store::FileStore;
+
+/// Number of files whose contents are held in memory at a time.
+const WRITE_CHUNK_SIZE: usize = 1000;
+
+/// Changes to the working copy to move it from one manifest to another.
+#[derive(Debug, Default)]
+pub struct CheckoutPlan {
+    /// Files to write, with their metadata in the target manifest.
+    writes: Vec<(RepoPathBuf, FileMetadata)>,
+
+    /// Files to remove.
+    removes: Vec<RepoPathBuf>,
+
+    /// Files that only change between regular and executable.
+    exec_changes: Vec<(RepoPathBuf, bool)>,
+
+    /// Files to stop tracking. They are left in the working copy.
+    forgets: Vec<RepoPathBuf>,
+}
+
+/// Counts of files changed by [`CheckoutPlan::apply`].
+#[derive(Debug, Default, PartialEq)]
+pub struct CheckoutStats {
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl CheckoutPlan {
+    /// Plan the changes from `old` to `new`. Paths not matched by `matcher`
+    /// (ex. outside the sparse profile) are ignored.
+    pub fn new(old: &TreeManifest, new: &TreeManifest, matcher: &dyn Matcher) -> Result<Self> {
+        let mut plan = Self::default();
+        for entry in Diff::new(old, new, matcher) {
+            let entry = entry?;
+            match entry.diff_type {
+                DiffType::LeftOnly(_) => plan.removes.push(entry.path),
+                DiffType::RightOnly(meta) => plan.writes.push((entry.path, meta)),
+                DiffType::Changed(old, new) => {
+                    if old.hgid == new.hgid
+                        && old.file_type != FileType::Symlink
+                        && new.file_type != FileType::Symlink
+                    {
+                        let executable = new.file_type == FileType::Executable;
+                        plan.exec_changes.push((entry.path, executable));
+                    } else {
+                        plan.writes.push((entry.path, new));
+                    }
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+            && self.removes.is_empty()
+            && self.exec_changes.is_empty()
+            && self.forgets.is_empty()
+    }
+
+    /// Paths the plan changes in the working copy.
+    pub fn paths(&self) -> impl Iterator<Item = &RepoPath> {
+        self.writes
+            .iter()
+            .map(|(path, _)| path.as_repo_path())
+            .chain(self.removes.iter().map(|path| path.as_repo_path()))
+            .chain(
+                self.exec_changes
+                    .iter()
+                    .map(|(path, _)| path.as_repo_path()),
+            )
+    }
+
+    /// Find local changes the plan would overwrite. `old` is the manifest
+    /// the working copy is based on.
+    ///
+    /// Files that are already removed or missing do not conflict with their
+    /// removal. Everything else changed locally does.
+    pub fn conflicts(
+        &self,
+        vfs: &VFS,
+        treestate: &mut TreeState,
+        old: &TreeManifest,
+        store: &dyn FileStore,
+    ) -> Result<Vec<RepoPathBuf>> {
+        let removes: HashSet<&RepoPath> = self.removes.iter().map(|p| p.as_repo_path()).collect();
+        let paths = self.paths().map(|p| p.to_owned());
+        let changed = find_changed(vfs, treestate, old, store, paths)?;
+        let conflicts = changed
+            .into_iter()
+            .filter(|(path, status)| {
+                let gone = *status == FileStatus::Removed || *status == FileStatus::Missing;
+                !(gone && removes.contains(path.as_repo_path()))
+            })
+            .map(|(path, _)| path)
+            .collect();
+        Ok(conflicts)
+    }
+
+    /// Extend the plan to also revert local changes of tracked files matched
+    /// by `matcher`, as `update --clean` does.
+    ///
+    /// Changed files are restored to their content in `new`. Added files are
+    /// forgotten but kept in the working copy.
+    pub fn discard_changes(
+        &mut self,
+        vfs: &VFS,
+        treestate: &mut TreeState,
+        old: &TreeManifest,
+        new: &TreeManifest,
+        matcher: &dyn Matcher,
+        store: &dyn FileStore,
+    ) -> Result<()> {
+        let planned: HashSet<RepoPathBuf> = self.paths().map(|p| p.to_owned()).collect();
+        let tracked = tracked_files(treestate)?
+            .into_iter()
+            .filter(|path| matcher.matches_file(path) && !planned.contains(path));
+        for (path, _status) in find_changed(vfs, treestate, old, store, tracked)? {
+            match new.get_file(&path)? {
+                Some(meta) => self.writes.push((path, meta)),
+                None => self.forgets.push(path),
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch file contents, apply the plan through `writer`, and record the
+    /// new file states in `treestate`. Contents are read in chunks, so only
+    /// part of them is in memory at a time.
+    ///
+    /// `treestate` is not flushed. The caller should flush it together with
+    /// the new parents.
+    pub fn apply(
+        self,
+        writer: &BatchWriter,
+        vfs: &VFS,
+        store: &dyn FileStore,
+        treestate: &mut TreeState,
+    ) -> Result<CheckoutStats> {
+        let keys: Vec<Key> = self
+            .writes
+            .iter()
+            .map(|(path, meta)| Key::new(path.clone(), meta.hgid))
+            .collect();
+        store.prefetch(&keys)?;
+
+        let mut actions = Vec::with_capacity(keys.len() + self.removes.len());
+        for path in self.removes.iter() {
+            actions.push(Action::Remove { path: path.clone() });
+        }
+        // Writes follow the removals. Their data is loaded by `writer`.
+        let first_write = actions.len();
+        for (path, meta) in self.writes.iter() {
+            let flag = match meta.file_type {
+                FileType::Regular => None,
+                FileType::Executable => Some(UpdateFlag::Executable),
+                FileType::Symlink => Some(UpdateFlag::Symlink),
+            };
+            actions.push(Action::Write {
+                path: path.clone(),
+                data: Bytes::new(),
+                flag,
+            });
+        }
+        for (path, executable) in self.exec_changes.iter() {
+            actions.push(Action::SetExecutable {
+                path: path.clone(),
+                executable: *executable,
+            });
+        }
+        writer.apply_chunked(actions, WRITE_CHUNK_SIZE, &mut |indexes| {
+            let keys: Vec<Key> = indexes
+                .iter()
+                .map(|index| keys[index - first_write].clone())
+                .collect();
+            store.get_files(&keys)
+        })?;
+
+        // Files modified in the current second might be modified again
+        // without changing size or mtime. Mark them as NEED_CHECK.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| (d.as_secs() & 0x7fffffff) as i32)
+            .unwrap_or(0);
+        let changed = self
+            .writes
+            .iter()
+            .map(|(path, _)| path)
+            .chain(self.exec_changes.iter().map(|(path, _)| path));
+        for path in changed {
+            let metadata = vfs.metadata(path)?;
+            let mut state = FileStateV2 {
+                mode: file_mode(&metadata),
+                size: metadata.len() as i32,
+                mtime: mtime(&metadata).unwrap_or(-1),
+                state: StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT,
+                copied: None,
+            };
+            if state.mtime < 0 || state.mtime >= now {
+                state.mtime = -1;
+                state.state |= StateFlags::NEED_CHECK;
+            }
+            treestate.insert(path, &state)?;
+        }
+        for path in self.removes.iter().chain(self.forgets.iter()) {
+            treestate.remove(path)?;
+        }
+
+        Ok(CheckoutStats {
+            updated: self.writes.len() + self.exec_changes.len(),
+            removed: self.removes.len(),
+        })
+    }
+}
+
+/// Paths tracked in the working copy parent or the next commit.
+fn tracked_files(treestate: &mut TreeState) -> Result<Vec<RepoPathBuf>> {
+    let mut result = Vec::new();
+    let mask = StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT;
+    treestate.visit(
+        &mut |components, _state| {
+            result.push(RepoPathBuf::from_utf8(components.concat())?);
+            Ok(treestate::tree::VisitorResult::NotChanged)
+        },
+        &|_, _| true,
+        &|_, state| state.state.intersects(mask),
+    )?;
+    Ok(result)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.mode()
+}
+
+#[cfg(windows)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        0o120644
+    } else {
+        0o100644
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use manifest_tree::testutil::make_tree_manifest;
+    use pathmatcher::AlwaysMatcher;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+    use types::testutil::{hgid, repo_path_buf};
+
+    /// Map file node hex to content.
+    struct TestStore(HashMap<String, &'static str>);
+
+    impl FileStore for TestStore {
+        fn get_files(&self, keys: &[Key]) -> Result<Vec<Bytes>> {
+            Ok(keys
+                .iter()
+                .map(|key| Bytes::from(self.0[&key.hgid.to_hex()]))
+                .collect())
+        }
+    }
+
+    fn store() -> TestStore {
+        let mut files = HashMap::new();
+        for (node, content) in &[("1", "one"), ("2", "two"), ("3", "three")] {
+            files.insert(hgid(node).to_hex(), *content);
+        }
+        TestStore(files)
+    }
+
+    /// Check out `manifest` into an empty working copy.
+    fn setup(root: &Path, manifest: &TreeManifest) -> (VFS, TreeState) {
+        fs::create_dir(root.join(".hg")).unwrap();
+        let vfs = VFS::new(root.to_path_buf()).unwrap();
+        let mut treestate = TreeState::open(root.join(".hg").join("treestate"), None).unwrap();
+        let empty = make_tree_manifest(&[]);
+        let plan = CheckoutPlan::new(&empty, manifest, &AlwaysMatcher::new()).unwrap();
+        let writer = BatchWriter::new(vfs.clone(), root.join(".hg").join("journal"), 2);
+        plan.apply(&writer, &vfs, &store(), &mut treestate).unwrap();
+        (vfs, treestate)
+    }
+
+    fn checkout(
+        root: &Path,
+        vfs: &VFS,
+        treestate: &mut TreeState,
+        old: &TreeManifest,
+        new: &TreeManifest,
+        clean: bool,
+    ) -> Result<CheckoutStats> {
+        let matcher = AlwaysMatcher::new();
+        let mut plan = CheckoutPlan::new(old, new, &matcher)?;
+        if clean {
+            plan.discard_changes(vfs, treestate, old, new, &matcher, &store())?;
+        } else {
+            let conflicts = plan.conflicts(vfs, treestate, old, &store())?;
+            if !conflicts.is_empty() {
+                anyhow::bail!("conflicts: {:?}", conflicts);
+            }
+        }
+        let writer = BatchWriter::new(vfs.clone(), root.join(".hg").join("journal"), 2);
+        plan.apply(&writer, vfs, &store(), treestate)
+    }
+
+    fn read(root: &Path, path: &str) -> String {
+        fs::read_to_string(root.join(path)).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_plan() {
+        let old = make_tree_manifest(&[("a", "1"), ("b", "1"), ("c", "1")]);
+        let mut new = make_tree_manifest(&[("a", "2"), ("c", "1"), ("d", "3")]);
+        new.insert(repo_path_buf("c"), FileMetadata::executable(hgid("1")))
+            .unwrap();
+        let plan = CheckoutPlan::new(&old, &new, &AlwaysMatcher::new()).unwrap();
+        assert_eq!(plan.writes.len(), 2);
+        assert_eq!(plan.removes, vec![repo_path_buf("b")]);
+        assert_eq!(plan.exec_changes, vec![(repo_path_buf("c"), true)]);
+        let mut paths: Vec<&str> = plan.paths().map(|p| p.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_checkout() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let old = make_tree_manifest(&[("a", "1"), ("x/b", "1")]);
+        let new = make_tree_manifest(&[("a", "2"), ("y/c", "3")]);
+        let (vfs, mut treestate) = setup(root, &old);
+        assert_eq!(read(root, "x/b"), "one");
+
+        let stats = checkout(root, &vfs, &mut treestate, &old, &new, false).unwrap();
+        assert_eq!(
+            stats,
+            CheckoutStats {
+                updated: 2,
+                removed: 1
+            }
+        );
+        assert_eq!(read(root, "a"), "two");
+        assert_eq!(read(root, "y/c"), "three");
+        assert!(!root.join("x").exists());
+        assert!(treestate.get("x/b").unwrap().is_none());
+        let state = treestate.get("y/c").unwrap().unwrap();
+        assert!(state
+            .state
+            .contains(StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT));
+        assert_eq!(state.size, 5);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let old = make_tree_manifest(&[("a", "1"), ("b", "1"), ("c", "1")]);
+        let new = make_tree_manifest(&[("a", "2"), ("b", "2"), ("d", "3")]);
+        let (vfs, mut treestate) = setup(root, &old);
+
+        // "a" is modified, "c" is deleted, and "d" is untracked.
+        fs::write(root.join("a"), "modified").unwrap();
+        fs::remove_file(root.join("c")).unwrap();
+        fs::write(root.join("d"), "untracked").unwrap();
+        let plan = CheckoutPlan::new(&old, &new, &AlwaysMatcher::new()).unwrap();
+        let conflicts = plan
+            .conflicts(&vfs, &mut treestate, &old, &store())
+            .unwrap();
+        assert_eq!(conflicts, vec![repo_path_buf("a"), repo_path_buf("d")]);
+        assert!(checkout(root, &vfs, &mut treestate, &old, &new, false).is_err());
+        assert_eq!(read(root, "a"), "modified");
+
+        // Content is compared if the metadata is not conclusive.
+        fs::write(root.join("a"), "one").unwrap();
+        fs::remove_file(root.join("d")).unwrap();
+        checkout(root, &vfs, &mut treestate, &old, &new, false).unwrap();
+        assert_eq!(read(root, "b"), "two");
+    }
+
+    #[test]
+    fn test_clean() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let old = make_tree_manifest(&[("a", "1"), ("b", "1")]);
+        let new = make_tree_manifest(&[("a", "2"), ("b", "1")]);
+        let (vfs, mut treestate) = setup(root, &old);
+
+        // "a" and "b" are modified, "n" is added.
+        fs::write(root.join("a"), "modified").unwrap();
+        fs::write(root.join("b"), "modified").unwrap();
+        fs::write(root.join("n"), "new").unwrap();
+        let added = FileStateV2 {
+            mode: 0o100644,
+            size: -1,
+            mtime: -1,
+            state: StateFlags::EXIST_NEXT,
+            copied: None,
+        };
+        treestate.insert("n", &added).unwrap();
+
+        checkout(root, &vfs, &mut treestate, &old, &new, true).unwrap();
+        assert_eq!(read(root, "a"), "two");
+        assert_eq!(read(root, "b"), "one");
+        assert_eq!(read(root, "n"), "new");
+        assert!(treestate.get("n").unwrap().is_none());
+    }
+}
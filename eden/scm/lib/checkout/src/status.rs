@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fs::{self, Metadata};
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use manifest::{FileType, Manifest};
+use manifest_tree::TreeManifest;
+use treestate::filestate::StateFlags;
+use treestate::treestate::TreeState;
+use types::{Key, RepoPathBuf};
+use vfs::{is_executable, is_symlink, VFS};
+
+use crate::store::FileStore;
+
+/// How a working copy file differs from the parent manifest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileStatus {
+    Modified,
+    Added,
+    Removed,
+    /// Tracked, but deleted by a non-hg command.
+    Missing,
+    /// Not tracked, but present in the working copy.
+    Unknown,
+}
+
+/// Find files in `paths` that differ from the `parent` manifest.
+///
+/// Files are first compared by size, flags and mtime recorded in
+/// `treestate`. Files that cannot be decided that way are compared by
+/// content, fetched in one batch from `store`.
+pub fn find_changed(
+    vfs: &VFS,
+    treestate: &mut TreeState,
+    parent: &TreeManifest,
+    store: &dyn FileStore,
+    paths: impl IntoIterator<Item = RepoPathBuf>,
+) -> Result<Vec<(RepoPathBuf, FileStatus)>> {
+    let mut changed = Vec::new();
+    let mut lookups = Vec::new();
+
+    for path in paths {
+        let metadata = vfs.metadata(&path).ok().filter(|m| !m.file_type().is_dir());
+        let state = treestate.get(&path)?.cloned();
+        let status = match (state, metadata) {
+            (None, None) => None,
+            (None, Some(_)) => Some(FileStatus::Unknown),
+            (Some(state), metadata) => {
+                let in_parent = state.state.intersects(StateFlags::EXIST_P1);
+                let in_next = state.state.intersects(StateFlags::EXIST_NEXT);
+                match (in_parent, in_next, metadata) {
+                    (false, true, _) => Some(FileStatus::Added),
+                    (true, false, _) => Some(FileStatus::Removed),
+                    (false, false, None) => None,
+                    (false, false, Some(_)) => Some(FileStatus::Unknown),
+                    (true, true, None) => Some(FileStatus::Missing),
+                    (true, true, Some(metadata)) => {
+                        let size_different = state.size >= 0 && metadata.len() != state.size as u64;
+                        let exec_different = vfs.supports_executables()
+                            && is_executable(&metadata) != state.is_executable();
+                        let symlink_different =
+                            vfs.supports_symlinks() && is_symlink(&metadata) != state.is_symlink();
+                        if size_different || exec_different || symlink_different {
+                            Some(FileStatus::Modified)
+                        } else {
+                            let needs_check = state.state.intersects(StateFlags::NEED_CHECK)
+                                || state.size < 0
+                                || state.mtime < 0
+                                || Some(state.mtime) != mtime(&metadata);
+                            if needs_check {
+                                lookups.push(path.clone());
+                            }
+                            None
+                        }
+                    }
+                }
+            }
+        };
+        if let Some(status) = status {
+            changed.push((path, status));
+        }
+    }
+
+    // Compare content of files whose metadata is inconclusive.
+    let mut keys = Vec::with_capacity(lookups.len());
+    let mut file_types = Vec::with_capacity(lookups.len());
+    for path in lookups {
+        match parent.get_file(&path)? {
+            Some(meta) => {
+                keys.push(Key::new(path, meta.hgid));
+                file_types.push(meta.file_type);
+            }
+            None => changed.push((path, FileStatus::Modified)),
+        }
+    }
+    let contents = store.get_files(&keys)?;
+    for ((key, file_type), content) in keys.into_iter().zip(file_types).zip(contents) {
+        let filepath = vfs.join(&key.path);
+        let local = if file_type == FileType::Symlink && vfs.supports_symlinks() {
+            fs::read_link(&filepath)
+                .map(|target| target.to_string_lossy().into_owned().into_bytes())
+        } else {
+            fs::read(&filepath)
+        };
+        if local.ok().as_deref() != Some(content.as_ref()) {
+            changed.push((key.path, FileStatus::Modified));
+        }
+    }
+
+    changed.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(changed)
+}
+
+/// Modification time as stored in the treestate.
+pub(crate) fn mtime(metadata: &Metadata) -> Option<i32> {
+    let modified = metadata.modified().ok()?;
+    let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((seconds & 0x7fffffff) as i32)
+}
@@ -0,0 +1,43 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Result};
+use bytes::Bytes;
+use revisionstore::{ContentStore, RemoteDataStore, StoreKey};
+use types::Key;
+
+/// Read file contents in batch.
+pub trait FileStore {
+    /// Get the contents of `keys`, in the same order as `keys`. Missing files
+    /// are errors.
+    fn get_files(&self, keys: &[Key]) -> Result<Vec<Bytes>>;
+
+    /// Fetch `keys` that are not local, so reading them later in smaller
+    /// batches does not fail halfway. The default does nothing.
+    fn prefetch(&self, _keys: &[Key]) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl FileStore for ContentStore {
+    fn get_files(&self, keys: &[Key]) -> Result<Vec<Bytes>> {
+        FileStore::prefetch(self, keys)?;
+        keys.iter()
+            .map(|key| {
+                self.get_file_content(key)?
+                    .ok_or_else(|| format_err!("file {} {} is not found", key.path, key.hgid))
+            })
+            .collect()
+    }
+
+    fn prefetch(&self, keys: &[Key]) -> Result<()> {
+        // Fetch everything that is not local in one request. A remote store
+        // backed by EdenAPI gets the whole batch at once.
+        let store_keys: Vec<StoreKey> = keys.iter().map(StoreKey::from).collect();
+        RemoteDataStore::prefetch(self, &store_keys)
+    }
+}
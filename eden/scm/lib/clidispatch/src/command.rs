@@ -5,7 +5,10 @@
  * GNU General Public License version 2.
  */
 
-use crate::{io::IO, repo::Repo};
+use crate::{
+    io::IO,
+    repo::{OptionalRepo, Repo},
+};
 use anyhow::Result;
 use cliparser::parser::{Flag, ParseOutput, StructFlags};
 use std::collections::BTreeMap;
@@ -14,7 +17,7 @@ use std::ops::Deref;
 
 pub enum CommandFunc {
     NoRepo(Box<dyn Fn(ParseOutput, &mut IO) -> Result<u8>>),
-    OptionalRepo(Box<dyn Fn(ParseOutput, &mut IO, Option<Repo>) -> Result<u8>>),
+    OptionalRepo(Box<dyn Fn(ParseOutput, &mut IO, OptionalRepo) -> Result<u8>>),
     Repo(Box<dyn Fn(ParseOutput, &mut IO, Repo) -> Result<u8>>),
 }
 
@@ -121,12 +124,12 @@ where
 impl<S, FN> Register<FN, ((), S)> for CommandTable
 where
     S: TryFrom<ParseOutput, Error = anyhow::Error> + StructFlags,
-    FN: Fn(S, &mut IO, Option<Repo>) -> Result<u8> + 'static,
+    FN: Fn(S, &mut IO, OptionalRepo) -> Result<u8> + 'static,
 {
     fn register(&mut self, f: FN, name: &str, doc: &str) {
         self.insert_aliases(name);
         let func =
-            move |opts: ParseOutput, io: &mut IO, repo: Option<Repo>| f(opts.try_into()?, io, repo);
+            move |opts: ParseOutput, io: &mut IO, repo: OptionalRepo| f(opts.try_into()?, io, repo);
         let func = CommandFunc::OptionalRepo(Box::new(func));
         let def = CommandDefinition::new(name, doc, S::flags, func);
         self.commands.insert(name.to_string(), def);
@@ -15,6 +15,7 @@ use cliparser::alias::{expand_aliases, find_command_name};
 use cliparser::parser::{ParseError, ParseOptions, ParseOutput, StructFlags};
 use configparser::config::ConfigSet;
 use configparser::hg::ConfigSetHgExt;
+use configparser::schema::Schema;
 use std::convert::TryInto;
 use std::sync::atomic::Ordering::SeqCst;
 use std::{env, path::Path};
@@ -77,7 +78,7 @@ fn last_chance_to_abort(opts: &HgGlobalOpts) -> Result<()> {
     Ok(())
 }
 
-fn early_parse(args: &Vec<String>) -> Result<ParseOutput, ParseError> {
+pub(crate) fn early_parse(args: &Vec<String>) -> Result<ParseOutput, ParseError> {
     ParseOptions::new()
         .ignore_prefix(true)
         .early_parse(true)
@@ -141,6 +142,21 @@ fn initialize_indexedlog(config: &ConfigSet) -> Result<()> {
 }
 
 pub fn dispatch(command_table: &CommandTable, args: Vec<String>, io: &mut IO) -> Result<u8> {
+    dispatch_with_loader(command_table, args, io, |repository, cwd| {
+        OptionalRepo::from_repository_path_and_cwd(repository, cwd, configparser::hg::load()?)
+    })
+}
+
+/// Like [`dispatch`], but load the config and the repo using `load_repo`,
+/// which takes the `--repository` flag and the current directory.
+///
+/// This allows the command server to reuse loaded repos.
+pub(crate) fn dispatch_with_loader(
+    command_table: &CommandTable,
+    args: Vec<String>,
+    io: &mut IO,
+    load_repo: impl FnOnce(&str, &Path) -> Result<OptionalRepo>,
+) -> Result<u8> {
     let early_result = early_parse(&args)?;
     let global_opts: HgGlobalOpts = early_result.clone().try_into()?;
 
@@ -149,11 +165,7 @@ pub fn dispatch(command_table: &CommandTable, args: Vec<String>, io: &mut IO) ->
     }
 
     // Load repo and configuration.
-    let mut optional_repo = OptionalRepo::from_repository_path_and_cwd(
-        &global_opts.repository,
-        &env::current_dir()?,
-        configparser::hg::load()?,
-    )?;
+    let mut optional_repo = load_repo(&global_opts.repository, &env::current_dir()?)?;
     override_config(
         optional_repo.config_mut(),
         &global_opts.configfile,
@@ -161,6 +173,10 @@ pub fn dispatch(command_table: &CommandTable, args: Vec<String>, io: &mut IO) ->
     )?;
     let config = optional_repo.config();
 
+    for issue in Schema::builtin().validate(&config) {
+        io.write_err(format!("warning: {}\n", issue))?;
+    }
+
     initialize_indexedlog(&config)?;
 
     // Prepare alias handling.
@@ -232,10 +248,32 @@ pub fn dispatch(command_table: &CommandTable, args: Vec<String>, io: &mut IO) ->
     let global_opts: HgGlobalOpts = parsed.clone().try_into()?;
     last_chance_to_abort(&global_opts)?;
 
+    // Like Python, expose --verbose, --debug and --quiet as config.
+    if global_opts.verbose || global_opts.debug || global_opts.quiet {
+        let flags = [
+            ("verbose", global_opts.verbose),
+            ("debug", global_opts.debug),
+            ("quiet", global_opts.quiet),
+        ];
+        for (name, value) in flags.iter() {
+            optional_repo.config_mut().set(
+                "ui",
+                name,
+                Some(value.to_string()),
+                &format!("--{}", name).into(),
+            );
+        }
+    }
+
     initialize_blackbox(&optional_repo)?;
 
     if global_opts.pager == "always" {
         io.start_pager(optional_repo.config())?;
+    } else if global_opts.pager == "never" {
+        // Commands starting the pager themselves check this, like Python.
+        optional_repo
+            .config_mut()
+            .set("ui", "paginate", Some("false"), &"--pager".into());
     }
 
     let handler = def.func();
@@ -255,10 +293,7 @@ pub fn dispatch(command_table: &CommandTable, args: Vec<String>, io: &mut IO) ->
                 }
             }
         }
-        CommandFunc::OptionalRepo(f) => match optional_repo {
-            OptionalRepo::Some(repo) => f(parsed, io, Some(repo)),
-            OptionalRepo::None(_config) => f(parsed, io, None),
-        },
+        CommandFunc::OptionalRepo(f) => f(parsed, io, optional_repo),
         CommandFunc::NoRepo(f) => f(parsed, io),
     }
 }
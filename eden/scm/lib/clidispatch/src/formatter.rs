@@ -0,0 +1,374 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Structured command output, selected by `-T` / `--template`.
+//!
+//! Commands describe each item they output as a [`Record`] together with
+//! its plain text form. The [`Formatter`] writes one of:
+//!
+//! - plain text (`-T ''` or `-T plain`), the default.
+//! - `json`: a JSON list of objects, like the Python `jsonformatter`.
+//! - `jsonl`: one JSON object per line.
+//! - a template string like `'{path}\n'`. Keywords are fields of records,
+//!   optionally followed by filters: `{path|upper}`. Available filters are
+//!   `json`, `upper`, `lower`, `short` (first 12 characters) and `basename`.
+//!   `\n`, `\t`, `\\` and `\{` are escapes.
+
+use crate::errors;
+use crate::io::IO;
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+/// Fields of an item written by a command.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    fields: Map<String, Value>,
+}
+
+impl Record {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set a field. The name must be one of the keywords documented by the
+    /// command.
+    pub fn field(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.fields.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.get(name)
+    }
+}
+
+enum Output {
+    Plain,
+    Json { count: usize },
+    JsonLines,
+    Template(Template),
+}
+
+/// Writes records in the format selected by `-T`.
+pub struct Formatter {
+    output: Output,
+}
+
+impl Formatter {
+    /// Create a formatter for the `-T` value `template`.
+    ///
+    /// `keywords` are the fields the command documents. Templates using
+    /// other keywords are rejected. Keywords missing from a record render
+    /// as empty strings.
+    pub fn new(template: &str, keywords: &[&str]) -> Result<Self> {
+        let output = match template {
+            "" | "plain" => Output::Plain,
+            "json" => Output::Json { count: 0 },
+            "jsonl" => Output::JsonLines,
+            template if template.contains('{') => {
+                let template = Template::parse(template)?;
+                for name in template.keywords() {
+                    if !keywords.contains(&name) {
+                        return Err(errors::Abort(
+                            format!(
+                                "unknown template keyword '{}' (available: {})",
+                                name,
+                                keywords.join(", ")
+                            )
+                            .into(),
+                        )
+                        .into());
+                    }
+                }
+                Output::Template(template)
+            }
+            template => {
+                return Err(
+                    errors::Abort(format!("unknown template '{}'", template).into()).into(),
+                );
+            }
+        };
+        Ok(Self { output })
+    }
+
+    /// Whether the plain text output is used. Commands can skip building
+    /// records, or add colors, in this case.
+    pub fn is_plain(&self) -> bool {
+        matches!(self.output, Output::Plain)
+    }
+
+    /// Write a record. `plain` is the text written by the plain formatter.
+    pub fn write(&mut self, io: &mut IO, record: &Record, plain: impl AsRef<[u8]>) -> Result<()> {
+        match &mut self.output {
+            Output::Plain => io.write(plain)?,
+            Output::Json { count } => {
+                // Same layout as the Python jsonformatter.
+                let prefix = if *count == 0 { "[\n" } else { ",\n" };
+                let fields: Vec<String> = record
+                    .fields
+                    .iter()
+                    .map(|(name, value)| {
+                        format!("  {}: {}", to_json(&name.as_str().into()), to_json(value))
+                    })
+                    .collect();
+                io.write(format!("{} {{\n{}\n }}", prefix, fields.join(",\n")))?;
+                *count += 1;
+            }
+            Output::JsonLines => {
+                io.write(format!("{}\n", Value::Object(record.fields.clone())))?;
+            }
+            Output::Template(template) => io.write(template.render(record))?,
+        }
+        Ok(())
+    }
+
+    /// Finish the output. Must be called after writing all records.
+    pub fn end(self, io: &mut IO) -> Result<()> {
+        if let Output::Json { count } = self.output {
+            io.write(if count == 0 { "[\n]\n" } else { "\n]\n" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Format a JSON value like the Python `json` template filter.
+fn to_json(value: &Value) -> String {
+    match value {
+        Value::Array(items) => format!(
+            "[{}]",
+            items.iter().map(to_json).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Object(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(name, value)| format!(
+                    "{}: {}",
+                    to_json(&name.as_str().into()),
+                    to_json(value)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        value => value.to_string(),
+    }
+}
+
+/// Format a value as template output.
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(true) => "True".to_string(),
+        Value::Bool(false) => "False".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(to_text).collect::<Vec<_>>().join(" "),
+        value => value.to_string(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Filter {
+    Json,
+    Upper,
+    Lower,
+    Short,
+    Basename,
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Keyword(String, Vec<Filter>),
+}
+
+/// A parsed template string.
+#[derive(Debug)]
+struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    fn parse(template: &str) -> Result<Self> {
+        let error = |message: String| -> anyhow::Error {
+            errors::Abort(format!("invalid template '{}': {}", template, message).into()).into()
+        };
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => literal.push('\n'),
+                    Some('t') => literal.push('\t'),
+                    Some(c @ '\\') | Some(c @ '{') => literal.push(c),
+                    Some(c) => {
+                        literal.push('\\');
+                        literal.push(c);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' => {
+                    let mut expr = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => expr.push(c),
+                            None => return Err(error("unterminated '{'".to_string())),
+                        }
+                    }
+                    let mut names = expr.split('|').map(|s| s.trim());
+                    let keyword = names.next().unwrap_or_default();
+                    if keyword.is_empty() {
+                        return Err(error("empty keyword".to_string()));
+                    }
+                    let filters = names
+                        .map(|name| match name {
+                            "json" => Ok(Filter::Json),
+                            "upper" => Ok(Filter::Upper),
+                            "lower" => Ok(Filter::Lower),
+                            "short" => Ok(Filter::Short),
+                            "basename" => Ok(Filter::Basename),
+                            name => Err(error(format!("unknown filter '{}'", name))),
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Keyword(keyword.to_string(), filters));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    fn keywords(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Keyword(name, _) => Some(name.as_str()),
+            Part::Literal(_) => None,
+        })
+    }
+
+    fn render(&self, record: &Record) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Keyword(name, filters) => {
+                    let mut value = record.get(name).cloned().unwrap_or(Value::Null);
+                    for filter in filters {
+                        value = Value::String(match filter {
+                            Filter::Json => to_json(&value),
+                            Filter::Upper => to_text(&value).to_uppercase(),
+                            Filter::Lower => to_text(&value).to_lowercase(),
+                            Filter::Short => to_text(&value).chars().take(12).collect(),
+                            Filter::Basename => to_text(&value)
+                                .rsplit(&['/', '\\'][..])
+                                .next()
+                                .unwrap_or_default()
+                                .to_string(),
+                        });
+                    }
+                    out.push_str(&to_text(&value));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn format(template: &str, records: &[Record]) -> String {
+        let mut io = IO::new(Cursor::new(Vec::new()), Vec::new(), None::<Vec<u8>>);
+        let mut formatter = Formatter::new(template, &["path", "status", "copy", "args"]).unwrap();
+        for record in records {
+            let plain = format!(
+                "{} {}\n",
+                to_text(&record.fields["status"]),
+                to_text(&record.fields["path"])
+            );
+            formatter.write(&mut io, record, plain).unwrap();
+        }
+        formatter.end(&mut io).unwrap();
+        let output = io.output.as_any().downcast_ref::<Vec<u8>>().unwrap();
+        String::from_utf8(output.clone()).unwrap()
+    }
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record::new().field("path", "a/x.txt").field("status", "M"),
+            Record::new()
+                .field("path", "b")
+                .field("status", "A")
+                .field("copy", "a/y")
+                .field("args", vec!["1", "2"]),
+        ]
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(format("", &records()), "M a/x.txt\nA b\n");
+        assert_eq!(
+            format("json", &records()),
+            r#"[
+ {
+  "path": "a/x.txt",
+  "status": "M"
+ },
+ {
+  "args": ["1", "2"],
+  "copy": "a/y",
+  "path": "b",
+  "status": "A"
+ }
+]
+"#
+        );
+        assert_eq!(format("json", &[]), "[\n]\n");
+        assert_eq!(
+            format("jsonl", &records()),
+            "{\"path\":\"a/x.txt\",\"status\":\"M\"}\n{\"args\":[\"1\",\"2\"],\"copy\":\"a/y\",\"path\":\"b\",\"status\":\"A\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_template() {
+        assert_eq!(
+            format(
+                r"{status|lower}\t{path|basename} {copy}\{{args|json}\n",
+                &records()
+            ),
+            "m\tx.txt {null\na\tb a/y{[\"1\", \"2\"]\n"
+        );
+        assert_eq!(
+            format("{path|upper|json},", &records()),
+            "\"A/X.TXT\",\"B\","
+        );
+
+        let error = |template: &str| match Formatter::new(template, &["path"]) {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string(),
+        };
+        assert_eq!(
+            error("{foo}"),
+            "unknown template keyword 'foo' (available: path)"
+        );
+        assert_eq!(
+            error("{path|foo}"),
+            "invalid template '{path|foo}': unknown filter 'foo'"
+        );
+        assert_eq!(error("{path"), "invalid template '{path': unterminated '{'");
+        assert_eq!(error("compact"), "unknown template 'compact'");
+    }
+}
@@ -10,6 +10,9 @@
 pub mod command;
 pub mod dispatch;
 pub mod errors;
+pub mod formatter;
 pub mod global_flags;
 pub mod io;
 pub mod repo;
+#[cfg(unix)]
+pub mod server;
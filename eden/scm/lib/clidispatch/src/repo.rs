@@ -12,6 +12,7 @@ use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 
+#[derive(Clone)]
 pub struct Repo {
     path: PathBuf,
     config: ConfigSet,
@@ -80,19 +81,27 @@ impl OptionalRepo {
         }
     }
 
-    pub fn config(&mut self) -> &ConfigSet {
+    pub fn config(&self) -> &ConfigSet {
         match self {
             OptionalRepo::Some(ref repo) => &repo.config,
             OptionalRepo::None(ref config) => config,
         }
     }
+
+    /// Return the repo, or `None` if there is no repo.
+    pub fn repo(&self) -> Option<&Repo> {
+        match self {
+            OptionalRepo::Some(ref repo) => Some(repo),
+            OptionalRepo::None(_) => None,
+        }
+    }
 }
 
 impl Repo {
     /// Load the repo from explicit path.
     ///
     /// Load repo configurations.
-    fn from_raw_path<P>(path: P, mut config: ConfigSet) -> Result<Self>
+    pub(crate) fn from_raw_path<P>(path: P, mut config: ConfigSet) -> Result<Self>
     where
         P: Into<PathBuf>,
     {
@@ -150,7 +159,7 @@ impl Repo {
     }
 }
 
-fn find_hg_repo_root(current_path: &Path) -> Option<PathBuf> {
+pub(crate) fn find_hg_repo_root(current_path: &Path) -> Option<PathBuf> {
     assert!(current_path.is_absolute());
     if current_path.join(".hg").is_dir() {
         Some(current_path.to_path_buf())
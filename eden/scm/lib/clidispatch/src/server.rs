@@ -0,0 +1,867 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Command server for native commands.
+//!
+//! Native commands spend much of their time loading config files and
+//! opening the repo. The command server is a long running process that
+//! keeps them loaded, and runs commands for clients connected to a Unix
+//! socket. Cached config and repos are reloaded when their files change.
+//!
+//! The protocol is similar to the Python command server. Messages are
+//! frames: a channel byte, a 4-byte big-endian length, and data.
+//!
+//! - The client sends a single byte with its stdin, stdout and stderr
+//!   attached as `SCM_RIGHTS`.
+//! - The client sends a request on channel `c`: a JSON object with `args`
+//!   (without the program name), `cwd` and `env`.
+//! - The server sends output on channel `o`, errors on channel `e`, and
+//!   ends the reply with the exit code as a 4-byte big-endian integer on
+//!   channel `r`. Or, it only sends an empty `f` frame, which means the
+//!   client should run the command by itself. For example, because the
+//!   command is implemented in Python.
+//!
+//! The server runs one command at a time in its own process. The current
+//! directory, environment variables and stdio are changed to the client's,
+//! so terminal and color detection work as if the client ran the command.
+//! Output is sent after the command completes, since the command can still
+//! fall back to Python. The input of commands is empty.
+
+use crate::command::CommandTable;
+use crate::dispatch::{dispatch_with_loader, early_parse};
+use crate::errors;
+use crate::global_flags::HgGlobalOpts;
+use crate::io::IO;
+use crate::repo::{find_hg_repo_root, OptionalRepo, Repo};
+use anyhow::Result;
+use configparser::config::ConfigSet;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryInto;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Environment variable set to the socket path to make `hg` use the server.
+/// It is not passed to commands run by the server, so `hg` started by them
+/// does not wait for the busy server.
+pub const SERVER_ENV: &str = "HGCOMMANDSERVER";
+
+/// Environment variables affecting `configparser::hg::load`.
+const CONFIG_ENV: &[&str] = &[
+    "HGRCPATH",
+    "HGPLAIN",
+    "HGPLAINEXCEPT",
+    "HGPROF",
+    "VISUAL",
+    "EDITOR",
+    "HOME",
+    "XDG_CONFIG_HOME",
+];
+
+/// A command to run, sent by the client.
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    pub env: Vec<(String, String)>,
+}
+
+impl Request {
+    /// A request to run `args` with the current directory and environment
+    /// of this process. Environment variables that are not UTF-8 are
+    /// skipped.
+    pub fn from_current_process(args: Vec<String>) -> Result<Self> {
+        let env = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        Ok(Request {
+            args,
+            cwd: env::current_dir()?,
+            env,
+        })
+    }
+
+    fn to_json(&self) -> Result<Vec<u8>> {
+        let cwd = self
+            .cwd
+            .to_str()
+            .ok_or_else(|| errors::Abort("current directory is not UTF-8".into()))?;
+        let env: serde_json::Map<String, Value> = self
+            .env
+            .iter()
+            .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+            .collect();
+        let request = json!({"args": self.args, "cwd": cwd, "env": env});
+        Ok(serde_json::to_vec(&request)?)
+    }
+
+    fn from_json(data: &[u8]) -> Result<Self> {
+        let invalid = || errors::Abort("invalid command server request".into());
+        let request: Value = serde_json::from_slice(data)?;
+        let args = request["args"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|arg| arg.as_str().map(ToString::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        let cwd = PathBuf::from(request["cwd"].as_str().ok_or_else(invalid)?);
+        let env = request["env"]
+            .as_object()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        Ok(Request { args, cwd, env })
+    }
+
+    /// Change the environment variables and the current directory of this
+    /// process to the request's.
+    fn apply(&self) -> Result<()> {
+        let names: HashSet<&str> = self.env.iter().map(|(name, _)| name.as_str()).collect();
+        for (name, _) in env::vars_os() {
+            if name.to_str().map_or(true, |name| !names.contains(name)) {
+                env::remove_var(name);
+            }
+        }
+        for (name, value) in &self.env {
+            if name != SERVER_ENV {
+                env::set_var(name, value);
+            }
+        }
+        env::set_current_dir(&self.cwd)?;
+        Ok(())
+    }
+}
+
+/// Run a command using the server listening at `path`.
+///
+/// Return `None` if the client should run the command by itself: the
+/// server is not running, closed the connection without a reply, or
+/// cannot run the command.
+pub fn run_client(path: &Path, request: &Request, io: &mut IO) -> Result<Option<i32>> {
+    let mut stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+    let stdio = [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO];
+    if send_fds(&stream, &stdio).is_err() {
+        return Ok(None);
+    }
+    write_frame(&mut stream, b'c', &request.to_json()?)?;
+    let mut replied = false;
+    loop {
+        let (channel, data) = match read_frame(&mut stream) {
+            Ok(frame) => frame,
+            Err(_) if !replied => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        replied = true;
+        match channel {
+            b'o' => io.write(data)?,
+            b'e' => io.write_err(data)?,
+            b'r' => {
+                let code: [u8; 4] = data
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| errors::Abort("invalid exit code from command server".into()))?;
+                return Ok(Some(i32::from_be_bytes(code)));
+            }
+            b'f' => return Ok(None),
+            _ => {
+                let message = format!("unexpected channel {:?} from command server", channel);
+                return Err(errors::Abort(message.into()).into());
+            }
+        }
+    }
+}
+
+/// Wraps each served command, for example to log it the same way as
+/// commands run by `hg` itself. It is called with the arguments and a
+/// function that runs the command, and returns the result of the command.
+pub type Wrapper<'a> =
+    dyn Fn(&[String], &mut IO, &mut dyn FnMut(&mut IO) -> Result<u8>) -> Result<u8> + 'a;
+
+/// Command server listening on a Unix socket.
+pub struct Server {
+    path: PathBuf,
+    /// Inode of the socket file, to tell if it was removed or replaced.
+    inode: u64,
+    listener: UnixListener,
+    cache: Cache,
+}
+
+impl Server {
+    /// Listen at `path`. The socket file is only accessible by the current
+    /// user. A stale socket file is replaced.
+    pub fn bind(path: &Path) -> Result<Self> {
+        if path.exists() && UnixStream::connect(path).is_ok() {
+            let message = format!("command server is already running at {}", path.display());
+            return Err(errors::Abort(message.into()).into());
+        }
+
+        // Bind in a private directory and move the socket in place, so it
+        // is never accessible by other users.
+        let dir = path.with_file_name(format!(".hgcs{}", std::process::id()));
+        fs::DirBuilder::new().mode(0o700).create(&dir)?;
+        let tmp_path = dir.join("s");
+        let listener = (|| -> io::Result<UnixListener> {
+            let listener = UnixListener::bind(&tmp_path)?;
+            fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&tmp_path, path)?;
+            Ok(listener)
+        })();
+        let _ = fs::remove_file(&tmp_path);
+        let _ = fs::remove_dir(&dir);
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Server {
+            path: path.to_path_buf(),
+            inode: fs::metadata(path)?.ino(),
+            listener,
+            cache: Default::default(),
+        })
+    }
+
+    /// Run commands for clients, one at a time, through `wrap`.
+    ///
+    /// Return if there is no request within `idle_timeout`, or if the
+    /// socket file is removed.
+    pub fn serve(
+        mut self,
+        command_table: &CommandTable,
+        idle_timeout: Duration,
+        wrap: &Wrapper,
+    ) -> Result<()> {
+        // Poll in this thread instead of watching in another thread, since
+        // commands change environment variables, which is not thread safe.
+        let mut last_active = Instant::now();
+        while self.is_bound() && last_active.elapsed() < idle_timeout {
+            if !wait_readable(&self.listener, idle_timeout.min(Duration::from_secs(1)))? {
+                continue;
+            }
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if let Err(err) = self.run(command_table, wrap, stream) {
+                tracing::warn!("command server: {}", err);
+            }
+            last_active = Instant::now();
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn accept_one(&mut self, command_table: &CommandTable, wrap: &Wrapper) -> Result<()> {
+        loop {
+            wait_readable(&self.listener, Duration::from_secs(1))?;
+            match self.listener.accept() {
+                Ok((stream, _)) => return self.run(command_table, wrap, stream),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn run(
+        &mut self,
+        command_table: &CommandTable,
+        wrap: &Wrapper,
+        stream: UnixStream,
+    ) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        handle(&mut self.cache, command_table, wrap, stream)
+    }
+
+    /// Whether the socket file is still the one bound by this server.
+    fn is_bound(&self) -> bool {
+        fs::symlink_metadata(&self.path).map_or(false, |m| {
+            m.file_type().is_socket() && m.ino() == self.inode
+        })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if self.is_bound() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Wait until `listener` has a connection to accept. Return `false` on
+/// timeout.
+fn wait_readable(listener: &UnixListener, timeout: Duration) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: listener.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ret = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+    Ok(ret > 0)
+}
+
+/// Run the command requested by a client.
+fn handle(
+    cache: &mut Cache,
+    command_table: &CommandTable,
+    wrap: &Wrapper,
+    mut stream: UnixStream,
+) -> Result<()> {
+    let stdio = recv_fds(&stream)?;
+    let (channel, data) = read_frame(&mut stream)?;
+    if channel != b'c' {
+        let message = format!("unexpected channel {:?} from client", channel);
+        return Err(errors::Abort(message.into()).into());
+    }
+    let request = Request::from_json(&data)?;
+    request.apply()?;
+    let _redirect = StdioRedirect::new(&stdio)?;
+
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let mut io = IO::new(
+        io::empty(),
+        ChannelWriter::new(b'o', output.clone()),
+        Some(ChannelWriter::new(b'e', output.clone())),
+    );
+    let result = if can_run(&request.args) {
+        let args = request.args;
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            wrap(&args, &mut io, &mut |io| {
+                dispatch_with_loader(command_table, args.clone(), io, |repository, cwd| {
+                    cache.load(repository, cwd)
+                })
+            })
+        }))
+        .unwrap_or_else(|_| {
+            // The cache might be broken. Let the client run the command.
+            *cache = Default::default();
+            Err(errors::FallbackToPython.into())
+        })
+    } else {
+        Err(errors::FallbackToPython.into())
+    };
+
+    let code = match result {
+        Ok(code) => code as i32,
+        Err(err)
+            if err.downcast_ref::<errors::FallbackToPython>().is_some()
+                || err.downcast_ref::<errors::UnknownCommand>().is_some() =>
+        {
+            // Drop the output, since the client runs the command again.
+            write_frame(&mut stream, b'f', &[])?;
+            return Ok(());
+        }
+        Err(err) => {
+            errors::print_error(&err, &mut io);
+            255
+        }
+    };
+    io.flush()?;
+    let output = mem::take(&mut *output.lock().unwrap());
+    stream.write_all(&output)?;
+    write_frame(&mut stream, b'r', &code.to_be_bytes())?;
+    Ok(())
+}
+
+/// Whether the server can run a command with `args`. The pager cannot be
+/// started by the server since it needs the client's terminal.
+fn can_run(args: &Vec<String>) -> bool {
+    let global_opts: Option<HgGlobalOpts> = early_parse(args)
+        .ok()
+        .and_then(|parsed| parsed.try_into().ok());
+    global_opts.map_or(false, |opts| opts.pager != "always")
+}
+
+/// Modification time and size of a file, or `None` if it does not exist.
+type Stamp = Option<(Option<SystemTime>, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
+
+/// Files used to load a cached value, and their stamps at that time.
+#[derive(Default)]
+struct Watched {
+    files: Vec<(PathBuf, Stamp)>,
+}
+
+impl Watched {
+    fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let paths: BTreeSet<PathBuf> = paths.into_iter().collect();
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let stamp = stamp(&path);
+                (path, stamp)
+            })
+            .collect();
+        Watched { files }
+    }
+
+    /// Whether any of the files was changed, created or deleted.
+    fn is_changed(&self) -> bool {
+        self.files.iter().any(|(path, old)| &stamp(path) != old)
+    }
+}
+
+/// Values of `CONFIG_ENV`. Config is cached separately for each key.
+type ConfigKey = Vec<Option<String>>;
+
+/// Loaded config and repos.
+#[derive(Default)]
+struct Cache {
+    configs: HashMap<ConfigKey, (ConfigSet, Watched)>,
+    repos: HashMap<(ConfigKey, PathBuf), (Repo, Watched)>,
+}
+
+impl Cache {
+    /// Like `OptionalRepo::from_repository_path_and_cwd` with
+    /// `configparser::hg::load`, but reuse loaded config and repos.
+    fn load(&mut self, repository: &str, cwd: &Path) -> Result<OptionalRepo> {
+        let key: ConfigKey = CONFIG_ENV.iter().map(|name| env::var(name).ok()).collect();
+        let config = self.config(&key)?;
+        if !repository.is_empty() {
+            // --repository can be a bundle. Do not cache it.
+            return OptionalRepo::from_repository_path_and_cwd(repository, cwd, config);
+        }
+        match find_hg_repo_root(&util::path::absolute(cwd)?) {
+            Some(path) => Ok(OptionalRepo::Some(self.repo(key, path, config)?)),
+            None => Ok(OptionalRepo::None(config)),
+        }
+    }
+
+    /// Load the system and user config.
+    fn config(&mut self, key: &ConfigKey) -> Result<ConfigSet> {
+        if let Some((config, watched)) = self.configs.get(key) {
+            if !watched.is_changed() {
+                return Ok(config.clone());
+            }
+            // Repo config includes the changed config.
+            self.repos.retain(|(repo_key, _), _| repo_key != key);
+        }
+        let config = configparser::hg::load()?;
+        let watched = Watched::new(config_paths(&config).into_iter().chain(user_config_paths()));
+        self.configs.insert(key.clone(), (config.clone(), watched));
+        Ok(config)
+    }
+
+    /// Load the repo at `path`. `config` is the system and user config.
+    fn repo(&mut self, key: ConfigKey, path: PathBuf, config: ConfigSet) -> Result<Repo> {
+        let cache_key = (key, path);
+        if let Some((repo, watched)) = self.repos.get(&cache_key) {
+            if !watched.is_changed() {
+                return Ok(repo.clone());
+            }
+        }
+        let repo = Repo::from_raw_path(cache_key.1.clone(), config)?;
+        let dot_hg_path = repo.dot_hg_path();
+        let repo_paths = vec![
+            dot_hg_path.join("hgrc"),
+            dot_hg_path.join("requires"),
+            dot_hg_path.join("sharedpath"),
+            repo.shared_dot_hg_path().join("hgrc.dynamic"),
+        ];
+        let watched = Watched::new(config_paths(repo.config()).into_iter().chain(repo_paths));
+        self.repos.insert(cache_key, (repo.clone(), watched));
+        Ok(repo)
+    }
+}
+
+/// Files that provided values of `config`.
+fn config_paths(config: &ConfigSet) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for section in config.sections() {
+        for name in config.keys(&section) {
+            for source in config.get_sources(&section, &name) {
+                if let Some((path, _)) = source.location() {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// System and user config files that are loaded if they exist.
+fn user_config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(rcpath) = env::var_os("HGRCPATH") {
+        paths.extend(env::split_paths(&rcpath));
+    } else {
+        paths.push(PathBuf::from("/etc/mercurial/system.rc"));
+        let home = env::var_os("HOME").map(PathBuf::from);
+        if let Some(home) = &home {
+            paths.push(home.join(".hgrc"));
+        }
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Some(home?.join(".config")));
+        if let Some(config_dir) = config_dir {
+            paths.push(config_dir.join("hg/hgrc"));
+        }
+    }
+    paths
+}
+
+fn write_frame(out: &mut impl Write, channel: u8, data: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(data.len() + 5);
+    frame.push(channel);
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+    out.write_all(&frame)
+}
+
+fn read_frame(input: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    input.read_exact(&mut header)?;
+    let mut len = [0u8; 4];
+    len.copy_from_slice(&header[1..]);
+    let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+    input.read_exact(&mut data)?;
+    Ok((header[0], data))
+}
+
+/// Buffers data as frames of a channel. Channels can share a buffer to
+/// keep the order of their frames.
+struct ChannelWriter {
+    channel: u8,
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ChannelWriter {
+    fn new(channel: u8, buffer: Arc<Mutex<Vec<u8>>>) -> Self {
+        ChannelWriter { channel, buffer }
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            write_frame(&mut *self.buffer.lock().unwrap(), self.channel, buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Number of file descriptors sent by the client: stdin, stdout and stderr.
+const STDIO_COUNT: usize = 3;
+
+/// Send `fds` attached to a single byte.
+fn send_fds(stream: &UnixStream, fds: &[RawFd]) -> io::Result<()> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: byte.len(),
+    };
+    let data_len = mem::size_of_val(fds);
+    let ret = unsafe {
+        let space = libc::CMSG_SPACE(data_len as _) as usize;
+        // u64 for the alignment of cmsghdr.
+        let mut control = vec![0u64; space / 8 + 1];
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = space as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(data_len as _) as _;
+        ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg) as *mut RawFd, fds.len());
+        libc::sendmsg(stream.as_raw_fd(), &msg, 0)
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receive the stdio file descriptors sent by `send_fds`.
+fn recv_fds(stream: &UnixStream) -> io::Result<Vec<File>> {
+    let mut byte = [0u8];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+        iov_len: byte.len(),
+    };
+    let mut files = Vec::new();
+    let ret = unsafe {
+        let space = libc::CMSG_SPACE((STDIO_COUNT * mem::size_of::<RawFd>()) as _) as usize;
+        let mut control = vec![0u64; space / 8 + 1];
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = space as _;
+        let ret = libc::recvmsg(stream.as_raw_fd(), &mut msg, 0);
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while ret > 0 && !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let data_len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                for i in 0..data_len / mem::size_of::<RawFd>() {
+                    files.push(File::from_raw_fd(ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        ret
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if files.len() != STDIO_COUNT {
+        let message = format!("expected {} file descriptors from client", STDIO_COUNT);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(files)
+}
+
+/// Replaces stdin, stdout and stderr of this process. Restored on drop.
+struct StdioRedirect {
+    saved: Vec<File>,
+}
+
+impl StdioRedirect {
+    fn new(files: &[File]) -> io::Result<Self> {
+        let mut redirect = StdioRedirect { saved: Vec::new() };
+        for (fd, file) in files.iter().enumerate() {
+            let saved = unsafe { libc::fcntl(fd as RawFd, libc::F_DUPFD_CLOEXEC, 0) };
+            if saved < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            redirect.saved.push(unsafe { File::from_raw_fd(saved) });
+            if unsafe { libc::dup2(file.as_raw_fd(), fd as RawFd) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(redirect)
+    }
+}
+
+impl Drop for StdioRedirect {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for (fd, file) in self.saved.iter().enumerate() {
+            unsafe { libc::dup2(file.as_raw_fd(), fd as RawFd) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Register;
+    use cliparser::define_flags;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    define_flags! {
+        struct EchoOpts {
+            /// exit code
+            code: i64,
+
+            #[args]
+            args: Vec<String>,
+        }
+    }
+
+    fn table() -> CommandTable {
+        let mut table = CommandTable::new();
+        table.register(
+            |opts: EchoOpts, io: &mut IO| {
+                io.write(opts.args.join(" "))?;
+                io.write_err("done")?;
+                Ok(opts.code as u8)
+            },
+            "echo",
+            "",
+        );
+        table.register(
+            |opts: EchoOpts, io: &mut IO| {
+                io.write(opts.args.join(" "))?;
+                Err(errors::FallbackToPython.into())
+            },
+            "fallback",
+            "",
+        );
+        table.register(
+            |_opts: EchoOpts, _io: &mut IO| -> Result<u8> { panic!("panic in command") },
+            "panic",
+            "",
+        );
+        table
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, b'o', b"abc").unwrap();
+        write_frame(&mut buf, b'f', b"").unwrap();
+        assert_eq!(buf, b"o\0\0\0\x03abcf\0\0\0\0");
+        let mut input = Cursor::new(buf);
+        assert_eq!(read_frame(&mut input).unwrap(), (b'o', b"abc".to_vec()));
+        assert_eq!(read_frame(&mut input).unwrap(), (b'f', Vec::new()));
+        assert!(read_frame(&mut input).is_err());
+
+        let request = Request {
+            args: vec!["status".to_string(), "-n".to_string()],
+            cwd: PathBuf::from("/a/b"),
+            env: vec![("HOME".to_string(), "/home/a".to_string())],
+        };
+        let json = request.to_json().unwrap();
+        assert_eq!(Request::from_json(&json).unwrap(), request);
+        assert!(Request::from_json(b"{\"args\": [1]}").is_err());
+    }
+
+    #[test]
+    fn test_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::write(&a, "1").unwrap();
+        let watched = Watched::new(vec![a.clone(), b.clone(), a.clone()]);
+        assert_eq!(watched.files.len(), 2);
+        assert!(!watched.is_changed());
+
+        fs::write(&b, "").unwrap();
+        assert!(watched.is_changed());
+
+        let watched = Watched::new(vec![a.clone(), b.clone()]);
+        fs::write(&a, "22").unwrap();
+        assert!(watched.is_changed());
+    }
+
+    #[test]
+    fn test_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let hgrc = dir.path().join("hgrc");
+        fs::write(&hgrc, "[alias]\nsay = echo --code 3\n").unwrap();
+        let path = dir.path().join("socket");
+        let mut server = Server::bind(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let request = |args: &[&str]| Request {
+            args: args.iter().map(ToString::to_string).collect(),
+            cwd: dir.path().to_path_buf(),
+            env: vec![("HGRCPATH".to_string(), hgrc.display().to_string())],
+        };
+        // Count wrapped commands.
+        let wrapped = AtomicUsize::new(0);
+        let wrap = |args: &[String], io: &mut IO, run: &mut dyn FnMut(&mut IO) -> Result<u8>| {
+            assert!(!args.is_empty());
+            wrapped.fetch_add(1, Ordering::SeqCst);
+            run(io)
+        };
+        let run = |server: &mut Server, request: Request| -> (Option<i32>, String) {
+            let path = path.clone();
+            let client = thread::spawn(move || {
+                let mut io = IO::new(Cursor::new(Vec::new()), Vec::new(), None::<Vec<u8>>);
+                let code = run_client(&path, &request, &mut io).unwrap();
+                let output = io.output.as_any().downcast_ref::<Vec<u8>>().unwrap();
+                (code, String::from_utf8(output.clone()).unwrap())
+            });
+            server.accept_one(&table(), &wrap).unwrap();
+            client.join().unwrap()
+        };
+
+        assert_eq!(
+            run(&mut server, request(&["say", "a", "b"])),
+            (Some(3), "a bdone".to_string())
+        );
+        assert_eq!(server.cache.configs.len(), 1);
+        assert_eq!(wrapped.load(Ordering::SeqCst), 1);
+
+        // Config changes are picked up.
+        fs::write(&hgrc, "[alias]\nsay = echo --code 4\n").unwrap();
+        assert_eq!(
+            run(&mut server, request(&["say", "c"])),
+            (Some(4), "cdone".to_string())
+        );
+
+        // Commands the server cannot run.
+        assert_eq!(run(&mut server, request(&["log"])), (None, String::new()));
+        assert_eq!(
+            run(&mut server, request(&["fallback", "a"])),
+            (None, String::new())
+        );
+        assert_eq!(run(&mut server, request(&["panic"])), (None, String::new()));
+        assert!(server.cache.configs.is_empty());
+        // Not wrapped, since the server does not run it.
+        let count = wrapped.load(Ordering::SeqCst);
+        assert_eq!(
+            run(&mut server, request(&["--pager", "always", "echo"])),
+            (None, String::new())
+        );
+        assert_eq!(wrapped.load(Ordering::SeqCst), count);
+        assert_eq!(
+            run(&mut server, request(&["echo", "--foo"])),
+            (
+                Some(255),
+                "abort: option --foo not recognized\n".to_string()
+            )
+        );
+
+        drop(server);
+        assert!(!path.exists());
+        let mut io = IO::new(Cursor::new(Vec::new()), Vec::new(), None::<Vec<u8>>);
+        assert_eq!(
+            run_client(&path, &request(&["echo"]), &mut io).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_serve_idle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("socket");
+        let server = Server::bind(&path).unwrap();
+        let wrap = |_: &[String], io: &mut IO, run: &mut dyn FnMut(&mut IO) -> Result<u8>| run(io);
+        server
+            .serve(&table(), Duration::from_millis(10), &wrap)
+            .unwrap();
+        assert!(!path.exists());
+
+        // A replaced socket file is not removed.
+        let server = Server::bind(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "").unwrap();
+        server
+            .serve(&table(), Duration::from_secs(60), &wrap)
+            .unwrap();
+        assert!(path.exists());
+    }
+}
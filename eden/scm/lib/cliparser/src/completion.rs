@@ -0,0 +1,697 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Shell completion scripts generated from command definitions.
+//!
+//! [`Completion`] collects commands with their [`Flag`]s, global flags and
+//! aliases, and generates a completion script for bash, zsh or fish. The
+//! scripts complete command names, flags, and values of flags and
+//! positional arguments. Bookmarks are completed by running a command at
+//! completion time (`hg debugnamecomplete` by default). File paths are
+//! completed by the shell.
+
+use crate::alias::expand_aliases;
+use crate::errors::UnknownShell;
+use crate::parser::{Flag, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Shells supported by [`Completion::generate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = UnknownShell;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(UnknownShell(s.to_string())),
+        }
+    }
+}
+
+/// How to complete a flag value or a positional argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Complete {
+    Nothing,
+    Bookmark,
+    File,
+}
+
+/// Long names of flags whose values are completed as bookmarks.
+const BOOKMARK_FLAGS: &[&str] = &[
+    "rev", "bookmark", "base", "dest", "source", "onto", "from", "to",
+];
+
+/// Long names of flags whose values are completed as file paths.
+const FILE_FLAGS: &[&str] = &[
+    "include",
+    "exclude",
+    "cwd",
+    "repository",
+    "configfile",
+    "logfile",
+];
+
+#[derive(Clone, Debug)]
+struct CommandSpec {
+    /// Command name, followed by its aliases.
+    names: Vec<String>,
+    doc: String,
+    flags: Vec<Flag>,
+    args: Complete,
+}
+
+impl CommandSpec {
+    fn has_name(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// Names to offer for completion. Prefix aliases like "stat" for
+    /// "status" are skipped if a shorter alias like "st" exists.
+    fn visible_names(&self) -> Vec<&str> {
+        let is_prefix =
+            |short: &str, long: &str| long.len() > short.len() && long.starts_with(short);
+        self.names
+            .iter()
+            .map(|name| name.as_str())
+            .filter(|name| {
+                !(self.names.iter().any(|other| is_prefix(name, other))
+                    && self.names.iter().any(|other| is_prefix(other, name)))
+            })
+            .collect()
+    }
+
+    /// First line of the help text.
+    fn summary(&self) -> &str {
+        self.doc.lines().next().unwrap_or_default().trim()
+    }
+}
+
+/// Generates shell completion scripts.
+///
+/// # Example
+/// ```
+/// use cliparser::completion::{Completion, Shell};
+/// let flags = vec![('r', "rev", "revisions", "").into()];
+/// let script = Completion::new("hg")
+///     .command("update|up|checkout", "update working copy", flags)
+///     .alias("co", "checkout --clean")
+///     .generate(Shell::Fish);
+/// assert!(script.contains("-a co -d 'alias for: checkout --clean'"));
+/// ```
+pub struct Completion {
+    program: String,
+    bookmark_command: String,
+    global_flags: Vec<Flag>,
+    commands: Vec<CommandSpec>,
+    aliases: BTreeMap<String, String>,
+}
+
+impl Completion {
+    /// Create an empty completion for `program`, like "hg".
+    pub fn new(program: impl ToString) -> Self {
+        let program = program.to_string();
+        Completion {
+            bookmark_command: format!("{} debugnamecomplete", program),
+            program,
+            global_flags: Vec::new(),
+            commands: Vec::new(),
+            aliases: BTreeMap::new(),
+        }
+    }
+
+    /// Shell command printing bookmark names, one per line.
+    pub fn bookmark_command(mut self, command: impl ToString) -> Self {
+        self.bookmark_command = command.to_string();
+        self
+    }
+
+    /// Flags accepted by all commands.
+    pub fn global_flags(mut self, flags: Vec<Flag>) -> Self {
+        self.global_flags = flags;
+        self
+    }
+
+    /// Add a command. `names` is the command name and its aliases separated
+    /// by "|", like in the command tables. Positional arguments are
+    /// completed as file paths.
+    ///
+    /// If the command shares a name with an existing command, the names and
+    /// flags are merged into the existing command.
+    pub fn command(mut self, names: &str, doc: &str, flags: Vec<Flag>) -> Self {
+        let names: Vec<String> = names
+            .trim_start_matches('^')
+            .split('|')
+            .map(ToString::to_string)
+            .collect();
+        let existing = self
+            .commands
+            .iter_mut()
+            .find(|command| command.names.iter().any(|name| names.contains(name)));
+        match existing {
+            Some(command) => {
+                for name in names {
+                    if !command.names.contains(&name) {
+                        command.names.push(name);
+                    }
+                }
+                if command.doc.is_empty() {
+                    command.doc = doc.to_string();
+                }
+                for flag in flags {
+                    if !command
+                        .flags
+                        .iter()
+                        .any(|f| f.long_name() == flag.long_name())
+                    {
+                        command.flags.push(flag);
+                    }
+                }
+            }
+            None => self.commands.push(CommandSpec {
+                names,
+                doc: doc.to_string(),
+                flags,
+                args: Complete::File,
+            }),
+        }
+        self
+    }
+
+    /// Change how positional arguments of the command `name` are completed.
+    pub fn args(mut self, name: &str, args: Complete) -> Self {
+        if let Some(command) = self.commands.iter_mut().find(|c| c.has_name(name)) {
+            command.args = args;
+        }
+        self
+    }
+
+    /// Add an alias, like `[alias]` config entries. Aliases complete the
+    /// flags and arguments of the command they expand to.
+    pub fn alias(mut self, name: impl ToString, expansion: impl ToString) -> Self {
+        self.aliases.insert(name.to_string(), expansion.to_string());
+        self
+    }
+
+    /// Generate the completion script.
+    pub fn generate(&self, shell: Shell) -> String {
+        let commands = self.resolve_aliases();
+        match shell {
+            Shell::Bash => self.bash(&commands),
+            Shell::Zsh => self.zsh(&commands),
+            Shell::Fish => self.fish(&commands),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&CommandSpec> {
+        self.commands.iter().find(|command| command.has_name(name))
+    }
+
+    /// Commands, followed by aliases that are not command names.
+    fn resolve_aliases(&self) -> Vec<CommandSpec> {
+        let mut commands = self.commands.clone();
+        for (name, expansion) in &self.aliases {
+            if self.find(name).is_some() {
+                // Aliases like "log = log -G" do not change completion.
+                continue;
+            }
+            // Malformed aliases are reported when they are used. Complete
+            // them like shell aliases.
+            let lookup = |name: &str| self.aliases.get(name);
+            let target = match expand_aliases(lookup, &[name]) {
+                Ok((args, _)) => args.first().and_then(|name| self.find(name)),
+                Err(_) => None,
+            };
+            commands.push(CommandSpec {
+                names: vec![name.clone()],
+                doc: format!("alias for: {}", expansion),
+                flags: target.map(|c| c.flags.clone()).unwrap_or_default(),
+                args: target.map(|c| c.args).unwrap_or(Complete::File),
+            });
+        }
+        commands
+    }
+
+    /// Name usable in shell function names.
+    fn function_name(&self) -> String {
+        self.program
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+
+    fn bash(&self, commands: &[CommandSpec]) -> String {
+        let name = self.function_name();
+        let mut out = format!(
+            "# bash completion for {program}. Generated, do not edit.\n\n\
+             _{name}_bookmarks() {{\n    {bookmark_command} 2>/dev/null\n}}\n\n\
+             _{name}() {{\n    \
+             local cur=${{COMP_WORDS[COMP_CWORD]}} prev=${{COMP_WORDS[COMP_CWORD-1]}}\n    \
+             local cmd i\n    \
+             for ((i = 1; i < COMP_CWORD; i++)); do\n        \
+             case ${{COMP_WORDS[i]}} in\n            \
+             {global_values}\
+             -*) ;;\n            \
+             *) cmd=${{COMP_WORDS[i]}}; break ;;\n        \
+             esac\n    \
+             done\n\n",
+            program = self.program,
+            name = name,
+            bookmark_command = self.bookmark_command,
+            global_values = match bash_flag_names(&self.global_flags, |c| c.is_some()) {
+                names if names.is_empty() => String::new(),
+                names => format!("{}) ((i++)) ;;\n            ", names.replace(' ', "|")),
+            },
+        );
+
+        out += &format!(
+            "    local flags=\"{}\"\n    local bookmark_flags=\"{}\"\n    local file_flags=\"{}\"\n    local value_flags=\"{}\"\n    local args=commands\n",
+            bash_flag_names(&self.global_flags, |_| true),
+            bash_flag_names(&self.global_flags, |c| c == Some(Complete::Bookmark)),
+            bash_flag_names(&self.global_flags, |c| c == Some(Complete::File)),
+            bash_flag_names(&self.global_flags, |c| c.is_some()),
+        );
+        out += "    case $cmd in\n        \"\") ;;\n";
+        for command in commands {
+            out += &format!("        {})\n", command.names.join("|"));
+            let lines = [
+                ("flags", bash_flag_names(&command.flags, |_| true)),
+                (
+                    "bookmark_flags",
+                    bash_flag_names(&command.flags, |c| c == Some(Complete::Bookmark)),
+                ),
+                (
+                    "file_flags",
+                    bash_flag_names(&command.flags, |c| c == Some(Complete::File)),
+                ),
+                (
+                    "value_flags",
+                    bash_flag_names(&command.flags, |c| c.is_some()),
+                ),
+            ];
+            for (variable, value) in lines.iter() {
+                if !value.is_empty() {
+                    out += &format!("            {}+=\" {}\"\n", variable, value);
+                }
+            }
+            out += &format!(
+                "            args={}\n            ;;\n",
+                bash_args(command.args)
+            );
+        }
+        out += "        *) args=file ;;\n    esac\n\n";
+
+        let mut all_names: Vec<&str> = commands.iter().flat_map(|c| c.visible_names()).collect();
+        all_names.sort_unstable();
+        out += &format!(
+            "    COMPREPLY=()\n    \
+             if [[ \" $bookmark_flags \" == *\" $prev \"* ]]; then\n        \
+             COMPREPLY=($(compgen -W \"$(_{name}_bookmarks)\" -- \"$cur\"))\n    \
+             elif [[ \" $file_flags \" == *\" $prev \"* ]]; then\n        \
+             COMPREPLY=($(compgen -f -- \"$cur\"))\n    \
+             elif [[ \" $value_flags \" == *\" $prev \"* ]]; then\n        \
+             :\n    \
+             elif [[ $cur == -* ]]; then\n        \
+             COMPREPLY=($(compgen -W \"$flags\" -- \"$cur\"))\n    \
+             else\n        \
+             case $args in\n            \
+             commands) COMPREPLY=($(compgen -W \"{names}\" -- \"$cur\")) ;;\n            \
+             bookmark) COMPREPLY=($(compgen -W \"$(_{name}_bookmarks)\" -- \"$cur\")) ;;\n            \
+             file) COMPREPLY=($(compgen -f -- \"$cur\")) ;;\n        \
+             esac\n    \
+             fi\n}}\n\n\
+             complete -o filenames -F _{name} {program}\n",
+            name = name,
+            names = all_names.join(" "),
+            program = self.program,
+        );
+        out
+    }
+
+    fn zsh(&self, commands: &[CommandSpec]) -> String {
+        let name = self.function_name();
+        let mut out = format!(
+            "#compdef {program}\n\
+             # zsh completion for {program}. Generated, do not edit.\n\n\
+             _{name}_bookmarks() {{\n  \
+             local -a bookmarks\n  \
+             bookmarks=(${{(f)\"$(_call_program bookmarks {bookmark_command} 2>/dev/null)\"}})\n  \
+             _describe -t bookmarks bookmark bookmarks\n}}\n\n\
+             _{name}() {{\n  \
+             local curcontext=$curcontext state line ret=1\n  \
+             typeset -A opt_args\n  \
+             _arguments -C -s \\\n",
+            program = self.program,
+            name = name,
+            bookmark_command = self.bookmark_command,
+        );
+        for flag in &self.global_flags {
+            out += &format!("    {} \\\n", zsh_flag(flag, &name));
+        }
+        out += "    '1:command:->command' \\\n    '*::argument:->argument' && ret=0\n\n";
+        out += "  case $state in\n    command)\n      local -a commands\n      commands=(\n";
+        for command in commands {
+            for command_name in command.visible_names() {
+                out += &format!(
+                    "        {}\n",
+                    sh_quote(&format!(
+                        "{}:{}",
+                        command_name.replace(':', "\\:"),
+                        command.summary()
+                    ))
+                );
+            }
+        }
+        out += "      )\n      _describe -t commands command commands && ret=0\n      ;;\n";
+        out += &format!(
+            "    argument)\n      curcontext=${{curcontext%:*:*}}:{}-$words[1]:\n      case $words[1] in\n",
+            self.program
+        );
+        for command in commands {
+            out += &format!("        {})\n", command.names.join("|"));
+            let mut specs: Vec<String> = command
+                .flags
+                .iter()
+                .map(|flag| zsh_flag(flag, &name))
+                .collect();
+            match command.args {
+                Complete::Nothing => {}
+                Complete::Bookmark => specs.push(format!("'*:bookmark:_{}_bookmarks'", name)),
+                Complete::File => specs.push("'*:file:_files'".to_string()),
+            }
+            if specs.is_empty() {
+                out += "          _message 'no more arguments'\n";
+            } else {
+                out += "          _arguments -s";
+                for spec in specs {
+                    out += &format!(" \\\n            {}", spec);
+                }
+                out += " && ret=0\n";
+            }
+            out += "          ;;\n";
+        }
+        out += "        *)\n          _files && ret=0\n          ;;\n      esac\n      ;;\n  esac\n\n  return ret\n}\n\n";
+        out += &format!("_{} \"$@\"\n", name);
+        out
+    }
+
+    fn fish(&self, commands: &[CommandSpec]) -> String {
+        let name = self.function_name();
+        let program = &self.program;
+        let mut out = format!(
+            "# fish completion for {program}. Generated, do not edit.\n\n\
+             function __{name}_bookmarks\n    {bookmark_command} 2>/dev/null\nend\n\n\
+             complete -c {program} -f\n",
+            program = program,
+            name = name,
+            bookmark_command = self.bookmark_command,
+        );
+        for flag in &self.global_flags {
+            out += &format!("complete -c {}{}\n", program, fish_flag(flag, &name));
+        }
+        for command in commands {
+            for command_name in command.visible_names() {
+                out += &format!(
+                    "complete -c {} -n __fish_use_subcommand -a {} -d {}\n",
+                    program,
+                    command_name,
+                    sh_quote(command.summary())
+                );
+            }
+        }
+        for command in commands {
+            let condition = sh_quote(&format!(
+                "__fish_seen_subcommand_from {}",
+                command.names.join(" ")
+            ));
+            for flag in &command.flags {
+                out += &format!(
+                    "complete -c {} -n {}{}\n",
+                    program,
+                    condition,
+                    fish_flag(flag, &name)
+                );
+            }
+            match command.args {
+                Complete::Nothing => {}
+                Complete::Bookmark => {
+                    out += &format!(
+                        "complete -c {} -n {} -a '(__{}_bookmarks)'\n",
+                        program, condition, name
+                    )
+                }
+                Complete::File => out += &format!("complete -c {} -n {} -F\n", program, condition),
+            }
+        }
+        out
+    }
+}
+
+/// How to complete the value of `flag`, or `None` if it takes no value.
+fn value_completion(flag: &Flag) -> Option<Complete> {
+    match flag.default_value() {
+        Value::Bool(_) | Value::OptBool() => None,
+        _ if BOOKMARK_FLAGS.contains(&flag.long_name()) => Some(Complete::Bookmark),
+        _ if FILE_FLAGS.contains(&flag.long_name()) => Some(Complete::File),
+        _ => Some(Complete::Nothing),
+    }
+}
+
+/// Quote for POSIX shells and fish.
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Space separated "--long -s" names of flags. `filter` takes the
+/// [`value_completion`] of a flag.
+fn bash_flag_names(flags: &[Flag], filter: impl Fn(Option<Complete>) -> bool) -> String {
+    let mut names = Vec::new();
+    for flag in flags {
+        if filter(value_completion(flag)) {
+            names.push(format!("--{}", flag.long_name()));
+            if let Some(short) = flag.short_name() {
+                names.push(format!("-{}", short));
+            }
+        }
+    }
+    names.join(" ")
+}
+
+fn bash_args(args: Complete) -> &'static str {
+    match args {
+        Complete::Nothing => "none",
+        Complete::Bookmark => "bookmark",
+        Complete::File => "file",
+    }
+}
+
+/// `_arguments` spec of a flag.
+fn zsh_flag(flag: &Flag, name: &str) -> String {
+    let description = flag
+        .description()
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]");
+    let (short_suffix, long_suffix, action) = match value_completion(flag) {
+        None => ("", "", String::new()),
+        Some(Complete::Nothing) => ("+", "=", format!(":{}: ", flag.long_name())),
+        Some(Complete::Bookmark) => ("+", "=", format!(":bookmark:_{}_bookmarks", name)),
+        Some(Complete::File) => ("+", "=", ":file:_files".to_string()),
+    };
+    let repeated = matches!(flag.default_value(), Value::List(_));
+    let rest = format!("[{}]{}", description, action);
+    match flag.short_name() {
+        Some(short) => {
+            let exclusion = if repeated {
+                "'*'".to_string()
+            } else {
+                format!("'(-{} --{})'", short, flag.long_name())
+            };
+            format!(
+                "{}{{-{}{},--{}{}}}{}",
+                exclusion,
+                short,
+                short_suffix,
+                flag.long_name(),
+                long_suffix,
+                sh_quote(&rest)
+            )
+        }
+        None => sh_quote(&format!(
+            "{}--{}{}{}",
+            if repeated { "*" } else { "" },
+            flag.long_name(),
+            long_suffix,
+            rest
+        )),
+    }
+}
+
+/// Options of `complete` for a flag, starting with a space.
+fn fish_flag(flag: &Flag, name: &str) -> String {
+    let mut out = String::new();
+    if let Some(short) = flag.short_name() {
+        out += &format!(" -s {}", short);
+    }
+    out += &format!(" -l {}", flag.long_name());
+    match value_completion(flag) {
+        None => {}
+        Some(Complete::Nothing) => out += " -x",
+        Some(Complete::Bookmark) => out += &format!(" -x -a '(__{}_bookmarks)'", name),
+        Some(Complete::File) => out += " -r -F",
+    }
+    out += &format!(" -d {}", sh_quote(flag.description()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completion() -> Completion {
+        let flags = |flags: Vec<(char, &'static str, &'static str, Value)>| -> Vec<Flag> {
+            flags.into_iter().map(Into::into).collect()
+        };
+        Completion::new("hg")
+            .global_flags(flags(vec![
+                ('R', "repository", "repository root directory", "".into()),
+                ('q', "quiet", "suppress output", false.into()),
+            ]))
+            .command(
+                "status|st|sta|stat|statu",
+                "list files with pending changes\n\n    More help.",
+                flags(vec![
+                    ('m', "modified", "show only modified files", false.into()),
+                    (
+                        ' ',
+                        "rev",
+                        "show difference from revision",
+                        Value::List(Vec::new()),
+                    ),
+                ]),
+            )
+            .command(
+                "update|up|checkout|co",
+                "check out a specific commit",
+                flags(vec![(
+                    'C',
+                    "clean",
+                    "discard 'uncommitted' changes",
+                    false.into(),
+                )]),
+            )
+            .args("update", Complete::Bookmark)
+            .command(
+                "^update|goto",
+                "update working directory (or switch revisions)",
+                flags(vec![(
+                    'd',
+                    "date",
+                    "tipmost revision matching date",
+                    "".into(),
+                )]),
+            )
+            .alias("s", "st -m")
+            .alias("status", "status -v")
+            .alias("ss", "!hg status")
+    }
+
+    #[test]
+    fn test_commands() {
+        let commands = completion().resolve_aliases();
+        let names: Vec<Vec<&str>> = commands.iter().map(|c| c.visible_names()).collect();
+        assert_eq!(
+            names,
+            vec![
+                vec!["status", "st"],
+                vec!["update", "up", "checkout", "co", "goto"],
+                vec!["s"],
+                vec!["ss"]
+            ]
+        );
+        assert_eq!(commands[1].summary(), "check out a specific commit");
+        let long_names: Vec<&str> = commands[1].flags.iter().map(|f| f.long_name()).collect();
+        assert_eq!(long_names, vec!["clean", "date"]);
+        assert_eq!(commands[1].args, Complete::Bookmark);
+        assert_eq!(commands[2].doc, "alias for: st -m");
+        assert_eq!(commands[2].flags.len(), 2);
+        assert_eq!(commands[3].flags.len(), 0);
+        assert_eq!(commands[3].args, Complete::File);
+    }
+
+    #[test]
+    fn test_bash() {
+        let script = completion().generate(Shell::Bash);
+        assert!(script.contains("            --repository|-R) ((i++)) ;;\n"));
+        assert!(script.contains(
+            r#"        status|st|sta|stat|statu)
+            flags+=" --modified -m --rev"
+            bookmark_flags+=" --rev"
+            value_flags+=" --rev"
+            args=file
+            ;;
+"#
+        ));
+        assert!(script.contains(r#"compgen -W "checkout co goto s ss st status up update""#));
+        assert!(script.ends_with("complete -o filenames -F _hg hg\n"));
+    }
+
+    #[test]
+    fn test_zsh() {
+        let script = completion().generate(Shell::Zsh);
+        assert!(script.starts_with("#compdef hg\n"));
+        assert!(script.contains(
+            r#"    '(-R --repository)'{-R+,--repository=}'[repository root directory]:file:_files' \
+    '(-q --quiet)'{-q,--quiet}'[suppress output]' \
+"#
+        ));
+        assert!(script.contains("        'st:list files with pending changes'\n"));
+        assert!(script.contains(
+            r#"        update|up|checkout|co|goto)
+          _arguments -s \
+            '(-C --clean)'{-C,--clean}'[discard '\''uncommitted'\'' changes]' \
+            '(-d --date)'{-d+,--date=}'[tipmost revision matching date]:date: ' \
+            '*:bookmark:_hg_bookmarks' && ret=0
+"#
+        ));
+        assert!(script.contains("'*--rev=[show difference from revision]:bookmark:_hg_bookmarks'"));
+    }
+
+    #[test]
+    fn test_fish() {
+        let script = completion().generate(Shell::Fish);
+        assert!(script
+            .contains("complete -c hg -s R -l repository -r -F -d 'repository root directory'\n"));
+        assert!(
+            script.contains("complete -c hg -n __fish_use_subcommand -a s -d 'alias for: st -m'\n")
+        );
+        assert!(script.contains(
+            "complete -c hg -n '__fish_seen_subcommand_from status st sta stat statu' -l rev -x -a '(__hg_bookmarks)' -d 'show difference from revision'\n"
+        ));
+        assert!(script.contains(
+            "complete -c hg -n '__fish_seen_subcommand_from update up checkout co goto' -a '(__hg_bookmarks)'\n"
+        ));
+        assert!(script.contains("complete -c hg -n '__fish_seen_subcommand_from ss' -F\n"));
+    }
+
+    #[test]
+    fn test_shell_from_str() {
+        assert_eq!("zsh".parse::<Shell>().unwrap(), Shell::Zsh);
+        assert_eq!(
+            "csh".parse::<Shell>().unwrap_err().to_string(),
+            "unknown shell 'csh' (supported: bash, zsh, fish)"
+        );
+    }
+}
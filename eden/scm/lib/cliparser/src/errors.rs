@@ -10,3 +10,7 @@ use thiserror::Error;
 #[derive(Debug, Error)]
 #[error("invalid arguments\n(use '--help' to get help)")]
 pub struct InvalidArguments;
+
+#[derive(Debug, Error)]
+#[error("unknown shell '{0}' (supported: bash, zsh, fish)")]
+pub struct UnknownShell(pub String);
@@ -23,6 +23,7 @@
 //!
 
 pub mod alias;
+pub mod completion;
 pub mod errors;
 pub mod macros;
 pub mod parser;
@@ -43,7 +43,7 @@ macro_rules! _define_flags_impl {
         impl $crate::parser::StructFlags for $name {
             fn flags() -> Vec<$crate::parser::Flag> {
                 let flags: Vec<(char, String, String, $crate::parser::Value)> = vec![
-                    $( ($short, stringify!($field).replace("_", "-"), $doc.trim().to_string(), $crate::parser::Value::from($default)), )*
+                    $( ($short, stringify!($field).trim_start_matches("r#").replace("_", "-"), $doc.trim().to_string(), $crate::parser::Value::from($default)), )*
                 ];
                 #[allow(unused_mut)]
                 let mut result: Vec<$crate::parser::Flag> = flags.into_iter().map(Into::into).collect();
@@ -64,7 +64,7 @@ macro_rules! _define_flags_impl {
                 }
 
                 Ok(Self {
-                    $( $field : out.pick::<$type>(&stringify!($field).replace("_", "-")), )*
+                    $( $field : out.pick::<$type>(&stringify!($field).trim_start_matches("r#").replace("_", "-")), )*
                     $( $varargs: out.args.get($varargs_offset..).map(|v| v.to_vec()).unwrap_or_default(), )?
                     $( $arg0: out.args.get(0).cloned().unwrap_or_default(), )?
                     $( $arg: out.args[$arg_index].clone(), )*
@@ -286,6 +286,9 @@ mod tests {
             /// follow renames
             follow: bool,
 
+            /// keyword as name
+            r#continue: bool,
+
             #[args]
             pats: Vec<String>,
 
@@ -326,7 +329,7 @@ mod tests {
         assert_eq!(flags, expected);
 
         let flags = AnotherTestOptions::flags();
-        assert_eq!(flags.len(), 1);
+        assert_eq!(flags.len(), 2);
     }
 
     #[test]
@@ -368,10 +371,18 @@ mod tests {
 
         let parsed = ParseOptions::new()
             .flags(AnotherTestOptions::flags())
-            .parse_args(&vec!["--no-follow", "foo", "b", "--follow", "c"])
+            .parse_args(&vec![
+                "--no-follow",
+                "foo",
+                "b",
+                "--follow",
+                "c",
+                "--continue",
+            ])
             .unwrap();
         let parsed = AnotherTestOptions::try_from(parsed).unwrap();
         assert_eq!(parsed.follow, true);
+        assert_eq!(parsed.r#continue, true);
         assert_eq!(parsed.pats, vec!["b", "c"]);
         assert_eq!(parsed.name, "foo");
     }
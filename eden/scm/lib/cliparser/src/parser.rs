@@ -227,6 +227,24 @@ pub struct Flag {
     default_value: Value,
 }
 
+impl Flag {
+    pub fn short_name(&self) -> Option<char> {
+        self.short_name
+    }
+
+    pub fn long_name(&self) -> &str {
+        &self.long_name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn default_value(&self) -> &Value {
+        &self.default_value
+    }
+}
+
 /// Convert a tuple to a [`Flag`].
 ///
 /// The tuple is similar to the command flag registration used in hg Python
@@ -67,6 +67,14 @@ mod defaults {
     pub fn error_throttling_rate_sec() -> u64 {
         60 * 5
     }
+
+    pub fn notification_transport() -> String {
+        "sse".to_owned()
+    }
+
+    pub fn reconnect_backoff_max_sec() -> u64 {
+        60
+    }
 }
 
 /// Struct for decoding Commit Cloud configuration from TOML.
@@ -79,9 +87,24 @@ pub struct CommitCloudConfig {
     pub service_url: Option<String>,
 
     /// Server-Sent Events endpoint for real-time Commit Cloud Notifications
+    /// With the "longpoll" transport, this is the long polling endpoint
     #[serde(default)]
     pub notification_url: Option<String>,
 
+    /// Transport for notifications: "sse", "longpoll" or "file"
+    /// "file" watches local files instead of a notification service
+    #[serde(default = "defaults::notification_transport")]
+    pub notification_transport: String,
+
+    /// Directory with notification files for the "file" transport
+    /// Notifications are json lines appended to <notification_dir>/<repo_name>/<workspace>
+    #[serde(default)]
+    pub notification_dir: Option<PathBuf>,
+
+    /// Maximal delay between reconnection attempts in sec
+    #[serde(default = "defaults::reconnect_backoff_max_sec")]
+    pub reconnect_backoff_max_sec: u64,
+
     /// Path to the directory containing current connected subscribers
     /// This is an optional override, see logic for the default location
     /// Subscriber is a simple ini file containing repo_name, repo_root and workspace
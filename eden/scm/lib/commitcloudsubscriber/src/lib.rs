@@ -10,11 +10,14 @@ pub mod config;
 pub mod error;
 pub mod receiver;
 pub mod subscriber;
+pub mod throttler;
+pub mod transport;
 pub(crate) mod util;
 
 pub use config::CommitCloudConfig;
 pub use receiver::TcpReceiverService as CommitCloudTcpReceiverService;
 pub use subscriber::WorkspaceSubscriberService as CommitCloudWorkspaceSubscriberService;
+pub use throttler::ThrottlingExecutor;
 
 #[cfg(test)]
 pub mod tests;
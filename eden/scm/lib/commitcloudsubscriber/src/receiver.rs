@@ -9,6 +9,7 @@ use anyhow::Result;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::Write;
 use std::net::{SocketAddr, TcpListener};
 use std::thread;
 
@@ -22,6 +23,10 @@ pub enum CommandName {
     CommitCloudCancelSubscriptions,
     #[serde(rename = "commitcloud::start_subscriptions")]
     CommitCloudStartSubscriptions,
+    #[serde(rename = "scm_daemon::health")]
+    ScmDaemonHealth,
+    #[serde(rename = "scm_daemon::run_job")]
+    ScmDaemonRunJob,
 }
 
 #[derive(Debug, Deserialize, Default, Serialize)]
@@ -38,18 +43,26 @@ pub struct Command(pub (CommandName, CommandData));
 /// Example: ["commitcloud::restart_subscriptions", {"foo": "bar"}]
 /// Example to test: echo '["commitcloud::restart_subscriptions", {}]' | nc localhost 15432
 /// with_actions builder is used to configure callbacks
+/// with_handlers builder is used to configure callbacks that reply
+/// Replies are written back as a single line of json
+/// Example: echo '["scm_daemon::health", {}]' | nc -N localhost 15432
 /// The serve function starts the service
 
 pub struct TcpReceiverService {
     port: u16,
     actions: HashMap<CommandName, Box<dyn Fn() + Send>>,
+    handlers: HashMap<CommandName, Handler>,
 }
 
+/// Callback that takes the command data and returns the reply
+pub type Handler = Box<dyn Fn(&CommandData) -> serde_json::Value + Send>;
+
 impl TcpReceiverService {
     pub fn new(port: u16) -> TcpReceiverService {
         TcpReceiverService {
             port,
             actions: HashMap::new(),
+            handlers: HashMap::new(),
         }
     }
 
@@ -65,6 +78,15 @@ impl TcpReceiverService {
         self
     }
 
+    pub fn with_handlers(mut self, handlers: HashMap<CommandName, Handler>) -> TcpReceiverService {
+        self.handlers = self
+            .handlers
+            .into_iter()
+            .chain(handlers.into_iter())
+            .collect();
+        self
+    }
+
     pub fn serve(self) -> Result<thread::JoinHandle<Result<()>>> {
         let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], self.port)))?;
         Ok(thread::spawn(move || {
@@ -72,16 +94,21 @@ impl TcpReceiverService {
             info!("Listening on port {}", self.port);
             for stream in listener.incoming() {
                 match stream {
-                    Ok(stream) => {
-                        match serde_json::from_reader::<_, Command>(stream) {
-                            Ok(command) => {
-                                let command_name = serde_json::to_string(&(command.0).0)
+                    Ok(mut stream) => {
+                        match serde_json::from_reader::<_, Command>(&stream) {
+                            Ok(Command((name, data))) => {
+                                let command_name = serde_json::to_string(&name)
                                     .ok()
                                     .unwrap_or("unknown".into());
 
                                 info!("Received {} command", command_name);
-                                if let Some(action) = self.actions.get(&((command.0).0)) {
+                                if let Some(action) = self.actions.get(&name) {
                                     action();
+                                } else if let Some(handler) = self.handlers.get(&name) {
+                                    let reply = handler(&data);
+                                    if let Err(e) = writeln!(stream, "{}", reply) {
+                                        error!("Reply to {} failed {}", command_name, e);
+                                    }
                                 } else {
                                     info!("No actions found for {}", command_name);
                                 }
@@ -12,9 +12,10 @@ use crate::receiver::CommandName::{
     self, CommitCloudCancelSubscriptions, CommitCloudRestartSubscriptions,
     CommitCloudStartSubscriptions,
 };
+use crate::throttler::ThrottlingExecutor;
+use crate::transport::{self, Transport};
 use crate::util;
 use anyhow::{bail, Result};
-use eventsource::reqwest::Client;
 use log::{error, info, warn};
 use reqwest::Url;
 use serde::Deserialize;
@@ -23,7 +24,7 @@ use std::net::ToSocketAddrs;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc};
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 use std::{str, thread};
 
 #[allow(unused_macros)]
@@ -53,42 +54,6 @@ pub struct Subscription {
     pub(crate) workspace: String,
 }
 
-struct ThrottlingExecutor {
-    /// throttling rate in seconds
-    rate: Duration,
-
-    /// last time of command execution
-    last_time: SystemTime,
-}
-
-impl ThrottlingExecutor {
-    /// create ThrottlingExecutor with some duration
-    pub fn new(rate: Duration) -> ThrottlingExecutor {
-        ThrottlingExecutor {
-            rate,
-            last_time: SystemTime::now() - rate,
-        }
-    }
-    /// Run function if it is time, skip otherwise
-    #[inline]
-    fn execute(&mut self, f: &dyn Fn()) {
-        let now = SystemTime::now();
-        if now
-            .duration_since(self.last_time)
-            .map(|elapsed| elapsed >= self.rate)
-            .unwrap_or(true)
-        {
-            f();
-            self.last_time = now;
-        }
-    }
-    /// Reset time to pretend the command last execution was a while ago
-    #[inline]
-    fn reset(&mut self) {
-        self.last_time = SystemTime::now() - self.rate;
-    }
-}
-
 /// WorkspaceSubscriberService manages a set of running subscriptions
 /// and trigger `hg cloud sync` on notifications
 /// The workflow is simple:
@@ -110,11 +75,12 @@ impl ThrottlingExecutor {
 /// The serve function starts the service
 
 pub struct WorkspaceSubscriberService {
-    /// Server-Sent Events endpoint for Commit Cloud Notifications
-    pub(crate) notification_url: String,
+    /// Transport delivering Commit Cloud Notifications
+    pub(crate) transport: Box<dyn Transport>,
 
     /// Http endpoint for Commit Cloud requests
-    pub(crate) service_url: String,
+    /// `hg cloud sync` is skipped while it cannot be resolved
+    pub(crate) service_url: Option<String>,
 
     /// OAuth token path (optional) for access to Commit Cloud SSE endpoint
     pub(crate) user_token_path: Option<PathBuf>,
@@ -141,14 +107,8 @@ pub struct WorkspaceSubscriberService {
 impl WorkspaceSubscriberService {
     pub fn new(config: &CommitCloudConfig) -> Result<WorkspaceSubscriberService> {
         Ok(WorkspaceSubscriberService {
-            notification_url: config
-                .notification_url
-                .clone()
-                .ok_or_else(|| ErrorKind::CommitCloudConfigError("undefined 'notification_url'"))?,
-            service_url: config
-                .service_url
-                .clone()
-                .ok_or_else(|| ErrorKind::CommitCloudConfigError("undefined 'service_url'"))?,
+            transport: transport::from_config(config)?,
+            service_url: config.service_url.clone(),
             user_token_path: config.user_token_path.clone(),
             connected_subscribers_path: config.connected_subscribers_path.clone().ok_or_else(
                 || ErrorKind::CommitCloudConfigError("undefined 'connected_subscribers_path'"),
@@ -225,32 +185,16 @@ impl WorkspaceSubscriberService {
                              Restarting subscriptions..."
                         );
                         self.interrupt.store(false, Ordering::Relaxed);
-                        // start subscription threads
-                        let access_token = util::read_access_token(&self.user_token_path);
-                        if let Ok(access_token) = access_token {
-                            let subscriptions = self.run_subscriptions(access_token)?;
-                            for child in subscriptions {
-                                let _ = child.join();
-                            }
-                        } else {
-                            info!("User is not authenticated with Commit Cloud yet");
-                            continue;
-                        }
+                        self.run_and_join_subscriptions()?;
                     }
                     Ok(CommitCloudStartSubscriptions) => {
                         info!("Starting subscriptions...");
                         self.interrupt.store(false, Ordering::Relaxed);
-                        let access_token = util::read_access_token(&self.user_token_path);
-                        // start subscription threads
-                        if let Ok(access_token) = access_token {
-                            let subscriptions = self.run_subscriptions(access_token)?;
-                            for child in subscriptions {
-                                let _ = child.join();
-                            }
-                        } else {
-                            info!("User is not authenticated with Commit Cloud yet");
-                            continue;
-                        }
+                        self.run_and_join_subscriptions()?;
+                    }
+                    Ok(command) => {
+                        // commands of other services are never sent here
+                        info!("Ignored {:?} command", command);
                     }
                     Err(mpsc::RecvTimeoutError::Timeout) => {
                         if !util::read_subscriptions(&self.connected_subscribers_path)?.is_empty() {
@@ -267,15 +211,38 @@ impl WorkspaceSubscriberService {
         }))
     }
 
+    /// Start subscription threads and wait until they join
+    /// Nothing is started if the transport needs an access token and there is none
+    fn run_and_join_subscriptions(&self) -> Result<()> {
+        let access_token = if self.transport.needs_access_token() {
+            match util::read_access_token(&self.user_token_path) {
+                Ok(access_token) => Some(access_token),
+                Err(_) => {
+                    info!("User is not authenticated with Commit Cloud yet");
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+        for child in self.run_subscriptions(access_token)? {
+            let _ = child.join();
+        }
+        Ok(())
+    }
+
     /// This helper function reads the list of current connected subscribers
     /// It starts all the requested subscriptions by simply runing a separate thread for each one
     /// All threads keep checking the interrupt flag and join gracefully if it is restart or stop
 
-    fn run_subscriptions(&self, access_token: util::Token) -> Result<Vec<thread::JoinHandle<()>>> {
+    fn run_subscriptions(
+        &self,
+        access_token: Option<util::Token>,
+    ) -> Result<Vec<thread::JoinHandle<()>>> {
         util::read_subscriptions(&self.connected_subscribers_path)?
             .into_iter()
             .map(|(subscription, repo_roots)| {
-                self.run_subscription(access_token.clone(), subscription, repo_roots)
+                self.run_subscription(access_token.as_ref(), subscription, repo_roots)
             })
             .collect::<Result<Vec<thread::JoinHandle<()>>>>()
     }
@@ -284,24 +251,23 @@ impl WorkspaceSubscriberService {
 
     fn run_subscription(
         &self,
-        access_token: util::Token,
+        access_token: Option<&util::Token>,
         subscription: Subscription,
         repo_roots: Vec<PathBuf>,
     ) -> Result<thread::JoinHandle<()>> {
-        let mut notification_url = Url::parse(&self.notification_url)?;
-        let service_url = Url::parse(&self.service_url)?;
+        let service_url = match self.service_url {
+            Some(ref service_url) => Some(Url::parse(service_url)?),
+            None => None,
+        };
 
         let sid = format!("({} @ {})", subscription.repo_name, subscription.workspace);
-        info!("{} Subscribing to {}", sid, notification_url);
-
-        notification_url
-            .query_pairs_mut()
-            .append_pair("workspace", &subscription.workspace)
-            .append_pair("repo_name", &subscription.repo_name)
-            .append_pair("access_token", &access_token.token)
-            .append_pair("token_type", &access_token.token_type.to_string());
+        info!(
+            "{} Subscribing via {} transport",
+            sid,
+            self.transport.name()
+        );
 
-        let client = Client::new(notification_url);
+        let events = self.transport.subscribe(&subscription, access_token)?;
 
         info!("{} Spawn a thread to handle the subscription", sid);
 
@@ -314,12 +280,14 @@ impl WorkspaceSubscriberService {
             info!("{} Thread started...", sid);
 
             let fire = |reason: &'static str, version: Option<u64>| {
-                if service_url.to_socket_addrs().is_err() {
-                    warn!(
-                        "{} Skip CloudSyncTrigger: failed to lookup address information {}",
-                        sid, service_url
-                    );
-                    return;
+                if let Some(ref service_url) = service_url {
+                    if service_url.to_socket_addrs().is_err() {
+                        warn!(
+                            "{} Skip CloudSyncTrigger: failed to lookup address information {}",
+                            sid, service_url
+                        );
+                        return;
+                    }
                 }
                 for repo_root in repo_roots.iter() {
                     info!(
@@ -347,13 +315,12 @@ impl WorkspaceSubscriberService {
             let mut throttler_error = ThrottlingExecutor::new(error_throttling_rate);
             let mut last_error = false;
 
-            // the library handles automatic reconnection
-            for event in client {
+            // the transport handles automatic reconnection
+            for event in events {
                 if interrupt.load(Ordering::Relaxed) {
                     return;
                 }
 
-                let event = event.map_err(|e| ErrorKind::CommitCloudHttpError(format!("{}", e)));
                 if let Err(e) = event {
                     terror!(throttler_error, "{} {}. Continue...", sid, e);
                     throttler_alive.reset();
@@ -366,7 +333,7 @@ impl WorkspaceSubscriberService {
                     continue;
                 }
 
-                let data = event.unwrap().data;
+                let data = event.unwrap();
                 if data.is_empty() {
                     tinfo!(
                         throttler_alive,
@@ -5,10 +5,15 @@
  * GNU General Public License version 2.
  */
 
+use crate::subscriber::Subscription;
+use crate::throttler::ThrottlingExecutor;
+use crate::transport::{Backoff, FileTransport, Transport};
 use crate::util::{read_access_token, TOKEN_FILENAME};
-use std::fs::File;
+use std::cell::Cell;
+use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 use tempfile::tempdir;
 
 #[test]
@@ -23,3 +28,53 @@ fn test_read_access_token_from_file_should_return_token() {
     dir.close().unwrap();
     assert_eq!(result.token, "token");
 }
+
+#[test]
+fn test_throttling_executor_should_skip_until_rate_passed() {
+    let mut throttler = ThrottlingExecutor::new(Duration::from_secs(3600));
+    let count = Cell::new(0);
+    assert!(throttler.execute(|| count.set(count.get() + 1)));
+    assert!(!throttler.execute(|| count.set(count.get() + 1)));
+    throttler.reset();
+    assert!(throttler.execute(|| count.set(count.get() + 1)));
+    assert_eq!(count.get(), 2);
+}
+
+#[test]
+fn test_backoff_should_double_until_max() {
+    let mut backoff = Backoff::new(Duration::from_secs(5));
+    let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+    assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+}
+
+#[test]
+fn test_file_transport_should_return_appended_notifications() {
+    let dir = tempdir().unwrap();
+    let transport = FileTransport {
+        notification_dir: dir.path().to_path_buf(),
+        poll_interval: Duration::from_millis(1),
+    };
+    let subscription = Subscription {
+        repo_name: "repo".into(),
+        workspace: "user/test/default".into(),
+    };
+    let path = dir.path().join("repo/user/test/default");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, "{\"version\": 1}\n").unwrap();
+
+    assert!(!transport.needs_access_token());
+    let mut events = transport.subscribe(&subscription, None).unwrap();
+    // notifications before subscribing are skipped
+    assert_eq!(events.next().unwrap().unwrap(), "");
+
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    write!(file, "{{\"version\": 2}}\n{{\"version\": 3}}\n{{\"vers").unwrap();
+    assert_eq!(events.next().unwrap().unwrap(), "{\"version\": 2}");
+    assert_eq!(events.next().unwrap().unwrap(), "{\"version\": 3}");
+    // incomplete lines wait for the rest
+    assert_eq!(events.next().unwrap().unwrap(), "");
+    writeln!(file, "ion\": 4}}").unwrap();
+    assert_eq!(events.next().unwrap().unwrap(), "{\"version\": 4}");
+}
@@ -0,0 +1,48 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::time::{Duration, SystemTime};
+
+pub struct ThrottlingExecutor {
+    /// throttling rate in seconds
+    rate: Duration,
+
+    /// last time of command execution
+    last_time: SystemTime,
+}
+
+impl ThrottlingExecutor {
+    /// create ThrottlingExecutor with some duration
+    pub fn new(rate: Duration) -> ThrottlingExecutor {
+        ThrottlingExecutor {
+            rate,
+            last_time: SystemTime::now() - rate,
+        }
+    }
+    /// Run function if it is time, skip otherwise
+    /// Returns true if the function was run
+    #[inline]
+    pub fn execute<F: FnOnce()>(&mut self, f: F) -> bool {
+        let now = SystemTime::now();
+        if now
+            .duration_since(self.last_time)
+            .map(|elapsed| elapsed >= self.rate)
+            .unwrap_or(true)
+        {
+            f();
+            self.last_time = now;
+            true
+        } else {
+            false
+        }
+    }
+    /// Reset time to pretend the command last execution was a while ago
+    #[inline]
+    pub fn reset(&mut self) {
+        self.last_time = SystemTime::now() - self.rate;
+    }
+}
@@ -0,0 +1,319 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::config::CommitCloudConfig;
+use crate::error::*;
+use crate::subscriber::{Notification, Subscription};
+use crate::util::Token;
+use anyhow::Result;
+use eventsource::reqwest::Client as EventSourceClient;
+use reqwest::{StatusCode, Url};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Stream of notification events of a subscription
+/// Each event is the json data of a `Notification`,
+/// or an empty string if the subscription is alive but nothing happened
+/// Errors are not fatal: the stream recovers and keeps going
+pub type EventStream = Box<dyn Iterator<Item = Result<String>> + Send>;
+
+/// Transport delivering Commit Cloud notifications
+pub trait Transport: Send {
+    /// Short name for logging
+    fn name(&self) -> &'static str;
+
+    /// Whether `subscribe` needs an access token
+    fn needs_access_token(&self) -> bool {
+        true
+    }
+
+    /// Start receiving notifications for the subscription
+    fn subscribe(
+        &self,
+        subscription: &Subscription,
+        access_token: Option<&Token>,
+    ) -> Result<EventStream>;
+}
+
+/// Create the transport selected by 'notification_transport'
+pub fn from_config(config: &CommitCloudConfig) -> Result<Box<dyn Transport>> {
+    let backoff = Backoff::new(Duration::from_secs(config.reconnect_backoff_max_sec));
+    let notification_url = || -> Result<Url> {
+        let url = config
+            .notification_url
+            .as_ref()
+            .ok_or_else(|| ErrorKind::CommitCloudConfigError("undefined 'notification_url'"))?;
+        Ok(Url::parse(url)?)
+    };
+    Ok(match config.notification_transport.as_str() {
+        "sse" => Box::new(SseTransport {
+            notification_url: notification_url()?,
+            backoff,
+        }),
+        "longpoll" => Box::new(LongPollTransport {
+            notification_url: notification_url()?,
+            backoff,
+        }),
+        "file" => Box::new(FileTransport {
+            notification_dir: config
+                .notification_dir
+                .clone()
+                .ok_or_else(|| ErrorKind::CommitCloudConfigError("undefined 'notification_dir'"))?,
+            poll_interval: Duration::from_secs(1),
+        }),
+        _ => {
+            return Err(ErrorKind::CommitCloudConfigError(
+                "unknown 'notification_transport', expected 'sse', 'longpoll' or 'file'",
+            )
+            .into());
+        }
+    })
+}
+
+/// Url of a subscription with the query parameters expected by the Commit Cloud endpoints
+fn subscription_url(url: &Url, subscription: &Subscription, access_token: Option<&Token>) -> Url {
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .append_pair("workspace", &subscription.workspace)
+        .append_pair("repo_name", &subscription.repo_name);
+    if let Some(access_token) = access_token {
+        url.query_pairs_mut()
+            .append_pair("access_token", &access_token.token)
+            .append_pair("token_type", &access_token.token_type.to_string());
+    }
+    url
+}
+
+/// Exponential backoff between reconnection attempts
+#[derive(Clone)]
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(max: Duration) -> Backoff {
+        let initial = Duration::from_secs(1).min(max);
+        Backoff {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// Delay before the next attempt, doubled for every failed attempt
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub(crate) fn wait(&mut self) {
+        thread::sleep(self.next_delay());
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+/// HTTP Server-Sent Events
+/// The eventsource client reconnects by itself, the backoff spaces out
+/// the attempts while the endpoint keeps failing
+pub struct SseTransport {
+    notification_url: Url,
+    backoff: Backoff,
+}
+
+impl Transport for SseTransport {
+    fn name(&self) -> &'static str {
+        "sse"
+    }
+
+    fn subscribe(
+        &self,
+        subscription: &Subscription,
+        access_token: Option<&Token>,
+    ) -> Result<EventStream> {
+        let client = EventSourceClient::new(subscription_url(
+            &self.notification_url,
+            subscription,
+            access_token,
+        ));
+        let mut backoff = self.backoff.clone();
+        Ok(Box::new(client.map(move |event| match event {
+            Ok(event) => {
+                backoff.reset();
+                Ok(event.data)
+            }
+            Err(e) => {
+                backoff.wait();
+                Err(ErrorKind::CommitCloudHttpError(format!("{}", e)).into())
+            }
+        })))
+    }
+}
+
+/// HTTP long polling
+/// Every request waits until the workspace version is newer than the last
+/// seen one, and the endpoint replies with the json of the notification,
+/// or replies with no content when the request times out
+pub struct LongPollTransport {
+    notification_url: Url,
+    backoff: Backoff,
+}
+
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(120);
+
+impl Transport for LongPollTransport {
+    fn name(&self) -> &'static str {
+        "longpoll"
+    }
+
+    fn subscribe(
+        &self,
+        subscription: &Subscription,
+        access_token: Option<&Token>,
+    ) -> Result<EventStream> {
+        let client = reqwest::Client::builder()
+            .timeout(LONG_POLL_TIMEOUT)
+            .build()?;
+        let url = subscription_url(&self.notification_url, subscription, access_token);
+        let mut backoff = self.backoff.clone();
+        let mut version: Option<u64> = None;
+        Ok(Box::new((0..).map(move |_| {
+            let mut url = url.clone();
+            if let Some(version) = version {
+                url.query_pairs_mut()
+                    .append_pair("version", &version.to_string());
+            }
+            let result = client
+                .get(url)
+                .send()
+                .map_err(|e| ErrorKind::CommitCloudHttpError(format!("{}", e)).into())
+                .and_then(|mut response| match response.status() {
+                    StatusCode::NO_CONTENT => Ok(String::new()),
+                    status if status.is_success() => Ok(response.text()?),
+                    status => Err(ErrorKind::CommitCloudHttpError(format!("{}", status)).into()),
+                });
+            match result {
+                Ok(data) => {
+                    backoff.reset();
+                    if let Ok(notification) = serde_json::from_str::<Notification>(&data) {
+                        version = Some(notification.version);
+                    }
+                    Ok(data)
+                }
+                Err(e) => {
+                    backoff.wait();
+                    Err(e)
+                }
+            }
+        })))
+    }
+}
+
+/// Local file watch, for tests and setups without a notification service
+/// Notifications are json lines appended to
+/// `<notification_dir>/<repo_name>/<workspace>`
+/// Only lines appended after subscribing are delivered
+pub struct FileTransport {
+    pub(crate) notification_dir: PathBuf,
+    pub(crate) poll_interval: Duration,
+}
+
+impl Transport for FileTransport {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn needs_access_token(&self) -> bool {
+        false
+    }
+
+    fn subscribe(
+        &self,
+        subscription: &Subscription,
+        _access_token: Option<&Token>,
+    ) -> Result<EventStream> {
+        let path = self
+            .notification_dir
+            .join(&subscription.repo_name)
+            .join(&subscription.workspace);
+        let offset = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        Ok(Box::new(FileEvents {
+            path,
+            offset,
+            pending: Vec::new(),
+            poll_interval: self.poll_interval,
+        }))
+    }
+}
+
+struct FileEvents {
+    path: PathBuf,
+    /// position of the first byte not read yet
+    offset: u64,
+    /// lines read but not delivered yet, last one first
+    pending: Vec<String>,
+    poll_interval: Duration,
+}
+
+impl FileEvents {
+    /// Read complete lines appended since the last read
+    fn read_lines(&mut self) -> io::Result<Vec<String>> {
+        let mut file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        if file.metadata()?.len() < self.offset {
+            // truncated, start over
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let complete = match buf.iter().rposition(|&b| b == b'\n') {
+            Some(pos) => pos + 1,
+            None => return Ok(Vec::new()),
+        };
+        self.offset += complete as u64;
+        Ok(String::from_utf8_lossy(&buf[..complete])
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+}
+
+impl Iterator for FileEvents {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(line) = self.pending.pop() {
+            return Some(Ok(line));
+        }
+        thread::sleep(self.poll_interval);
+        match self.read_lines() {
+            Ok(mut lines) => {
+                if lines.is_empty() {
+                    // alive
+                    return Some(Ok(String::new()));
+                }
+                lines.reverse();
+                self.pending = lines;
+                self.pending.pop().map(Ok)
+            }
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
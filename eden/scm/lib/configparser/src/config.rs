@@ -416,6 +416,85 @@ impl ConfigSet {
         result
     }
 
+    /// Return a copy of this `ConfigSet` that only keeps values for which `predicate` returns
+    /// `true`. Config items without remaining values are dropped.
+    ///
+    /// This is useful to answer "what would the config be without a given layer", for example
+    /// without values set by `--config`, or loaded from a given file.
+    pub fn filter_sources(&self, predicate: impl Fn(&ValueSource) -> bool) -> ConfigSet {
+        let mut result = ConfigSet::new();
+        for (sname, section) in self.sections.iter() {
+            for (kname, values) in section.items.iter() {
+                let values: Vec<ValueSource> =
+                    values.iter().filter(|v| predicate(v)).cloned().collect();
+                if !values.is_empty() {
+                    result
+                        .sections
+                        .entry(sname.clone())
+                        .or_insert_with(Default::default)
+                        .items
+                        .insert(kname.clone(), values);
+                }
+            }
+        }
+        result
+    }
+
+    /// Append values from `other`, as if they were loaded after the values in this `ConfigSet`.
+    ///
+    /// Together with `filter_sources`, this can insert a layer between existing layers.
+    pub fn extend(&mut self, other: ConfigSet) {
+        for (sname, section) in other.sections {
+            let target = self.sections.entry(sname).or_insert_with(Default::default);
+            for (kname, mut values) in section.items {
+                target
+                    .items
+                    .entry(kname)
+                    .or_insert_with(Vec::new)
+                    .append(&mut values);
+            }
+        }
+    }
+
+    /// Compare effective values with `other`. Return config items whose values differ, in the
+    /// order of sections and names in `self`, followed by items that only exist in `other`.
+    ///
+    /// An unset config item (`%unset`) is considered equal to a config item that was never set.
+    pub fn diff(&self, other: &ConfigSet) -> Vec<ConfigDiff> {
+        let mut result = Vec::new();
+        let mut visit = |section: &Text, name: &Text| {
+            let old = self.get_sources(section, name).pop();
+            let new = other.get_sources(section, name).pop();
+            let old_value = old.as_ref().and_then(|v| v.value().clone());
+            let new_value = new.as_ref().and_then(|v| v.value().clone());
+            if old_value != new_value {
+                result.push(ConfigDiff {
+                    section: section.clone(),
+                    name: name.clone(),
+                    old,
+                    new,
+                });
+            }
+        };
+        for (sname, section) in self.sections.iter() {
+            for kname in section.items.keys() {
+                visit(sname, kname);
+            }
+        }
+        for (sname, section) in other.sections.iter() {
+            for kname in section.items.keys() {
+                let exists = self
+                    .sections
+                    .get(sname)
+                    .map_or(false, |s| s.items.contains_key(kname));
+                if !exists {
+                    visit(sname, kname);
+                }
+            }
+        }
+        result
+    }
+
     /// Ensures that every value set by `superset_location` matches the final value set by one of
     /// `subset_locations`. This is used during config migrations to ensure the final config
     /// location contains the exact same configs as the original locations.
@@ -511,6 +590,18 @@ impl ConfigSet {
     }
 }
 
+/// A config item that has different effective values in two `ConfigSet`s.
+/// See `ConfigSet::diff`.
+#[derive(Clone, Debug)]
+pub struct ConfigDiff {
+    pub section: Text,
+    pub name: Text,
+    /// The effective value in the first `ConfigSet`, or `None` if it was never set.
+    pub old: Option<ValueSource>,
+    /// The effective value in the second `ConfigSet`, or `None` if it was never set.
+    pub new: Option<ValueSource>,
+}
+
 impl ValueSource {
     /// Return the actual value stored in this config value, or `None` if uset.
     pub fn value(&self) -> &Option<Text> {
@@ -541,6 +632,18 @@ impl ValueSource {
             None => None,
         }
     }
+
+    /// Return the 1-based line number of the config value in its file.
+    /// Or `None` if there is no such information.
+    pub fn line_number(&self) -> Option<usize> {
+        match self.location {
+            Some(ref src) => {
+                let prefix = &src.content.as_bytes()[..src.location.start];
+                Some(prefix.iter().filter(|&&b| b == b'\n').count() + 1)
+            }
+            None => None,
+        }
+    }
 }
 
 impl Options {
@@ -660,6 +763,79 @@ pub(crate) mod tests {
         assert_eq!(sources[1].file_content(), None);
     }
 
+    #[test]
+    fn test_filter_sources() {
+        let mut cfg = ConfigSet::new();
+        cfg.set("x", "a", Some("1"), &"user".into());
+        cfg.set("x", "a", Some("2"), &"--config".into());
+        cfg.set("x", "b", Some("3"), &"--config".into());
+        cfg.set("y", "c", Some("4"), &"user".into());
+
+        let filtered = cfg.filter_sources(|v| v.source().as_ref() != "--config");
+        assert_eq!(filtered.sections(), vec![Text::from("x"), Text::from("y")]);
+        assert_eq!(filtered.keys("x"), vec![Text::from("a")]);
+        assert_eq!(filtered.get("x", "a"), Some(Text::from("1")));
+        assert_eq!(filtered.get("y", "c"), Some(Text::from("4")));
+        assert_eq!(cfg.get("x", "a"), Some(Text::from("2")));
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut cfg = ConfigSet::new();
+        cfg.set("x", "a", Some("1"), &"user".into());
+        cfg.set("x", "a", Some("2"), &"--config".into());
+
+        let mut extended = cfg.filter_sources(|v| v.source().as_ref() != "--config");
+        extended.set("x", "a", Some("3"), &"dynamic".into());
+        extended.set("y", "b", Some("4"), &"dynamic".into());
+        extended.extend(cfg.filter_sources(|v| v.source().as_ref() == "--config"));
+
+        let sources: Vec<String> = extended
+            .get_sources("x", "a")
+            .iter()
+            .map(|v| v.source().to_string())
+            .collect();
+        assert_eq!(sources, vec!["user", "dynamic", "--config"]);
+        assert_eq!(extended.get("x", "a"), Some(Text::from("2")));
+        assert_eq!(extended.get("y", "b"), Some(Text::from("4")));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut cfg1 = ConfigSet::new();
+        cfg1.set("x", "a", Some("1"), &"set1".into());
+        cfg1.set("x", "b", Some("2"), &"set1".into());
+        cfg1.set("x", "c", Some("3"), &"set1".into());
+        cfg1.set("x", "d", None::<Text>, &"set1".into());
+
+        let mut cfg2 = cfg1.clone();
+        cfg2.set("x", "a", Some("1"), &"set2".into());
+        cfg2.set("x", "b", Some("5"), &"set2".into());
+        cfg2.set("x", "c", None::<Text>, &"set2".into());
+        cfg2.set("y", "e", Some("6"), &"set2".into());
+
+        let diff: Vec<_> = cfg1
+            .diff(&cfg2)
+            .into_iter()
+            .map(|d| {
+                (
+                    format!("{}.{}", d.section.as_ref(), d.name.as_ref()),
+                    d.old.and_then(|v| v.value().clone()),
+                    d.new.and_then(|v| v.value().clone()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            diff,
+            vec![
+                ("x.b".to_string(), Some("2".into()), Some("5".into())),
+                ("x.c".to_string(), Some("3".into()), None),
+                ("y.e".to_string(), None, Some("6".into())),
+            ]
+        );
+        assert!(cfg2.diff(&cfg2).is_empty());
+    }
+
     #[test]
     fn test_clone() {
         let mut cfg = ConfigSet::new();
@@ -912,6 +1088,8 @@ pub(crate) mod tests {
         assert_eq!(sources.len(), 2);
         assert_eq!(sources[0].location().unwrap(), (PathBuf::new(), 8..9));
         assert_eq!(sources[1].location().unwrap(), (PathBuf::new(), 26..35));
+        assert_eq!(sources[0].line_number(), Some(2));
+        assert_eq!(sources[1].line_number(), Some(5));
     }
 
     #[test]
@@ -71,6 +71,7 @@ pub mod config;
 pub mod error;
 pub mod hg;
 pub mod parser;
+pub mod schema;
 
 pub use error::Error;
 
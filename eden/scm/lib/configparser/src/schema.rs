@@ -0,0 +1,555 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Declarative description of known config items.
+//!
+//! A [`Schema`] lists config items with their types, defaults, allowed values
+//! and help text. It can validate a [`ConfigSet`] to find typos in config
+//! names and values that cannot be parsed, and render reference
+//! documentation.
+//!
+//! ```
+//! use configparser::config::ConfigSet;
+//! use configparser::schema::{ConfigItem, Schema, ValueType};
+//!
+//! let mut schema = Schema::new();
+//! schema.register(
+//!     ConfigItem::new("edenapi", "url", ValueType::String).help("Base URL of the EdenAPI server."),
+//! );
+//!
+//! let mut cfg = ConfigSet::new();
+//! cfg.parse("[edenapi]\nurll = https://example.com\n", &"user".into());
+//! let issues = schema.validate(&cfg);
+//! assert_eq!(issues.len(), 1);
+//! assert_eq!(issues[0].to_string(), "edenapi.urll (user): unknown config");
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use minibytes::Text;
+
+use crate::config::{ConfigSet, ValueSource};
+use crate::hg::{ByteCount, FromConfigValue};
+
+/// Type of a config value. Values are checked using the same conversion as
+/// [`ConfigSetHgExt::get_opt`](crate::hg::ConfigSetHgExt::get_opt).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    String,
+    ByteCount,
+    Path,
+    List,
+}
+
+impl ValueType {
+    /// Check if `value` can be converted to this type.
+    pub fn check(self, value: &str) -> Result<()> {
+        match self {
+            ValueType::Bool => bool::try_from_str(value).map(|_| ()),
+            ValueType::Int => i64::try_from_str(value).map(|_| ()),
+            ValueType::Float => f64::try_from_str(value).map(|_| ()),
+            ValueType::ByteCount => ByteCount::try_from_str(value).map(|_| ()),
+            ValueType::Path => PathBuf::try_from_str(value).map(|_| ()),
+            ValueType::String | ValueType::List => Ok(()),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValueType::Bool => "bool",
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::String => "string",
+            ValueType::ByteCount => "bytes",
+            ValueType::Path => "path",
+            ValueType::List => "list",
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Description of a single config item.
+#[derive(Clone, Debug)]
+pub struct ConfigItem {
+    section: Text,
+    name: Text,
+    value_type: ValueType,
+    default: Option<Text>,
+    allowed: Option<Vec<Text>>,
+    help: Text,
+}
+
+impl ConfigItem {
+    /// Describe `section.name` with the given type.
+    pub fn new(section: impl Into<Text>, name: impl Into<Text>, value_type: ValueType) -> Self {
+        ConfigItem {
+            section: section.into(),
+            name: name.into(),
+            value_type,
+            default: None,
+            allowed: None,
+            help: Text::new(),
+        }
+    }
+
+    /// Set the default value, as it would be written in a config file.
+    pub fn default(mut self, default: impl Into<Text>) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    /// Restrict the value to one of `allowed`. For `ValueType::List`, each
+    /// list item is checked.
+    pub fn allowed<S: Into<Text>>(mut self, allowed: impl IntoIterator<Item = S>) -> Self {
+        self.allowed = Some(allowed.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the help text.
+    pub fn help(mut self, help: impl Into<Text>) -> Self {
+        self.help = help.into();
+        self
+    }
+
+    pub fn section(&self) -> &Text {
+        &self.section
+    }
+
+    pub fn name(&self) -> &Text {
+        &self.name
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    pub fn default_value(&self) -> Option<&Text> {
+        self.default.as_ref()
+    }
+
+    pub fn allowed_values(&self) -> Option<&[Text]> {
+        self.allowed.as_deref()
+    }
+
+    pub fn help_text(&self) -> &Text {
+        &self.help
+    }
+
+    /// Check a value against the type and allowed values.
+    /// Return a human-readable reason if the value is invalid.
+    pub fn check(&self, value: &str) -> Option<String> {
+        if let Err(err) = self.value_type.check(value) {
+            return Some(format!(
+                "cannot parse {:?} as {}: {}",
+                value, self.value_type, err
+            ));
+        }
+        if let Some(allowed) = &self.allowed {
+            let values = match self.value_type {
+                ValueType::List => crate::hg::parse_list(value),
+                _ => vec![Text::copy_from_slice(value.trim())],
+            };
+            for value in values {
+                if !allowed.contains(&value) {
+                    let allowed: Vec<&str> = allowed.iter().map(|s| s.as_ref()).collect();
+                    return Some(format!(
+                        "{:?} is not one of: {}",
+                        value.as_ref(),
+                        allowed.join(", ")
+                    ));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Registry of known config items.
+#[derive(Clone, Default, Debug)]
+pub struct Schema {
+    items: IndexMap<(Text, Text), ConfigItem>,
+    section_help: IndexMap<Text, Text>,
+    free_form_sections: HashSet<Text>,
+}
+
+/// Kind of a problem found by [`Schema::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The section is known, but the name is not.
+    UnknownConfig,
+    /// The value does not match the type or allowed values.
+    InvalidValue(String),
+}
+
+/// A problem found by [`Schema::validate`].
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub section: Text,
+    pub name: Text,
+    pub kind: IssueKind,
+    /// Where the offending value was set.
+    pub source: ValueSource,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{} ", self.section.as_ref(), self.name.as_ref())?;
+        match (self.source.location(), self.source.line_number()) {
+            (Some((path, _)), Some(line)) if !path.as_os_str().is_empty() => {
+                write!(f, "({}:{})", path.display(), line)?
+            }
+            _ => write!(f, "({})", self.source.source().as_ref())?,
+        }
+        match &self.kind {
+            IssueKind::UnknownConfig => write!(f, ": unknown config"),
+            IssueKind::InvalidValue(reason) => write!(f, ": {}", reason),
+        }
+    }
+}
+
+impl Schema {
+    /// Return an empty `Schema`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Return the schema of config items known to this crate. It is used to
+    /// validate the config when it is loaded.
+    ///
+    /// Only register sections whose items are all listed, since other names
+    /// in a registered section are reported as unknown.
+    pub fn builtin() -> Self {
+        let mut schema = Schema::new();
+        schema
+            .section_help(
+                "edenapi",
+                "Settings of the EdenAPI HTTP data fetching protocol.",
+            )
+            .register(
+                ConfigItem::new("edenapi", "enabled", ValueType::Bool)
+                    .default("false")
+                    .help("Fetch files and trees using EdenAPI."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "debug", ValueType::Bool)
+                    .default("false")
+                    .help("Print debug information about EdenAPI requests."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "url", ValueType::String)
+                    .help("Base URL of the EdenAPI server."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "databatchsize", ValueType::Int)
+                    .help("Maximum number of files fetched in one request."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "historybatchsize", ValueType::Int)
+                    .help("Maximum number of history entries fetched in one request."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "maxretries", ValueType::Int)
+                    .default("3")
+                    .help("Number of times a failed request is retried."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "validate", ValueType::Bool)
+                    .default("true")
+                    .help("Check the hashes of fetched data."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "streamdata", ValueType::Bool)
+                    .default("false")
+                    .help("Use streaming responses for file data."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "streamhistory", ValueType::Bool)
+                    .default("false")
+                    .help("Use streaming responses for history."),
+            )
+            .register(
+                ConfigItem::new("edenapi", "streamtrees", ValueType::Bool)
+                    .default("false")
+                    .help("Use streaming responses for trees."),
+            );
+        schema
+    }
+
+    /// Register a config item. Registering the same `section.name` again
+    /// replaces the previous definition.
+    pub fn register(&mut self, item: ConfigItem) -> &mut Self {
+        let key = (item.section.clone(), item.name.clone());
+        self.items.insert(key, item);
+        self
+    }
+
+    /// Set the help text of a section. Used by `generate_docs`.
+    pub fn section_help(&mut self, section: impl Into<Text>, help: impl Into<Text>) -> &mut Self {
+        self.section_help.insert(section.into(), help.into());
+        self
+    }
+
+    /// Mark a section as free-form, like `[alias]` or `[paths]`. Names in
+    /// free-form sections are chosen by users and are never reported as
+    /// unknown.
+    pub fn free_form_section(&mut self, section: impl Into<Text>) -> &mut Self {
+        self.free_form_sections.insert(section.into());
+        self
+    }
+
+    /// Look up a config item.
+    pub fn get(&self, section: &str, name: &str) -> Option<&ConfigItem> {
+        self.items
+            .get(&(Text::copy_from_slice(section), Text::copy_from_slice(name)))
+    }
+
+    /// Iterate through registered config items in registration order.
+    pub fn items(&self) -> impl Iterator<Item = &ConfigItem> {
+        self.items.values()
+    }
+
+    /// Check config values in `config` against the schema.
+    ///
+    /// Only sections with at least one registered item are checked, since
+    /// many sections are owned by code that does not register a schema.
+    /// Every value that was set is checked, including overridden ones, so
+    /// problems in a config file are reported even if another file wins.
+    pub fn validate(&self, config: &ConfigSet) -> Vec<Issue> {
+        let known_sections: HashSet<&Text> = self.items.keys().map(|(s, _)| s).collect();
+        let mut issues = Vec::new();
+        for section in config.sections() {
+            if !known_sections.contains(&section) || self.free_form_sections.contains(&section) {
+                continue;
+            }
+            for name in config.keys(&section) {
+                let item = self.get(&section, &name);
+                for source in config.get_sources(&section, &name) {
+                    let kind = match (item, source.value()) {
+                        (None, _) => Some(IssueKind::UnknownConfig),
+                        (Some(item), Some(value)) => item.check(value).map(IssueKind::InvalidValue),
+                        (Some(_), None) => None,
+                    };
+                    if let Some(kind) = kind {
+                        issues.push(Issue {
+                            section: section.clone(),
+                            name: name.clone(),
+                            kind,
+                            source,
+                        });
+                    }
+                }
+            }
+        }
+        issues
+    }
+
+    /// Render reference documentation for registered items, in the
+    /// reStructuredText style used by `hg help config`.
+    pub fn generate_docs(&self) -> String {
+        let mut sections: IndexMap<&Text, Vec<&ConfigItem>> = IndexMap::new();
+        for item in self.items.values() {
+            sections.entry(&item.section).or_default().push(item);
+        }
+        sections.sort_keys();
+
+        let mut result = String::new();
+        for (section, mut items) in sections {
+            items.sort_by(|a, b| a.name.cmp(&b.name));
+            let title = format!("``{}``", section.as_ref());
+            result.push_str(&title);
+            result.push('\n');
+            result.push_str(&"-".repeat(title.len()));
+            result.push_str("\n\n");
+            if let Some(help) = self.section_help.get(section) {
+                result.push_str(help);
+                result.push_str("\n\n");
+            }
+            for item in items {
+                result.push_str(&format!("``{}``\n", item.name.as_ref()));
+                if !item.help.is_empty() {
+                    for line in item.help.lines() {
+                        push_indented(&mut result, line);
+                    }
+                    result.push('\n');
+                }
+                let mut facts = vec![format!("Type: {}.", item.value_type)];
+                if let Some(allowed) = &item.allowed {
+                    let allowed: Vec<String> = allowed
+                        .iter()
+                        .map(|v| format!("``{}``", v.as_ref()))
+                        .collect();
+                    facts.push(format!("Allowed: {}.", allowed.join(", ")));
+                }
+                if let Some(default) = &item.default {
+                    facts.push(format!("Default: ``{}``.", default.as_ref()));
+                }
+                push_indented(&mut result, &facts.join(" "));
+                result.push('\n');
+            }
+        }
+        result
+    }
+}
+
+fn push_indented(out: &mut String, line: &str) {
+    if !line.is_empty() {
+        out.push_str("    ");
+        out.push_str(line);
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        let mut schema = Schema::new();
+        schema
+            .register(ConfigItem::new("ui", "merge", ValueType::String).help("Merge tool to use."))
+            .register(
+                ConfigItem::new("ui", "paginate", ValueType::Bool)
+                    .default("true")
+                    .help("Use a pager."),
+            )
+            .register(
+                ConfigItem::new("diff", "algorithm", ValueType::String)
+                    .allowed(vec!["myers", "histogram"]),
+            )
+            .register(ConfigItem::new(
+                "remotefilelog",
+                "cachelimit",
+                ValueType::ByteCount,
+            ))
+            .register(ConfigItem::new("alias", "ll", ValueType::String))
+            .free_form_section("alias");
+        schema
+    }
+
+    #[test]
+    fn test_check_value() {
+        let schema = schema();
+        let item = schema.get("ui", "paginate").unwrap();
+        assert_eq!(item.check("yes"), None);
+        assert!(item.check("maybe").is_some());
+
+        let item = schema.get("diff", "algorithm").unwrap();
+        assert_eq!(item.check("histogram"), None);
+        assert_eq!(
+            item.check("patience"),
+            Some("\"patience\" is not one of: myers, histogram".to_string())
+        );
+
+        let item = schema.get("remotefilelog", "cachelimit").unwrap();
+        assert_eq!(item.check("10 GB"), None);
+        assert!(item.check("lots").is_some());
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = schema();
+        let mut cfg = ConfigSet::new();
+        cfg.parse(
+            "[ui]\npaginate = nope\nmerge = :merge3\nmerg = internal:merge\n\
+             [alias]\nfoo = log\n\
+             [unregistered]\nx = y\n",
+            &"test".into(),
+        );
+        cfg.set("ui", "paginate", Some("false"), &"--config".into());
+
+        let issues: Vec<String> = schema
+            .validate(&cfg)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "ui.paginate (test): cannot parse \"nope\" as bool: invalid bool: nope",
+                "ui.merg (test): unknown config",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builtin() {
+        let schema = Schema::builtin();
+        let mut cfg = ConfigSet::new();
+        cfg.parse(
+            "[edenapi]\nurl = https://example.com\nurll = https://example.com\n\
+             validate = maybe\n",
+            &"user".into(),
+        );
+        let issues: Vec<String> = schema
+            .validate(&cfg)
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "edenapi.urll (user): unknown config",
+                "edenapi.validate (user): cannot parse \"maybe\" as bool: invalid bool: maybe",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_docs() {
+        let mut schema = schema();
+        schema.section_help("ui", "User interface controls.");
+        assert_eq!(
+            schema.generate_docs(),
+            r#"``alias``
+---------
+
+``ll``
+    Type: string.
+
+``diff``
+--------
+
+``algorithm``
+    Type: string. Allowed: ``myers``, ``histogram``.
+
+``remotefilelog``
+-----------------
+
+``cachelimit``
+    Type: bytes.
+
+``ui``
+------
+
+User interface controls.
+
+``merge``
+    Merge tool to use.
+
+    Type: string.
+
+``paginate``
+    Use a pager.
+
+    Type: bool. Default: ``true``.
+
+"#
+        );
+    }
+}
@@ -18,15 +18,18 @@ use anyhow::Error;
 use anyhow::{anyhow, bail, Result};
 use hostname;
 use minibytes::Text;
+use serde::Deserialize;
 
 use configparser::config::ConfigSet;
 use hgtime::HgTime;
 
 #[cfg(feature = "fb")]
 mod fb;
+pub mod rules;
 
 #[cfg(feature = "fb")]
 use fb::Repo;
+pub use rules::Rules;
 
 #[cfg(not(feature = "fb"))]
 #[derive(Clone, Debug, PartialEq)]
@@ -69,7 +72,8 @@ impl HgGroup {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Platform {
     Centos,
     Fedora,
@@ -79,7 +83,8 @@ pub enum Platform {
     Windows,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Domain {
     Corp,
     Prod,
@@ -88,16 +93,22 @@ pub enum Domain {
 pub struct Generator {
     tiers: HashSet<String>,
     repo: Repo,
+    repo_name: String,
+    user: String,
     group: HgGroup,
     shard: u8,
     config: ConfigSet,
     platform: Platform,
     domain: Domain,
+    rules: Option<Rules>,
 }
 
 impl Generator {
     pub fn new(repo_name: String) -> Result<Self> {
         let repo = Repo::from_str(&repo_name)?;
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_default();
 
         let tiers: HashSet<String> = if Path::new("/etc/smc.tiers").exists() {
             fs::read_to_string("/etc/smc.tiers")?
@@ -133,14 +144,23 @@ impl Generator {
         Ok(Generator {
             tiers,
             repo,
+            repo_name,
+            user,
             group,
             shard,
             config: ConfigSet::new(),
             platform,
             domain,
+            rules: None,
         })
     }
 
+    /// Also apply rules loaded from a data file, after the built-in rules.
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
     pub(crate) fn group(&self) -> HgGroup {
         self.group
     }
@@ -149,6 +169,14 @@ impl Generator {
         &self.repo
     }
 
+    pub fn repo_name(&self) -> &str {
+        &self.repo_name
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
     pub fn in_repos(&self, repos: &[Repo]) -> bool {
         repos.iter().any(|r| r == self.repo)
     }
@@ -160,6 +188,11 @@ impl Generator {
         self.shard = shard;
     }
 
+    #[cfg(test)]
+    fn set_user(&mut self, user: impl ToString) {
+        self.user = user.to_string();
+    }
+
     #[allow(dead_code)]
     pub fn in_tier(&self, tier: impl AsRef<str>) -> bool {
         self.in_tiers(&[tier])
@@ -237,14 +270,31 @@ impl Generator {
         Ok(())
     }
 
-    pub fn execute(mut self) -> Result<ConfigSet> {
+    pub fn execute(self) -> Result<ConfigSet> {
+        Ok(self.execute_with_report()?.0)
+    }
+
+    /// Like `execute`, but also return the names of data file rules that
+    /// matched.
+    pub fn execute_with_report(mut self) -> Result<(ConfigSet, Vec<String>)> {
         if std::env::var("HG_TEST_DYNAMICCONFIG").is_ok() {
             self._execute(test_rules)?;
         } else {
             #[cfg(feature = "fb")]
             self._execute(fb::fb_rules)?;
         }
-        Ok(self.config)
+
+        let mut matched = Vec::new();
+        if let Some(rules) = self.rules.take() {
+            let now = HgTime::now().ok_or_else(|| anyhow!("invalid HgTime::now()"))?;
+            for rule in rules.rules() {
+                if rule.matches(&self, now)? {
+                    rule.apply(&mut self);
+                    matched.push(rule.name.clone());
+                }
+            }
+        }
+        Ok((self.config, matched))
     }
 
     fn _execute(&mut self, mut rules: impl FnMut(&mut Generator) -> Result<()>) -> Result<()> {
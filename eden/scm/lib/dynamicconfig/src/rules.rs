@@ -0,0 +1,358 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Dynamic config rules loaded from data files.
+//!
+//! Rules let config rollouts be staged without rebuilding the binary. A rule
+//! file is TOML (or JSON, if the file name ends with `.json`):
+//!
+//! ```toml
+//! [[rule]]
+//! name = "new-checkout"
+//! repos = ["fbsource"]
+//! platforms = ["centos", "osx"]
+//! percentage = 20
+//! start = "2020-04-01"
+//! end = "2020-05-01"
+//!
+//! [rule.config.checkout]
+//! use-rust = "true"
+//! ```
+//!
+//! A rule matches if all of its conditions match. Conditions that are not
+//! specified always match. Rules are applied in file order, so later rules
+//! override earlier ones.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use hgtime::HgTime;
+use serde::Deserialize;
+
+use crate::{Domain, Generator, Platform};
+
+/// A list of rules loaded from a data file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+/// A set of conditions and the config to apply if all of them match.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Name of the rule. Also used to seed the percentage hash.
+    pub name: String,
+
+    /// Repo names. Empty means all repos.
+    #[serde(default)]
+    pub repos: Vec<String>,
+
+    /// Host tiers. Empty means all hosts.
+    #[serde(default)]
+    pub tiers: Vec<String>,
+
+    /// Platforms. Empty means all platforms.
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
+
+    /// Domains. Empty means all domains.
+    #[serde(default)]
+    pub domains: Vec<Domain>,
+
+    /// Percentage (0 to 100) of users that match, decided by a stable hash
+    /// of the rule name and the user name.
+    pub percentage: Option<u32>,
+
+    /// Time the rule becomes active, in any format `HgTime::parse` accepts.
+    pub start: Option<String>,
+
+    /// Time the rule stops being active.
+    pub end: Option<String>,
+
+    /// Config to set, as `section -> name -> value`.
+    #[serde(default)]
+    pub config: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Rules {
+    /// Parse rules from TOML.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Parse rules from JSON.
+    pub fn from_json(text: &str) -> Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Load rules from a file. The format is decided by the file extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let result = if path.extension().map_or(false, |e| e == "json") {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        };
+        result.map_err(|e| anyhow!("cannot parse {}: {}", path.display(), e))
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Check the rules for mistakes that parsing does not catch.
+    ///
+    /// Return a list of human-readable problems. An empty list means the
+    /// rules are valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut names = HashSet::new();
+        for rule in &self.rules {
+            if !names.insert(rule.name.as_str()) {
+                problems.push(format!("rule {:?}: duplicated name", rule.name));
+            }
+            if let Err(e) = rule.check() {
+                problems.push(format!("rule {:?}: {}", rule.name, e));
+            }
+        }
+        problems
+    }
+}
+
+impl Rule {
+    fn check(&self) -> Result<()> {
+        if let Some(percentage) = self.percentage {
+            if percentage > 100 {
+                return Err(anyhow!("percentage {} is over 100", percentage));
+            }
+        }
+        if let (Some(start), Some(end)) = self.time_window()? {
+            if start >= end {
+                return Err(anyhow!("start time is not before end time"));
+            }
+        }
+        if self.config.values().all(|items| items.is_empty()) {
+            return Err(anyhow!("no config is set"));
+        }
+        Ok(())
+    }
+
+    fn time_window(&self) -> Result<(Option<HgTime>, Option<HgTime>)> {
+        let parse = |s: &Option<String>| -> Result<Option<HgTime>> {
+            match s {
+                Some(s) => HgTime::parse(s)
+                    .map(Some)
+                    .ok_or_else(|| anyhow!("invalid time {:?}", s)),
+                None => Ok(None),
+            }
+        };
+        Ok((parse(&self.start)?, parse(&self.end)?))
+    }
+
+    /// Test whether the rule matches the given generator at time `now`.
+    pub(crate) fn matches(&self, gen: &Generator, now: HgTime) -> Result<bool> {
+        if !self.repos.is_empty() && !self.repos.iter().any(|r| r == gen.repo_name()) {
+            return Ok(false);
+        }
+        if !self.tiers.is_empty() && !gen.in_tiers(&self.tiers) {
+            return Ok(false);
+        }
+        if !self.platforms.is_empty() && !self.platforms.contains(&gen.platform()) {
+            return Ok(false);
+        }
+        if !self.domains.is_empty() && !self.domains.contains(&gen.domain()) {
+            return Ok(false);
+        }
+        if let Some(percentage) = self.percentage {
+            if user_bucket(&self.name, gen.user()) >= percentage {
+                return Ok(false);
+            }
+        }
+        let (start, end) = self.time_window()?;
+        if start.map_or(false, |start| now < start) || end.map_or(false, |end| now >= end) {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn apply(&self, gen: &mut Generator) {
+        for (section, items) in &self.config {
+            for (name, value) in items {
+                gen.set_config(section, name, value);
+            }
+        }
+    }
+}
+
+/// Map a user to a bucket in `0..100` for a given rule.
+///
+/// Uses FNV-1a so the result is stable across platforms and releases.
+/// The rule name is part of the input so different rules pick different
+/// subsets of users.
+pub(crate) fn user_bucket(rule_name: &str, user: &str) -> u32 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in rule_name.bytes().chain(Some(b':')).chain(user.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % 100) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HgGroup;
+    use std::iter::FromIterator;
+
+    const RULES: &str = r#"
+[[rule]]
+name = "all"
+[rule.config.a]
+x = "1"
+
+[[rule]]
+name = "tier"
+tiers = ["in_tier1"]
+[rule.config.a]
+y = "2"
+
+[[rule]]
+name = "other-repo"
+repos = ["other"]
+[rule.config.a]
+z = "3"
+
+[[rule]]
+name = "expired"
+start = "2000-01-01"
+end = "2001-01-01"
+[rule.config.b]
+x = "4"
+
+[[rule]]
+name = "override"
+repos = ["test_repo"]
+[rule.config.a]
+x = "5"
+"#;
+
+    fn generator() -> Generator {
+        let mut gen = Generator::new("test_repo".to_string()).unwrap();
+        let tiers = HashSet::from_iter(["in_tier1"].iter().map(|s| s.to_string()));
+        gen.set_inputs(tiers, HgGroup::Stable, 10);
+        gen.set_user("alice");
+        gen
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let rules = Rules::from_toml(RULES).unwrap();
+        assert!(rules.validate().is_empty());
+
+        let (config, matched) = generator().with_rules(rules).execute_with_report().unwrap();
+        assert_eq!(matched, ["all", "tier", "override"]);
+        assert_eq!(config.to_string(), "[a]\nx=5\ny=2\n\n");
+    }
+
+    #[test]
+    fn test_json() {
+        let rules = Rules::from_json(
+            r#"{"rule": [{"name": "j", "platforms": ["osx"], "config": {"a": {"b": "c"}}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(rules.rules()[0].platforms, [Platform::OSX]);
+        assert!(Rules::from_json(r#"{"rule": [{"name": "j", "oops": 1}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let rules = Rules::from_toml(
+            r#"
+[[rule]]
+name = "a"
+percentage = 101
+start = "2001-01-01"
+[rule.config.a]
+x = "1"
+
+[[rule]]
+name = "a"
+start = "2001-01-01"
+end = "2000-01-01"
+[rule.config.a]
+x = "1"
+
+[[rule]]
+name = "b"
+start = "not a time"
+[rule.config.a]
+x = "1"
+
+[[rule]]
+name = "c"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules.validate(),
+            [
+                "rule \"a\": percentage 101 is over 100",
+                "rule \"a\": duplicated name",
+                "rule \"a\": start time is not before end time",
+                "rule \"b\": invalid time \"not a time\"",
+                "rule \"c\": no config is set",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percentage() {
+        // The bucket is deterministic.
+        assert_eq!(user_bucket("r", "alice"), user_bucket("r", "alice"));
+
+        // Roughly the requested fraction of users match.
+        let count = (0..1000)
+            .filter(|i| user_bucket("rollout", &format!("user{}", i)) < 30)
+            .count();
+        assert!(count > 250 && count < 350, "count = {}", count);
+
+        let gen = generator();
+        let mut rule = Rules::from_toml("[[rule]]\nname = \"p\"\n[rule.config.a]\nx = \"1\"")
+            .unwrap()
+            .rules
+            .remove(0);
+        let now = HgTime::now().unwrap();
+        rule.percentage = Some(100);
+        assert!(rule.matches(&gen, now).unwrap());
+        rule.percentage = Some(0);
+        assert!(!rule.matches(&gen, now).unwrap());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let gen = generator();
+        let mut rule = Rules::from_toml(
+            "[[rule]]\nname = \"t\"\nstart = \"2010-01-01\"\nend = \"2011-01-01\"\n[rule.config.a]\nx = \"1\"",
+        )
+        .unwrap()
+        .rules
+        .remove(0);
+        let at = |s: &str| HgTime::parse(s).unwrap();
+        assert!(!rule.matches(&gen, at("2009-12-31")).unwrap());
+        assert!(rule.matches(&gen, at("2010-06-01")).unwrap());
+        assert!(!rule.matches(&gen, at("2011-01-01")).unwrap());
+
+        rule.end = None;
+        assert!(rule.matches(&gen, at("2020-01-01")).unwrap());
+    }
+}
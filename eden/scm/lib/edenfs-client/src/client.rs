@@ -0,0 +1,532 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Typed client for the commonly used EdenFS Thrift calls.
+//!
+//! `EdenFsClient` talks to the EdenFS instance serving one checkout. The connection is made on
+//! the first call and reused by the following ones. A call that times out, or fails because of
+//! the connection, drops the connection so the next call reconnects.
+
+use thrift_types::edenfs as eden;
+
+use anyhow::{bail, Error, Result};
+use eden::client::EdenService;
+use eden::errors::eden_service::{
+    CheckOutRevisionError, DebugGetScmTreeError, GetCurrentJournalPositionError,
+    GetFilesChangedSinceError, GetSHA1Error, GlobFilesError, ResetParentCommitsError, UnmountError,
+};
+use eden::{EdenErrorType, GlobParams, SHA1Result, WorkingDirectoryParents};
+#[cfg(unix)]
+use fbthrift_socket::SocketTransport;
+use std::fmt;
+use std::fs::read_link;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(unix)]
+use thrift_types::fbthrift::binary_protocol::BinaryProtocol;
+use thrift_types::futures::compat::Future01CompatExt;
+use thrift_types::futures::future::{self, BoxFuture, Either, FutureExt, TryFutureExt};
+use tokio_core::reactor::{Core, Handle, Timeout};
+#[cfg(unix)]
+use tokio_uds::UnixStream;
+
+pub use eden::{
+    CheckoutConflict, CheckoutMode, ConflictType, FileDelta, JournalPosition, ScmTreeEntry,
+};
+
+/// Default timeout of a call. Checkouts of large commits can take a while.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Errors of `EdenFsClient` calls.
+#[derive(Debug)]
+pub enum EdenFsError {
+    /// EdenFS received the call and failed it.
+    Eden {
+        method: &'static str,
+        error: eden::EdenError,
+    },
+    /// EdenFS does not know the method, or failed to decode the call.
+    Application {
+        method: &'static str,
+        message: String,
+    },
+    /// The connection failed or was closed.
+    Connection { method: &'static str, error: Error },
+    /// The call did not complete in time.
+    Timeout {
+        method: &'static str,
+        timeout: Duration,
+    },
+}
+
+impl EdenFsError {
+    /// Type of the error reported by EdenFS, like `EdenErrorType::JOURNAL_TRUNCATED`.
+    pub fn error_type(&self) -> Option<EdenErrorType> {
+        match self {
+            EdenFsError::Eden { error, .. } => Some(error.errorType),
+            _ => None,
+        }
+    }
+
+    /// Whether the connection cannot be used anymore.
+    fn drops_connection(&self) -> bool {
+        match self {
+            EdenFsError::Connection { .. } | EdenFsError::Timeout { .. } => true,
+            EdenFsError::Eden { .. } | EdenFsError::Application { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for EdenFsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdenFsError::Eden { method, error } => {
+                write!(f, "EdenFS {} failed: {}", method, error.message)
+            }
+            EdenFsError::Application { method, message } => {
+                write!(f, "EdenFS {} failed: {}", method, message)
+            }
+            EdenFsError::Connection { method, error } => {
+                write!(f, "EdenFS {} failed: {}", method, error)
+            }
+            EdenFsError::Timeout { method, timeout } => write!(
+                f,
+                "EdenFS {} did not complete in {} seconds",
+                method,
+                timeout.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EdenFsError {}
+
+/// Conversion of the error types generated for each Thrift method.
+trait IntoEdenFsError {
+    fn into_edenfs_error(self, method: &'static str) -> EdenFsError;
+}
+
+macro_rules! impl_into_edenfs_error {
+    ( $( $error:ident ),* ) => {
+        $(
+            impl IntoEdenFsError for $error {
+                fn into_edenfs_error(self, method: &'static str) -> EdenFsError {
+                    match self {
+                        $error::ex(error) => EdenFsError::Eden { method, error },
+                        $error::ApplicationException(e) => EdenFsError::Application {
+                            method,
+                            message: format!("{:?}", e),
+                        },
+                        $error::ThriftError(error) => EdenFsError::Connection { method, error },
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_into_edenfs_error!(
+    CheckOutRevisionError,
+    DebugGetScmTreeError,
+    GetCurrentJournalPositionError,
+    GetFilesChangedSinceError,
+    GetSHA1Error,
+    GlobFilesError,
+    ResetParentCommitsError,
+    UnmountError
+);
+
+/// Client of the EdenFS instance serving one checkout.
+pub struct EdenFsClient {
+    /// Mount point as EdenFS knows it, see `from_repo_root`.
+    mount_point: Vec<u8>,
+    /// None if the client was given by `with_client`, which cannot reconnect.
+    socket_path: Option<PathBuf>,
+    timeout: Duration,
+    core: Core,
+    client: Option<Arc<dyn EdenService>>,
+}
+
+impl EdenFsClient {
+    /// Creates a client for the EdenFS checkout at `repo_root`. Fails if `repo_root` is not an
+    /// EdenFS checkout. Connecting is deferred to the first call.
+    pub fn from_repo_root(repo_root: &Path) -> Result<Self> {
+        // Look up the mount point name where Eden thinks this repository is located. This may be
+        // different from repo_root if a parent directory of the Eden mount has been bind mounted
+        // to another location.
+        let dot_eden = repo_root.join(".eden");
+        let mount_point = read_link(dot_eden.join("root"))?;
+        let mount_point = match mount_point.into_os_string().into_string() {
+            Ok(mount_point) => mount_point.into_bytes(),
+            Err(_) => bail!("EdenFS mount point of {:?} is not UTF-8", repo_root),
+        };
+        let socket_path = read_link(dot_eden.join("socket"))?;
+
+        Ok(EdenFsClient {
+            mount_point,
+            socket_path: Some(socket_path),
+            timeout: DEFAULT_TIMEOUT,
+            core: Core::new()?,
+            client: None,
+        })
+    }
+
+    /// Creates a client using an existing Thrift client, like a mock made by
+    /// `<dyn EdenService>::mock()`.
+    pub fn with_client(mount_point: &[u8], client: Arc<dyn EdenService>) -> Result<Self> {
+        Ok(EdenFsClient {
+            mount_point: mount_point.to_vec(),
+            socket_path: None,
+            timeout: DEFAULT_TIMEOUT,
+            core: Core::new()?,
+            client: Some(client),
+        })
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn mount_point(&self) -> &[u8] {
+        &self.mount_point
+    }
+
+    fn connect(&mut self) -> Result<Arc<dyn EdenService>> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let client = match &self.socket_path {
+            Some(socket_path) => connect_socket(socket_path, &self.core.handle())?,
+            None => bail!("EdenFS client is disconnected"),
+        };
+        self.client = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Runs one Thrift call, within the timeout.
+    fn call<T, E, F>(&mut self, method: &'static str, call: F) -> Result<T>
+    where
+        E: IntoEdenFsError,
+        F: FnOnce(&dyn EdenService, &[u8]) -> BoxFuture<'static, std::result::Result<T, E>>,
+    {
+        let client = self
+            .connect()
+            .map_err(|error| EdenFsError::Connection { method, error })?;
+        let request = call(&*client, &self.mount_point);
+        let timeout = Timeout::new(self.timeout, &self.core.handle())?.compat();
+        let outcome = self
+            .core
+            .run(future::select(request, timeout).map(Ok::<_, ()>).compat());
+
+        let result = match outcome {
+            Ok(Either::Left((result, _))) => result.map_err(|e| e.into_edenfs_error(method)),
+            Ok(Either::Right((Ok(()), _))) => Err(EdenFsError::Timeout {
+                method,
+                timeout: self.timeout,
+            }),
+            Ok(Either::Right((Err(e), _))) => Err(EdenFsError::Connection {
+                method,
+                error: e.into(),
+            }),
+            Err(()) => unreachable!(),
+        };
+        if let Err(ref e) = result {
+            if e.drops_connection() && self.socket_path.is_some() {
+                self.client = None;
+            }
+        }
+        result.map_err(Error::from)
+    }
+
+    /// Position of the journal of the checkout. Use it with `files_changed_since` to find the
+    /// files changed between two points in time.
+    pub fn current_journal_position(&mut self) -> Result<JournalPosition> {
+        self.call("getCurrentJournalPosition", |client, mount_point| {
+            client.getCurrentJournalPosition(&mount_point.to_vec())
+        })
+    }
+
+    /// Files changed since `position`. Fails with `EdenErrorType::JOURNAL_TRUNCATED` if EdenFS
+    /// does not remember that far, or `MOUNT_GENERATION_CHANGED` if EdenFS restarted since.
+    pub fn files_changed_since(&mut self, position: &JournalPosition) -> Result<FileDelta> {
+        let position = position.clone();
+        self.call("getFilesChangedSince", move |client, mount_point| {
+            client.getFilesChangedSince(&mount_point.to_vec(), &position)
+        })
+    }
+
+    /// Checks out `commit`, and returns the conflicts. With `CheckoutMode::DRY_RUN`, only the
+    /// conflicts are computed.
+    pub fn checkout(&mut self, commit: &[u8], mode: CheckoutMode) -> Result<Vec<CheckoutConflict>> {
+        let commit = commit.to_vec();
+        self.call("checkOutRevision", move |client, mount_point| {
+            client.checkOutRevision(&mount_point.to_vec(), &commit, &mode)
+        })
+    }
+
+    /// Sets the parents of the working copy without changing any file.
+    pub fn reset_parents(&mut self, p1: &[u8], p2: Option<&[u8]>) -> Result<()> {
+        let parents = WorkingDirectoryParents {
+            parent1: p1.to_vec(),
+            parent2: p2.map(|p2| p2.to_vec()),
+        };
+        self.call("resetParentCommits", move |client, mount_point| {
+            client.resetParentCommits(&mount_point.to_vec(), &parents)
+        })
+    }
+
+    /// Paths of the files matching any of `globs`, relative to the root of the checkout.
+    pub fn glob_files(&mut self, globs: &[String], include_dotfiles: bool) -> Result<Vec<Vec<u8>>> {
+        let globs = globs.to_vec();
+        let glob = self.call("globFiles", move |client, mount_point| {
+            client.globFiles(&GlobParams {
+                mountPoint: mount_point.to_vec(),
+                globs,
+                includeDotfiles: include_dotfiles,
+                ..Default::default()
+            })
+        })?;
+        Ok(glob.matchingFiles)
+    }
+
+    /// SHA-1 of the content of each of the files at `paths`.
+    pub fn get_sha1(&mut self, paths: &[Vec<u8>]) -> Result<Vec<Result<Vec<u8>>>> {
+        let paths = paths.to_vec();
+        let results = self.call("getSHA1", move |client, mount_point| {
+            client.getSHA1(&mount_point.to_vec(), &paths)
+        })?;
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                SHA1Result::sha1(sha1) => Ok(sha1),
+                SHA1Result::error(error) => Err(EdenFsError::Eden {
+                    method: "getSHA1",
+                    error,
+                }
+                .into()),
+                SHA1Result::UnknownField(field) => {
+                    bail!("getSHA1 returned unknown field {}", field)
+                }
+            })
+            .collect())
+    }
+
+    /// Entries of the source control tree `id`, as EdenFS imported it.
+    pub fn debug_get_scm_tree(
+        &mut self,
+        id: &[u8],
+        local_store_only: bool,
+    ) -> Result<Vec<ScmTreeEntry>> {
+        let id = id.to_vec();
+        self.call("debugGetScmTree", move |client, mount_point| {
+            client.debugGetScmTree(&mount_point.to_vec(), &id, local_store_only)
+        })
+    }
+
+    pub fn unmount(&mut self) -> Result<()> {
+        self.call("unmount", |client, mount_point| {
+            client.unmount(&mount_point.to_vec())
+        })
+    }
+}
+
+#[cfg(unix)]
+fn connect_socket(socket_path: &Path, handle: &Handle) -> Result<Arc<dyn EdenService>> {
+    let sock = UnixStream::connect(socket_path, handle)?;
+    let transport = SocketTransport::new(handle, sock);
+    Ok(<dyn EdenService>::new(BinaryProtocol, transport))
+}
+
+#[cfg(not(unix))]
+fn connect_socket(_socket_path: &Path, _handle: &Handle) -> Result<Arc<dyn EdenService>> {
+    bail!("EdenFS Thrift client is not supported on this platform")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::mock::MockEdenFs;
+
+    fn client(mock: &MockEdenFs) -> EdenFsClient {
+        EdenFsClient::from_repo_root(mock.repo_root()).unwrap()
+    }
+
+    #[test]
+    fn test_files_changed_since() {
+        let mock = MockEdenFs::new().unwrap();
+        let _server = mock.serve().unwrap();
+        mock.add_file("a", &[1; 20]);
+        let mut client = client(&mock);
+        assert_eq!(
+            client.mount_point(),
+            mock.repo_root().to_str().unwrap().as_bytes()
+        );
+        let position = client.current_journal_position().unwrap();
+
+        mock.add_file("b", &[2; 20]);
+        mock.modify_file("a", &[3; 20]);
+        let delta = client.files_changed_since(&position).unwrap();
+        assert_eq!(delta.fromPosition, position);
+        assert_eq!(delta.toPosition, client.current_journal_position().unwrap());
+        assert_eq!(delta.createdPaths, vec![b"b".to_vec()]);
+        assert_eq!(delta.changedPaths, vec![b"a".to_vec()]);
+        assert!(delta.removedPaths.is_empty());
+        assert_eq!(mock.connections(), 1);
+    }
+
+    #[test]
+    fn test_checkout_and_reset_parents() {
+        let mock = MockEdenFs::new().unwrap();
+        let _server = mock.serve().unwrap();
+        let conflict = CheckoutConflict {
+            path: b"a".to_vec(),
+            type_: ConflictType::MODIFIED_MODIFIED,
+            message: String::new(),
+        };
+        mock.set_checkout_conflicts(&[2; 20], vec![conflict.clone()]);
+        let mut client = client(&mock);
+
+        let conflicts = client.checkout(&[2; 20], CheckoutMode::DRY_RUN).unwrap();
+        assert_eq!(conflicts, vec![conflict]);
+        assert_eq!(mock.parents(), (vec![0; 20], None));
+
+        client.checkout(&[2; 20], CheckoutMode::FORCE).unwrap();
+        assert_eq!(mock.parents(), (vec![2; 20], None));
+
+        client.reset_parents(&[3; 20], Some(&[4; 20])).unwrap();
+        assert_eq!(mock.parents(), (vec![3; 20], Some(vec![4; 20])));
+    }
+
+    #[test]
+    fn test_glob_and_sha1() {
+        let mock = MockEdenFs::new().unwrap();
+        let _server = mock.serve().unwrap();
+        mock.add_file("a/b.rs", &[1; 20]);
+        mock.add_file("a/c/d.rs", &[2; 20]);
+        mock.add_file("a/.e.rs", &[3; 20]);
+        mock.add_file("f.txt", &[4; 20]);
+        let mut client = client(&mock);
+
+        let files = client.glob_files(&["a/*.rs".to_string()], false).unwrap();
+        assert_eq!(files, vec![b"a/b.rs".to_vec()]);
+        let files = client.glob_files(&["**/*.rs".to_string()], true).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                b"a/.e.rs".to_vec(),
+                b"a/b.rs".to_vec(),
+                b"a/c/d.rs".to_vec()
+            ]
+        );
+
+        let sha1 = client
+            .get_sha1(&[b"f.txt".to_vec(), b"g.txt".to_vec()])
+            .unwrap();
+        assert_eq!(sha1[0].as_ref().unwrap(), &vec![4; 20]);
+        let error = sha1[1].as_ref().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<EdenFsError>().unwrap().error_type(),
+            Some(EdenErrorType::POSIX_ERROR)
+        );
+    }
+
+    #[test]
+    fn test_debug_get_scm_tree_and_unmount() {
+        let mock = MockEdenFs::new().unwrap();
+        let _server = mock.serve().unwrap();
+        let entry = ScmTreeEntry {
+            name: b"a".to_vec(),
+            mode: 0o100644,
+            id: vec![1; 20],
+        };
+        mock.add_tree(&[5; 20], vec![entry.clone()]);
+        let mut client = client(&mock);
+
+        assert_eq!(
+            client.debug_get_scm_tree(&[5; 20], false).unwrap(),
+            vec![entry]
+        );
+        assert!(mock.is_mounted());
+        client.unmount().unwrap();
+        assert!(!mock.is_mounted());
+        assert_eq!(mock.calls(), vec!["debugGetScmTree", "unmount"]);
+    }
+
+    #[test]
+    fn test_eden_error() {
+        let mock = MockEdenFs::new().unwrap();
+        let _server = mock.serve().unwrap();
+        let mut client = client(&mock);
+        let position = client.current_journal_position().unwrap();
+        mock.add_file("a", &[1; 20]);
+        mock.truncate_journal();
+
+        let error = client.files_changed_since(&position).unwrap_err();
+        let error = error.downcast_ref::<EdenFsError>().unwrap();
+        assert_eq!(error.error_type(), Some(EdenErrorType::JOURNAL_TRUNCATED));
+        assert_eq!(
+            error.to_string(),
+            "EdenFS getFilesChangedSince failed: Journal entry range has been truncated."
+        );
+
+        // EdenFS errors keep the connection.
+        client.current_journal_position().unwrap();
+        assert_eq!(mock.connections(), 1);
+    }
+
+    #[test]
+    fn test_not_eden_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(EdenFsClient::from_repo_root(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_timeout_reconnects() {
+        let mock = MockEdenFs::new().unwrap();
+        let _server = mock.serve().unwrap();
+        let mut client = client(&mock).timeout(Duration::from_millis(100));
+        client.current_journal_position().unwrap();
+
+        mock.set_delay(Duration::from_secs(1));
+        let error = client.current_journal_position().unwrap_err();
+        match error.downcast_ref::<EdenFsError>() {
+            Some(EdenFsError::Timeout { .. }) => {}
+            _ => panic!("unexpected error: {}", error),
+        }
+
+        mock.set_delay(Duration::from_secs(0));
+        client.current_journal_position().unwrap();
+        assert_eq!(mock.connections(), 2);
+    }
+
+    #[test]
+    fn test_restart_reconnects() {
+        let mock = MockEdenFs::new().unwrap();
+        let server = mock.serve().unwrap();
+        let mut client = client(&mock).timeout(Duration::from_secs(5));
+        client.current_journal_position().unwrap();
+
+        // Calls fail while EdenFS is down, and the connection is made again once it is back.
+        drop(server);
+        let error = client.current_journal_position().unwrap_err();
+        match error.downcast_ref::<EdenFsError>() {
+            Some(EdenFsError::Connection { .. }) => {}
+            _ => panic!("unexpected error: {}", error),
+        }
+        let error = client.current_journal_position().unwrap_err();
+        match error.downcast_ref::<EdenFsError>() {
+            Some(EdenFsError::Connection { .. }) => {}
+            _ => panic!("unexpected error: {}", error),
+        }
+
+        let _server = mock.serve().unwrap();
+        client.current_journal_position().unwrap();
+        assert_eq!(mock.connections(), 2);
+    }
+}
@@ -7,5 +7,10 @@
 
 //! # Communicating to EdenFS via Thrift
 
+pub mod client;
+#[cfg(all(unix, any(test, feature = "for-tests")))]
+pub mod mock;
 mod path_relativizer;
 pub mod status;
+
+pub use client::{EdenFsClient, EdenFsError};
@@ -0,0 +1,567 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! In-process EdenFS for tests.
+//!
+//! `MockEdenFs` keeps the state of one checkout in memory, and serves the Thrift calls of
+//! `client::EdenFsClient` on a Unix socket, so tests run without an EdenFS daemon but go through
+//! `EdenFsClient::from_repo_root` and the real transport. Tests change the checkout with
+//! `add_file`, `modify_file` and friends, and check the effects of the calls with `parents`,
+//! `is_mounted` and `calls`. `set_delay` and dropping the `MockServer` exercise the timeout and
+//! reconnect paths of the client.
+
+use thrift_types::edenfs as eden;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use eden::server::{EdenService, EdenServiceProcessor};
+use eden::services::eden_service::{
+    CheckOutRevisionExn, DebugGetScmTreeExn, GetCurrentJournalPositionExn, GetFilesChangedSinceExn,
+    GetSHA1Exn, GlobFilesExn, ResetParentCommitsExn, UnmountExn,
+};
+use eden::{
+    BinaryHash, CheckoutConflict, CheckoutMode, EdenError, EdenErrorType, FileDelta, Glob,
+    GlobParams, JournalPosition, PathString, SHA1Result, ScmTreeEntry, WorkingDirectoryParents,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::symlink;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tempfile::TempDir;
+use thrift_types::fb303_core::server::{BaseService, BaseServiceProcessor};
+use thrift_types::fbthrift::binary_protocol::BinaryProtocol;
+use thrift_types::fbthrift::ThriftService;
+use thrift_types::futures::executor::block_on;
+
+const ENOENT: i32 = 2;
+const DT_REG: i16 = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Change {
+    Created,
+    Modified,
+    Removed,
+}
+
+struct State {
+    mount_point: Vec<u8>,
+    mounted: bool,
+    parents: WorkingDirectoryParents,
+    /// SHA-1 of the files of the checkout.
+    files: BTreeMap<Vec<u8>, Vec<u8>>,
+    trees: HashMap<Vec<u8>, Vec<ScmTreeEntry>>,
+    conflicts: HashMap<Vec<u8>, Vec<CheckoutConflict>>,
+    /// Sequence number of the latest journal entry.
+    sequence: i64,
+    /// Sequence number of the latest entry forgotten by `truncate_journal`.
+    truncated: i64,
+    journal: Vec<(i64, Vec<u8>, Change)>,
+    delay: Duration,
+    connections: usize,
+    calls: Vec<&'static str>,
+}
+
+impl State {
+    fn position(&self) -> JournalPosition {
+        JournalPosition {
+            mountGeneration: 1,
+            sequenceNumber: self.sequence,
+            snapshotHash: self.parents.parent1.clone(),
+        }
+    }
+
+    fn record(&mut self, path: &str, change: Change) {
+        self.sequence += 1;
+        let sequence = self.sequence;
+        self.journal
+            .push((sequence, path.as_bytes().to_vec(), change));
+    }
+
+    fn files_changed_since(&self, from: JournalPosition) -> FileDelta {
+        // first and last change of each path
+        let mut changes: BTreeMap<&[u8], (Change, Change)> = BTreeMap::new();
+        for (sequence, path, change) in self.journal.iter() {
+            if *sequence > from.sequenceNumber {
+                changes
+                    .entry(path)
+                    .and_modify(|changes| changes.1 = *change)
+                    .or_insert((*change, *change));
+            }
+        }
+
+        let mut delta = FileDelta {
+            fromPosition: from,
+            toPosition: self.position(),
+            ..Default::default()
+        };
+        for (path, changes) in changes {
+            let path = path.to_vec();
+            match changes {
+                (Change::Created, Change::Removed) => {}
+                (Change::Created, _) => delta.createdPaths.push(path),
+                (_, Change::Removed) => delta.removedPaths.push(path),
+                _ => delta.changedPaths.push(path),
+            }
+        }
+        delta
+    }
+
+    fn glob(&self, globs: &[String], include_dotfiles: bool, want_dtype: bool) -> Glob {
+        let matching: BTreeSet<&Vec<u8>> = self
+            .files
+            .keys()
+            .filter(|path| {
+                globs
+                    .iter()
+                    .any(|glob| glob_match(glob.as_bytes(), path, include_dotfiles))
+            })
+            .collect();
+        Glob {
+            dtypes: if want_dtype {
+                vec![DT_REG; matching.len()]
+            } else {
+                Vec::new()
+            },
+            matchingFiles: matching.into_iter().cloned().collect(),
+        }
+    }
+}
+
+/// Whether `path` matches `glob`. `*` and `?` do not match `/`, and a `**` component matches
+/// any number of directories. Names starting with `.` only match if `include_dotfiles` is true,
+/// or if the glob spells out the `.`.
+fn glob_match(glob: &[u8], path: &[u8], include_dotfiles: bool) -> bool {
+    let glob: Vec<&[u8]> = glob.split(|&b| b == b'/').collect();
+    let path: Vec<&[u8]> = path.split(|&b| b == b'/').collect();
+    match_components(&glob, &path, include_dotfiles)
+}
+
+fn match_components(glob: &[&[u8]], path: &[&[u8]], include_dotfiles: bool) -> bool {
+    match (glob.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&b"**", rest)), _) => {
+            match_components(rest, path, include_dotfiles)
+                || path.split_first().map_or(false, |(name, path_rest)| {
+                    (include_dotfiles || !name.starts_with(b"."))
+                        && match_components(glob, path_rest, include_dotfiles)
+                })
+        }
+        (Some((pattern, glob_rest)), Some((name, path_rest))) => {
+            let hidden = name.starts_with(b".") && !pattern.starts_with(b".");
+            (include_dotfiles || !hidden)
+                && match_name(pattern, name)
+                && match_components(glob_rest, path_rest, include_dotfiles)
+        }
+        _ => false,
+    }
+}
+
+fn match_name(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_name(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_name(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_name(rest, &name[1..]),
+    }
+}
+
+pub struct MockEdenFs {
+    /// Root of the checkout, with the `.eden` directory `client::EdenFsClient` looks for.
+    repo_root: TempDir,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockEdenFs {
+    /// Creates a mounted checkout with no files, and a null parent, in a temporary directory.
+    /// Use `serve` to accept connections.
+    pub fn new() -> Result<Self> {
+        let repo_root = TempDir::new()?;
+        let dot_eden = repo_root.path().join(".eden");
+        fs::create_dir(&dot_eden)?;
+        symlink(repo_root.path(), dot_eden.join("root"))?;
+        symlink(dot_eden.join("thrift.sock"), dot_eden.join("socket"))?;
+        let mount_point = match repo_root.path().to_str() {
+            Some(mount_point) => mount_point.as_bytes().to_vec(),
+            None => bail!("{:?} is not UTF-8", repo_root.path()),
+        };
+
+        Ok(MockEdenFs {
+            repo_root,
+            state: Arc::new(Mutex::new(State {
+                mount_point,
+                mounted: true,
+                parents: WorkingDirectoryParents {
+                    parent1: vec![0; 20],
+                    parent2: None,
+                },
+                files: BTreeMap::new(),
+                trees: HashMap::new(),
+                conflicts: HashMap::new(),
+                sequence: 0,
+                truncated: 0,
+                journal: Vec::new(),
+                delay: Duration::from_secs(0),
+                connections: 0,
+                calls: Vec::new(),
+            })),
+        })
+    }
+
+    /// Accepts connections on the socket of the checkout until the returned `MockServer` is
+    /// dropped.
+    pub fn serve(&self) -> Result<MockServer> {
+        let socket_path = self.repo_root.path().join(".eden").join("thrift.sock");
+        let listener = UnixListener::bind(&socket_path)?;
+        listener.set_nonblocking(true)?;
+
+        let handler = Handler {
+            state: self.state.clone(),
+        };
+        let processor = Arc::new(Processor::new(handler, BaseProcessor::new(BaseHandler)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let streams = Arc::new(Mutex::new(Vec::new()));
+        let thread = {
+            let state = self.state.clone();
+            let stop = stop.clone();
+            let streams = streams.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    let stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(10));
+                            continue;
+                        }
+                        Err(_) => break,
+                    };
+                    let _ = stream.set_nonblocking(false);
+                    if let Ok(clone) = stream.try_clone() {
+                        streams.lock().unwrap().push(clone);
+                    }
+                    state.lock().unwrap().connections += 1;
+                    let processor = processor.clone();
+                    thread::spawn(move || serve_connection(&processor, stream));
+                }
+            })
+        };
+
+        Ok(MockServer {
+            socket_path,
+            stop,
+            streams,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn add_file(&self, path: &str, sha1: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(path.as_bytes().to_vec(), sha1.to_vec());
+        state.record(path, Change::Created);
+    }
+
+    pub fn modify_file(&self, path: &str, sha1: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(path.as_bytes().to_vec(), sha1.to_vec());
+        state.record(path, Change::Modified);
+    }
+
+    pub fn remove_file(&self, path: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.files.remove(path.as_bytes());
+        state.record(path, Change::Removed);
+    }
+
+    /// Makes `debugGetScmTree` return `entries` for the tree `id`.
+    pub fn add_tree(&self, id: &[u8], entries: Vec<ScmTreeEntry>) {
+        let mut state = self.state.lock().unwrap();
+        state.trees.insert(id.to_vec(), entries);
+    }
+
+    /// Makes `checkOutRevision` of `commit` report `conflicts`.
+    pub fn set_checkout_conflicts(&self, commit: &[u8], conflicts: Vec<CheckoutConflict>) {
+        let mut state = self.state.lock().unwrap();
+        state.conflicts.insert(commit.to_vec(), conflicts);
+    }
+
+    /// Forgets the journal, so `getFilesChangedSince` of an earlier position fails with
+    /// `JOURNAL_TRUNCATED`.
+    pub fn truncate_journal(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.truncated = state.sequence;
+        state.journal.clear();
+    }
+
+    /// Makes every call wait `delay` before it is answered.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    pub fn parents(&self) -> (Vec<u8>, Option<Vec<u8>>) {
+        let state = self.state.lock().unwrap();
+        (state.parents.parent1.clone(), state.parents.parent2.clone())
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.state.lock().unwrap().mounted
+    }
+
+    /// Names of the Thrift methods called so far.
+    pub fn calls(&self) -> Vec<&'static str> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+
+    pub fn repo_root(&self) -> &Path {
+        self.repo_root.path()
+    }
+}
+
+/// Serves a `MockEdenFs`. Dropping it closes the socket and the connections.
+pub struct MockServer {
+    socket_path: PathBuf,
+    stop: Arc<AtomicBool>,
+    streams: Arc<Mutex<Vec<UnixStream>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        for stream in self.streams.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+type BaseProcessor = BaseServiceProcessor<BinaryProtocol, BaseHandler, ()>;
+type Processor = EdenServiceProcessor<BinaryProtocol, Handler, (), BaseProcessor>;
+
+/// Answers the framed Thrift calls of one connection, until it is closed.
+fn serve_connection(processor: &Processor, mut stream: UnixStream) -> Result<()> {
+    loop {
+        let mut len = [0; 4];
+        match stream.read_exact(&mut len) {
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let mut request = vec![0; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut request)?;
+        let response = block_on(processor.call(Cursor::new(Bytes::from(request)), &()))?;
+        stream.write_all(&(response.len() as u32).to_be_bytes())?;
+        stream.write_all(&response)?;
+    }
+}
+
+struct BaseHandler;
+
+impl BaseService for BaseHandler {}
+
+struct Handler {
+    state: Arc<Mutex<State>>,
+}
+
+impl Handler {
+    /// Runs `call` on the state of the checkout, after the delay set by `set_delay`.
+    fn call<T>(
+        &self,
+        method: &'static str,
+        mount_point: &[u8],
+        call: impl FnOnce(&mut State) -> std::result::Result<T, EdenError>,
+    ) -> std::result::Result<T, EdenError> {
+        let delay = self.state.lock().unwrap().delay;
+        thread::sleep(delay);
+
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(method);
+        if mount_point != state.mount_point.as_slice() {
+            return Err(EdenError {
+                message: format!(
+                    "{}: No such file or directory",
+                    String::from_utf8_lossy(mount_point)
+                ),
+                errorCode: Some(ENOENT),
+                errorType: EdenErrorType::POSIX_ERROR,
+            });
+        }
+        call(&mut *state)
+    }
+}
+
+#[async_trait]
+impl EdenService for Handler {
+    async fn getCurrentJournalPosition(
+        &self,
+        mount_point: PathString,
+    ) -> std::result::Result<JournalPosition, GetCurrentJournalPositionExn> {
+        self.call("getCurrentJournalPosition", &mount_point, |state| {
+            Ok(state.position())
+        })
+        .map_err(Into::into)
+    }
+
+    async fn getFilesChangedSince(
+        &self,
+        mount_point: PathString,
+        from_position: JournalPosition,
+    ) -> std::result::Result<FileDelta, GetFilesChangedSinceExn> {
+        self.call("getFilesChangedSince", &mount_point, |state| {
+            if from_position.sequenceNumber < state.truncated {
+                return Err(EdenError {
+                    message: "Journal entry range has been truncated.".to_string(),
+                    errorCode: None,
+                    errorType: EdenErrorType::JOURNAL_TRUNCATED,
+                });
+            }
+            Ok(state.files_changed_since(from_position))
+        })
+        .map_err(Into::into)
+    }
+
+    async fn checkOutRevision(
+        &self,
+        mount_point: PathString,
+        commit: BinaryHash,
+        mode: CheckoutMode,
+    ) -> std::result::Result<Vec<CheckoutConflict>, CheckOutRevisionExn> {
+        self.call("checkOutRevision", &mount_point, |state| {
+            let conflicts = state.conflicts.get(&commit).cloned().unwrap_or_default();
+            if mode != CheckoutMode::DRY_RUN {
+                state.parents = WorkingDirectoryParents {
+                    parent1: commit,
+                    parent2: None,
+                };
+            }
+            Ok(conflicts)
+        })
+        .map_err(Into::into)
+    }
+
+    async fn resetParentCommits(
+        &self,
+        mount_point: PathString,
+        parents: WorkingDirectoryParents,
+    ) -> std::result::Result<(), ResetParentCommitsExn> {
+        self.call("resetParentCommits", &mount_point, |state| {
+            state.parents = parents;
+            Ok(())
+        })
+        .map_err(Into::into)
+    }
+
+    async fn globFiles(&self, params: GlobParams) -> std::result::Result<Glob, GlobFilesExn> {
+        self.call("globFiles", &params.mountPoint, |state| {
+            Ok(state.glob(&params.globs, params.includeDotfiles, params.wantDtype))
+        })
+        .map_err(Into::into)
+    }
+
+    async fn getSHA1(
+        &self,
+        mount_point: PathString,
+        paths: Vec<PathString>,
+    ) -> std::result::Result<Vec<SHA1Result>, GetSHA1Exn> {
+        self.call("getSHA1", &mount_point, |state| {
+            Ok(paths
+                .iter()
+                .map(|path| match state.files.get(path) {
+                    Some(sha1) => SHA1Result::sha1(sha1.clone()),
+                    None => SHA1Result::error(EdenError {
+                        message: format!(
+                            "{}: No such file or directory",
+                            String::from_utf8_lossy(path)
+                        ),
+                        errorCode: Some(ENOENT),
+                        errorType: EdenErrorType::POSIX_ERROR,
+                    }),
+                })
+                .collect())
+        })
+        .map_err(Into::into)
+    }
+
+    async fn debugGetScmTree(
+        &self,
+        mount_point: PathString,
+        id: BinaryHash,
+        _local_store_only: bool,
+    ) -> std::result::Result<Vec<ScmTreeEntry>, DebugGetScmTreeExn> {
+        self.call("debugGetScmTree", &mount_point, |state| {
+            Ok(state.trees.get(&id).cloned().unwrap_or_default())
+        })
+        .map_err(Into::into)
+    }
+
+    async fn unmount(&self, mount_point: PathString) -> std::result::Result<(), UnmountExn> {
+        self.call("unmount", &mount_point, |state| {
+            state.mounted = false;
+            Ok(())
+        })
+        .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let check = |glob: &str, path: &str, include_dotfiles| {
+            glob_match(glob.as_bytes(), path.as_bytes(), include_dotfiles)
+        };
+        assert!(check("a/*.rs", "a/b.rs", false));
+        assert!(!check("a/*.rs", "a/b/c.rs", false));
+        assert!(!check("a/*.rs", "a/.b.rs", false));
+        assert!(check("a/*.rs", "a/.b.rs", true));
+        assert!(check("a/.*", "a/.b.rs", false));
+        assert!(check("**/*.rs", "b.rs", false));
+        assert!(check("**/*.rs", "a/b/c.rs", false));
+        assert!(!check("**/*.rs", ".a/b.rs", false));
+        assert!(check("**/*.rs", ".a/b.rs", true));
+        assert!(check("a/b.?s", "a/b.rs", false));
+        assert!(!check("a/b?rs", "a/b/rs", false));
+        assert!(check("a/b*", "a/b.rs", false));
+    }
+
+    #[test]
+    fn test_files_changed_since() {
+        let mock = MockEdenFs::new().unwrap();
+        mock.add_file("a", &[1; 20]);
+        mock.add_file("b", &[1; 20]);
+        let state = mock.state.lock().unwrap();
+        let from = state.position();
+        drop(state);
+
+        mock.add_file("c", &[1; 20]);
+        mock.remove_file("c");
+        mock.add_file("d", &[1; 20]);
+        mock.modify_file("d", &[2; 20]);
+        mock.remove_file("a");
+        mock.modify_file("b", &[2; 20]);
+
+        let delta = mock.state.lock().unwrap().files_changed_since(from);
+        assert_eq!(delta.createdPaths, vec![b"d".to_vec()]);
+        assert_eq!(delta.changedPaths, vec![b"b".to_vec()]);
+        assert_eq!(delta.removedPaths, vec![b"a".to_vec()]);
+    }
+}
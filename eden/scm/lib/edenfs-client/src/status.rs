@@ -10,7 +10,11 @@ use thrift_types::edenfs as eden;
 use crate::path_relativizer::PathRelativizer;
 use anyhow::{bail, ensure, Error, Result};
 use byteorder::{BigEndian, ByteOrder};
-use clidispatch::{errors::FallbackToPython, io::IO};
+use clidispatch::{
+    errors::FallbackToPython,
+    formatter::{Formatter, Record},
+    io::IO,
+};
 use eden::client::EdenService;
 use eden::{GetScmStatusParams, GetScmStatusResult, ScmFileStatus, ScmStatus};
 #[cfg(unix)]
@@ -58,8 +62,9 @@ pub fn maybe_status_fastpath(
     cwd: &Path,
     print_config: PrintConfig,
     io: &mut IO,
+    formatter: &mut Formatter,
 ) -> Result<u8> {
-    maybe_status_fastpath_internal(repo_root, cwd, print_config, io)
+    maybe_status_fastpath_internal(repo_root, cwd, print_config, io, formatter)
 }
 
 #[cfg(windows)]
@@ -68,6 +73,7 @@ fn maybe_status_fastpath_internal(
     cwd: &Path,
     print_config: PrintConfig,
     io: &mut IO,
+    formatter: &mut Formatter,
 ) -> Result<u8> {
     Err(FallbackToPython.into())
 }
@@ -78,6 +84,7 @@ fn maybe_status_fastpath_internal(
     cwd: &Path,
     print_config: PrintConfig,
     io: &mut IO,
+    formatter: &mut Formatter,
 ) -> Result<u8> {
     let mut core = Core::new().expect("Core creation failed");
     let handle = core.handle();
@@ -118,7 +125,7 @@ fn maybe_status_fastpath_internal(
     }
 
     let stdout = io::stdout();
-    let use_color = should_colorize_output(&stdout);
+    let use_color = formatter.is_plain() && should_colorize_output(&stdout);
 
     let status = get_status_helper(
         &mut core,
@@ -138,6 +145,7 @@ fn maybe_status_fastpath_internal(
         &relativizer,
         use_color,
         io,
+        formatter,
     )?;
 
     if let Ok(version) = status.version.parse::<u32>() {
@@ -411,55 +419,52 @@ impl PrintConfig {
         relativizer: &HgStatusPathRelativizer,
         use_color: bool,
         io: &mut IO,
+        formatter: &mut Formatter,
     ) -> Result<u8> {
         let groups = group_entries(&repo_root, &status, &dirstate_data)?;
         let endl = self.endl;
 
-        let mut print_group =
-            |print_group, enabled: bool, group: &Vec<PathBuf>| -> Result<(), io::Error> {
-                if !enabled {
-                    return Ok(());
-                }
+        let mut print_group = |print_group, enabled: bool, group: &Vec<PathBuf>| -> Result<()> {
+            if !enabled {
+                return Ok(());
+            }
 
-                // `hg config | grep color` did not yield the entries for color.status listed on
-                // https://www.mercurial-scm.org/wiki/ColorExtension. At Facebook, we seem to match
-                // the defaults listed on the wiki page, except we don't change the background color.
-                let (code, ansi_prefix) = match print_group {
-                    PrintGroup::Modified => ("M ", format!("{}{}", BLUE, BOLD)),
-                    PrintGroup::Added => ("A ", format!("{}{}", GREEN, BOLD)),
-                    PrintGroup::Removed => ("R ", format!("{}{}", RED, BOLD)),
-                    PrintGroup::Deleted => ("! ", format!("{}{}{}", CYAN, BOLD, UNDERLINE)),
-                    PrintGroup::Unknown => ("? ", format!("{}{}{}", MAGENTA, BOLD, UNDERLINE)),
-                    PrintGroup::Ignored => ("I ", format!("{}{}", BRIGHT_BLACK, BOLD)),
-                    PrintGroup::Clean => ("C ", "".to_owned()),
-                };
-                let prefix = if self.no_status { "" } else { code };
-                let (prefix, suffix) = if use_color {
-                    (format!("{}{}", ansi_prefix, prefix), RESET.to_string())
-                } else {
-                    (prefix.to_owned(), "".to_owned())
-                };
+            // `hg config | grep color` did not yield the entries for color.status listed on
+            // https://www.mercurial-scm.org/wiki/ColorExtension. At Facebook, we seem to match
+            // the defaults listed on the wiki page, except we don't change the background color.
+            let (code, ansi_prefix) = match print_group {
+                PrintGroup::Modified => ("M ", format!("{}{}", BLUE, BOLD)),
+                PrintGroup::Added => ("A ", format!("{}{}", GREEN, BOLD)),
+                PrintGroup::Removed => ("R ", format!("{}{}", RED, BOLD)),
+                PrintGroup::Deleted => ("! ", format!("{}{}{}", CYAN, BOLD, UNDERLINE)),
+                PrintGroup::Unknown => ("? ", format!("{}{}{}", MAGENTA, BOLD, UNDERLINE)),
+                PrintGroup::Ignored => ("I ", format!("{}{}", BRIGHT_BLACK, BOLD)),
+                PrintGroup::Clean => ("C ", "".to_owned()),
+            };
+            let prefix = if self.no_status { "" } else { code };
+            let (prefix, suffix) = if use_color {
+                (format!("{}{}", ansi_prefix, prefix), RESET.to_string())
+            } else {
+                (prefix.to_owned(), "".to_owned())
+            };
 
-                for path in group {
-                    io.write(format!(
-                        "{}{}{}{}",
-                        prefix,
-                        &relativizer.relativize(&path).display(),
-                        suffix,
-                        endl
-                    ))?;
-                    if self.copies {
-                        if let Some(ref p) = dirstate_data.copymap.get(path) {
-                            io.write(format!(
-                                "  {}{}",
-                                &relativizer.relativize(p).display(),
-                                endl
-                            ))?;
-                        }
+            for path in group {
+                let relative_path = relativizer.relativize(&path).display().to_string();
+                let mut record = Record::new()
+                    .field("path", relative_path.as_str())
+                    .field("status", code.trim_end());
+                let mut plain = format!("{}{}{}{}", prefix, relative_path, suffix, endl);
+                if self.copies {
+                    if let Some(ref p) = dirstate_data.copymap.get(path) {
+                        let relative_copy = relativizer.relativize(p).display().to_string();
+                        plain += &format!("  {}{}", relative_copy, endl);
+                        record = record.field("copy", relative_copy);
                     }
                 }
-                return Ok(());
-            };
+                formatter.write(io, &record, plain)?;
+            }
+            return Ok(());
+        };
 
         print_group(
             PrintGroup::Modified,
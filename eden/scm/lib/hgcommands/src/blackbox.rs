@@ -0,0 +1,209 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Result;
+use blackbox::{command_stats, event::Event, serde_json, Query, SessionId, ToValue};
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    io::IO,
+    repo::Repo,
+};
+use cliparser::define_flags;
+use configparser::hg::ConfigSetHgExt;
+use hgtime::HgTime;
+use std::time::SystemTime;
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        blackbox,
+        "blackbox",
+        r#"view recent repository events
+
+    By default, show events in the last 15 minutes. Use '--start 60' to get
+    events in the past hour.
+
+    Sessions can be selected by '--session-id', '--command', '--exit-code'
+    and '--min-duration'. Use '--type' to only show events of the given
+    types, like 'start', 'finish' or 'network'.
+
+    Use '--format json' to output one JSON object per event, or
+    '--format stats' to show duration percentiles per command.
+
+    Use '--debug' to see raw JSON values instead of human-readable messages.
+
+    Use '--pattern' to filter events by JSON patterns. Examples::
+
+        # matches watchman events ("_" matches anything)
+        {"watchman": "_"}
+
+        # matches "ssh_getfiles" network operations that takes 10 to 100ms.
+        {"network": {"op": "ssh_getfiles", "duration_ms": ["range", 10, 100]}}
+
+        # matches pager, or editor, or pythonhook blocked events
+        {"blocked": {"name": ["or", "pager", "editor", "pythonhook"]}}
+
+        # matches process start events with non-root uid
+        {"start": {"uid": ["not", 0]}}
+
+        # matches start, or finish, or alias events
+        ["or", {"start": "_"}, {"finish": "_"}, {"alias": "_"}]"#,
+    );
+}
+
+define_flags! {
+    pub struct BlackboxOpts {
+        /// start time (minutes in the past, relative to now)
+        #[short('s')]
+        start: i64 = 15,
+
+        /// end time (minutes in the past, relative to now)
+        #[short('e')]
+        end: i64 = 0,
+
+        /// JSON pattern to match (ADVANCED)
+        #[short('p')]
+        pattern: String,
+
+        /// show timestamp (ADVANCED)
+        timestamp: bool = true,
+
+        /// show session id (ADVANCED)
+        sid: bool = true,
+
+        /// only show the given session (overrides --start and --end)
+        session_id: i64,
+
+        /// only show events of the given type
+        #[short('t')]
+        r#type: Vec<String>,
+
+        /// only show sessions running the given command
+        command: String,
+
+        /// only show sessions exiting with the given code (-1: any)
+        exit_code: i64 = -1,
+
+        /// only show sessions taking at least the given milliseconds
+        min_duration: i64,
+
+        /// output format (text, json, stats)
+        #[short('f')]
+        format: String = "text",
+    }
+}
+
+pub fn blackbox(opts: BlackboxOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    let config = repo.config();
+    // Like Python, use the pager if the output is a terminal.
+    let is_terminal = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+    if is_terminal && config.get_or("ui", "paginate", || true)? {
+        io.start_pager(config)?;
+    }
+    let debug: bool = config.get_or_default("ui", "debug")?;
+    if opts.exit_code < -1 || opts.exit_code > 255 {
+        return Err(errors::Abort("exit code must be between 0 and 255".into()).into());
+    }
+
+    let mut query = Query::new();
+    if opts.session_id != 0 {
+        query = query.session_id(SessionId(opts.session_id as u64));
+    } else {
+        // Blackbox uses milliseconds.
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis() as i64;
+        let minutes_ago = |minutes: i64| now.saturating_sub(minutes * 60_000).max(0) as u64;
+        query = query.start_time_range(minutes_ago(opts.start), minutes_ago(opts.end) + 1000);
+    }
+    for name in opts.r#type {
+        query = query.event_type(name);
+    }
+    if !opts.command.is_empty() {
+        query = query.command(opts.command);
+    }
+    if opts.exit_code >= 0 {
+        query = query.exit_code(opts.exit_code as u8);
+    }
+    if opts.min_duration > 0 {
+        query = query.min_duration_ms(opts.min_duration as u64);
+    }
+    if !opts.pattern.is_empty() {
+        let pattern = serde_json::from_str(&opts.pattern)
+            .map_err(|e| errors::Abort(format!("invalid pattern: {}", e).into()))?;
+        query = query.pattern(pattern);
+    }
+
+    let sessions = blackbox::SINGLETON.lock().query(&query);
+
+    match opts.format.as_str() {
+        "text" | "json" => {
+            let mut entries: Vec<_> = sessions.iter().flat_map(|s| s.entries.iter()).collect();
+            entries.sort_by_key(|e| e.timestamp);
+            for entry in entries {
+                let line = if opts.format == "json" {
+                    serde_json::to_string(&entry.to_json())?
+                } else {
+                    let message = if debug {
+                        match &entry.data {
+                            // Skip converting TracingData to JSON.
+                            Event::TracingData { .. } => "{}".to_string(),
+                            data => serde_json::to_string(&data.to_value())?,
+                        }
+                    } else {
+                        format!("{}", entry.data).trim().to_string()
+                    };
+                    let mut line = String::new();
+                    if opts.timestamp {
+                        let time = HgTime {
+                            unixtime: (entry.timestamp / 1000) as i64,
+                            offset: 0,
+                        };
+                        line += &format!(
+                            "{}.{:03} ",
+                            time.to_local().format("%Y/%m/%d %H:%M:%S"),
+                            entry.timestamp % 1000
+                        );
+                    }
+                    if opts.sid {
+                        // The lowest 3 bytes are "pid". See blackbox.rs.
+                        let sid = if debug {
+                            entry.session_id
+                        } else {
+                            entry.session_id & 0xffffff
+                        };
+                        line += &format!("{:10} ", sid);
+                    }
+                    line + &message
+                };
+                io.write(line + "\n")?;
+            }
+        }
+        "stats" => {
+            io.write(format!(
+                "{:<20} {:>6} {:>8} {:>8} {:>8} {:>8}\n",
+                "COMMAND", "COUNT", "FAILED", "P50 MS", "P95 MS", "MAX MS"
+            ))?;
+            for stats in command_stats(&sessions) {
+                io.write(format!(
+                    "{:<20} {:>6} {:>8} {:>8} {:>8} {:>8}\n",
+                    stats.command,
+                    stats.count,
+                    stats.failures,
+                    stats.p50_ms,
+                    stats.p95_ms,
+                    stats.max_ms
+                ))?;
+            }
+        }
+        format => {
+            return Err(errors::Abort(format!("unknown format: {}", format).into()).into());
+        }
+    }
+
+    Ok(0)
+}
@@ -9,6 +9,7 @@ use anyhow::Result;
 use clidispatch::{
     command::{CommandTable, Register},
     errors,
+    formatter::{Formatter, Record},
     io::IO,
     repo::Repo,
 };
@@ -16,7 +17,7 @@ use cliparser::define_flags;
 use filetime::{set_file_mtime, FileTime};
 
 use blackbox::{event::Event, json, SessionId};
-use dynamicconfig::Generator;
+use dynamicconfig::{Generator, Rules};
 use edenapi::{Config as EdenApiConfig, EdenApi, EdenApiCurlClient};
 use revisionstore::{
     CorruptionPolicy, DataPackStore, HgIdDataStore, IndexedLogHgIdDataStore, UnionHgIdDataStore,
@@ -25,7 +26,15 @@ use types::{HgId, Key, RepoPathBuf};
 
 use std::{fs, path::Path, str::FromStr};
 
+#[cfg(unix)]
+use crate::commandserver;
+use crate::completion;
+use crate::config;
+use crate::debugmetalog;
+use crate::files;
+use crate::grep;
 use crate::status;
+use crate::update;
 
 #[allow(dead_code)]
 /// Return the main command table including all Rust commands.
@@ -38,15 +47,30 @@ pub fn table() -> CommandTable {
 
     Print the root directory of the current repository.
 
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``root``.
+
     Returns 0 on success."#,
     );
     table.register(
         version,
         "version|vers|versi|versio",
-        r#"output version and copyright information"#,
+        r#"output version and copyright information
+
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``ver``."#,
     );
     status::register(&mut table);
     status::register(&mut table);
+    config::register(&mut table);
+    completion::register(&mut table);
+    #[cfg(unix)]
+    commandserver::register(&mut table);
+    crate::blackbox::register(&mut table);
+    debugmetalog::register(&mut table);
+    update::register(&mut table);
+    files::register(&mut table);
+    grep::register(&mut table);
 
     table.register(dump_trace, "dump-trace", "export tracing information");
 
@@ -56,7 +80,14 @@ pub fn table() -> CommandTable {
         "print information about blobstore",
     );
     table.register(debugpython, "debugpython|debugpy", "run python interpreter");
-    table.register(debugargs, "debug-args", "print arguments received");
+    table.register(
+        debugargs,
+        "debug-args",
+        r#"print arguments received
+
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``args``."#,
+    );
     table.register(
         debugindexedlogdump,
         "debugindexedlog-dump",
@@ -70,12 +101,25 @@ pub fn table() -> CommandTable {
     table.register(
         debughttp,
         "debughttp",
-        "check whether api server is reachable",
+        r#"check whether api server is reachable
+
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``hostname``."#,
     );
     table.register(
         debugdynamicconfig,
         "debugdynamicconfig",
-        "generate the dynamic configuration",
+        r#"generate the dynamic configuration
+
+    Rules from the file set by ``configs.rulespath`` or ``--rules`` are
+    applied after the built-in rules. Names of matched rules are printed.
+
+    With --validate, only check the rules file for problems and print them.
+    Returns 1 if there are problems.
+
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``rule`` for matched rules, ``problem`` for
+    problems found by --validate."#,
     );
 
     table
@@ -101,6 +145,12 @@ define_flags! {
     pub struct RootOpts {
         /// show root of the shared repo
         shared: bool,
+
+        formatter_opts: FormatterOpts,
+    }
+
+    pub struct VersionOpts {
+        formatter_opts: FormatterOpts,
     }
 
     pub struct DumpTraceOpts {
@@ -112,9 +162,13 @@ define_flags! {
         #[short('s')]
         session_id: i64,
 
-        /// output path (.txt, .json, .json.gz, .spans.json)
+        /// output path (.txt, .json, .json.gz, .spans.json, .otlp.json, .folded)
         #[short('o')]
         output_path: String,
+
+        /// output format (ascii, trace-event, trace-event-gzip, spans, otlp, folded)
+        #[short('f')]
+        format: String,
     }
 
     pub struct DebugstoreOpts {
@@ -137,10 +191,31 @@ define_flags! {
     }
 
     pub struct DebugArgsOpts {
+        formatter_opts: FormatterOpts,
+
+        #[args]
+        args: Vec<String>,
+    }
+
+    pub struct DebugIndexedLogOpts {
         #[args]
         args: Vec<String>,
     }
 
+    pub struct DebugHttpOpts {
+        formatter_opts: FormatterOpts,
+    }
+
+    pub struct DebugDynamicConfigOpts {
+        /// rules file to apply (default: configs.rulespath)
+        rules: String,
+
+        /// check the rules file for problems without generating config
+        validate: bool,
+
+        formatter_opts: FormatterOpts,
+    }
+
     pub struct NoOpts {}
 }
 
@@ -151,15 +226,18 @@ pub fn root(opts: RootOpts, io: &mut IO, repo: Repo) -> Result<u8> {
         repo.path()
     };
 
-    io.write(format!(
-        "{}\n",
-        util::path::strip_unc_prefix(&path).display()
-    ))?;
+    let path = util::path::strip_unc_prefix(&path).display().to_string();
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["root"])?;
+    let record = Record::new().field("root", path.as_str());
+    formatter.write(io, &record, format!("{}\n", path))?;
+    formatter.end(io)?;
     Ok(0)
 }
 
-pub fn version(_opts: NoOpts, io: &mut IO) -> Result<u8> {
-    io.write(format!(
+pub fn version(opts: VersionOpts, io: &mut IO) -> Result<u8> {
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["ver"])?;
+    let record = Record::new().field("ver", ::version::VERSION);
+    let plain = format!(
         r#"Mercurial Distributed SCM (version {})
 (see https://mercurial-scm.org for more information)
 
@@ -168,7 +246,9 @@ This is free software; see the source for copying conditions. There is NO
 warranty; not even for MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
 "#,
         ::version::VERSION
-    ))?;
+    );
+    formatter.write(io, &record, plain)?;
+    formatter.end(io)?;
     Ok(0)
 }
 
@@ -203,7 +283,7 @@ pub fn dump_trace(opts: DumpTraceOpts, io: &mut IO, _repo: Repo) -> Result<u8> {
     }
     let merged = tracing_collector::TracingData::merge(tracing_data_list);
 
-    crate::run::write_trace(io, &opts.output_path, &merged)?;
+    crate::run::write_trace(io, &opts.output_path, &opts.format, &merged)?;
 
     Ok(0)
 }
@@ -246,13 +326,18 @@ pub fn debugpython(opts: DebugPythonOpts, io: &mut IO) -> Result<u8> {
 }
 
 pub fn debugargs(opts: DebugArgsOpts, io: &mut IO) -> Result<u8> {
-    match io.write(format!("{:?}\n", opts.args)) {
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["args"])?;
+    let record = Record::new().field("args", opts.args.clone());
+    match formatter
+        .write(io, &record, format!("{:?}\n", opts.args))
+        .and_then(|_| formatter.end(io))
+    {
         Ok(_) => Ok(0),
         Err(_) => Ok(255),
     }
 }
 
-pub fn debugindexedlogdump(opts: DebugArgsOpts, io: &mut IO) -> Result<u8> {
+pub fn debugindexedlogdump(opts: DebugIndexedLogOpts, io: &mut IO) -> Result<u8> {
     for path in opts.args {
         let _ = io.write(format!("{}\n", path));
         let path = Path::new(&path);
@@ -273,7 +358,7 @@ pub fn debugindexedlogdump(opts: DebugArgsOpts, io: &mut IO) -> Result<u8> {
     Ok(0)
 }
 
-pub fn debugindexedlogrepair(opts: DebugArgsOpts, io: &mut IO) -> Result<u8> {
+pub fn debugindexedlogrepair(opts: DebugIndexedLogOpts, io: &mut IO) -> Result<u8> {
     for path in opts.args {
         io.write(format!("Repairing {:?}\n", path))?;
         io.write(format!(
@@ -285,19 +370,62 @@ pub fn debugindexedlogrepair(opts: DebugArgsOpts, io: &mut IO) -> Result<u8> {
     Ok(0)
 }
 
-pub fn debughttp(_opts: NoOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+pub fn debughttp(opts: DebugHttpOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["hostname"])?;
     let config = EdenApiConfig::from_hg_config(repo.config())?;
     let client = EdenApiCurlClient::new(config)?;
     let hostname = client.hostname()?;
-    io.write(format!("successfully connected to: {}\n", hostname))?;
+    let record = Record::new().field("hostname", hostname.to_string());
+    formatter.write(
+        io,
+        &record,
+        format!("successfully connected to: {}\n", hostname),
+    )?;
+    formatter.end(io)?;
     Ok(0)
 }
 
-pub fn debugdynamicconfig(_opts: NoOpts, _io: &mut IO, repo: Repo) -> Result<u8> {
+pub fn debugdynamicconfig(opts: DebugDynamicConfigOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["rule", "problem"])?;
+    let rules_path = if opts.rules.is_empty() {
+        repo.config()
+            .get("configs", "rulespath")
+            .map(util::path::expand_path)
+    } else {
+        Some(util::path::expand_path(&opts.rules))
+    };
+    let rules = match rules_path {
+        Some(path) => Some(Rules::load(path)?),
+        None => None,
+    };
+
+    if opts.validate {
+        let rules = match rules {
+            Some(rules) => rules,
+            None => return Err(errors::Abort("no rules file to validate".into()).into()),
+        };
+        let problems = rules.validate();
+        for problem in problems.iter() {
+            let record = Record::new().field("problem", problem.to_string());
+            formatter.write(io, &record, format!("{}\n", problem))?;
+        }
+        formatter.end(io)?;
+        return Ok(if problems.is_empty() { 0 } else { 1 });
+    }
+
     let repo_name: String = repo
         .repo_name()
         .map_or_else(|| "".to_string(), |s| s.to_string());
-    let config = Generator::new(repo_name)?.execute()?;
+    let mut generator = Generator::new(repo_name)?;
+    if let Some(rules) = rules {
+        generator = generator.with_rules(rules);
+    }
+    let (config, matched) = generator.execute_with_report()?;
+    for name in matched {
+        let record = Record::new().field("rule", name.to_string());
+        formatter.write(io, &record, format!("matched rule: {}\n", name))?;
+    }
+    formatter.end(io)?;
     let config_str = config.to_string();
     let config_str = format!(
         "# version={}\n# Generated by `hg debugdynamicconfig` - DO NOT MODIFY\n{}",
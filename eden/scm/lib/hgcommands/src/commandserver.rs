@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Result;
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    io::IO,
+    server::Server,
+};
+use cliparser::define_flags;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Whether this process is a command server.
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        debugcommandserver,
+        "debugcommandserver",
+        r#"run a command server for native commands
+
+    The server keeps config and repos loaded, and runs native commands
+    like ``status`` for clients connected to the Unix socket at --socket.
+    Config and repos are reloaded when their files change.
+
+    ``hg`` uses the server if the HGCOMMANDSERVER environment variable is
+    set to the socket path. Commands the server cannot run, like commands
+    implemented in Python, are run by ``hg`` as usual.
+
+    The server exits after being idle for --idle-timeout seconds, or if
+    the socket file is removed. This command itself is not run by the
+    server.
+
+    Returns 0 on success."#,
+    );
+}
+
+define_flags! {
+    pub struct DebugCommandServerOpts {
+        /// path of the Unix socket to listen at
+        socket: String,
+
+        /// exit after being idle for the given seconds
+        idle_timeout: i64 = 600,
+    }
+}
+
+pub fn debugcommandserver(opts: DebugCommandServerOpts, io: &mut IO) -> Result<u8> {
+    if SERVING.load(Ordering::Acquire) {
+        // Let the client run it.
+        return Err(errors::FallbackToPython.into());
+    }
+    if opts.socket.is_empty() {
+        return Err(errors::Abort("--socket is required".into()).into());
+    }
+    let server = Server::bind(Path::new(&opts.socket))?;
+    io.write(format!("listening at {}\n", opts.socket))?;
+    io.flush()?;
+    let idle_timeout = Duration::from_secs(opts.idle_timeout.max(1) as u64);
+    SERVING.store(true, Ordering::Release);
+    // Served commands are logged like commands run by `hg`.
+    server.serve(
+        &crate::commands::table(),
+        idle_timeout,
+        &crate::run::run_served,
+    )?;
+    Ok(0)
+}
@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Result;
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    global_flags::HgGlobalOpts,
+    io::IO,
+    repo::OptionalRepo,
+};
+use cliparser::completion::{Complete, Completion, Shell};
+use cliparser::define_flags;
+use cliparser::parser::{Flag, StructFlags, Value};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+
+/// Commands whose positional arguments are completed as bookmarks instead
+/// of file paths.
+const BOOKMARK_COMMANDS: &[&str] = &[
+    "update",
+    "bookmarks",
+    "graft",
+    "backout",
+    "export",
+    "hide",
+    "unhide",
+];
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        debugcompletion,
+        "debugcompletion",
+        r#"generate a shell completion script
+
+    The script completes command names, aliases from the ``[alias]``
+    config section, flags, bookmarks and file paths.
+
+    --shell selects the shell: 'bash', 'zsh' or 'fish'. By default, the
+    shell is picked from the SHELL environment variable.
+
+    Commands defined in Python and extensions are exported by Python to a
+    JSON file passed with --commands. It maps names like "log|history" to
+    objects with a "doc" string and a "flags" list of
+    ``[short, long, default, description]``.
+
+    Examples:
+
+    - enable completion in bash::
+
+        source <(hg debugcompletion --shell bash)
+
+    - install completion for fish::
+
+        hg debugcompletion --shell fish > ~/.config/fish/completions/hg.fish
+
+    Returns 0 on success."#,
+    );
+}
+
+define_flags! {
+    pub struct DebugCompletionOpts {
+        /// shell to generate the script for (bash, zsh or fish)
+        shell: String,
+
+        /// JSON file with more command definitions
+        commands: String,
+    }
+}
+
+pub fn debugcompletion(opts: DebugCompletionOpts, io: &mut IO, repo: OptionalRepo) -> Result<u8> {
+    // Python exports the merged command table and calls back with --commands.
+    if opts.commands.is_empty() {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let shell = if opts.shell.is_empty() {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        match Path::new(&shell).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => {
+                return Err(
+                    errors::Abort("cannot detect the shell, use --shell to set it".into()).into(),
+                );
+            }
+        }
+    } else {
+        opts.shell
+    };
+    let shell: Shell = shell
+        .parse()
+        .map_err(|e| errors::Abort(format!("{}", e).into()))?;
+
+    let mut completion = Completion::new("hg").global_flags(HgGlobalOpts::flags());
+    for (names, command) in crate::commands::table().iter() {
+        completion = completion.command(names, command.doc(), command.flags());
+    }
+    for (names, doc, flags) in load_commands(Path::new(&opts.commands))? {
+        completion = completion.command(&names, &doc, flags);
+    }
+    for name in BOOKMARK_COMMANDS {
+        completion = completion.args(name, Complete::Bookmark);
+    }
+
+    let config = repo.config();
+    for name in config.keys("alias") {
+        // [alias] can have "<name>:doc" entries that are not commands. Skip them.
+        if name.contains(':') {
+            continue;
+        }
+        if let Some(expansion) = config.get("alias", &name) {
+            completion = completion.alias(&*name, &*expansion);
+        }
+    }
+
+    io.write(completion.generate(shell))?;
+    Ok(0)
+}
+
+/// Read command definitions exported from the Python command table.
+fn load_commands(path: &Path) -> Result<Vec<(String, String, Vec<Flag>)>> {
+    let invalid = |message: &str| -> anyhow::Error {
+        errors::Abort(format!("invalid command table {}: {}", path.display(), message).into())
+            .into()
+    };
+    let table: JsonValue =
+        serde_json::from_slice(&fs::read(path)?).map_err(|e| invalid(&e.to_string()))?;
+    let table = table
+        .as_object()
+        .ok_or_else(|| invalid("expected an object"))?;
+
+    let mut commands = Vec::with_capacity(table.len());
+    for (names, command) in table {
+        let doc = command["doc"].as_str().unwrap_or_default().to_string();
+        let mut flags = Vec::new();
+        for flag in command["flags"].as_array().into_iter().flatten() {
+            let (short, long, default, description) = match flag.as_array().map(Vec::as_slice) {
+                Some([short, long, default, description]) => (short, long, default, description),
+                _ => return Err(invalid(&format!("unexpected flag {} of {}", flag, names))),
+            };
+            let default = match default {
+                JsonValue::Bool(b) => Value::Bool(*b),
+                JsonValue::Number(n) => Value::Int(n.as_i64().unwrap_or_default()),
+                JsonValue::Array(items) => Value::List(
+                    items
+                        .iter()
+                        .map(|item| item.as_str().unwrap_or_default().to_string())
+                        .collect(),
+                ),
+                value => Value::Str(value.as_str().unwrap_or_default().to_string()),
+            };
+            flags.push(Flag::from((
+                short.as_str().and_then(|s| s.chars().next()),
+                long.as_str().unwrap_or_default().to_string(),
+                description.as_str().unwrap_or_default().to_string(),
+                default,
+            )));
+        }
+        commands.push((names.clone(), doc, flags));
+    }
+    Ok(commands)
+}
@@ -0,0 +1,254 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::commands::FormatterOpts;
+use anyhow::Result;
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    formatter::{Formatter, Record},
+    io::IO,
+    repo::OptionalRepo,
+};
+use cliparser::define_flags;
+use configparser::config::{ConfigSet, ValueSource};
+use configparser::hg::ConfigSetHgExt;
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        config,
+        "config|showconfig|debugconfig|conf|confi",
+        r#"show config settings
+
+    With no arguments, print names and values of all config items.
+
+    With one argument of the form section.name, print just the value
+    of that config item.
+
+    With multiple arguments, print names and values of all config
+    items with matching section names.
+
+    With --edit, start an editor on the user-level config file. With
+    --global, edit the system-wide config file. With --local, edit the
+    repository-level config file.
+
+    With --debug, the source (filename and line number) is printed
+    for each config item.
+
+    With --explain, print every place that set the given config items,
+    in the order they were loaded. The last one is the effective value,
+    the others are overridden.
+
+    See :hg:`help config` for more information about config files.
+
+    Returns 0 on success, 1 if NAME does not exist."#,
+    );
+    table.register(
+        debugconfigdiff,
+        "debugconfigdiff",
+        r#"show config items affected by given config sources
+
+    Compare the config with and without values from the given sources,
+    and print config items that have different effective values. Lines
+    starting with '-' are values without those sources, lines starting
+    with '+' are the actual values.
+
+    Sources are names like 'system', 'user', 'dynamic', 'repository' or
+    '--config'. By default, the 'dynamic' source is compared.
+
+    With arguments, only config items in the given sections are shown.
+
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``name``, ``old``, ``new``, ``oldsource``
+    and ``newsource``. Values are empty if unset."#,
+    );
+}
+
+define_flags! {
+    pub struct ConfigOpts {
+        /// show untrusted configuration options
+        #[short('u')]
+        untrusted: bool,
+
+        /// edit user config
+        #[short('e')]
+        edit: bool,
+
+        /// edit repository config
+        #[short('l')]
+        local: bool,
+
+        /// edit global config
+        #[short('g')]
+        global: bool,
+
+        /// show where config items are set and which one is effective
+        explain: bool,
+
+        formatter_opts: FormatterOpts,
+
+        #[args]
+        args: Vec<String>,
+    }
+
+    pub struct DebugConfigDiffOpts {
+        /// config source to compare (ex. dynamic, user, --config) (default: dynamic)
+        #[short('s')]
+        source: Vec<String>,
+
+        formatter_opts: FormatterOpts,
+
+        #[args]
+        args: Vec<String>,
+    }
+}
+
+pub fn config(opts: ConfigOpts, io: &mut IO, repo: OptionalRepo) -> Result<u8> {
+    if !opts.explain {
+        return Err(errors::FallbackToPython.into());
+    }
+    if opts.edit || opts.local || opts.global || !opts.formatter_opts.template.is_empty() {
+        return Err(errors::Abort("--explain cannot be used with other options".into()).into());
+    }
+    if opts.args.is_empty() {
+        return Err(errors::Abort("--explain requires a config name".into()).into());
+    }
+
+    let config = &load_dynamic(&repo)?;
+    let mut found = false;
+    for arg in opts.args {
+        for (section, name) in config_names(config, &arg) {
+            let sources = config.get_sources(&section, &name);
+            let effective = match sources.last().and_then(|s| s.value().clone()) {
+                Some(value) => value.to_string(),
+                None => "%unset".to_string(),
+            };
+            io.write(format!("{}.{}={}\n", section, name, effective))?;
+            let last = sources.len().saturating_sub(1);
+            for (i, source) in sources.iter().enumerate() {
+                let state = if i == last { "effective" } else { "overridden" };
+                io.write(format!(
+                    "    {}: {} ({})\n",
+                    describe_source(source),
+                    describe_value(source),
+                    state
+                ))?;
+            }
+            found = true;
+        }
+    }
+    Ok(if found { 0 } else { 1 })
+}
+
+pub fn debugconfigdiff(opts: DebugConfigDiffOpts, io: &mut IO, repo: OptionalRepo) -> Result<u8> {
+    let sources = if opts.source.is_empty() {
+        vec!["dynamic".to_string()]
+    } else {
+        opts.source
+    };
+
+    let mut formatter = Formatter::new(
+        &opts.formatter_opts.template,
+        &["name", "old", "new", "oldsource", "newsource"],
+    )?;
+    let config = &load_dynamic(&repo)?;
+    let without = config.filter_sources(|v| !sources.iter().any(|s| s == v.source().as_ref()));
+    for diff in without.diff(config) {
+        if !opts.args.is_empty() && !opts.args.iter().any(|s| s == diff.section.as_ref()) {
+            continue;
+        }
+        let name = format!("{}.{}", diff.section.as_ref(), diff.name.as_ref());
+        let mut record = Record::new().field("name", name.as_str());
+        let mut plain = String::new();
+        for (prefix, key, source) in [("-", "old", &diff.old), ("+", "new", &diff.new)].iter() {
+            if let Some(source) = source {
+                if let Some(value) = source.value() {
+                    plain += &format!(
+                        "{} {}={}  # {}\n",
+                        prefix,
+                        name,
+                        value.as_ref(),
+                        describe_source(source)
+                    );
+                    record = record
+                        .field(key, value.as_ref())
+                        .field(&format!("{}source", key), describe_source(source));
+                }
+            }
+        }
+        formatter.write(io, &record, plain)?;
+    }
+    formatter.end(io)?;
+    Ok(0)
+}
+
+/// Sources loaded after the dynamic config. Global flags like `--verbose`
+/// are set as config when the command is dispatched.
+const AFTER_DYNAMIC: &[&str] = &[
+    "repository",
+    "--config",
+    "--configfile",
+    "--verbose",
+    "--debug",
+    "--quiet",
+    "--pager",
+];
+
+/// Return the config with the dynamic config of the repo loaded, like the
+/// Python code does. The dynamic config is loaded before the repo config,
+/// so the repo config, `--config` and global flags can override it.
+fn load_dynamic(repo: &OptionalRepo) -> Result<ConfigSet> {
+    let config = repo.config();
+    let repo = match repo.repo() {
+        Some(repo) if config.get_or_default::<bool>("configs", "loaddynamicconfig")? => repo,
+        _ => return Ok(config.clone()),
+    };
+    let is_later = |v: &ValueSource| AFTER_DYNAMIC.contains(&v.source().as_ref());
+    let mut result = config.filter_sources(|v| !is_later(v));
+    let path = repo.shared_dot_hg_path().join("hgrc.dynamic");
+    if let Some(error) = result.load_hgrc(path, "dynamic").pop() {
+        return Err(error.into());
+    }
+    result.extend(config.filter_sources(is_later));
+    Ok(result)
+}
+
+/// Resolve a `section.name` or `section` argument to a list of config names.
+fn config_names(config: &ConfigSet, arg: &str) -> Vec<(String, String)> {
+    match arg.find('.') {
+        Some(index) => {
+            let (section, name) = (&arg[..index], &arg[index + 1..]);
+            if config.get_sources(section, name).is_empty() {
+                Vec::new()
+            } else {
+                vec![(section.to_string(), name.to_string())]
+            }
+        }
+        None => config
+            .keys(arg)
+            .into_iter()
+            .map(|name| (arg.to_string(), name.to_string()))
+            .collect(),
+    }
+}
+
+/// Describe where a value comes from, like `/home/foo/.hgrc:3 (user)`.
+fn describe_source(source: &ValueSource) -> String {
+    match (source.location(), source.line_number()) {
+        (Some((path, _)), Some(line)) if !path.as_os_str().is_empty() => {
+            format!("{}:{} ({})", path.display(), line, source.source().as_ref())
+        }
+        _ => source.source().to_string(),
+    }
+}
+
+fn describe_value(source: &ValueSource) -> String {
+    match source.value() {
+        Some(value) => value.to_string(),
+        None => "%unset".to_string(),
+    }
+}
@@ -0,0 +1,204 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::commands::FormatterOpts;
+use crate::lock;
+use anyhow::Result;
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    formatter::{Formatter, Record},
+    io::IO,
+    repo::Repo,
+};
+use cliparser::define_flags;
+use hgtime::HgTime;
+use metalog::{CommitOptions, KeyChange, MetaLog, RootInfo};
+use std::path::Path;
+use std::time::SystemTime;
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        debugmetalog,
+        "debugmetalog",
+        r#"show changes in commit graph over time
+
+    With --history, list roots stored in metalog, the newest first.
+
+    With --diff, show keys changed since the root selected by --root or
+    --before.
+
+    With --restore, copy the given keys (ex. bookmarks, visibleheads) from
+    the root selected by --root or --before, and write them as a new root.
+
+    --root accepts a root index as shown by --history, or a hex prefix of
+    a root id.
+
+    With --history and -T/--template, the output can be 'json', 'jsonl', or
+    a template string. Template keywords: ``index``, ``date`` (seconds
+    since epoch), ``id`` and ``message``."#,
+    );
+}
+
+define_flags! {
+    pub struct DebugMetaLogOpts {
+        /// select time range
+        #[short('t')]
+        time_range: Vec<String>,
+
+        /// list roots stored in metalog
+        history: bool,
+
+        /// show keys changed since the selected root
+        diff: bool,
+
+        /// restore the given key from the selected root
+        restore: Vec<String>,
+
+        /// select a root by index or hex prefix
+        #[short('r')]
+        root: String,
+
+        /// select the last root before the given time
+        before: String,
+
+        formatter_opts: FormatterOpts,
+    }
+}
+
+pub fn debugmetalog(opts: DebugMetaLogOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    if !opts.history && !opts.diff && opts.restore.is_empty() {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let path = repo.shared_dot_hg_path().join("store").join("metalog");
+    if opts.history {
+        return history(&opts, io, &path);
+    }
+
+    // Like Python, metalog is only changed with the repo lock held. Take it
+    // before writing anything, so Python can wait for it instead.
+    let _lock = if opts.restore.is_empty() {
+        None
+    } else {
+        match lock::try_lock_store(&repo)? {
+            Some(lock) => Some(lock),
+            None => return Err(errors::FallbackToPython.into()),
+        }
+    };
+
+    let info = select_root(&opts, &path)?;
+    let old = MetaLog::open(&path, Some(info.id))?;
+    let mut current = MetaLog::open(&path, None)?;
+
+    if opts.diff {
+        for diff in old.diff(&current)? {
+            match diff.change {
+                KeyChange::Added(_) => io.write(format!("{}: added\n", diff.key))?,
+                KeyChange::Removed(_) => io.write(format!("{}: removed\n", diff.key))?,
+                KeyChange::Changed { old, new } => {
+                    io.write(format!("{}: changed\n", diff.key))?;
+                    let opts = xdiff::HeaderlessDiffOpts::default();
+                    io.write(xdiff::diff_unified_headerless(&old, &new, opts))?;
+                }
+            }
+        }
+    }
+
+    if !opts.restore.is_empty() {
+        let keys: Vec<&str> = opts.restore.iter().map(|s| s.as_str()).collect();
+        current.restore_keys(&old, &keys)?;
+        let message = format!("restore {} from root {}", keys.join(", "), info.id.to_hex());
+        let mut commit_opts = CommitOptions::default();
+        commit_opts.message = &message;
+        commit_opts.timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let id = current.commit(commit_opts)?;
+        io.write(format!("{} (new root {})\n", message, id.to_hex()))?;
+    }
+
+    Ok(0)
+}
+
+fn history(opts: &DebugMetaLogOpts, io: &mut IO, path: &Path) -> Result<u8> {
+    let mut ranges = Vec::new();
+    for time_range in opts.time_range.iter() {
+        match HgTime::parse_range(time_range) {
+            Some(range) => ranges.push(range),
+            None => {
+                return Err(errors::Abort(format!("invalid date: {}", time_range).into()).into());
+            }
+        }
+    }
+
+    let mut formatter = Formatter::new(
+        &opts.formatter_opts.template,
+        &["index", "date", "id", "message"],
+    )?;
+    let infos = MetaLog::history(path)?.collect::<metalog::Result<Vec<RootInfo>>>()?;
+    for info in infos.into_iter().rev() {
+        let time = HgTime {
+            unixtime: info.timestamp as i64,
+            offset: 0,
+        };
+        if !ranges.is_empty() && !ranges.iter().any(|r| r.contains(&time)) {
+            continue;
+        }
+        let record = Record::new()
+            .field("index", info.index)
+            .field("date", info.timestamp)
+            .field("id", info.id.to_hex())
+            .field("message", info.message.as_str());
+        let plain = format!(
+            "{:>5} {} {} {}\n",
+            info.index,
+            time.to_utc().format("%Y-%m-%d %H:%M:%S"),
+            info.id.to_hex(),
+            info.message.lines().next().unwrap_or("")
+        );
+        formatter.write(io, &record, plain)?;
+    }
+    formatter.end(io)?;
+    Ok(0)
+}
+
+/// Find the root selected by --root or --before.
+fn select_root(opts: &DebugMetaLogOpts, path: &Path) -> Result<RootInfo> {
+    if !opts.before.is_empty() {
+        let time = match HgTime::parse(&opts.before) {
+            Some(time) => time,
+            None => {
+                return Err(errors::Abort(format!("invalid date: {}", opts.before).into()).into());
+            }
+        };
+        return Ok(MetaLog::find_root_before(
+            path,
+            time.unixtime.max(0) as u64,
+        )?);
+    }
+    if opts.root.is_empty() {
+        return Err(errors::Abort("--root or --before is required".into()).into());
+    }
+
+    let infos = MetaLog::history(path)?.collect::<metalog::Result<Vec<RootInfo>>>()?;
+    if let Ok(index) = opts.root.parse::<usize>() {
+        if let Some(info) = infos.get(index) {
+            return Ok(info.clone());
+        }
+    }
+    let matched: Vec<&RootInfo> = infos
+        .iter()
+        .filter(|info| info.id.to_hex().starts_with(&opts.root))
+        .collect();
+    match matched.len() {
+        1 => Ok(matched[0].clone()),
+        0 => Err(errors::Abort(format!("unknown root: {}", opts.root).into()).into()),
+        _ => Err(errors::Abort(format!("ambiguous root: {}", opts.root).into()).into()),
+    }
+}
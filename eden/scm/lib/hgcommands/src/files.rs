@@ -0,0 +1,356 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Native `hg files -r REV`.
+//!
+//! The manifest of the revision is walked with the matcher, so directories
+//! that cannot match are never fetched, and missing trees are fetched one
+//! level at a time. Listing the working copy, and patterns that need the
+//! Python matcher like `re:` or `set:`, fall back to Python.
+
+use crate::commands::{FormatterOpts, WalkOpts};
+use crate::update::{
+    self, has_supported_store, manifest_node, open_changelog, open_stores, read_bookmarks,
+    relative_path, LocalOrRemoteStore,
+};
+use anyhow::Result;
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    formatter::{Formatter, Record},
+    io::IO,
+    repo::Repo,
+};
+use cliparser::define_flags;
+use manifest::{File, Manifest};
+use manifest_tree::TreeManifest;
+use pathmatcher::{DifferenceMatcher, IntersectMatcher, Matcher, TreeMatcher};
+use treestate::dirstate::Dirstate;
+use types::HgId;
+
+use std::path::Path;
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        files,
+        "files|fi|fil|file",
+        r#"list tracked files
+
+    Print files under Mercurial control in the working directory or
+    specified revision for given files (excluding removed files).
+    Files can be specified as filenames or filesets.
+
+    If no files are given to match, this command prints the names
+    of all files under Mercurial control.
+
+    .. container:: verbose
+
+      Examples:
+
+      - list all files under the current directory::
+
+          hg files .
+
+      - shows sizes and flags for current revision::
+
+          hg files -vr .
+
+      - list all files named README::
+
+          hg files -I "**/README"
+
+      - list all binary files::
+
+          hg files "set:binary()"
+
+      - find files containing a regular expression::
+
+          hg files "set:grep('bob')"
+
+      - search tracked file contents with xargs and grep::
+
+          hg files -0 | xargs -0 grep foo
+
+    See :hg:`help patterns` and :hg:`help filesets` for more information
+    on specifying file patterns.
+
+    Returns 0 if a match is found, 1 otherwise."#,
+    );
+}
+
+define_flags! {
+    pub struct FilesOpts {
+        /// search the repository as it is in REV
+        #[short('r')]
+        rev: String,
+
+        /// end filenames with NUL, for use with xargs
+        #[short('0')]
+        print0: bool,
+
+        walk_opts: WalkOpts,
+        formatter_opts: FormatterOpts,
+
+        #[args]
+        args: Vec<String>,
+    }
+}
+
+pub fn files(opts: FilesOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    // The working copy needs the dirstate to hide removed files.
+    if opts.rev.is_empty() || opts.rev == "wdir()" {
+        return Err(errors::FallbackToPython.into());
+    }
+    // Python prints the size and flags of each file in verbose mode.
+    let config = repo.config();
+    if config.get_or_default::<bool>("ui", "verbose")?
+        || config.get_or_default::<bool>("ui", "debug")?
+    {
+        return Err(errors::FallbackToPython.into());
+    }
+    let cwd = std::env::current_dir()?;
+    let matcher = match build_matcher(repo.path(), &cwd, &opts.args, &opts.walk_opts)? {
+        Some(matcher) => matcher,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+    let (manifest, _) = match open_revision(&repo, &opts.rev)? {
+        Some(revision) => revision,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+
+    let end = if opts.print0 { '\0' } else { '\n' };
+    // Python supports more template keywords, like size and flags, and styles.
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["path", "abspath"])
+        .map_err(|_| errors::FallbackToPython)?;
+    let files = list_files(&manifest, &*matcher)?;
+    for file in files.iter() {
+        let path = relative_path(repo.path(), &cwd, &file.path);
+        let plain = format!("{}{}", path, end);
+        let record = Record::new()
+            .field("abspath", file.path.as_str())
+            .field("path", path);
+        formatter.write(io, &record, plain)?;
+    }
+    formatter.end(io)?;
+
+    Ok(if files.is_empty() { 1 } else { 0 })
+}
+
+/// Open the manifest of `rev`, and the store of file contents. Return `None`
+/// if the repo or the revision needs Python.
+pub(crate) fn open_revision(
+    repo: &Repo,
+    rev: &str,
+) -> Result<Option<(TreeManifest, LocalOrRemoteStore)>> {
+    if !has_supported_store(repo)? {
+        return Ok(None);
+    }
+    let config = repo.config();
+    let store_path = repo.shared_dot_hg_path().join("store");
+    let dirstate = Dirstate::read(repo.dot_hg_path())?;
+    let changelog = open_changelog(&store_path)?;
+    let bookmarks = read_bookmarks(config, &store_path)?;
+    let current = HgId::from_byte_array(dirstate.p1);
+    let node = match update::resolve(rev, &changelog, &store_path, &bookmarks, current, config)? {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+    let (file_store, tree_store) = open_stores(config, &store_path)?;
+    let manifest = TreeManifest::durable(tree_store, manifest_node(&changelog, node)?);
+    Ok(Some((manifest, file_store)))
+}
+
+/// Files matched by `matcher`, sorted by path.
+pub(crate) fn list_files(manifest: &TreeManifest, matcher: &dyn Matcher) -> Result<Vec<File>> {
+    let mut files = manifest.files(&matcher).collect::<Result<Vec<_>>>()?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Build the matcher for file patterns and `-I` / `-X` patterns. Return
+/// `None` for patterns that need Python, and for patterns outside the repo
+/// so Python reports them.
+pub(crate) fn build_matcher(
+    root: &Path,
+    cwd: &Path,
+    patterns: &[String],
+    walk_opts: &WalkOpts,
+) -> Result<Option<Box<dyn Matcher>>> {
+    let cwd: Vec<String> = match cwd.strip_prefix(root) {
+        Ok(cwd) => cwd
+            .iter()
+            .map(|c| c.to_string_lossy().into_owned())
+            .collect(),
+        Err(_) if patterns.is_empty() && walk_opts.include.is_empty() => Vec::new(),
+        Err(_) => return Ok(None),
+    };
+
+    let mut matchers: Vec<Box<dyn Matcher>> = Vec::new();
+    for (patterns, default_kind) in &[(patterns, "relpath"), (&walk_opts.include[..], "glob")] {
+        if !patterns.is_empty() {
+            match pattern_matcher(patterns, default_kind, &cwd)? {
+                Some(matcher) => matchers.push(matcher),
+                None => return Ok(None),
+            }
+        }
+    }
+    let matcher = Box::new(IntersectMatcher::new(matchers));
+    if walk_opts.exclude.is_empty() {
+        return Ok(Some(matcher));
+    }
+    match pattern_matcher(&walk_opts.exclude, "glob", &cwd)? {
+        Some(exclude) => Ok(Some(Box::new(DifferenceMatcher::new(matcher, exclude)))),
+        None => Ok(None),
+    }
+}
+
+/// Match any of `patterns`. Like in Python, a pattern also matches
+/// everything under the directories it matches.
+fn pattern_matcher(
+    patterns: &[String],
+    default_kind: &str,
+    cwd: &[String],
+) -> Result<Option<Box<dyn Matcher>>> {
+    let mut rules = Vec::new();
+    for pattern in patterns {
+        let glob = match pattern_to_glob(pattern, default_kind, cwd) {
+            Some(glob) => glob,
+            None => return Ok(None),
+        };
+        if glob.is_empty() {
+            rules.push("**".to_string());
+        } else {
+            rules.push(format!("{}/**", glob));
+            rules.push(glob);
+        }
+    }
+    Ok(Some(Box::new(TreeMatcher::from_rules(rules.iter())?)))
+}
+
+/// Convert a pattern to a glob relative to the repo root. Only `path:`,
+/// `relpath:`, `glob:` and `relglob:` are supported.
+fn pattern_to_glob(pattern: &str, default_kind: &str, cwd: &[String]) -> Option<String> {
+    let (kind, pat) = match pattern.find(':') {
+        Some(index) if is_pattern_kind(&pattern[..index]) => {
+            (&pattern[..index], &pattern[index + 1..])
+        }
+        _ => (default_kind, pattern),
+    };
+    let cwd: Vec<String> = cwd.iter().map(|c| pathmatcher::plain_to_glob(c)).collect();
+    let (base, pat) = match kind {
+        "path" => (Vec::new(), pathmatcher::plain_to_glob(pat)),
+        "relpath" => (cwd, pathmatcher::plain_to_glob(pat)),
+        "glob" => (cwd, pathmatcher::normalize_glob(pat)),
+        "relglob" => {
+            let glob = pathmatcher::normalize_glob(pat);
+            return Some(format!("**/{}", glob.trim_end_matches('/')));
+        }
+        _ => return None,
+    };
+
+    // Resolve "." and ".." like Python's canonpath.
+    let mut components = base;
+    for component in pat.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            _ => components.push(component.to_string()),
+        }
+    }
+    Some(components.join("/"))
+}
+
+/// Whether `prefix` looks like a pattern kind. Unsupported kinds like `re`
+/// or `set` fall back to Python, which also reports unknown kinds.
+fn is_pattern_kind(prefix: &str) -> bool {
+    !prefix.is_empty()
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::RepoPath;
+
+    fn walk_opts(include: &[&str], exclude: &[&str]) -> WalkOpts {
+        WalkOpts {
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    fn matches(matcher: &dyn Matcher, path: &str) -> bool {
+        matcher.matches_file(RepoPath::from_str(path).unwrap())
+    }
+
+    #[test]
+    fn test_pattern_to_glob() {
+        let cwd = vec!["a".to_string(), "b".to_string()];
+        let glob = |pattern, default_kind| pattern_to_glob(pattern, default_kind, &cwd);
+
+        assert_eq!(glob("x", "relpath"), Some("a/b/x".to_string()));
+        assert_eq!(glob("./x/", "relpath"), Some("a/b/x".to_string()));
+        assert_eq!(glob("../x", "relpath"), Some("a/x".to_string()));
+        assert_eq!(glob("../..", "relpath"), Some("".to_string()));
+        assert_eq!(glob("../../..", "relpath"), None);
+        assert_eq!(glob("relpath:x*", "glob"), Some("a/b/x\\*".to_string()));
+        assert_eq!(glob("path:x", "relpath"), Some("x".to_string()));
+        assert_eq!(glob("path:../x", "relpath"), None);
+        assert_eq!(glob("*.c", "glob"), Some("a/b/*.c".to_string()));
+        assert_eq!(glob("glob:../*.c", "relpath"), Some("a/*.c".to_string()));
+        assert_eq!(glob("relglob:*.c", "relpath"), Some("**/*.c".to_string()));
+        assert_eq!(glob("re:.*", "relpath"), None);
+        assert_eq!(glob("set:added()", "relpath"), None);
+        // Not a pattern kind.
+        assert_eq!(glob("C:x", "relpath"), Some("a/b/C:x".to_string()));
+    }
+
+    #[test]
+    fn test_build_matcher() {
+        let root = Path::new("/repo");
+        let build = |cwd: &str, patterns: &[&str], walk_opts: &WalkOpts| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            build_matcher(root, Path::new(cwd), &patterns, walk_opts).unwrap()
+        };
+        let none = walk_opts(&[], &[]);
+
+        let matcher = build("/repo", &[], &none).unwrap();
+        assert!(matches(&*matcher, "a/x.c"));
+
+        // Patterns are relative to the current directory.
+        let matcher = build("/repo/d", &["x.c", "../e"], &none).unwrap();
+        assert!(matches(&*matcher, "d/x.c"));
+        assert!(matches(&*matcher, "e/y.c"));
+        assert!(!matches(&*matcher, "x.c"));
+        assert!(!matches(&*matcher, "d/y.c"));
+
+        // So are -I and -X globs.
+        let matcher = build("/repo/d", &["."], &walk_opts(&["**.c"], &["y.c"])).unwrap();
+        assert!(matches(&*matcher, "d/x.c"));
+        assert!(matches(&*matcher, "d/e/y.c"));
+        assert!(!matches(&*matcher, "d/y.c"));
+        assert!(!matches(&*matcher, "d/x.h"));
+        assert!(!matches(&*matcher, "b/x.c"));
+
+        let matcher = build("/repo", &[], &walk_opts(&[], &["path:d"])).unwrap();
+        assert!(matches(&*matcher, "x.c"));
+        assert!(!matches(&*matcher, "d/x.c"));
+
+        // Outside of the repo, only the whole repo can be listed natively.
+        assert!(build("/", &[], &none).is_some());
+        assert!(build("/", &["repo/x.c"], &none).is_none());
+        assert!(build("/", &[], &walk_opts(&["*.c"], &[])).is_none());
+
+        // Patterns Python handles.
+        assert!(build("/repo", &["re:.*"], &none).is_none());
+        assert!(build("/repo", &[], &walk_opts(&[], &["set:binary()"])).is_none());
+    }
+}
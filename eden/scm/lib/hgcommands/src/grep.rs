@@ -0,0 +1,330 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Native `hg grep -r REV`.
+//!
+//! Files of the revision are listed like `hg files`, their contents are read
+//! from the store in batches, and each batch is searched by a pool of
+//! threads. Searching the working copy is done by Python with an external
+//! grep.
+
+use crate::commands::WalkOpts;
+use crate::files::{build_matcher, list_files, open_revision};
+use crate::update::{num_threads, relative_path};
+use anyhow::{format_err, Result};
+use bytes::Bytes;
+use checkout::FileStore;
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    io::IO,
+    repo::Repo,
+};
+use cliparser::define_flags;
+use manifest::FileType;
+use regex::bytes::{Regex, RegexBuilder};
+use types::Key;
+
+use std::sync::Arc;
+use std::thread;
+
+/// Number of files read from the store at once.
+const BATCH_SIZE: usize = 1000;
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        grep,
+        "grep|gre",
+        r#"search for a pattern in tracked files in the working directory
+
+    The default regexp style is POSIX basic regexps. If no FILE parameters are
+    passed in, the current directory and its subdirectories will be searched.
+
+    With --rev, files are searched as they are in REV, without checking it
+    out. The pattern then uses the syntax of Rust regular expressions, which
+    is close to -E.
+
+    For the old 'hg grep', which searches through history, see 'histgrep'."#,
+    );
+}
+
+define_flags! {
+    pub struct GrepOpts {
+        /// print NUM lines of trailing context
+        #[short('A')]
+        after_context: String,
+
+        /// print NUM lines of leading context
+        #[short('B')]
+        before_context: String,
+
+        /// print NUM lines of output context
+        #[short('C')]
+        context: String,
+
+        /// ignore case when matching
+        #[short('i')]
+        ignore_case: bool,
+
+        /// print only filenames that match
+        #[short('l')]
+        files_with_matches: bool,
+
+        /// print matching line numbers
+        #[short('n')]
+        line_number: bool,
+
+        /// select non-matching lines
+        #[short('V')]
+        invert_match: bool,
+
+        /// match whole words only
+        #[short('w')]
+        word_regexp: bool,
+
+        /// use POSIX extended regexps
+        #[short('E')]
+        extended_regexp: bool,
+
+        /// interpret pattern as fixed string
+        #[short('F')]
+        fixed_strings: bool,
+
+        /// use Perl-compatible regexps
+        #[short('P')]
+        perl_regexp: bool,
+
+        /// search the repository as it is in REV
+        #[short('r')]
+        rev: String,
+
+        walk_opts: WalkOpts,
+
+        #[args]
+        args: Vec<String>,
+    }
+}
+
+pub fn grep(opts: GrepOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    if opts.rev.is_empty() || opts.rev == "wdir()" || opts.args.is_empty() {
+        return Err(errors::FallbackToPython.into());
+    }
+    let searcher = Searcher::new(&opts)?;
+
+    let cwd = std::env::current_dir()?;
+    let patterns = if opts.args.len() > 1 {
+        opts.args[1..].to_vec()
+    } else {
+        vec![".".to_string()]
+    };
+    let matcher = match build_matcher(repo.path(), &cwd, &patterns, &opts.walk_opts)? {
+        Some(matcher) => matcher,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+    let (manifest, file_store) = match open_revision(&repo, &opts.rev)? {
+        Some(revision) => revision,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+
+    // Symlinks are not followed.
+    let files: Vec<_> = list_files(&manifest, &*matcher)?
+        .into_iter()
+        .filter(|file| file.meta.file_type != FileType::Symlink)
+        .collect();
+    let keys: Vec<Key> = files
+        .iter()
+        .map(|file| Key::new(file.path.clone(), file.meta.hgid))
+        .collect();
+    // Fetch missing contents in one request. If they cannot be fetched
+    // natively, fall back to Python before anything is printed.
+    file_store.prefetch(&keys)?;
+
+    let searcher = Arc::new(searcher);
+    let threads = num_threads(repo.config())?;
+    let mut found = false;
+    let mut printed = false;
+    for (batch, keys) in files.chunks(BATCH_SIZE).zip(keys.chunks(BATCH_SIZE)) {
+        let contents = file_store.get_files(keys)?;
+        let items = batch
+            .iter()
+            .map(|file| relative_path(repo.path(), &cwd, &file.path))
+            .zip(contents)
+            .collect();
+        for groups in search_parallel(&searcher, items, threads)? {
+            for group in groups {
+                if printed && searcher.has_context() {
+                    io.write("--\n")?;
+                }
+                io.write(group)?;
+                printed = true;
+                found = true;
+            }
+        }
+    }
+
+    Ok(if found { 0 } else { 1 })
+}
+
+/// Search `items` of display path and content using up to `threads`
+/// threads. Return the output groups of each item, in the same order.
+fn search_parallel(
+    searcher: &Arc<Searcher>,
+    items: Vec<(String, Bytes)>,
+    threads: usize,
+) -> Result<Vec<Vec<Vec<u8>>>> {
+    let count = items.len();
+    let threads = threads.min(count).max(1);
+    let mut chunks: Vec<Vec<(usize, String, Bytes)>> = vec![Vec::new(); threads];
+    for (index, (path, content)) in items.into_iter().enumerate() {
+        chunks[index % threads].push((index, path, content));
+    }
+    let handles: Vec<_> = chunks
+        .into_iter()
+        .map(|chunk| {
+            let searcher = searcher.clone();
+            thread::spawn(move || {
+                chunk
+                    .into_iter()
+                    .map(|(index, path, content)| (index, searcher.search(&path, &content)))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let mut results = vec![Vec::new(); count];
+    for handle in handles {
+        let chunk = handle
+            .join()
+            .map_err(|_| format_err!("grep worker thread panicked"))?;
+        for (index, groups) in chunk {
+            results[index] = groups;
+        }
+    }
+    Ok(results)
+}
+
+struct Searcher {
+    regex: Regex,
+    invert: bool,
+    before: usize,
+    after: usize,
+    line_number: bool,
+    files_with_matches: bool,
+}
+
+impl Searcher {
+    fn new(opts: &GrepOpts) -> Result<Self> {
+        let context = parse_context(&opts.context)?;
+        let before = match opts.before_context.as_str() {
+            "" => context,
+            value => parse_context(value)?,
+        };
+        let after = match opts.after_context.as_str() {
+            "" => context,
+            value => parse_context(value)?,
+        };
+
+        let mut pattern = if opts.fixed_strings {
+            regex::escape(&opts.args[0])
+        } else {
+            opts.args[0].clone()
+        };
+        if opts.word_regexp {
+            pattern = format!(r"\b(?:{})\b", pattern);
+        }
+        // Lines are matched one by one. Multi-line mode lets a whole file be
+        // checked first, with "^" and "$" still matching at line boundaries.
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(opts.ignore_case)
+            .multi_line(true)
+            .build()
+            .map_err(|e| errors::Abort(format!("invalid pattern: {}", e).into()))?;
+
+        Ok(Searcher {
+            regex,
+            invert: opts.invert_match,
+            before,
+            after,
+            line_number: opts.line_number,
+            files_with_matches: opts.files_with_matches,
+        })
+    }
+
+    fn has_context(&self) -> bool {
+        !self.files_with_matches && (self.before > 0 || self.after > 0)
+    }
+
+    /// Search `content`. Return the output lines for the file, in groups of
+    /// adjacent lines. Groups are separated by "--" if context is shown.
+    fn search(&self, path: &str, content: &[u8]) -> Vec<Vec<u8>> {
+        // Like grep --binary-files=without-match.
+        if content.is_empty()
+            || content.contains(&0)
+            || (!self.invert && !self.regex.is_match(content))
+        {
+            return Vec::new();
+        }
+        let content = if content.ends_with(b"\n") {
+            &content[..content.len() - 1]
+        } else {
+            content
+        };
+        let lines: Vec<&[u8]> = content.split(|&b| b == b'\n').collect();
+
+        // `Some(true)` for matching lines, `Some(false)` for context lines.
+        let mut marks: Vec<Option<bool>> = vec![None; lines.len()];
+        for (index, line) in lines.iter().enumerate() {
+            if self.regex.is_match(line) == self.invert {
+                continue;
+            }
+            if self.files_with_matches {
+                return vec![format!("{}\n", path).into_bytes()];
+            }
+            marks[index] = Some(true);
+            let start = index.saturating_sub(self.before);
+            let end = (index + self.after).min(lines.len() - 1);
+            for mark in marks[start..=end].iter_mut() {
+                mark.get_or_insert(false);
+            }
+        }
+
+        let mut groups = Vec::new();
+        let mut group = Vec::new();
+        let mut last = None;
+        for (index, mark) in marks.iter().enumerate() {
+            let is_match = match mark {
+                Some(is_match) => *is_match,
+                None => continue,
+            };
+            if last.map_or(false, |last| last + 1 != index) {
+                groups.push(std::mem::take(&mut group));
+            }
+            let separator = if is_match { ':' } else { '-' };
+            group.extend_from_slice(path.as_bytes());
+            group.push(separator as u8);
+            if self.line_number {
+                group.extend_from_slice(format!("{}{}", index + 1, separator).as_bytes());
+            }
+            group.extend_from_slice(lines[index]);
+            group.push(b'\n');
+            last = Some(index);
+        }
+        if !group.is_empty() {
+            groups.push(group);
+        }
+        groups
+    }
+}
+
+fn parse_context(value: &str) -> Result<usize> {
+    if value.is_empty() {
+        return Ok(0);
+    }
+    value.parse().map_err(|_| {
+        errors::Abort(format!("{}: invalid context length argument", value).into()).into()
+    })
+}
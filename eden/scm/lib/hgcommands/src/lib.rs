@@ -5,11 +5,21 @@
  * GNU General Public License version 2.
  */
 
+mod blackbox;
 pub mod commands;
+#[cfg(unix)]
+mod commandserver;
+mod completion;
+mod config;
+mod debugmetalog;
+mod files;
+mod grep;
 mod hgpython;
+mod lock;
 mod python;
 mod run;
 mod status;
+mod update;
 
 pub use crate::hgpython::HgPython;
 pub use run::run_command;
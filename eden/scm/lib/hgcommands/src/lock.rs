@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Repo locks compatible with `edenscm/mercurial/lock.py`.
+//!
+//! A lock is a file holding `namespace:pid`, with an exclusive `flock` on it
+//! while it is held. Creating or removing stale locks is done while holding
+//! a `flock` on the parent directory, and a symlink placeholder is created
+//! first so legacy symlink locks are respected. See `posix.makelock`.
+
+use anyhow::Result;
+use clidispatch::repo::Repo;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A held lock. Released on drop.
+pub(crate) struct RepoLock {
+    file: File,
+    path: PathBuf,
+}
+
+/// Take the store lock, like `localrepo.lock`. Return `None` if it is held
+/// by another process.
+pub(crate) fn try_lock_store(repo: &Repo) -> Result<Option<RepoLock>> {
+    RepoLock::try_lock(&repo.shared_dot_hg_path().join("store"), "lock")
+}
+
+/// The working copy lock. Like `localrepo.wlock`, the wlock of the shared
+/// repo is also taken for shared repos.
+pub(crate) struct WorkingCopyLock {
+    _local: RepoLock,
+    _shared: Option<RepoLock>,
+}
+
+/// Take the working copy lock. Return `None` if it is held by another
+/// process.
+pub(crate) fn try_lock_working_copy(repo: &Repo) -> Result<Option<WorkingCopyLock>> {
+    let shared = if repo.shared_dot_hg_path() != repo.dot_hg_path() {
+        match RepoLock::try_lock(repo.shared_dot_hg_path(), "wlock")? {
+            Some(lock) => Some(lock),
+            None => return Ok(None),
+        }
+    } else {
+        None
+    };
+    let local = RepoLock::try_lock(repo.dot_hg_path(), "wlock")?;
+    Ok(local.map(|local| WorkingCopyLock {
+        _local: local,
+        _shared: shared,
+    }))
+}
+
+impl RepoLock {
+    /// Take lock `name` in `dir`. Return `None` if the lock is held.
+    #[cfg(unix)]
+    pub(crate) fn try_lock(dir: &Path, name: &str) -> Result<Option<Self>> {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let path = dir.join(name);
+        let info = format!("{}:{}", current_namespace(), std::process::id());
+        let _dir_lock = DirLock::lock(dir)?;
+
+        // Remove a stale lock.
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&path)
+        {
+            Ok(file) => match flock(&file, libc::LOCK_EX | libc::LOCK_NB) {
+                Ok(()) => fs::remove_file(&path)?,
+                Err(e) if e.raw_os_error() == Some(libc::EWOULDBLOCK) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            },
+            Err(e) if e.raw_os_error() == Some(libc::ELOOP) => {
+                let old_info = fs::read_link(&path)?;
+                if !is_symlink_lock_stale(&old_info.to_string_lossy(), &info) {
+                    return Ok(None);
+                }
+                fs::remove_file(&path)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // The symlink placeholder stops legacy code from taking the lock.
+        match std::os::unix::fs::symlink(&info, &path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let tmp_path = dir.join(format!("makelock{}", std::process::id()));
+        let _ = fs::remove_file(&tmp_path);
+        let result = (|| -> io::Result<File> {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&tmp_path)?;
+            file.set_permissions(fs::Permissions::from_mode(0o664))?;
+            flock(&file, libc::LOCK_EX | libc::LOCK_NB)?;
+            file.write_all(info.as_bytes())?;
+            fs::rename(&tmp_path, &path)?;
+            Ok(file)
+        })();
+        match result {
+            Ok(file) => Ok(Some(RepoLock { file, path })),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                let _ = fs::remove_file(&path);
+                Err(e.into())
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn try_lock(_dir: &Path, _name: &str) -> Result<Option<Self>> {
+        Ok(None)
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        // Like `posix.releaselock`. The flock is still held, so nobody else
+        // can have taken the lock.
+        let _ = fs::remove_file(&self.path);
+        #[cfg(unix)]
+        let _ = flock(&self.file, libc::LOCK_UN);
+    }
+}
+
+/// A `flock` on a directory, to make decisions about lock files atomic.
+#[cfg(unix)]
+struct DirLock {
+    _file: File,
+}
+
+#[cfg(unix)]
+impl DirLock {
+    fn lock(dir: &Path) -> io::Result<Self> {
+        let file = File::open(dir)?;
+        flock(&file, libc::LOCK_EX)?;
+        Ok(DirLock { _file: file })
+    }
+}
+
+#[cfg(unix)]
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Like `lockinfo.getcurrentnamespace`.
+#[cfg(unix)]
+fn current_namespace() -> String {
+    let host = hostname();
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(meta) = fs::metadata("/proc/self/ns/pid") {
+            return format!("{}/{:x}", host, meta.ino());
+        }
+    }
+    host
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return "localhost".to_string();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Like `posix._issymlinklockstale`. Return `true` only if it is certain that
+/// the process holding the lock is dead.
+#[cfg(unix)]
+fn is_symlink_lock_stale(old_info: &str, new_info: &str) -> bool {
+    let split = |info: &str| -> Option<(String, String)> {
+        let index = info.find(':')?;
+        Some((info[..index].to_string(), info[index + 1..].to_string()))
+    };
+    let (old_host, old_pid) = match split(old_info) {
+        Some(parts) => parts,
+        None => return false,
+    };
+    match split(new_info) {
+        Some((new_host, _)) if new_host == old_host => {}
+        _ => return false,
+    }
+    let pid: libc::pid_t = match old_pid.parse() {
+        Ok(pid) => pid,
+        Err(_) => return false,
+    };
+    let failed = unsafe { libc::kill(pid, 0) } != 0;
+    failed && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+}
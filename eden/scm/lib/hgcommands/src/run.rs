@@ -38,6 +38,94 @@ pub fn run_command(args: Vec<String>, io: &mut clidispatch::io::IO) -> i32 {
         return HgPython::new(&args).run_hg(args, io);
     }
 
+    run_logged(args.clone(), now, io, |io| {
+        let cwd = match current_dir(io) {
+            Err(e) => {
+                let _ = io.write_err(format!("abort: cannot get current directory: {}\n", e));
+                return Some(exitcode::IOERR);
+            }
+            Ok(dir) => dir,
+        };
+
+        // env_logger cannot be inited twice. So this will fail (as expected)
+        // if hgcommands is nested (ex. for "hg continue").
+        let _ = env_logger::try_init();
+
+        let table = commands::table();
+        let exit_code = match dispatch::dispatch(&table, args[1..].to_vec(), io) {
+            Ok(ret) => ret as i32,
+            Err(err) => {
+                if !should_fallback(&err) {
+                    errors::print_error(&err, io);
+                    return Some(255);
+                }
+
+                // Change the current dir back to the original so it is not surprising to the Python
+                // code.
+                let _ = env::set_current_dir(cwd);
+
+                HgPython::new(&args).run_hg(args, io)
+            }
+        };
+        Some(exit_code)
+    })
+    .unwrap_or(255)
+}
+
+/// Run a command for the command server, with the same tracing and blackbox
+/// logging as [`run_command`]. `args` do not include the program name.
+///
+/// Commands that fall back to Python are run again by the client, which
+/// logs them. So their finish is not logged here.
+#[cfg(unix)]
+pub(crate) fn run_served(
+    args: &[String],
+    io: &mut clidispatch::io::IO,
+    run: &mut dyn FnMut(&mut clidispatch::io::IO) -> Result<u8>,
+) -> Result<u8> {
+    // Each served command has its own trace.
+    *pytracing::DATA.lock() = TracingData::new();
+
+    let mut full_args = vec!["hg".to_string()];
+    full_args.extend_from_slice(args);
+    let mut result = None;
+    run_logged(full_args, SystemTime::now(), io, |io| {
+        let ret = run(io);
+        let exit_code = match &ret {
+            Ok(code) => Some(*code as i32),
+            Err(err) if should_fallback(err) => None,
+            Err(_) => Some(255),
+        };
+        result = Some(ret);
+        exit_code
+    });
+    result.expect("run_logged calls run")
+}
+
+/// Whether a command that failed with `err` should be run by Python.
+fn should_fallback(err: &anyhow::Error) -> bool {
+    // XXX: Right now the Rust command table does not have all Python
+    // commands. Therefore Rust "UnknownCommand" needs a fallback.
+    //
+    // Ideally the Rust command table has Python command information and
+    // there is no fallback path (ex. all commands are in Rust, and the
+    // Rust implementation might just call into Python cmdutil utilities).
+    err.downcast_ref::<errors::FallbackToPython>().is_some()
+        || err.downcast_ref::<errors::UnknownCommand>().is_some()
+}
+
+/// Set up tracing, log the start of `args` to blackbox, and call `run`.
+/// Then log the finish and write the trace requested by
+/// `EDENSCM_TRACE_OUTPUT`.
+///
+/// `run` returns the exit code, or `None` if the command did not finish
+/// here. In that case, only the start is logged.
+fn run_logged(
+    args: Vec<String>,
+    now: SystemTime,
+    io: &mut clidispatch::io::IO,
+    run: impl FnOnce(&mut clidispatch::io::IO) -> Option<i32>,
+) -> Option<i32> {
     // Setup tracing early since "log_start" will use it immediately.
     // The tracing clock starts ticking from here.
     let (_tracing_level, tracing_data) = setup_tracing();
@@ -52,7 +140,7 @@ pub fn run_command(args: Vec<String>, io: &mut clidispatch::io::IO) -> i32 {
     // which is a bit more desiable. Since run_command is very close to process
     // start, it should reflect the duration of the command relatively
     // accurately, at least for non-chg cases.
-    let span = log_start(args.clone(), now);
+    let span = log_start(args, now);
 
     // Ad-hoc environment variable: EDENSCM_TRACE_OUTPUT. A more standard way
     // to access the data is via the blackbox interface.
@@ -63,58 +151,19 @@ pub fn run_command(args: Vec<String>, io: &mut clidispatch::io::IO) -> i32 {
         std::env::remove_var("EDENSCM_TRACE_OUTPUT");
     }
 
-    let cwd = match current_dir(io) {
-        Err(e) => {
-            let _ = io.write_err(format!("abort: cannot get current directory: {}\n", e));
-            return exitcode::IOERR;
-        }
-        Ok(dir) => dir,
-    };
-
-    // env_logger cannot be inited twice. So this will fail (as expected)
-    // if hgcommands is nested (ex. for "hg continue").
-    let _ = env_logger::try_init();
-
     let exit_code = {
         let _guard = span.enter();
-        let table = commands::table();
-
-        let exit_code = match dispatch::dispatch(&table, args[1..].to_vec(), io) {
-            Ok(ret) => ret as i32,
-            Err(err) => {
-                let should_fallback = if err.downcast_ref::<errors::FallbackToPython>().is_some() {
-                    true
-                } else if err.downcast_ref::<errors::UnknownCommand>().is_some() {
-                    // XXX: Right now the Rust command table does not have all Python
-                    // commands. Therefore Rust "UnknownCommand" needs a fallback.
-                    //
-                    // Ideally the Rust command table has Python command information and
-                    // there is no fallback path (ex. all commands are in Rust, and the
-                    // Rust implementation might just call into Python cmdutil utilities).
-                    true
-                } else {
-                    false
-                };
-
-                if !should_fallback {
-                    errors::print_error(&err, io);
-                    return 255;
-                }
-
-                // Change the current dir back to the original so it is not surprising to the Python
-                // code.
-                let _ = env::set_current_dir(cwd);
-
-                HgPython::new(&args).run_hg(args, io)
-            }
-        };
-        span.record("exit_code", &exit_code);
+        let exit_code = run(io);
+        if let Some(exit_code) = exit_code {
+            span.record("exit_code", &exit_code);
+        }
         exit_code
     };
 
-    let _ = maybe_write_trace(io, &tracing_data, trace_output_path);
-
-    log_end(exit_code as u8, now, tracing_data, &span);
+    if let Some(exit_code) = exit_code {
+        let _ = maybe_write_trace(io, &tracing_data, trace_output_path);
+        log_end(exit_code as u8, now, tracing_data, &span);
+    }
 
     // Sync the blackbox before returning: this exit code is going to be used to process::exit(),
     // so we need to flush now.
@@ -180,7 +229,7 @@ fn maybe_write_trace(
     if let Some(path) = path {
         // A hardcoded minimal duration (in microseconds).
         let data = tracing_data.lock();
-        match write_trace(io, &path, &data) {
+        match write_trace(io, &path, "", &data) {
             Ok(_) => io.write_err(format!("(Trace was written to {})\n", &path))?,
             Err(err) => {
                 io.write_err(format!("(Failed to write Trace to {}: {})\n", &path, &err))?
@@ -193,6 +242,7 @@ fn maybe_write_trace(
 pub(crate) fn write_trace(
     io: &mut clidispatch::io::IO,
     path: &str,
+    format: &str,
     data: &TracingData,
 ) -> Result<()> {
     enum Format {
@@ -200,18 +250,37 @@ pub(crate) fn write_trace(
         TraceEventJSON,
         TraceEventGzip,
         SpansJSON,
+        OtlpJSON,
+        Folded,
     }
 
-    let format = if path.ends_with(".txt") {
-        Format::ASCII
-    } else if path.ends_with("spans.json") {
-        Format::SpansJSON
-    } else if path.ends_with(".json") {
-        Format::TraceEventJSON
-    } else if path.ends_with(".gz") {
-        Format::TraceEventGzip
-    } else {
-        Format::ASCII
+    let format = match format {
+        "ascii" => Format::ASCII,
+        "trace-event" => Format::TraceEventJSON,
+        "trace-event-gzip" => Format::TraceEventGzip,
+        "spans" => Format::SpansJSON,
+        "otlp" => Format::OtlpJSON,
+        "folded" => Format::Folded,
+        "" => {
+            if path.ends_with(".txt") {
+                Format::ASCII
+            } else if path.ends_with("spans.json") {
+                Format::SpansJSON
+            } else if path.ends_with("otlp.json") {
+                Format::OtlpJSON
+            } else if path.ends_with(".json") {
+                Format::TraceEventJSON
+            } else if path.ends_with(".gz") {
+                Format::TraceEventGzip
+            } else if path.ends_with(".folded") {
+                Format::Folded
+            } else {
+                Format::ASCII
+            }
+        }
+        _ => {
+            return Err(errors::Abort(format!("unknown trace format: {}", format).into()).into());
+        }
     };
 
     let mut out: Box<dyn Write> = if path == "-" || path.is_empty() {
@@ -245,6 +314,14 @@ pub(crate) fn write_trace(
             data.write_trace_event_json(&mut out, Default::default())?;
             out.flush()?;
         }
+        Format::OtlpJSON => {
+            data.write_otlp_json(&mut out, "hg")?;
+            out.flush()?;
+        }
+        Format::Folded => {
+            out.write_all(data.folded_stacks().as_bytes())?;
+            out.flush()?;
+        }
     }
 
     Ok(())
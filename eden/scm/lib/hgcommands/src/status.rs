@@ -10,6 +10,7 @@ use anyhow::Result;
 use clidispatch::{
     command::{CommandTable, Register},
     errors,
+    formatter::Formatter,
     io::IO,
     repo::Repo,
 };
@@ -107,6 +108,10 @@ pub(crate) fn register(table: &mut CommandTable) {
 
           hg status -v -t mardu
 
+    With -T/--template, the output can be 'json', 'jsonl', or a template
+    string. Template keywords: ``path``, ``status``, and ``copy`` (with
+    --copies).
+
     Returns 0 on success."#,
     );
 }
@@ -189,7 +194,6 @@ fn status(opts: StatusOpts, io: &mut IO, repo: Repo) -> Result<u8> {
         || !rev_check
         || !opts.walk_opts.include.is_empty()
         || !opts.walk_opts.exclude.is_empty()
-        || !opts.formatter_opts.template.is_empty()
         || !args_check
     {
         return Err(errors::FallbackToPython.into());
@@ -235,6 +239,11 @@ fn status(opts: StatusOpts, io: &mut IO, repo: Repo) -> Result<u8> {
         root_relative: opts.root_relative,
     };
 
+    // Python supports more template keywords and styles.
+    let mut formatter = Formatter::new(&opts.formatter_opts.template, &["path", "status", "copy"])
+        .map_err(|_| errors::FallbackToPython)?;
     let cwd = std::env::current_dir()?;
-    maybe_status_fastpath(repo.path(), &cwd, print_config, io)
+    let code = maybe_status_fastpath(repo.path(), &cwd, print_config, io, &mut formatter)?;
+    formatter.end(io)?;
+    Ok(code)
 }
@@ -0,0 +1,718 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Native `hg update`.
+//!
+//! Only the common case is handled: a non-merge update of a treemanifest,
+//! remotefilelog, treestate repo to a commit given by hash, bookmark or
+//! remote name, optionally with `--clean`. Everything else falls back to
+//! Python.
+
+use crate::lock;
+use anyhow::{format_err, Result};
+use bytes::Bytes;
+use checkout::{CheckoutPlan, FileStore};
+use clidispatch::{
+    command::{CommandTable, Register},
+    errors,
+    io::IO,
+    repo::Repo,
+};
+use cliparser::define_flags;
+use configparser::{
+    config::ConfigSet,
+    hg::{ByteCount, ConfigSetHgExt},
+};
+use edenapi::{EdenApi, EdenApiCurlClient};
+use manifest_tree::{TreeManifest, TreeStore};
+use metalog::MetaLog;
+use pathmatcher::{AlwaysMatcher, Matcher, TreeMatcher};
+use revisionstore::{
+    ContentStore, ContentStoreBuilder, EdenApiHgIdRemoteStore, HgIdDataStore, LocalStore,
+    RemoteDataStore, StoreKey,
+};
+use revlogindex::{Changelog, UnsupportedRevlog};
+use treestate::dirstate::Dirstate;
+use types::{HgId, Key, RepoPath};
+use vfs::{BatchWriter, VFS};
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub(crate) fn register(table: &mut CommandTable) {
+    table.register(
+        update,
+        "update|up|checkout|co|upd|upda|updat|che|chec|check|checko|checkou",
+        r#"check out a specific commit
+
+    Update your checkout to the given destination commit. More precisely, make
+    the destination commit the current commit and update the contents of all
+    files in your checkout to match their state in the destination commit.
+
+    By default, if you attempt to check out a commit while you have pending
+    changes, and the destination commit is not an ancestor or descendant of
+    the current commit, the checkout will abort. However, if the destination
+    commit is an ancestor or descendant of the current commit, the pending
+    changes will be merged into the new checkout.
+
+    Use one of the following flags to modify this behavior:
+
+    --check: abort if there are pending changes
+
+    --clean: permanently discard any pending changes (use with caution)
+
+    --merge: attempt to merge the pending changes into the new checkout, even
+    if the destination commit is not an ancestor or descendant of the current
+    commit
+
+    If merge conflicts occur during checkout, Mercurial enters an unfinished
+    merge state. If this happens, fix the conflicts manually and then run
+    hg commit to exit the unfinished merge state and save your changes in a
+    new commit. Alternatively, run hg checkout --clean to discard your pending
+    changes.
+
+    Specify null as the destination commit to get an empty checkout (sometimes
+    known as a bare repository).
+
+    Returns 0 on success, 1 if there are unresolved files."#,
+    );
+}
+
+define_flags! {
+    pub struct UpdateOpts {
+        /// discard uncommitted changes (no backup)
+        #[short('C')]
+        clean: bool,
+
+        /// require clean working directory
+        #[short('c')]
+        check: bool,
+
+        /// merge uncommitted changes
+        #[short('m')]
+        merge: bool,
+
+        /// tipmost revision matching date (ADVANCED)
+        #[short('d')]
+        date: String,
+
+        /// revision
+        #[short('r')]
+        rev: String,
+
+        /// update without activating bookmarks
+        inactive: bool,
+
+        /// resume interrupted update --merge (ADVANCED)
+        r#continue: bool,
+
+        /// specify merge tool
+        #[short('t')]
+        tool: String,
+
+        #[args]
+        args: Vec<String>,
+    }
+}
+
+/// Files in `.hg` that indicate an unfinished operation.
+const UNFINISHED_STATES: &[&str] = &[
+    "graftstate",
+    "updatemergestate",
+    "histedit-state",
+    "rebasestate",
+    "shelvedstate",
+    "merge/state",
+    "bisect.state",
+];
+
+/// Extensions that record working copy changes. They need Python.
+const RECORDING_EXTENSIONS: &[&str] = &["fsmonitor", "journal", "undo"];
+
+/// Destination of the checkout recorded in the journal, so a journal left
+/// by an interrupted checkout that Python finished can be told apart.
+const CHECKOUT_TARGET: &str = "checkout-journal.target";
+
+pub fn update(opts: UpdateOpts, io: &mut IO, repo: Repo) -> Result<u8> {
+    if opts.check
+        || opts.merge
+        || opts.r#continue
+        || !opts.date.is_empty()
+        || !opts.tool.is_empty()
+        || opts.args.len() + (!opts.rev.is_empty() as usize) != 1
+        || !cfg!(unix)
+    {
+        return Err(errors::FallbackToPython.into());
+    }
+    let config = repo.config();
+    if !config.get_or_default::<bool>("checkout", "use-rust")? {
+        return Err(errors::FallbackToPython.into());
+    }
+    let update_check = config.get("commands", "update.check");
+    if !opts.clean && update_check.as_deref() != Some("noconflict") {
+        return Err(errors::FallbackToPython.into());
+    }
+    if !is_supported_repo(&repo)? {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let dot_hg = repo.dot_hg_path().to_path_buf();
+    let store_path = repo.shared_dot_hg_path().join("store");
+    let _lock = match lock::try_lock_working_copy(&repo)? {
+        Some(lock) => lock,
+        // Let Python wait for the lock and report the holder.
+        None => return Err(errors::FallbackToPython.into()),
+    };
+
+    let mut dirstate = Dirstate::read(&dot_hg)?;
+    if dirstate.p2 != [0; 20] || dirstate.needs_repack() {
+        return Err(errors::FallbackToPython.into());
+    }
+    let old_node = HgId::from_byte_array(dirstate.p1);
+
+    let vfs = VFS::new(repo.path().to_path_buf())?;
+    let writer = BatchWriter::new(
+        vfs.clone(),
+        dot_hg.join("checkout-journal"),
+        num_threads(config)?,
+    );
+    let target_path = dot_hg.join(CHECKOUT_TARGET);
+    if dot_hg.join("updatestate").exists() {
+        // An interrupted native checkout can be rolled back. Otherwise let
+        // Python deal with the interrupted update.
+        if !(opts.clean && writer.is_interrupted()) {
+            return Err(errors::FallbackToPython.into());
+        }
+        writer.rollback()?;
+    } else if writer.is_interrupted() {
+        // Python removes "updatestate" after finishing an update. Only drop
+        // the backups if it finished the interrupted checkout.
+        let target = fs::read_to_string(&target_path).unwrap_or_default();
+        if target != old_node.to_hex() {
+            return Err(errors::FallbackToPython.into());
+        }
+        fs::remove_dir_all(dot_hg.join("checkout-journal"))?;
+        fs::remove_file(&target_path)?;
+    }
+
+    let changelog = open_changelog(&store_path)?;
+    let rev = if opts.rev.is_empty() {
+        &opts.args[0]
+    } else {
+        &opts.rev
+    };
+    let bookmarks = read_bookmarks(config, &store_path)?;
+    let new_node = match resolve(rev, &changelog, &store_path, &bookmarks, old_node, config)? {
+        Some(node) => node,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+    // Updating to a hidden commit makes it visible.
+    if !is_visible(&changelog, &store_path, new_node, &bookmarks, config)? {
+        return Err(errors::FallbackToPython.into());
+    }
+    let matcher = match sparse_matcher(&dot_hg)? {
+        Some(matcher) => matcher,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+
+    let (file_store, tree_store) = open_stores(config, &store_path)?;
+    let old = TreeManifest::durable(tree_store.clone(), manifest_node(&changelog, old_node)?);
+    let new = TreeManifest::durable(tree_store, manifest_node(&changelog, new_node)?);
+
+    let mut treestate = dirstate.open_tree(&dot_hg)?;
+    let mut plan = CheckoutPlan::new(&old, &new, &*matcher)?;
+    if opts.clean {
+        plan.discard_changes(&vfs, &mut treestate, &old, &new, &*matcher, &file_store)?;
+    } else {
+        let conflicts = plan.conflicts(&vfs, &mut treestate, &old, &file_store)?;
+        if !conflicts.is_empty() {
+            return Err(conflict_error(&repo, conflicts).into());
+        }
+    }
+
+    // Mark the update as in progress so Python commands notice if it gets
+    // interrupted.
+    fs::write(&target_path, new_node.to_hex())?;
+    fs::write(dot_hg.join("updatestate"), new_node.to_hex())?;
+    let stats = match plan.apply(&writer, &vfs, &file_store, &mut treestate) {
+        Ok(stats) => stats,
+        Err(e) => {
+            // Restore the working copy, so Python can start over after
+            // `FallbackToPython`, and other errors leave nothing to clean up.
+            writer.rollback()?;
+            fs::remove_file(dot_hg.join("updatestate"))?;
+            fs::remove_file(&target_path)?;
+            return Err(e);
+        }
+    };
+    dirstate.p1.copy_from_slice(new_node.as_ref());
+    dirstate.flush_tree(&mut treestate)?;
+    let min_size = config.get_or("treestate", "minrepackthreshold", || {
+        ByteCount::from(10u64 << 20)
+    })?;
+    let factor = config.get_or("treestate", "repackfactor", || 3)?;
+    dirstate.update_repack_threshold(min_size.value(), factor);
+    dirstate.write(&dot_hg)?;
+    fs::remove_file(dot_hg.join("updatestate"))?;
+    fs::remove_file(&target_path)?;
+
+    io.write(format!(
+        "{} files updated, 0 files merged, {} files removed, 0 files unresolved\n",
+        stats.updated, stats.removed
+    ))?;
+
+    let active_path = dot_hg.join("bookmarks.current");
+    let active = fs::read_to_string(&active_path).ok();
+    if !opts.inactive && bookmarks.iter().any(|(name, _)| name == rev) {
+        if active.as_deref() != Some(rev.as_str()) {
+            io.write(format!("(activating bookmark {})\n", rev))?;
+            fs::write(&active_path, rev)?;
+        }
+    } else if let Some(active) = active {
+        io.write(format!("(leaving bookmark {})\n", active))?;
+        fs::remove_file(&active_path)?;
+    }
+
+    Ok(0)
+}
+
+/// Check requirements and configs that the native update does not handle.
+fn is_supported_repo(repo: &Repo) -> Result<bool> {
+    if !has_supported_store(repo)? {
+        return Ok(false);
+    }
+
+    let config = repo.config();
+    // Hooks need Python.
+    let has_hooks = config
+        .keys("hooks")
+        .iter()
+        .any(|name| name.starts_with("preupdate") || name.starts_with("update"));
+    if has_hooks {
+        return Ok(false);
+    }
+    let has_recording_extensions = config.keys("extensions").iter().any(|name| {
+        let enabled = config
+            .get("extensions", name)
+            .map_or(false, |value| !value.starts_with('!'));
+        enabled && RECORDING_EXTENSIONS.contains(&name.trim_start_matches("hgext."))
+    });
+    if has_recording_extensions {
+        return Ok(false);
+    }
+
+    let dot_hg = repo.dot_hg_path();
+    if UNFINISHED_STATES
+        .iter()
+        .any(|name| dot_hg.join(name).exists())
+    {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Check that the store formats can be read natively: a treemanifest-only,
+/// remotefilelog, treestate repo.
+pub(crate) fn has_supported_store(repo: &Repo) -> Result<bool> {
+    let requires = read_lines(&repo.dot_hg_path().join("requires"))?;
+    let store_requires = read_lines(&repo.shared_dot_hg_path().join("store").join("requires"))?;
+    if !requires.contains("treestate")
+        || !requires.contains("remotefilelog")
+        || requires.contains("eden")
+        || store_requires.contains("zstorecommitdata")
+        || store_requires
+            .iter()
+            .any(|r| r.starts_with("exp-compression-"))
+    {
+        return Ok(false);
+    }
+
+    repo.config()
+        .get_or_default::<bool>("treemanifest", "treeonly")
+}
+
+/// Number of threads for writing or searching files. The work is IO bound,
+/// so the default is more than the number of CPUs.
+pub(crate) fn num_threads(config: &ConfigSet) -> Result<usize> {
+    config.get_or("checkout", "threads", || 16)
+}
+
+/// Resolve `rev` to a commit. Return `None` for anything that needs the
+/// revset language or Python extensions.
+pub(crate) fn resolve(
+    rev: &str,
+    changelog: &Changelog,
+    store_path: &Path,
+    bookmarks: &[(String, HgId)],
+    current: HgId,
+    config: &ConfigSet,
+) -> Result<Option<HgId>> {
+    match rev {
+        "." => return Ok(Some(current)),
+        "tip" => return Ok(changelog.tip().map(HgId::from_byte_array)),
+        "null" => return Ok(None),
+        _ => {}
+    }
+    // Revision numbers are not supported.
+    if rev.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    if rev.len() == HgId::hex_len() {
+        if let Ok(node) = HgId::from_str(rev) {
+            if changelog.rev(node.as_ref())?.is_some() {
+                return Ok(Some(node));
+            }
+        }
+    }
+    if let Some((_, node)) = bookmarks.iter().find(|(name, _)| name == rev) {
+        return Ok(Some(*node));
+    }
+
+    let remotenames = read_remotenames(config, store_path)?;
+    let hoist = config.get("remotenames", "hoist");
+    let hoist = hoist.as_deref().unwrap_or("default");
+    let hoisted = format!("{}/{}", hoist, rev);
+    for name in &[rev, hoisted.as_str()] {
+        if let Some((_, node)) = remotenames.iter().find(|(n, _)| n == name) {
+            return Ok(Some(*node));
+        }
+    }
+
+    if rev.chars().all(|c| c.is_ascii_hexdigit()) {
+        return match changelog.index.nodemap.hex_prefix_to_node(rev)? {
+            Some(node) => Ok(Some(HgId::from_slice(node)?)),
+            None => Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+/// Test if `node` is an ancestor of a visible head, a bookmark or a remote
+/// name. Commits only made public by phase roots are treated as hidden.
+fn is_visible(
+    changelog: &Changelog,
+    store_path: &Path,
+    node: HgId,
+    bookmarks: &[(String, HgId)],
+    config: &ConfigSet,
+) -> Result<bool> {
+    if node.is_null() {
+        return Ok(true);
+    }
+    // Without visible heads, visibility is decided by obsolete markers.
+    if !read_lines(&store_path.join("requires"))?.contains("visibleheads") {
+        return Ok(false);
+    }
+    let data = read_store_file(config, store_path, "visibleheads")?;
+    let text = String::from_utf8_lossy(&data);
+    let remotenames = read_remotenames(config, store_path)?;
+    let heads = text
+        .lines()
+        .skip(1)
+        .filter_map(|line| HgId::from_str(line.trim()).ok())
+        .chain(bookmarks.iter().map(|(_, node)| *node))
+        .chain(remotenames.iter().map(|(_, node)| *node));
+
+    let rev = match changelog.rev(node.as_ref())? {
+        Some(rev) => rev,
+        None => return Ok(false),
+    };
+    let mut head_revs = Vec::new();
+    for head in heads {
+        if let Some(head_rev) = changelog.rev(head.as_ref())? {
+            head_revs.push(head_rev);
+        }
+    }
+    Ok(!changelog.index.range_revs(&[rev], &head_revs).is_empty())
+}
+
+/// Read local bookmarks as `(name, node)` pairs.
+pub(crate) fn read_bookmarks(config: &ConfigSet, store_path: &Path) -> Result<Vec<(String, HgId)>> {
+    let data = read_store_file(config, store_path, "bookmarks")?;
+    Ok(parse_names(&String::from_utf8_lossy(&data), 1))
+}
+
+/// Read remote names as `(name, node)` pairs.
+fn read_remotenames(config: &ConfigSet, store_path: &Path) -> Result<Vec<(String, HgId)>> {
+    let data = read_store_file(config, store_path, "remotenames")?;
+    Ok(parse_names(&String::from_utf8_lossy(&data), 2))
+}
+
+/// Read a file in the store. With `experimental.metalog`, Python keeps
+/// bookmarks, remote names and visible heads in metalog. Fall back to
+/// Python if they have not been migrated there yet.
+fn read_store_file(config: &ConfigSet, store_path: &Path, name: &str) -> Result<Vec<u8>> {
+    if config.get_or_default::<bool>("experimental", "metalog")? {
+        let metalog = MetaLog::open(store_path.join("metalog"), None)?;
+        let tracked = metalog.get("tracked")?.unwrap_or_default();
+        if !String::from_utf8_lossy(&tracked)
+            .split_whitespace()
+            .any(|tracked| tracked == name)
+        {
+            return Err(errors::FallbackToPython.into());
+        }
+        return Ok(metalog.get(name)?.unwrap_or_default().to_vec());
+    }
+    match fs::read(store_path.join(name)) {
+        Ok(data) => Ok(data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Parse lines like `<hex> [<type>] <name>`. `skip` is the number of fields
+/// before the name.
+fn parse_names(text: &str, skip: usize) -> Vec<(String, HgId)> {
+    let mut result = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.splitn(skip + 1, ' ');
+        let hex = fields.next().unwrap_or("");
+        let name = match fields.nth(skip - 1) {
+            Some(name) => name,
+            None => continue,
+        };
+        if let Ok(node) = HgId::from_str(hex) {
+            result.push((name.to_string(), node));
+        }
+    }
+    result
+}
+
+fn read_lines(path: &Path) -> Result<HashSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().map(|l| l.to_string()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Build the sparse matcher from `.hg/sparse`. Return `None` if the sparse
+/// config uses profiles or patterns that are not supported.
+fn sparse_matcher(dot_hg: &Path) -> Result<Option<Box<dyn Matcher>>> {
+    if dot_hg.join("tempsparse").exists() {
+        return Ok(None);
+    }
+    let text = match fs::read_to_string(dot_hg.join("sparse")) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Some(Box::new(AlwaysMatcher::new())));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut includes = Vec::new();
+    let mut excludes = Vec::new();
+    let mut in_exclude = false;
+    let mut in_metadata = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with("%include") {
+            return Ok(None);
+        }
+        match line {
+            "[include]" => {
+                in_exclude = false;
+                in_metadata = false;
+            }
+            "[exclude]" => {
+                in_exclude = true;
+                in_metadata = false;
+            }
+            "[metadata]" => in_metadata = true,
+            _ if in_metadata => {}
+            _ => {
+                let glob = match sparse_pattern_to_glob(line) {
+                    Some(glob) => glob,
+                    None => return Ok(None),
+                };
+                // A pattern matches the path itself and everything under it.
+                let rules = vec![format!("{}/**", glob), glob];
+                if in_exclude {
+                    excludes.extend(rules.into_iter().map(|r| format!("!{}", r)));
+                } else {
+                    includes.extend(rules);
+                }
+            }
+        }
+    }
+
+    if includes.is_empty() && excludes.is_empty() {
+        return Ok(Some(Box::new(AlwaysMatcher::new())));
+    }
+    if includes.is_empty() {
+        includes.push("**".to_string());
+    } else {
+        // Python always includes files like ".hgignore".
+        includes.push(".hg*".to_string());
+        includes.push(".hg*/**".to_string());
+    }
+    let rules = includes.into_iter().chain(excludes);
+    Ok(Some(Box::new(TreeMatcher::from_rules(rules)?)))
+}
+
+/// Convert a sparse pattern to a glob.
+fn sparse_pattern_to_glob(pattern: &str) -> Option<String> {
+    let glob = if pattern.starts_with("glob:") {
+        pathmatcher::normalize_glob(&pattern[5..])
+    } else if pattern.starts_with("path:") {
+        pathmatcher::plain_to_glob(&pattern[5..])
+    } else if pattern.contains(':') {
+        // Other kinds like "re:" are not supported.
+        return None;
+    } else {
+        pathmatcher::plain_to_glob(pattern)
+    };
+    Some(glob.trim_end_matches('/').to_string())
+}
+
+fn conflict_error(repo: &Repo, mut conflicts: Vec<types::RepoPathBuf>) -> errors::Abort {
+    conflicts.sort();
+    let cwd = std::env::current_dir().unwrap_or_else(|_| repo.path().to_path_buf());
+    let mut msg = format!("{} conflicting file changes:\n", conflicts.len());
+    for path in conflicts.iter().take(5) {
+        msg += &format!(" {}\n", relative_path(repo.path(), &cwd, path));
+    }
+    if conflicts.len() > 5 {
+        msg += &format!(" ...and {} more\n", conflicts.len() - 5);
+    }
+    msg += "(commit, shelve, update --clean to discard them, or update --merge to merge them)";
+    errors::Abort(msg.into())
+}
+
+/// Show `path` relative to `cwd`.
+pub(crate) fn relative_path(root: &Path, cwd: &Path, path: &RepoPath) -> String {
+    let cwd = match cwd.strip_prefix(root) {
+        Ok(cwd) => cwd,
+        Err(_) => return path.to_string(),
+    };
+    let cwd: Vec<_> = cwd.iter().map(|c| c.to_string_lossy()).collect();
+    let components: Vec<_> = path.as_str().split('/').collect();
+    let common = cwd
+        .iter()
+        .zip(components.iter())
+        .take_while(|(a, b)| a == *b)
+        .count();
+    let mut result = vec![".."; cwd.len() - common];
+    result.extend_from_slice(&components[common..]);
+    result.join("/")
+}
+
+/// Open the file and tree stores. Remote fetching uses EdenAPI if it is
+/// enabled.
+pub(crate) fn open_stores(
+    config: &ConfigSet,
+    store_path: &Path,
+) -> Result<(LocalOrRemoteStore, Arc<ManifestStore>)> {
+    let files = ContentStoreBuilder::new(config).local_path(store_path);
+    let trees = ContentStoreBuilder::new(config)
+        .local_path(store_path)
+        .suffix(Path::new("manifests"));
+    let remote = config.get_or_default::<bool>("edenapi", "enabled")?;
+    let (files, trees) = if remote {
+        let edenapi_config = edenapi::Config::from_hg_config(config)?;
+        let edenapi: Arc<dyn EdenApi> = Arc::new(EdenApiCurlClient::new(edenapi_config)?);
+        let fileremotestore = Arc::new(EdenApiHgIdRemoteStore::filestore(edenapi.clone()));
+        let treeremotestore = Arc::new(EdenApiHgIdRemoteStore::treestore(edenapi));
+        (
+            files.remotestore(fileremotestore).build()?,
+            trees.remotestore(treeremotestore).build()?,
+        )
+    } else {
+        (files.build()?, trees.build()?)
+    };
+    let files = LocalOrRemoteStore {
+        inner: files,
+        remote,
+    };
+    let trees = Arc::new(ManifestStore { inner: trees });
+    Ok((files, trees))
+}
+
+/// Fall back to Python if content is missing locally and cannot be fetched
+/// natively. Callers prefetch everything before writing, so nothing is
+/// written before falling back.
+pub(crate) struct LocalOrRemoteStore {
+    inner: ContentStore,
+    remote: bool,
+}
+
+impl FileStore for LocalOrRemoteStore {
+    fn get_files(&self, keys: &[Key]) -> Result<Vec<Bytes>> {
+        self.prefetch(keys)?;
+        self.inner.get_files(keys)
+    }
+
+    fn prefetch(&self, keys: &[Key]) -> Result<()> {
+        if self.remote {
+            return FileStore::prefetch(&self.inner, keys);
+        }
+        let keys: Vec<StoreKey> = keys.iter().map(StoreKey::from).collect();
+        if !self.inner.get_missing(&keys)?.is_empty() {
+            return Err(errors::FallbackToPython.into());
+        }
+        Ok(())
+    }
+}
+
+/// Manifest trees stored in a `ContentStore`.
+pub(crate) struct ManifestStore {
+    inner: ContentStore,
+}
+
+impl TreeStore for ManifestStore {
+    fn get(&self, path: &RepoPath, hgid: HgId) -> Result<Bytes> {
+        let key = Key::new(path.to_owned(), hgid);
+        match self.inner.get(&key)? {
+            Some(data) => Ok(data.into()),
+            // Missing trees are fetched by Python.
+            None => Err(errors::FallbackToPython.into()),
+        }
+    }
+
+    fn insert(&self, _path: &RepoPath, _hgid: HgId, _data: Bytes) -> Result<()> {
+        Err(format_err!("insert is not implemented."))
+    }
+
+    fn prefetch(&self, keys: Vec<Key>) -> Result<()> {
+        // Without EdenAPI this does nothing and `get` falls back to Python.
+        let keys: Vec<StoreKey> = keys.into_iter().map(StoreKey::from).collect();
+        self.inner.prefetch(&keys)
+    }
+}
+
+/// Open the changelog. Fall back to Python if it cannot be read natively.
+pub(crate) fn open_changelog(store_path: &Path) -> Result<Changelog> {
+    Changelog::open(store_path).map_err(fallback_if_unsupported)
+}
+
+/// Manifest node of a commit.
+pub(crate) fn manifest_node(changelog: &Changelog, node: HgId) -> Result<HgId> {
+    if node.is_null() {
+        return Ok(*HgId::null_id());
+    }
+    let rev = match changelog.rev(node.as_ref())? {
+        Some(rev) => rev,
+        None => return Err(format_err!("unknown revision {}", node)),
+    };
+    let text = changelog.revision(rev).map_err(fallback_if_unsupported)?;
+    let line = text.split(|&b| b == b'\n').next().unwrap_or_default();
+    Ok(HgId::from_str(std::str::from_utf8(line)?)?)
+}
+
+fn fallback_if_unsupported(e: anyhow::Error) -> anyhow::Error {
+    if e.is::<UnsupportedRevlog>() {
+        errors::FallbackToPython.into()
+    } else {
+        e
+    }
+}
@@ -34,13 +34,17 @@ impl<'a> BfsIter<'a> {
         }
     }
 
+    /// Fetch `extra` together with the queued directories that are not loaded yet. The queue
+    /// only holds directories accepted by the matcher, so pruned subtrees are never fetched.
     fn prefetch(&self, extra: (&RepoPath, &DurableEntry)) -> Result<()> {
         let mut keys = vec![Key::new(extra.0.to_owned(), extra.1.hgid)];
         let mut entries = vec![extra];
         for (path, link) in self.queue.iter() {
             if let Link::Durable(durable_entry) = link {
-                keys.push(Key::new(path.clone(), durable_entry.hgid));
-                entries.push((path, durable_entry));
+                if durable_entry.get_links().is_none() {
+                    keys.push(Key::new(path.clone(), durable_entry.hgid));
+                    entries.push((path, durable_entry));
+                }
             }
         }
         self.store.prefetch(keys)?;
@@ -406,6 +410,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_items_prefetch() {
+        let store = Arc::new(TestStore::new());
+        let mut tree = TreeManifest::ephemeral(store.clone());
+        tree.insert(repo_path_buf("a1/b1/c1"), make_meta("10"))
+            .unwrap();
+        tree.insert(repo_path_buf("a2/b2/c2"), make_meta("20"))
+            .unwrap();
+        tree.insert(repo_path_buf("a3/b3/c3"), make_meta("30"))
+            .unwrap();
+        tree.insert(repo_path_buf("a4/b4/c4"), make_meta("40"))
+            .unwrap();
+        let hgid = tree.flush().unwrap();
+        let tree = TreeManifest::durable(store.clone(), hgid);
+        tree.get(repo_path("a2/b2/c2")).unwrap();
+
+        let matcher = TreeMatcher::from_rules(["a1/**", "a2/**", "a4/**"].iter()).unwrap();
+        assert_eq!(
+            tree.files(&matcher).collect::<Result<Vec<_>>>().unwrap(),
+            vec!(
+                make_file("a1/b1/c1", "10"),
+                make_file("a2/b2/c2", "20"),
+                make_file("a4/b4/c4", "40"),
+            )
+        );
+
+        // Directories are fetched one level at a time. Loaded and pruned directories are not
+        // fetched.
+        let fetches: Vec<Vec<String>> = store
+            .fetches()
+            .iter()
+            .map(|keys| keys.iter().map(|k| k.path.to_string()).collect())
+            .collect();
+        assert_eq!(fetches, [["a1", "a4"], ["a1/b1", "a4/b4"]]);
+    }
+
     #[test]
     fn test_files_finish_on_error_when_collecting_to_vec() {
         let tree = TreeManifest::durable(Arc::new(TestStore::new()), hgid("1"));
@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::metalog::load_root;
+use crate::Id20;
+use crate::MetaLog;
+use crate::Result;
+use minibytes::Bytes;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Information about a root in the history.
+#[derive(Clone, Debug)]
+pub struct RootInfo {
+    /// Position in the history. The empty root is at 0.
+    pub index: usize,
+    pub id: Id20,
+    pub timestamp: u64,
+    pub message: String,
+}
+
+/// How a key changed between two roots.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyChange {
+    Added(Bytes),
+    Removed(Bytes),
+    Changed { old: Bytes, new: Bytes },
+}
+
+/// A changed key between two roots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyDiff {
+    pub key: String,
+    pub change: KeyChange,
+}
+
+impl MetaLog {
+    /// Iterate through roots stored in `path`, the oldest first.
+    pub fn history(path: impl AsRef<Path>) -> Result<impl Iterator<Item = Result<RootInfo>>> {
+        let path = path.as_ref();
+        let root_ids = Self::list_roots(path)?;
+        let blobs = zstore::OpenOptions::default().open(&path.join("blobs"))?;
+        let iter = root_ids.into_iter().enumerate().map(move |(index, id)| {
+            let root = load_root(&blobs, id)?;
+            Ok(RootInfo {
+                index,
+                id,
+                timestamp: root.timestamp,
+                message: root.message,
+            })
+        });
+        Ok(iter)
+    }
+
+    /// Find the last root in the history with a timestamp that is not after
+    /// `timestamp`.
+    ///
+    /// The empty root at the beginning of the history is returned if no
+    /// other root matches.
+    pub fn find_root_before(path: impl AsRef<Path>, timestamp: u64) -> Result<RootInfo> {
+        let mut result = None;
+        for info in Self::history(path)? {
+            let info = info?;
+            if info.index == 0 || info.timestamp <= timestamp {
+                result = Some(info);
+            }
+        }
+        Ok(result.expect("history always has the empty root"))
+    }
+
+    /// Compare keys with `other`. Changes are from `self` to `other`, sorted
+    /// by key.
+    pub fn diff(&self, other: &MetaLog) -> Result<Vec<KeyDiff>> {
+        let keys: BTreeSet<&str> = self.keys().into_iter().chain(other.keys()).collect();
+        let mut result = Vec::new();
+        for key in keys {
+            if self.root.map.get(key) == other.root.map.get(key) {
+                continue;
+            }
+            let change = match (self.get(key)?, other.get(key)?) {
+                (None, Some(new)) => KeyChange::Added(new),
+                (Some(old), None) => KeyChange::Removed(old),
+                (Some(old), Some(new)) => KeyChange::Changed { old, new },
+                (None, None) => continue,
+            };
+            result.push(KeyDiff {
+                key: key.to_string(),
+                change,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Copy values of `keys` from `other`. Keys missing in `other` are
+    /// removed.
+    ///
+    /// Changes are not flushed to disk. Use `commit` to write them.
+    pub fn restore_keys(&mut self, other: &MetaLog, keys: &[&str]) -> Result<()> {
+        for key in keys {
+            match other.get(key)? {
+                Some(value) => {
+                    self.set(key, &value)?;
+                }
+                None => self.remove(key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommitOptions;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_history() {
+        let dir = TempDir::new().unwrap();
+        let mut metalog = MetaLog::open(&dir, None).unwrap();
+        metalog.set("a", b"1").unwrap();
+        metalog.set("b", b"1").unwrap();
+        metalog.commit(commit_opt("commit 1", 10)).unwrap();
+        metalog.set("a", b"2").unwrap();
+        metalog.remove("b").unwrap();
+        metalog.set("c", b"2").unwrap();
+        metalog.commit(commit_opt("commit 2", 20)).unwrap();
+
+        let history: Vec<_> = MetaLog::history(&dir)
+            .unwrap()
+            .map(|info| {
+                let info = info.unwrap();
+                (info.index, info.timestamp, info.message)
+            })
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (0, 0, "".to_string()),
+                (1, 10, "commit 1".to_string()),
+                (2, 20, "commit 2".to_string())
+            ]
+        );
+
+        assert_eq!(MetaLog::find_root_before(&dir, 5).unwrap().index, 0);
+        assert_eq!(MetaLog::find_root_before(&dir, 10).unwrap().index, 1);
+        assert_eq!(MetaLog::find_root_before(&dir, 15).unwrap().index, 1);
+        assert_eq!(MetaLog::find_root_before(&dir, 25).unwrap().index, 2);
+    }
+
+    #[test]
+    fn test_diff_and_restore() {
+        let dir = TempDir::new().unwrap();
+        let mut metalog = MetaLog::open(&dir, None).unwrap();
+        metalog.set("a", b"1").unwrap();
+        metalog.set("b", b"1").unwrap();
+        metalog.set("d", b"1").unwrap();
+        let root1 = metalog.commit(commit_opt("commit 1", 10)).unwrap();
+        metalog.set("a", b"2").unwrap();
+        metalog.remove("b").unwrap();
+        metalog.set("c", b"2").unwrap();
+        metalog.commit(commit_opt("commit 2", 20)).unwrap();
+
+        let old = MetaLog::open(&dir, Some(root1)).unwrap();
+        let diff = old.diff(&metalog).unwrap();
+        let b = |s: &[u8]| Bytes::from(s.to_vec());
+        assert_eq!(
+            diff,
+            [
+                KeyDiff {
+                    key: "a".to_string(),
+                    change: KeyChange::Changed {
+                        old: b(b"1"),
+                        new: b(b"2")
+                    }
+                },
+                KeyDiff {
+                    key: "b".to_string(),
+                    change: KeyChange::Removed(b(b"1"))
+                },
+                KeyDiff {
+                    key: "c".to_string(),
+                    change: KeyChange::Added(b(b"2"))
+                },
+            ]
+        );
+
+        metalog.restore_keys(&old, &["a", "b", "c"]).unwrap();
+        metalog.commit(commit_opt("restore", 30)).unwrap();
+        assert!(old.diff(&metalog).unwrap().is_empty());
+        let metalog = MetaLog::open(&dir, None).unwrap();
+        assert_eq!(metalog.keys(), ["a", "b", "d"]);
+        assert_eq!(metalog.message(), "restore");
+    }
+
+    fn commit_opt(message: &str, timestamp: u64) -> CommitOptions {
+        let mut opts = CommitOptions::default();
+        opts.message = message;
+        opts.timestamp = timestamp;
+        opts
+    }
+}
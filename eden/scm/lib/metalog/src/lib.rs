@@ -11,8 +11,10 @@
 
 mod errors;
 mod export;
+mod history;
 mod metalog;
 
 pub use crate::metalog::{resolver, CommitOptions, Id20, MetaLog};
 pub use errors::{Error, Result};
+pub use history::{KeyChange, KeyDiff, RootInfo};
 pub use indexedlog::Repair;
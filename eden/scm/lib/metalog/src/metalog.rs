@@ -12,7 +12,7 @@ use indexedlog::Repair;
 use lazy_static::lazy_static;
 use minibytes::Bytes;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -52,7 +52,7 @@ pub struct MetaLog {
     pub(crate) orig_root_id: Id20,
 
     /// The current (possibly modified) root.
-    root: Root,
+    pub(crate) root: Root,
 }
 
 /// Options used by the `commit` API.
@@ -123,6 +123,37 @@ impl MetaLog {
         Ok(result)
     }
 
+    /// Remove blobs that are not referred by roots in the "roots" log, and
+    /// rebuild delta chains of the remaining blobs.
+    ///
+    /// Roots written with `detached` but not yet added to the "roots" log
+    /// are removed. The caller should hold the repo lock so there are no
+    /// pending transactions. Other [`MetaLog`]s opened at `path` should be
+    /// reopened afterwards.
+    pub fn gc(path: impl AsRef<Path>) -> Result<zstore::GcStats> {
+        let path = path.as_ref();
+        let _lock = ScopedDirLock::new(path)?;
+        let root_ids: HashSet<Id20> = Self::list_roots(path)?
+            .into_iter()
+            .filter(|id| *id != *EMPTY_ROOT_ID)
+            .collect();
+        let roots: Vec<Id20> = root_ids.iter().cloned().collect();
+        let mut blobs = Zstore::open(path.join("blobs"))?;
+        let stats = blobs.gc(
+            &roots,
+            |id, data| {
+                if root_ids.contains(&id) {
+                    let root: Root = mincode::deserialize(data)?;
+                    Ok(root.map.values().cloned().collect())
+                } else {
+                    Ok(Vec::new())
+                }
+            },
+            &Default::default(),
+        )?;
+        Ok(stats)
+    }
+
     /// Lookup a blob by key.
     pub fn get(&self, name: &str) -> Result<Option<Bytes>> {
         match self.root.map.get(name) {
@@ -360,10 +391,287 @@ pub(crate) struct Root {
 }
 
 /// Predefined conflict resolutions.
+///
+/// Resolvers take `(this, other, ancestor)` and are used by
+/// [`CommitOptions::resolver`](crate::CommitOptions). Other than [`fail`],
+/// resolvers merge per key: a key changed by only one side takes the
+/// changed value, and a key changed by both sides differently is merged
+/// by a [`Strategy`].
 pub mod resolver {
     use super::MetaLog;
     use crate::Result;
-    use std::collections::BTreeSet;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::str::FromStr;
+
+    /// Custom merge function for a single key.
+    ///
+    /// Called with the key, and values from `this`, `other`, and `ancestor`.
+    /// `None` means the key does not exist. Return the merged value, or
+    /// `None` to remove the key.
+    pub type MergeFunc = Box<
+        dyn FnMut(&str, Option<&[u8]>, Option<&[u8]>, Option<&[u8]>) -> Result<Option<Vec<u8>>>,
+    >;
+
+    /// How to merge a key changed differently by both sides.
+    pub enum Strategy {
+        /// Report a conflict.
+        Fail,
+
+        /// Use the value from `this`.
+        PreferThis,
+
+        /// Use the value from `other`.
+        PreferOther,
+
+        /// Treat the value as a set of lines. Keep lines added by either
+        /// side, and drop lines removed by either side.
+        Union,
+
+        /// Treat each line as `<value> <name>` (ex. `<hex> <bookmark>`), and
+        /// merge per name. Fail if both sides changed a same name differently.
+        ByName,
+
+        /// Use a custom function.
+        Custom(MergeFunc),
+    }
+
+    impl FromStr for Strategy {
+        type Err = crate::Error;
+
+        fn from_str(s: &str) -> Result<Self> {
+            let strategy = match s {
+                "fail" => Strategy::Fail,
+                "prefer-this" => Strategy::PreferThis,
+                "prefer-other" => Strategy::PreferOther,
+                "union" => Strategy::Union,
+                "by-name" => Strategy::ByName,
+                _ => return Err(crate::Error(format!("unknown merge strategy: {}", s))),
+            };
+            Ok(strategy)
+        }
+    }
+
+    /// A table of per-key strategies.
+    ///
+    /// ```
+    /// use metalog::resolver::{Strategies, Strategy};
+    /// let strategies = Strategies::new(Strategy::Fail)
+    ///     .with_key("bookmarks", Strategy::ByName)
+    ///     .with_key("visibleheads", Strategy::Union);
+    /// let resolver = strategies.into_resolver();
+    /// ```
+    pub struct Strategies {
+        default: Strategy,
+        keys: HashMap<String, Strategy>,
+    }
+
+    impl Strategies {
+        /// Create a table that uses `default` for keys without a specific
+        /// strategy.
+        pub fn new(default: Strategy) -> Self {
+            Self {
+                default,
+                keys: HashMap::new(),
+            }
+        }
+
+        /// Set the strategy for a key.
+        pub fn with_key(mut self, key: impl ToString, strategy: Strategy) -> Self {
+            self.keys.insert(key.to_string(), strategy);
+            self
+        }
+
+        /// Build the table from `(key, strategy name)` pairs, ex.
+        /// `[("bookmarks", "by-name"), ("visibleheads", "union")]`.
+        /// The key `*` sets the default strategy, which is otherwise `fail`.
+        pub fn from_names<K: AsRef<str>, V: AsRef<str>>(
+            names: impl IntoIterator<Item = (K, V)>,
+        ) -> Result<Self> {
+            let mut strategies = Self::new(Strategy::Fail);
+            for (key, name) in names {
+                let strategy = name.as_ref().parse()?;
+                match key.as_ref() {
+                    "*" => strategies.default = strategy,
+                    key => strategies = strategies.with_key(key, strategy),
+                }
+            }
+            Ok(strategies)
+        }
+
+        /// Convert to a function that can be used as
+        /// [`CommitOptions::resolver`](crate::CommitOptions).
+        pub fn into_resolver(
+            mut self,
+        ) -> Box<dyn FnMut(&mut MetaLog, &MetaLog, &MetaLog) -> Result<()>> {
+            Box::new(move |this, other, ancestor| self.resolve(this, other, ancestor))
+        }
+
+        /// Merge changes from `other` into `this`.
+        pub fn resolve(
+            &mut self,
+            this: &mut MetaLog,
+            other: &MetaLog,
+            ancestor: &MetaLog,
+        ) -> Result<()> {
+            let keys: BTreeSet<String> = this
+                .keys()
+                .into_iter()
+                .chain(other.keys())
+                .chain(ancestor.keys())
+                .map(|k| k.to_string())
+                .collect();
+            let mut conflicts = Vec::new();
+            for key in keys {
+                let ancestor_id = ancestor.root.map.get(&key).cloned();
+                let other_id = other.root.map.get(&key).cloned();
+                let this_id = this.root.map.get(&key).cloned();
+                if this_id == other_id || ancestor_id == other_id {
+                    // Same, or only changed by this side.
+                    continue;
+                }
+                let other_value = other.get(&key)?;
+                let merged = if ancestor_id == this_id {
+                    // Only changed by the other side.
+                    other_value.map(|v| v.as_ref().to_vec())
+                } else {
+                    let this_value = this.get(&key)?;
+                    let ancestor_value = ancestor.get(&key)?;
+                    let this_value = this_value.as_ref().map(|v| v.as_ref());
+                    let other_value = other_value.as_ref().map(|v| v.as_ref());
+                    let ancestor_value = ancestor_value.as_ref().map(|v| v.as_ref());
+                    let strategy = self.keys.get_mut(&key).unwrap_or(&mut self.default);
+                    match merge_value(strategy, &key, this_value, other_value, ancestor_value)? {
+                        Ok(merged) => merged,
+                        Err(conflict) => {
+                            conflicts.push(conflict);
+                            continue;
+                        }
+                    }
+                };
+                match merged {
+                    Some(value) => {
+                        this.set(&key, &value)?;
+                    }
+                    None => this.remove(&key)?,
+                }
+            }
+            if conflicts.is_empty() {
+                Ok(())
+            } else {
+                Err(this.error(format!("conflict detected:\n{}", conflicts.join("\n"))))
+            }
+        }
+    }
+
+    /// Merge a value changed by both sides.
+    ///
+    /// The inner `Err` describes an unresolved conflict.
+    fn merge_value(
+        strategy: &mut Strategy,
+        key: &str,
+        this: Option<&[u8]>,
+        other: Option<&[u8]>,
+        ancestor: Option<&[u8]>,
+    ) -> Result<std::result::Result<Option<Vec<u8>>, String>> {
+        let to_vec = |v: Option<&[u8]>| v.map(|v| v.to_vec());
+        let merged = match strategy {
+            Strategy::Fail => return Ok(Err(format!("  {}: both changed, diverged", key))),
+            Strategy::PreferThis => to_vec(this),
+            Strategy::PreferOther => to_vec(other),
+            Strategy::Union => Some(union_lines(this, other, ancestor)),
+            Strategy::ByName => match merge_by_name(this, other, ancestor) {
+                Ok(merged) => Some(merged),
+                Err(names) => {
+                    return Ok(Err(format!(
+                        "  {}: both changed, diverged ({})",
+                        key,
+                        names.join(", ")
+                    )));
+                }
+            },
+            Strategy::Custom(func) => func(key, this, other, ancestor)?,
+        };
+        Ok(Ok(merged))
+    }
+
+    fn lines(value: Option<&[u8]>) -> Vec<&[u8]> {
+        match value {
+            Some(value) => value
+                .split(|&b| b == b'\n')
+                .filter(|l| !l.is_empty())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn join_lines<'a>(lines: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+        let mut result = Vec::new();
+        for line in lines {
+            result.extend_from_slice(line);
+            result.push(b'\n');
+        }
+        result
+    }
+
+    fn union_lines(this: Option<&[u8]>, other: Option<&[u8]>, ancestor: Option<&[u8]>) -> Vec<u8> {
+        let this = lines(this);
+        let other = lines(other);
+        let this_set: HashSet<&[u8]> = this.iter().cloned().collect();
+        let other_set: HashSet<&[u8]> = other.iter().cloned().collect();
+        let removed: HashSet<&[u8]> = lines(ancestor)
+            .into_iter()
+            .filter(|l| !this_set.contains(l) || !other_set.contains(l))
+            .collect();
+        let mut seen = HashSet::new();
+        join_lines(
+            this.into_iter()
+                .chain(other)
+                .filter(|l| !removed.contains(l) && seen.insert(*l)),
+        )
+    }
+
+    /// Merge `<value> <name>` lines per name. Return names in conflict on
+    /// failure.
+    fn merge_by_name(
+        this: Option<&[u8]>,
+        other: Option<&[u8]>,
+        ancestor: Option<&[u8]>,
+    ) -> std::result::Result<Vec<u8>, Vec<String>> {
+        fn by_name(value: Option<&[u8]>) -> BTreeMap<&[u8], &[u8]> {
+            lines(value)
+                .into_iter()
+                .map(|line| match line.iter().position(|&b| b == b' ') {
+                    Some(pos) => (&line[pos + 1..], line),
+                    None => (line, line),
+                })
+                .collect()
+        }
+        let this = by_name(this);
+        let other = by_name(other);
+        let ancestor = by_name(ancestor);
+        let names: BTreeSet<&[u8]> = this.keys().chain(other.keys()).cloned().collect();
+        let mut merged = Vec::new();
+        let mut conflicts = Vec::new();
+        for name in names {
+            let (t, o, a) = (this.get(name), other.get(name), ancestor.get(name));
+            let line = if t == o || o == a {
+                t
+            } else if t == a {
+                o
+            } else {
+                conflicts.push(String::from_utf8_lossy(name).to_string());
+                continue;
+            };
+            if let Some(line) = line {
+                merged.push(*line);
+            }
+        }
+        if conflicts.is_empty() {
+            Ok(join_lines(merged))
+        } else {
+            Err(conflicts)
+        }
+    }
 
     /// Fail the merge unconditionally on any kind of conflicts.
     pub fn fail(this: &mut MetaLog, other: &MetaLog, ancestor: &MetaLog) -> Result<()> {
@@ -395,6 +703,21 @@ pub mod resolver {
         };
         Err(this.error(message))
     }
+
+    /// Merge per key. Use values from `this` for keys changed by both sides.
+    pub fn prefer_this(this: &mut MetaLog, other: &MetaLog, ancestor: &MetaLog) -> Result<()> {
+        Strategies::new(Strategy::PreferThis).resolve(this, other, ancestor)
+    }
+
+    /// Merge per key. Use values from `other` for keys changed by both sides.
+    pub fn prefer_other(this: &mut MetaLog, other: &MetaLog, ancestor: &MetaLog) -> Result<()> {
+        Strategies::new(Strategy::PreferOther).resolve(this, other, ancestor)
+    }
+
+    /// Merge per key. Merge values changed by both sides as sets of lines.
+    pub fn union(this: &mut MetaLog, other: &MetaLog, ancestor: &MetaLog) -> Result<()> {
+        Strategies::new(Strategy::Union).resolve(this, other, ancestor)
+    }
 }
 
 #[cfg(test)]
@@ -515,6 +838,144 @@ mod tests {
         assert_eq!(metalog3.get("c").unwrap().unwrap(), b"c");
     }
 
+    #[test]
+    fn test_prefer_resolvers() {
+        let base: &[(&str, &[u8])] = &[("a", b"0"), ("b", b"0"), ("c", b"0")];
+        let this: &[(&str, Option<&[u8]>)] = &[("a", Some(b"1")), ("b", Some(b"1"))];
+        let other: &[(&str, Option<&[u8]>)] = &[
+            ("a", Some(b"2")),
+            ("b", None),
+            ("c", Some(b"2")),
+            ("d", Some(b"2")),
+        ];
+
+        let merged = merge_with(base, this, other, Box::new(resolver::prefer_this)).unwrap();
+        assert_eq!(merged, "a=1 b=1 c=2 d=2");
+
+        let merged = merge_with(base, this, other, Box::new(resolver::prefer_other)).unwrap();
+        assert_eq!(merged, "a=2 c=2 d=2");
+    }
+
+    #[test]
+    fn test_union_resolver() {
+        let merged = merge_with(
+            &[("a", b"1\n2\n3\n")],
+            &[("a", Some(b"1\n3\n4\n"))],
+            &[("a", Some(b"1\n2\n3\n5\n")), ("b", Some(b"x\n"))],
+            Box::new(resolver::union),
+        )
+        .unwrap();
+        assert_eq!(merged, "a=1\n3\n4\n5\n b=x\n");
+    }
+
+    #[test]
+    fn test_strategies() {
+        let strategies = resolver::Strategies::new(resolver::Strategy::Fail)
+            .with_key("bookmarks", resolver::Strategy::ByName)
+            .with_key("visibleheads", resolver::Strategy::Union)
+            .with_key(
+                "x",
+                resolver::Strategy::Custom(Box::new(|key, this, other, _ancestor| {
+                    let mut value = key.as_bytes().to_vec();
+                    value.extend_from_slice(this.unwrap_or_default());
+                    value.extend_from_slice(other.unwrap_or_default());
+                    Ok(Some(value))
+                })),
+            );
+        let merged = merge_with(
+            &[
+                ("bookmarks", b"00 a\n00 b\n00 d\n"),
+                ("visibleheads", b"v1\n00\n"),
+                ("x", b"0"),
+            ],
+            &[
+                ("bookmarks", Some(b"11 a\n00 b\n11 c\n")),
+                ("visibleheads", Some(b"v1\n11\n")),
+                ("x", Some(b"1")),
+            ],
+            &[
+                ("bookmarks", Some(b"00 a\n22 b\n")),
+                ("visibleheads", Some(b"v1\n00\n22\n")),
+                ("x", Some(b"2")),
+            ],
+            strategies.into_resolver(),
+        )
+        .unwrap();
+        assert_eq!(
+            merged,
+            "bookmarks=11 a\n22 b\n11 c\n visibleheads=v1\n11\n22\n x=x12"
+        );
+
+        // Conflicts are reported.
+        let strategies =
+            resolver::Strategies::from_names(vec![("bookmarks", "by-name"), ("*", "fail")])
+                .unwrap();
+        let err = merge_with(
+            &[("bookmarks", b"00 a\n00 b\n"), ("y", b"0")],
+            &[("bookmarks", Some(b"11 a\n11 b\n")), ("y", Some(b"1"))],
+            &[("bookmarks", Some(b"22 a\n00 b\n")), ("y", Some(b"2"))],
+            strategies.into_resolver(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.ends_with(
+            r#"conflict detected:
+  bookmarks: both changed, diverged (a)
+  y: both changed, diverged"#
+        ));
+
+        assert!(resolver::Strategies::from_names(vec![("a", "foo")]).is_err());
+    }
+
+    /// Commit `this` and `other` changes on top of `base` concurrently, using
+    /// `resolver` to merge. Return the merged content as "key=value" pairs.
+    fn merge_with(
+        base: &[(&str, &[u8])],
+        this: &[(&str, Option<&[u8]>)],
+        other: &[(&str, Option<&[u8]>)],
+        resolver: Box<dyn FnMut(&mut MetaLog, &MetaLog, &MetaLog) -> Result<()>>,
+    ) -> Result<String> {
+        let dir = TempDir::new().unwrap();
+        let mut metalog = MetaLog::open(&dir, None).unwrap();
+        for (key, value) in base {
+            metalog.set(key, value).unwrap();
+        }
+        metalog.commit(commit_opt("base", 0)).unwrap();
+
+        let apply = |changes: &[(&str, Option<&[u8]>)]| {
+            let mut metalog = MetaLog::open(&dir, None).unwrap();
+            for (key, value) in changes {
+                match value {
+                    Some(value) => metalog.set(key, value).unwrap(),
+                    None => {
+                        metalog.remove(key).unwrap();
+                        continue;
+                    }
+                };
+            }
+            metalog
+        };
+        let mut metalog_this = apply(this);
+        let mut metalog_other = apply(other);
+        metalog_other.commit(commit_opt("other", 1)).unwrap();
+
+        let mut opts = commit_opt("this", 2);
+        opts.resolver = Some(resolver);
+        metalog_this.commit(opts)?;
+
+        let metalog = MetaLog::open(&dir, None).unwrap();
+        let merged = metalog
+            .keys()
+            .iter()
+            .map(|key| {
+                let value = metalog.get(key).unwrap().unwrap();
+                format!("{}={}", key, String::from_utf8_lossy(&value))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(merged)
+    }
+
     quickcheck! {
         fn test_random_round_trips(map: BTreeMap<String, (Vec<u8>, Vec<u8>)>) -> bool {
             test_round_trips(map);
@@ -558,6 +1019,39 @@ mod tests {
         opts
     }
 
+    #[test]
+    fn test_gc() {
+        let dir = TempDir::new().unwrap();
+        let mut metalog = MetaLog::open(&dir, None).unwrap();
+        metalog.set("a", b"1").unwrap();
+        metalog.set("b", b"2").unwrap();
+        metalog.commit(commit_opt("commit 1", 1)).unwrap();
+        metalog.set("a", b"3").unwrap();
+        metalog.commit(commit_opt("commit 2", 2)).unwrap();
+
+        // Blobs written without a root, or by a detached root, are garbage.
+        metalog.set("c", b"4").unwrap();
+        let mut opts = commit_opt("detached", 3);
+        opts.detached = true;
+        let detached_root_id = metalog.commit(opts).unwrap();
+        metalog.set("d", b"5").unwrap();
+        metalog.blobs.flush().unwrap();
+        drop(metalog);
+
+        // 2 roots, 3 values; 1 detached root, 2 unreferenced values.
+        let stats = MetaLog::gc(&dir).unwrap();
+        assert_eq!(stats.objects_before, 8);
+        assert_eq!(stats.objects_after, 5);
+
+        let roots = MetaLog::list_roots(&dir).unwrap();
+        let metalog = MetaLog::open(&dir, Some(roots[1])).unwrap();
+        assert_eq!(metalog.get("a").unwrap().unwrap(), &b"1"[..]);
+        let metalog = MetaLog::open(&dir, None).unwrap();
+        assert_eq!(metalog.get("a").unwrap().unwrap(), &b"3"[..]);
+        assert_eq!(metalog.get("b").unwrap().unwrap(), &b"2"[..]);
+        assert!(MetaLog::open(&dir, Some(detached_root_id)).is_err());
+    }
+
     // Repair
 
     #[test]
@@ -35,7 +35,8 @@ use indexedlog::{
     log::{self as ilog, IndexDef, IndexOutput, Log},
     DefaultOpenOptions,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
 use std::io::Cursor;
 use std::path::Path;
 use types::mutation::MutationEntry;
@@ -48,6 +49,63 @@ pub struct MutationStore {
     log: Log,
 }
 
+/// Kind of a mutation, classified from a [`MutationEntry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+    /// The commit was changed in place (amend, metaedit, absorb, ...).
+    Amend,
+    /// The commit was moved to another parent.
+    Rebase,
+    /// Multiple commits were combined into one.
+    Fold,
+    /// One commit was split into multiple commits.
+    Split,
+    /// The commit was landed (pushed) to a public branch.
+    Land,
+    /// Other operations, including `histedit` which can be any of the above.
+    Other,
+}
+
+impl MutationKind {
+    /// Classify a mutation entry.
+    ///
+    /// The shape of the entry (multiple predecessors, split successors)
+    /// takes precedence over the recorded `op` name.
+    pub fn from_entry(entry: &MutationEntry) -> MutationKind {
+        if !entry.split.is_empty() || entry.op == "split" {
+            return MutationKind::Split;
+        }
+        if entry.preds.len() > 1 || entry.op == "fold" {
+            return MutationKind::Fold;
+        }
+        match entry.op.as_str() {
+            "amend" | "amend-copy" | "metaedit" | "absorb" | "uncommit" | "unamend" => {
+                MutationKind::Amend
+            }
+            "rebase" | "rebase-copy" | "restack" => MutationKind::Rebase,
+            "land" | "pushrebase" => MutationKind::Land,
+            _ => MutationKind::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MutationKind::Amend => "amend",
+            MutationKind::Rebase => "rebase",
+            MutationKind::Fold => "fold",
+            MutationKind::Split => "split",
+            MutationKind::Land => "land",
+            MutationKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for MutationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 const INDEX_PRED: usize = 0;
 const INDEX_SUCC: usize = 1;
 const INDEX_SPLIT: usize = 2;
@@ -162,6 +220,93 @@ impl MutationStore {
         Ok(mutation_entry)
     }
 
+    /// Return the latest versions of `node`.
+    ///
+    /// Successors, including split and fold successors, are followed
+    /// recursively. Commits without successors are returned in sorted order.
+    /// If `node` has no successors, it is returned.
+    ///
+    /// Commits in a mutation loop have successors, so they are never final.
+    /// If every path from `node` ends in a loop, the result is empty.
+    pub fn get_final_successors(&self, node: Node) -> Result<Vec<Node>> {
+        let mut result = BTreeSet::new();
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![node];
+        while let Some(node) = to_visit.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            let successors: Vec<Node> = self
+                .get_successors_sets(node)?
+                .into_iter()
+                .flatten()
+                .collect();
+            if successors.is_empty() {
+                result.insert(node);
+            }
+            to_visit.extend(successors);
+        }
+        Ok(result.into_iter().collect())
+    }
+
+    /// Return all predecessors of `node`, recursively, back to commits without
+    /// predecessors. Closer predecessors are returned first. `node` itself is
+    /// not included.
+    pub fn get_all_predecessors(&self, node: Node) -> Result<Vec<Node>> {
+        let mut result = Vec::new();
+        let mut visited = HashSet::new();
+        visited.insert(node);
+        let mut to_visit: VecDeque<Node> = self.get_predecessors(node)?.into();
+        while let Some(node) = to_visit.pop_front() {
+            if !visited.insert(node) {
+                continue;
+            }
+            result.push(node);
+            to_visit.extend(self.get_predecessors(node)?);
+        }
+        Ok(result)
+    }
+
+    /// Find divergence among `node` and its successors, recursively.
+    ///
+    /// A commit is divergent if it was rewritten more than once, and the
+    /// rewrites lead to different final successors. For example, amending
+    /// a commit twice from different working copies.
+    ///
+    /// Return each divergent commit with its final successors, one sorted
+    /// list per rewrite.
+    pub fn get_divergence(&self, node: Node) -> Result<Vec<(Node, Vec<Vec<Node>>)>> {
+        let mut result = Vec::new();
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![node];
+        while let Some(node) = to_visit.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            let successors_sets = self.get_successors_sets(node)?;
+            if successors_sets.len() > 1 {
+                let mut final_sets: Vec<Vec<Node>> = Vec::new();
+                for successors in successors_sets.iter() {
+                    let mut finals = BTreeSet::new();
+                    for &succ in successors {
+                        finals.extend(self.get_final_successors(succ)?);
+                    }
+                    let finals: Vec<Node> = finals.into_iter().collect();
+                    if !final_sets.contains(&finals) {
+                        final_sets.push(finals);
+                    }
+                }
+                if final_sets.len() > 1 {
+                    final_sets.sort();
+                    result.push((node, final_sets));
+                }
+            }
+            to_visit.extend(successors_sets.into_iter().flatten());
+        }
+        result.sort();
+        Ok(result)
+    }
+
     /// Return a connected component that includes `nodes` and represents 1:1
     /// commit replacement relations.  The returned graph supports graph
     /// operations like common ancestors, heads, roots, etc. Parents in the
@@ -319,6 +464,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_transitive_queries() -> Result<()> {
+        let dir = TempDir::new("mutationstore")?;
+        let mut ms = MutationStore::open(dir.path())?;
+        let n = |s: &str| -> Node { Node::from_slice(s.repeat(Node::len()).as_bytes()).unwrap() };
+        let mut add = |succ: &str, preds: &[&str], split: &[&str], op: &str| {
+            ms.add(&MutationEntry {
+                succ: n(succ),
+                preds: preds.iter().map(|p| n(p)).collect(),
+                split: split.iter().map(|s| n(s)).collect(),
+                op: op.into(),
+                user: "test".into(),
+                time: 123456789,
+                tz: -7200,
+                extra: vec![],
+            })
+            .unwrap()
+        };
+
+        // A -> B -> C
+        add("B", &["A"], &[], "amend");
+        add("C", &["B"], &[], "rebase");
+        // D is split into E and F, then E -> G
+        add("F", &["D"], &["E"], "split");
+        add("G", &["E"], &[], "amend");
+        // H and I are folded into J
+        add("J", &["H", "I"], &[], "fold");
+        // K is amended twice (divergence), then L is landed as N
+        add("L", &["K"], &[], "amend");
+        add("M", &["K"], &[], "amend");
+        add("N", &["L"], &[], "pushrebase");
+        // P is rewritten twice, but both lead to S (no divergence)
+        add("Q", &["P"], &[], "amend");
+        add("R", &["P"], &[], "rebase");
+        add("S", &["Q", "R"], &[], "fold");
+        // T -> U -> T (loop)
+        add("U", &["T"], &[], "amend");
+        add("T", &["U"], &[], "amend");
+        // W -> X -> W (loop), and X is also rebased to Y
+        add("X", &["W"], &[], "amend");
+        add("W", &["X"], &[], "amend");
+        add("Y", &["X"], &[], "rebase");
+
+        assert_eq!(ms.get_final_successors(n("A"))?, vec![n("C")]);
+        assert_eq!(ms.get_final_successors(n("C"))?, vec![n("C")]);
+        assert_eq!(ms.get_final_successors(n("D"))?, vec![n("F"), n("G")]);
+        assert_eq!(ms.get_final_successors(n("K"))?, vec![n("M"), n("N")]);
+        assert_eq!(ms.get_final_successors(n("P"))?, vec![n("S")]);
+        assert_eq!(ms.get_final_successors(n("T"))?, vec![]);
+        assert_eq!(ms.get_final_successors(n("U"))?, vec![]);
+        assert_eq!(ms.get_final_successors(n("W"))?, vec![n("Y")]);
+
+        assert_eq!(ms.get_all_predecessors(n("C"))?, vec![n("B"), n("A")]);
+        assert_eq!(ms.get_all_predecessors(n("G"))?, vec![n("E"), n("D")]);
+        assert_eq!(ms.get_all_predecessors(n("J"))?, vec![n("H"), n("I")]);
+        assert_eq!(ms.get_all_predecessors(n("A"))?, vec![]);
+        assert_eq!(ms.get_all_predecessors(n("T"))?, vec![n("U")]);
+
+        assert_eq!(
+            ms.get_divergence(n("K"))?,
+            vec![(n("K"), vec![vec![n("M")], vec![n("N")]])]
+        );
+        assert_eq!(ms.get_divergence(n("P"))?, vec![]);
+        assert_eq!(ms.get_divergence(n("A"))?, vec![]);
+        assert_eq!(ms.get_divergence(n("T"))?, vec![]);
+
+        let kind = |succ: &str| MutationKind::from_entry(&ms.get(n(succ)).unwrap().unwrap());
+        assert_eq!(kind("B"), MutationKind::Amend);
+        assert_eq!(kind("C"), MutationKind::Rebase);
+        assert_eq!(kind("F"), MutationKind::Split);
+        assert_eq!(kind("J"), MutationKind::Fold);
+        assert_eq!(kind("N"), MutationKind::Land);
+        assert_eq!(kind("S").to_string(), "fold");
+        Ok(())
+    }
+
     #[test]
     fn test_dag() -> Result<()> {
         let dir = TempDir::new("mutationstore")?;
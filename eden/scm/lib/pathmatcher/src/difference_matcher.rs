@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use types::RepoPath;
+
+use crate::{DirectoryMatch, Matcher};
+
+/// Matches files matched by `include` but not by `exclude`.
+pub struct DifferenceMatcher {
+    include: Box<dyn Matcher>,
+    exclude: Box<dyn Matcher>,
+}
+
+impl DifferenceMatcher {
+    pub fn new(include: Box<dyn Matcher>, exclude: Box<dyn Matcher>) -> Self {
+        DifferenceMatcher { include, exclude }
+    }
+}
+
+impl Matcher for DifferenceMatcher {
+    fn matches_directory(&self, path: &RepoPath) -> DirectoryMatch {
+        match (
+            self.include.matches_directory(path),
+            self.exclude.matches_directory(path),
+        ) {
+            (DirectoryMatch::Nothing, _) | (_, DirectoryMatch::Everything) => {
+                DirectoryMatch::Nothing
+            }
+            (DirectoryMatch::Everything, DirectoryMatch::Nothing) => DirectoryMatch::Everything,
+            _ => DirectoryMatch::ShouldTraverse,
+        }
+    }
+
+    fn matches_file(&self, path: &RepoPath) -> bool {
+        self.include.matches_file(path) && !self.exclude.matches_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{AlwaysMatcher, TreeMatcher};
+
+    fn path(path: &str) -> &RepoPath {
+        RepoPath::from_str(path).unwrap()
+    }
+
+    #[test]
+    fn test_difference() {
+        let m = DifferenceMatcher::new(
+            Box::new(TreeMatcher::from_rules(["a/**", "b/**"].iter()).unwrap()),
+            Box::new(TreeMatcher::from_rules(["a/x/**", "b/*.h"].iter()).unwrap()),
+        );
+        assert_eq!(m.matches_directory(path("a")), DirectoryMatch::ShouldTraverse);
+        assert_eq!(m.matches_directory(path("a/x")), DirectoryMatch::Nothing);
+        assert_eq!(m.matches_directory(path("a/y")), DirectoryMatch::Everything);
+        assert_eq!(m.matches_directory(path("c")), DirectoryMatch::Nothing);
+        assert!(m.matches_file(path("a/y/z")));
+        assert!(!m.matches_file(path("a/x/z")));
+        assert!(m.matches_file(path("b/z.c")));
+        assert!(!m.matches_file(path("b/z.h")));
+    }
+
+    #[test]
+    fn test_exclude_everything() {
+        let m = DifferenceMatcher::new(
+            Box::new(AlwaysMatcher::new()),
+            Box::new(AlwaysMatcher::new()),
+        );
+        assert_eq!(m.matches_directory(path("a")), DirectoryMatch::Nothing);
+        assert!(!m.matches_file(path("a")));
+    }
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use types::RepoPath;
+
+use crate::{DirectoryMatch, Matcher};
+
+/// Matches files matched by all of the given matchers. An empty list of
+/// matchers matches everything.
+pub struct IntersectMatcher {
+    matchers: Vec<Box<dyn Matcher>>,
+}
+
+impl IntersectMatcher {
+    pub fn new(matchers: Vec<Box<dyn Matcher>>) -> Self {
+        IntersectMatcher { matchers }
+    }
+}
+
+impl Matcher for IntersectMatcher {
+    fn matches_directory(&self, path: &RepoPath) -> DirectoryMatch {
+        let mut result = DirectoryMatch::Everything;
+        for matcher in &self.matchers {
+            match matcher.matches_directory(path) {
+                DirectoryMatch::Nothing => return DirectoryMatch::Nothing,
+                DirectoryMatch::ShouldTraverse => result = DirectoryMatch::ShouldTraverse,
+                DirectoryMatch::Everything => {}
+            }
+        }
+        result
+    }
+
+    fn matches_file(&self, path: &RepoPath) -> bool {
+        self.matchers.iter().all(|m| m.matches_file(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{AlwaysMatcher, NeverMatcher, TreeMatcher};
+
+    fn path(path: &str) -> &RepoPath {
+        RepoPath::from_str(path).unwrap()
+    }
+
+    #[test]
+    fn test_empty() {
+        let m = IntersectMatcher::new(Vec::new());
+        assert_eq!(m.matches_directory(path("a")), DirectoryMatch::Everything);
+        assert!(m.matches_file(path("a/b")));
+    }
+
+    #[test]
+    fn test_intersect() {
+        let m = IntersectMatcher::new(vec![
+            Box::new(TreeMatcher::from_rules(["a/**"].iter()).unwrap()),
+            Box::new(TreeMatcher::from_rules(["**/*.c"].iter()).unwrap()),
+        ]);
+        assert_eq!(m.matches_directory(path("a")), DirectoryMatch::ShouldTraverse);
+        assert_eq!(m.matches_directory(path("b")), DirectoryMatch::Nothing);
+        assert!(m.matches_file(path("a/x.c")));
+        assert!(!m.matches_file(path("a/x.h")));
+        assert!(!m.matches_file(path("b/x.c")));
+
+        let m = IntersectMatcher::new(vec![
+            Box::new(AlwaysMatcher::new()),
+            Box::new(NeverMatcher::new()),
+        ]);
+        assert_eq!(m.matches_directory(path("a")), DirectoryMatch::Nothing);
+        assert!(!m.matches_file(path("a")));
+    }
+}
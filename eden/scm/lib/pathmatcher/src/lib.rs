@@ -5,7 +5,9 @@
  * GNU General Public License version 2.
  */
 
+mod difference_matcher;
 mod gitignore_matcher;
+mod intersect_matcher;
 mod tree_matcher;
 mod utils;
 
@@ -84,6 +86,8 @@ impl Matcher for NeverMatcher {
     }
 }
 
+pub use difference_matcher::DifferenceMatcher;
 pub use gitignore_matcher::GitignoreMatcher;
+pub use intersect_matcher::IntersectMatcher;
 pub use tree_matcher::TreeMatcher;
 pub use utils::{expand_curly_brackets, normalize_glob, plain_to_glob};
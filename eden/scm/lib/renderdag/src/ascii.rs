@@ -61,6 +61,7 @@ where
         glyph: String,
         message: String,
     ) -> String {
+        let options = &self.options;
         let line = self.inner.next_row(node, parents, glyph, message);
         let mut out = String::new();
         let mut message_lines = line
@@ -77,14 +78,14 @@ where
 
         // Render the nodeline
         let mut node_line = String::new();
-        for entry in line.node_line.iter() {
+        for (i, entry) in line.node_line.iter().enumerate() {
             match entry {
                 NodeLine::Node => {
                     node_line.push_str(&line.glyph);
                     node_line.push_str(" ");
                 }
-                NodeLine::Parent => node_line.push_str("| "),
-                NodeLine::Ancestor => node_line.push_str(". "),
+                NodeLine::Parent => options.push_cell(&mut node_line, i, "| "),
+                NodeLine::Ancestor => options.push_cell(&mut node_line, i, ". "),
                 NodeLine::Blank => node_line.push_str("  "),
             }
         }
@@ -98,58 +99,61 @@ where
         // Render the link line
         if let Some(link_row) = line.link_line {
             let mut link_line = String::new();
-            for (prev, cur, next) in Some(LinkLine::empty())
+            for (i, (prev, cur, next)) in Some(LinkLine::empty())
                 .iter()
                 .chain(link_row.iter())
                 .chain(Some(LinkLine::empty()).iter())
                 .tuple_windows()
+                .enumerate()
             {
+                let mut cell = String::new();
                 let adj = prev.clone() | next.clone();
 
                 // Draw the parent/ancestor line.
                 if cur.contains(LinkLine::HORIZONTAL) {
                     if cur.intersects(LinkLine::CHILD | LinkLine::ANY_FORK_OR_MERGE) {
-                        link_line.push_str("+");
+                        cell.push_str("+");
                     } else {
-                        link_line.push_str("-");
+                        cell.push_str("-");
                     }
                 } else if cur.intersects(LinkLine::PARENT | LinkLine::ANCESTOR) {
                     if cur.intersects(LinkLine::ANY_FORK_OR_MERGE)
                         && adj.contains(LinkLine::HORIZONTAL)
                     {
-                        link_line.push_str("+");
+                        cell.push_str("+");
                     } else if cur.contains(LinkLine::ANCESTOR) {
-                        link_line.push_str(".");
+                        cell.push_str(".");
                     } else {
-                        link_line.push_str("|");
+                        cell.push_str("|");
                     }
                 } else if cur.intersects(LinkLine::ANY_MERGE) && adj.contains(LinkLine::HORIZONTAL)
                 {
-                    link_line.push_str("'");
+                    cell.push_str("'");
                 } else if cur.intersects(LinkLine::ANY_FORK) && adj.contains(LinkLine::HORIZONTAL) {
-                    link_line.push_str(".");
+                    cell.push_str(".");
                 } else {
-                    link_line.push_str(" ");
+                    cell.push_str(" ");
                 }
 
                 // Draw the connecting line.
                 if cur.contains(LinkLine::HORIZONTAL) {
-                    link_line.push_str("-");
+                    cell.push_str("-");
                 } else if cur.contains(LinkLine::RIGHT_MERGE) {
                     if next.contains(LinkLine::LEFT_FORK) && !next.contains(LinkLine::HORIZONTAL) {
-                        link_line.push_str("\\");
+                        cell.push_str("\\");
                     } else {
-                        link_line.push_str("-");
+                        cell.push_str("-");
                     }
                 } else if cur.contains(LinkLine::RIGHT_FORK) {
                     if next.contains(LinkLine::LEFT_MERGE) && !next.contains(LinkLine::HORIZONTAL) {
-                        link_line.push_str("/");
+                        cell.push_str("/");
                     } else {
-                        link_line.push_str("-");
+                        cell.push_str("-");
                     }
                 } else {
-                    link_line.push_str(" ");
+                    cell.push_str(" ");
                 }
+                options.push_cell(&mut link_line, i, &cell);
             }
             if let Some(msg) = message_lines.next() {
                 link_line.push_str(" ");
@@ -166,13 +170,14 @@ where
                 let mut term_line = String::new();
                 for (i, term) in term_row.iter().enumerate() {
                     if *term {
-                        term_line.push_str(term_str);
+                        options.push_cell(&mut term_line, i, term_str);
                     } else {
-                        term_line.push_str(match line.pad_lines[i] {
+                        let cell = match line.pad_lines[i] {
                             PadLine::Parent => "| ",
                             PadLine::Ancestor => ". ",
                             PadLine::Blank => "  ",
-                        });
+                        };
+                        options.push_cell(&mut term_line, i, cell);
                     }
                 }
                 if let Some(msg) = message_lines.next() {
@@ -186,12 +191,13 @@ where
         }
 
         let mut base_pad_line = String::new();
-        for entry in line.pad_lines.iter() {
-            base_pad_line.push_str(match entry {
+        for (i, entry) in line.pad_lines.iter().enumerate() {
+            let cell = match entry {
                 PadLine::Parent => "| ",
                 PadLine::Ancestor => ". ",
                 PadLine::Blank => "  ",
-            });
+            };
+            options.push_cell(&mut base_pad_line, i, cell);
         }
 
         // Render any pad lines
@@ -216,7 +222,7 @@ where
 mod tests {
     use crate::render::GraphRowRenderer;
     use crate::test_fixtures::{self, TestFixture};
-    use crate::test_utils::render_string;
+    use crate::test_utils::{render_string, strip_ansi};
 
     fn render(fixture: &TestFixture) -> String {
         let mut renderer = GraphRowRenderer::new().output().build_ascii();
@@ -450,4 +456,20 @@ mod tests {
                long message 3"#
         );
     }
+
+    #[test]
+    fn ansi_colors() {
+        let mut renderer = GraphRowRenderer::new()
+            .output()
+            .with_ansi_colors()
+            .build_ascii();
+        let colored = render_string(&test_fixtures::BRANCHES_AND_MERGES, &mut renderer);
+        assert_eq!(
+            strip_ansi(&colored),
+            render(&test_fixtures::BRANCHES_AND_MERGES)
+        );
+        // Each column has its own color.
+        assert!(colored
+            .contains("\x1B[31m|\x1B[0m \x1B[32m+-\x1B[0m\x1B[33m--\x1B[0m\x1B[34m.\x1B[0m\n"));
+    }
 }
@@ -127,6 +127,7 @@ where
         message: String,
     ) -> String {
         let glyphs = self.glyphs;
+        let options = &self.options;
         let line = self.inner.next_row(node, parents, glyph, message);
         let mut out = String::new();
         let mut message_lines = line
@@ -143,14 +144,14 @@ where
 
         // Render the nodeline
         let mut node_line = String::new();
-        for entry in line.node_line.iter() {
+        for (i, entry) in line.node_line.iter().enumerate() {
             match entry {
                 NodeLine::Node => {
                     node_line.push_str(&line.glyph);
                     node_line.push_str(" ");
                 }
-                NodeLine::Parent => node_line.push_str(glyphs[glyph::PARENT]),
-                NodeLine::Ancestor => node_line.push_str(glyphs[glyph::ANCESTOR]),
+                NodeLine::Parent => options.push_cell(&mut node_line, i, glyphs[glyph::PARENT]),
+                NodeLine::Ancestor => options.push_cell(&mut node_line, i, glyphs[glyph::ANCESTOR]),
                 NodeLine::Blank => node_line.push_str(glyphs[glyph::SPACE]),
             }
         }
@@ -164,34 +165,34 @@ where
         // Render the link line
         if let Some(link_row) = line.link_line {
             let mut link_line = String::new();
-            for cur in link_row.iter() {
-                if cur.contains(LinkLine::HORIZONTAL) {
+            for (i, cur) in link_row.iter().enumerate() {
+                let cell = if cur.contains(LinkLine::HORIZONTAL) {
                     if cur.intersects(LinkLine::CHILD) {
-                        link_line.push_str(glyphs[glyph::JOIN_BOTH]);
+                        glyphs[glyph::JOIN_BOTH]
                     } else if cur.intersects(LinkLine::ANY_FORK)
                         && cur.intersects(LinkLine::ANY_MERGE)
                     {
-                        link_line.push_str(glyphs[glyph::JOIN_BOTH]);
+                        glyphs[glyph::JOIN_BOTH]
                     } else if cur.intersects(LinkLine::ANY_FORK)
                         && cur.intersects(LinkLine::PARENT)
                         && !line.merge
                     {
-                        link_line.push_str(glyphs[glyph::JOIN_BOTH]);
+                        glyphs[glyph::JOIN_BOTH]
                     } else if cur.intersects(LinkLine::ANY_FORK) {
-                        link_line.push_str(glyphs[glyph::FORK_BOTH]);
+                        glyphs[glyph::FORK_BOTH]
                     } else if cur.intersects(LinkLine::ANY_MERGE) {
-                        link_line.push_str(glyphs[glyph::MERGE_BOTH]);
+                        glyphs[glyph::MERGE_BOTH]
                     } else {
-                        link_line.push_str(glyphs[glyph::HORIZONTAL]);
+                        glyphs[glyph::HORIZONTAL]
                     }
                 } else if cur.contains(LinkLine::PARENT) && !line.merge {
                     let left = cur.intersects(LinkLine::LEFT_MERGE | LinkLine::LEFT_FORK);
                     let right = cur.intersects(LinkLine::RIGHT_MERGE | LinkLine::RIGHT_FORK);
                     match (left, right) {
-                        (true, true) => link_line.push_str(glyphs[glyph::JOIN_BOTH]),
-                        (true, false) => link_line.push_str(glyphs[glyph::JOIN_LEFT]),
-                        (false, true) => link_line.push_str(glyphs[glyph::JOIN_RIGHT]),
-                        (false, false) => link_line.push_str(glyphs[glyph::PARENT]),
+                        (true, true) => glyphs[glyph::JOIN_BOTH],
+                        (true, false) => glyphs[glyph::JOIN_LEFT],
+                        (false, true) => glyphs[glyph::JOIN_RIGHT],
+                        (false, false) => glyphs[glyph::PARENT],
                     }
                 } else if cur.intersects(LinkLine::PARENT | LinkLine::ANCESTOR)
                     && !cur.intersects(LinkLine::LEFT_FORK | LinkLine::RIGHT_FORK)
@@ -199,40 +200,41 @@ where
                     let left = cur.contains(LinkLine::LEFT_MERGE);
                     let right = cur.contains(LinkLine::RIGHT_MERGE);
                     match (left, right) {
-                        (true, true) => link_line.push_str(glyphs[glyph::JOIN_BOTH]),
-                        (true, false) => link_line.push_str(glyphs[glyph::JOIN_LEFT]),
-                        (false, true) => link_line.push_str(glyphs[glyph::JOIN_RIGHT]),
+                        (true, true) => glyphs[glyph::JOIN_BOTH],
+                        (true, false) => glyphs[glyph::JOIN_LEFT],
+                        (false, true) => glyphs[glyph::JOIN_RIGHT],
                         (false, false) => {
                             if cur.contains(LinkLine::ANCESTOR) {
-                                link_line.push_str(glyphs[glyph::ANCESTOR]);
+                                glyphs[glyph::ANCESTOR]
                             } else {
-                                link_line.push_str(glyphs[glyph::PARENT]);
+                                glyphs[glyph::PARENT]
                             }
                         }
                     }
                 } else if cur.contains(LinkLine::LEFT_FORK)
                     && cur.intersects(LinkLine::LEFT_MERGE | LinkLine::CHILD)
                 {
-                    link_line.push_str(glyphs[glyph::JOIN_LEFT]);
+                    glyphs[glyph::JOIN_LEFT]
                 } else if cur.contains(LinkLine::RIGHT_FORK)
                     && cur.intersects(LinkLine::RIGHT_MERGE | LinkLine::CHILD)
                 {
-                    link_line.push_str(glyphs[glyph::JOIN_RIGHT]);
+                    glyphs[glyph::JOIN_RIGHT]
                 } else if cur.contains(LinkLine::ANY_MERGE) {
-                    link_line.push_str(glyphs[glyph::MERGE_BOTH]);
+                    glyphs[glyph::MERGE_BOTH]
                 } else if cur.contains(LinkLine::ANY_FORK) {
-                    link_line.push_str(glyphs[glyph::FORK_BOTH]);
+                    glyphs[glyph::FORK_BOTH]
                 } else if cur.contains(LinkLine::LEFT_FORK) {
-                    link_line.push_str(glyphs[glyph::FORK_LEFT]);
+                    glyphs[glyph::FORK_LEFT]
                 } else if cur.contains(LinkLine::LEFT_MERGE) {
-                    link_line.push_str(glyphs[glyph::MERGE_LEFT]);
+                    glyphs[glyph::MERGE_LEFT]
                 } else if cur.contains(LinkLine::RIGHT_FORK) {
-                    link_line.push_str(glyphs[glyph::FORK_RIGHT]);
+                    glyphs[glyph::FORK_RIGHT]
                 } else if cur.contains(LinkLine::RIGHT_MERGE) {
-                    link_line.push_str(glyphs[glyph::MERGE_RIGHT]);
+                    glyphs[glyph::MERGE_RIGHT]
                 } else {
-                    link_line.push_str(glyphs[glyph::SPACE]);
-                }
+                    glyphs[glyph::SPACE]
+                };
+                options.push_cell(&mut link_line, i, cell);
             }
             if let Some(msg) = message_lines.next() {
                 link_line.push_str(" ");
@@ -249,9 +251,9 @@ where
                 let mut term_line = String::new();
                 for (i, term) in term_row.iter().enumerate() {
                     if *term {
-                        term_line.push_str(term_str);
+                        options.push_cell(&mut term_line, i, term_str);
                     } else {
-                        term_line.push_str(glyphs[line.pad_lines[i].to_glyph()]);
+                        options.push_cell(&mut term_line, i, glyphs[line.pad_lines[i].to_glyph()]);
                     }
                 }
                 if let Some(msg) = message_lines.next() {
@@ -265,8 +267,8 @@ where
         }
 
         let mut base_pad_line = String::new();
-        for entry in line.pad_lines.iter() {
-            base_pad_line.push_str(glyphs[entry.to_glyph()]);
+        for (i, entry) in line.pad_lines.iter().enumerate() {
+            options.push_cell(&mut base_pad_line, i, glyphs[entry.to_glyph()]);
         }
 
         // Render any pad lines
@@ -291,7 +293,7 @@ where
 mod tests {
     use crate::render::GraphRowRenderer;
     use crate::test_fixtures::{self, TestFixture};
-    use crate::test_utils::{render_string, render_string_with_order};
+    use crate::test_utils::{render_string, render_string_with_order, strip_ansi};
 
     fn render(fixture: &TestFixture) -> String {
         let mut renderer = GraphRowRenderer::new().output().build_box_drawing();
@@ -658,4 +660,19 @@ mod tests {
             o  A"#
         );
     }
+
+    #[test]
+    fn ansi_colors() {
+        let mut renderer = GraphRowRenderer::new()
+            .output()
+            .with_ansi_colors()
+            .build_box_drawing();
+        let colored = render_string(&test_fixtures::BRANCHES_AND_MERGES, &mut renderer);
+        assert_eq!(
+            strip_ansi(&colored),
+            render(&test_fixtures::BRANCHES_AND_MERGES)
+        );
+        // Each column has its own color.
+        assert!(colored.contains("\x1B[31m│\x1B[0m \x1B[32m├─\x1B[0m\x1B[33m╮\x1B[0m\n"));
+    }
 }
@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::render::Ancestor;
+
+/// A row to render after eliding linear runs.
+pub struct ElidedRow<N> {
+    /// The node to render. For an elided run, this is the first node of the
+    /// run.
+    pub node: N,
+
+    /// Parents of the row. For an elided run, these are the parents of the
+    /// last node of the run.
+    pub parents: Vec<Ancestor<N>>,
+
+    /// Number of nodes this row stands for. 1 for a normal row.
+    pub count: usize,
+}
+
+/// Replace runs of at least `min_run` linear nodes with a single row.
+///
+/// `rows` are nodes with their parents, in rendering order. A node can be
+/// elided if its only parent is a [`Ancestor::Parent`], it is the only
+/// parent of the previous row and no other row, and `keep` returns false
+/// for it. The caller typically renders an elided run with a message like
+/// "… N commits".
+pub fn elide_linear_runs<N>(
+    rows: impl IntoIterator<Item = (N, Vec<Ancestor<N>>)>,
+    min_run: usize,
+    keep: impl Fn(&N) -> bool,
+) -> Vec<ElidedRow<N>>
+where
+    N: Clone + Eq + Hash,
+{
+    let rows: Vec<_> = rows.into_iter().collect();
+
+    // Count the children of each node.
+    let mut child_count: HashMap<&N, usize> = HashMap::new();
+    for (_, parents) in rows.iter() {
+        for parent in parents.iter() {
+            if let Some(parent) = parent.id() {
+                *child_count.entry(parent).or_default() += 1;
+            }
+        }
+    }
+    let elidable: Vec<bool> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, (node, parents))| {
+            let linear = matches!(parents.as_slice(), [Ancestor::Parent(_)]);
+            let child_is_previous = i > 0
+                && match rows[i - 1].1.as_slice() {
+                    [Ancestor::Parent(parent)] => parent == node,
+                    _ => false,
+                };
+            linear && child_is_previous && child_count.get(node) == Some(&1) && !keep(node)
+        })
+        .collect();
+
+    let mut result = Vec::with_capacity(rows.len());
+    let mut rows = rows.into_iter().zip(elidable).peekable();
+    while let Some((row, elidable)) = rows.next() {
+        let mut run = vec![row];
+        if elidable {
+            while let Some((_, true)) = rows.peek() {
+                run.push(rows.next().unwrap().0);
+            }
+        }
+        if elidable && run.len() >= min_run {
+            let count = run.len();
+            let node = run[0].0.clone();
+            let (_, parents) = run.pop().unwrap();
+            result.push(ElidedRow {
+                node,
+                parents,
+                count,
+            });
+        } else {
+            result.extend(run.into_iter().map(|(node, parents)| ElidedRow {
+                node,
+                parents,
+                count: 1,
+            }));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{GraphRowRenderer, Renderer};
+
+    fn rows() -> Vec<(&'static str, Vec<Ancestor<&'static str>>)> {
+        use Ancestor::Parent;
+        // E and C have two children. K is kept.
+        vec![
+            ("G", vec![Parent("F")]),
+            ("X", vec![Parent("E")]),
+            ("F", vec![Parent("E"), Parent("C")]),
+            ("E", vec![Parent("D")]),
+            ("D", vec![Parent("K")]),
+            ("K", vec![Parent("J")]),
+            ("J", vec![Parent("I")]),
+            ("I", vec![Parent("H")]),
+            ("H", vec![Parent("C")]),
+            ("C", vec![Parent("B")]),
+            ("B", vec![Parent("A")]),
+            ("A", vec![]),
+        ]
+    }
+
+    fn render(min_run: usize) -> String {
+        let rows = rows();
+        let mut renderer = GraphRowRenderer::new().output().build_box_drawing();
+        let mut out = String::new();
+        for row in elide_linear_runs(rows, min_run, |n| *n == "K") {
+            let (glyph, message) = match row.count {
+                1 => ("o", row.node.to_string()),
+                count => ("┆", format!("… {} commits", count)),
+            };
+            out += &renderer.next_row(row.node, row.parents, glyph.to_string(), message);
+        }
+        out
+    }
+
+    #[test]
+    fn test_elide() {
+        assert_eq!(
+            render(3),
+            r#"o  G
+│
+│ o  X
+│ │
+o │  F
+├─╮
+│ o  E
+│ │
+│ o  D
+│ │
+│ o  K
+│ │
+│ ┆  … 3 commits
+├─╯
+o  C
+│
+o  B
+│
+o  A
+
+"#
+        );
+
+        // Runs shorter than min_run are kept.
+        let rendered = render(4);
+        assert!(rendered.contains("o  J"));
+        assert!(!rendered.contains("…"));
+    }
+}
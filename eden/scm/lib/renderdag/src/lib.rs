@@ -9,9 +9,11 @@ mod ascii;
 mod ascii_large;
 mod box_drawing;
 mod column;
+mod elide;
 mod output;
 mod render;
 mod render_utils;
+mod svg;
 
 #[cfg(test)]
 mod test_fixtures;
@@ -22,5 +24,8 @@ mod test_utils;
 pub use crate::ascii::AsciiRenderer;
 pub use crate::ascii_large::AsciiLargeRenderer;
 pub use crate::box_drawing::BoxDrawingRenderer;
+pub use crate::elide::{elide_linear_runs, ElidedRow};
+pub use crate::output::ColumnStyle;
 pub use crate::render::{Ancestor, GraphRowRenderer, LinkLine, NodeLine, PadLine, Renderer};
 pub use crate::render_utils::render_namedag;
+pub use crate::svg::{SvgRenderer, SvgStyle};
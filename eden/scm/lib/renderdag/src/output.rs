@@ -6,14 +6,43 @@
  */
 
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use crate::ascii::AsciiRenderer;
 use crate::ascii_large::AsciiLargeRenderer;
 use crate::box_drawing::BoxDrawingRenderer;
 use crate::render::{GraphRow, Renderer};
+use crate::svg::SvgRenderer;
+
+/// Styles the glyphs of a graph column. Called with the column index and the
+/// glyphs of one cell, without trailing spaces.
+pub type ColumnStyle = Arc<dyn Fn(usize, &str) -> String + Send + Sync>;
+
+/// ANSI foreground colors used by `with_ansi_colors`.
+const ANSI_COLORS: [u8; 6] = [31, 32, 33, 34, 35, 36];
 
 pub(crate) struct OutputRendererOptions {
     pub(crate) min_row_height: usize,
+    pub(crate) column_style: Option<ColumnStyle>,
+}
+
+impl OutputRendererOptions {
+    /// Append the glyphs of a cell in `column` to `line`.
+    ///
+    /// Trailing spaces are kept outside of the styled span, so that lines
+    /// can still be trimmed.
+    pub(crate) fn push_cell(&self, line: &mut String, column: usize, glyphs: &str) {
+        match &self.column_style {
+            Some(style) => {
+                let trimmed = glyphs.trim_end();
+                if !trimmed.is_empty() {
+                    line.push_str(&style(column, trimmed));
+                }
+                line.push_str(&glyphs[trimmed.len()..]);
+            }
+            None => line.push_str(glyphs),
+        }
+    }
 }
 
 pub struct OutputRendererBuilder<N, R>
@@ -32,7 +61,10 @@ where
     pub fn new(inner: R) -> Self {
         OutputRendererBuilder {
             inner,
-            options: OutputRendererOptions { min_row_height: 2 },
+            options: OutputRendererOptions {
+                min_row_height: 2,
+                column_style: None,
+            },
             _phantom: PhantomData,
         }
     }
@@ -42,6 +74,26 @@ where
         self
     }
 
+    /// Style the edges of each column with `style`, for example to color
+    /// them. A column keeps its style across rows.
+    ///
+    /// Only the `ascii` and `box_drawing` renderers support styles.
+    pub fn with_column_style(
+        mut self,
+        style: impl Fn(usize, &str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.options.column_style = Some(Arc::new(style));
+        self
+    }
+
+    /// Color the edges of each column with ANSI escape codes.
+    pub fn with_ansi_colors(self) -> Self {
+        self.with_column_style(|column, glyphs| {
+            let color = ANSI_COLORS[column % ANSI_COLORS.len()];
+            format!("\x1B[{}m{}\x1B[0m", color, glyphs)
+        })
+    }
+
     pub fn build_ascii(self) -> AsciiRenderer<N, R> {
         AsciiRenderer::new(self.inner, self.options)
     }
@@ -53,4 +105,8 @@ where
     pub fn build_box_drawing(self) -> BoxDrawingRenderer<N, R> {
         BoxDrawingRenderer::new(self.inner, self.options)
     }
+
+    pub fn build_svg(self) -> SvgRenderer<N, R> {
+        SvgRenderer::new(self.inner, self.options)
+    }
 }
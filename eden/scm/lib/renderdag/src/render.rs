@@ -71,7 +71,7 @@ impl<N> Ancestor<N> {
         }
     }
 
-    fn id(&self) -> Option<&N> {
+    pub(crate) fn id(&self) -> Option<&N> {
         match self {
             Ancestor::Ancestor(n) => Some(&n),
             Ancestor::Parent(n) => Some(&n),
@@ -0,0 +1,528 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt::Write;
+use std::marker::PhantomData;
+
+use crate::output::OutputRendererOptions;
+use crate::render::{Ancestor, GraphRow, LinkLine, NodeLine, PadLine, Renderer};
+
+/// Width of a graph column in pixels.
+const COLUMN_WIDTH: usize = 16;
+
+/// Height of a line of text in pixels.
+const LINE_HEIGHT: usize = 20;
+
+/// Offset from the top of a line to the text baseline.
+const BASELINE: usize = 14;
+
+/// Radius of a node.
+const NODE_RADIUS: usize = 5;
+
+/// Space between the graph and the labels.
+const LABEL_PADDING: usize = 8;
+
+/// Returns the URL a node links to.
+type LinkFn<N> = Box<dyn Fn(&N) -> Option<String>>;
+
+/// Colors and fonts used by [`SvgRenderer`].
+#[derive(Clone, Debug)]
+pub struct SvgStyle {
+    /// Colors of edges. Columns cycle through the list.
+    pub edge_colors: Vec<String>,
+
+    /// Fill color of nodes.
+    pub node_color: String,
+
+    /// Color of glyphs drawn inside nodes.
+    pub glyph_color: String,
+
+    /// Color of the label text.
+    pub text_color: String,
+
+    /// Background color. `None` means transparent.
+    pub background: Option<String>,
+
+    /// Font family of the label text.
+    pub font_family: String,
+
+    /// Font size of the label text in pixels.
+    pub font_size: usize,
+}
+
+impl Default for SvgStyle {
+    fn default() -> Self {
+        SvgStyle {
+            edge_colors: [
+                "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+            ]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+            node_color: "#444444".to_string(),
+            glyph_color: "#ffffff".to_string(),
+            text_color: "#222222".to_string(),
+            background: None,
+            font_family: "monospace".to_string(),
+            font_size: 12,
+        }
+    }
+}
+
+/// Renders the graph as SVG.
+///
+/// Each call to `next_row` returns the SVG elements for that row. Use
+/// [`SvgRenderer::finish_svg`] or [`SvgRenderer::finish_html`] to get a
+/// complete document of all rows rendered so far.
+pub struct SvgRenderer<N, R>
+where
+    R: Renderer<N, Output = GraphRow<N>> + Sized,
+{
+    inner: R,
+    options: OutputRendererOptions,
+    style: SvgStyle,
+    link: Option<LinkFn<N>>,
+
+    /// Elements of all rows rendered so far.
+    body: String,
+
+    /// Top of the next row.
+    height: usize,
+
+    /// Right edge of the widest label.
+    width: usize,
+
+    /// The pad line of the previous row, to connect nodes to edges above.
+    last_pad_line: Vec<PadLine>,
+
+    _phantom: PhantomData<N>,
+}
+
+impl<N, R> SvgRenderer<N, R>
+where
+    R: Renderer<N, Output = GraphRow<N>> + Sized,
+{
+    pub(crate) fn new(inner: R, options: OutputRendererOptions) -> Self {
+        SvgRenderer {
+            inner,
+            options,
+            style: SvgStyle::default(),
+            link: None,
+            body: String::new(),
+            height: 0,
+            width: 0,
+            last_pad_line: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn with_style(mut self, style: SvgStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Make nodes and their labels links to the URL returned by `link`.
+    pub fn with_link(mut self, link: impl Fn(&N) -> Option<String> + 'static) -> Self {
+        self.link = Some(Box::new(link));
+        self
+    }
+
+    /// Complete SVG document of the rendered rows.
+    pub fn finish_svg(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" "#,
+                r#"width="{w}" height="{h}" viewBox="0 0 {w} {h}" "#,
+                r#"font-family="{font}" font-size="{size}">"#,
+                "\n"
+            ),
+            w = self.width,
+            h = self.height,
+            font = escape(&self.style.font_family),
+            size = self.style.font_size,
+        );
+        if let Some(background) = &self.style.background {
+            let _ = writeln!(
+                out,
+                r#"<rect width="100%" height="100%" fill="{}"/>"#,
+                escape(background)
+            );
+        }
+        out.push_str(&self.body);
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Complete HTML page with the SVG document inline.
+    pub fn finish_html(&self) -> String {
+        format!(
+            concat!(
+                "<!DOCTYPE html>\n",
+                "<html>\n",
+                "<head><meta charset=\"utf-8\"></head>\n",
+                "<body>\n",
+                "{}",
+                "</body>\n",
+                "</html>\n"
+            ),
+            self.finish_svg()
+        )
+    }
+
+    fn edge_color(&self, column: usize) -> &str {
+        match self.style.edge_colors.len() {
+            0 => "#000000",
+            len => &self.style.edge_colors[column % len],
+        }
+    }
+
+    /// Draw the part of a column's edge between `from` and `to`, which are
+    /// offsets relative to the center of the cell.
+    fn segment(
+        &self,
+        out: &mut String,
+        column: usize,
+        top: usize,
+        from: (isize, isize),
+        to: (isize, isize),
+        dashed: bool,
+    ) {
+        let x = (column * COLUMN_WIDTH + COLUMN_WIDTH / 2) as isize;
+        let y = (top + LINE_HEIGHT / 2) as isize;
+        let from = (x + from.0, y + from.1);
+        let to = (x + to.0, y + to.1);
+        self.line(out, column, from, to, dashed);
+    }
+
+    /// Draw a line in the color of `column`.
+    fn line(
+        &self,
+        out: &mut String,
+        column: usize,
+        from: (isize, isize),
+        to: (isize, isize),
+        dashed: bool,
+    ) {
+        let dash = if dashed {
+            r#" stroke-dasharray="2,3""#
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="2"{}/>"#,
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            escape(self.edge_color(column)),
+            dash
+        );
+    }
+}
+
+/// Half of the cell sizes, as offsets from the cell center.
+const HALF_WIDTH: isize = (COLUMN_WIDTH / 2) as isize;
+const HALF_HEIGHT: isize = (LINE_HEIGHT / 2) as isize;
+const CENTER: (isize, isize) = (0, 0);
+const TOP: (isize, isize) = (0, -HALF_HEIGHT);
+const BOTTOM: (isize, isize) = (0, HALF_HEIGHT);
+const LEFT: (isize, isize) = (-HALF_WIDTH, 0);
+const RIGHT: (isize, isize) = (HALF_WIDTH, 0);
+
+impl<N, R> Renderer<N> for SvgRenderer<N, R>
+where
+    N: Clone + Eq,
+    R: Renderer<N, Output = GraphRow<N>> + Sized,
+{
+    type Output = String;
+
+    fn width(&self, node: Option<&N>, parents: Option<&Vec<Ancestor<N>>>) -> u64 {
+        self.inner
+            .width(node, parents)
+            .saturating_mul(COLUMN_WIDTH as u64)
+    }
+
+    fn reserve(&mut self, node: N) {
+        self.inner.reserve(node);
+    }
+
+    fn next_row(
+        &mut self,
+        node: N,
+        parents: Vec<Ancestor<N>>,
+        glyph: String,
+        message: String,
+    ) -> String {
+        let line = self.inner.next_row(node, parents, glyph, message);
+        let mut out = String::new();
+        let link = self.link.as_ref().and_then(|link| link(&line.node));
+        let message_lines: Vec<&str> = line.message.lines().collect();
+        let graph_lines = 1 + line.link_line.is_some() as usize + line.term_line.is_some() as usize;
+        let row_lines = message_lines
+            .len()
+            .max(self.options.min_row_height)
+            .max(graph_lines);
+        let row_top = self.height;
+        let row_bottom = row_top + row_lines * LINE_HEIGHT;
+        let continues = |i: usize| {
+            line.pad_lines[i] != PadLine::Blank
+                || line.term_line.as_ref().map(|term| term[i]) == Some(true)
+        };
+
+        // Render the node line. The node is drawn last so it is on top of
+        // the edges.
+        let mut top = row_top;
+        let mut node_column = 0;
+        for (i, entry) in line.node_line.iter().enumerate() {
+            match entry {
+                NodeLine::Node => {
+                    node_column = i;
+                    match self.last_pad_line.get(i) {
+                        Some(PadLine::Parent) => self.segment(&mut out, i, top, TOP, CENTER, false),
+                        Some(PadLine::Ancestor) => {
+                            self.segment(&mut out, i, top, TOP, CENTER, true)
+                        }
+                        _ => {}
+                    }
+                    if line.link_line.is_some() || continues(i) {
+                        let dashed =
+                            line.link_line.is_none() && line.pad_lines[i] == PadLine::Ancestor;
+                        self.segment(&mut out, i, top, CENTER, BOTTOM, dashed);
+                    }
+                }
+                NodeLine::Parent => self.segment(&mut out, i, top, TOP, BOTTOM, false),
+                NodeLine::Ancestor => self.segment(&mut out, i, top, TOP, BOTTOM, true),
+                NodeLine::Blank => {}
+            }
+        }
+        top += LINE_HEIGHT;
+
+        // Render the link line. Each cell is drawn as up to four half lines
+        // meeting at the center of the cell.
+        if let Some(link_line) = &line.link_line {
+            for (i, cur) in link_line.iter().enumerate() {
+                // Forks towards an ancestor are dashed.
+                let dashed =
+                    cur.intersects(LinkLine::ANY_FORK) && line.pad_lines[i] == PadLine::Ancestor;
+                if line.node_line[i] != NodeLine::Blank {
+                    let dashed = line.node_line[i] == NodeLine::Ancestor;
+                    self.segment(&mut out, i, top, TOP, CENTER, dashed);
+                }
+                if continues(i) {
+                    let dashed = line.pad_lines[i] == PadLine::Ancestor;
+                    self.segment(&mut out, i, top, CENTER, BOTTOM, dashed);
+                }
+                if cur.intersects(LinkLine::HORIZONTAL | LinkLine::LEFT_MERGE | LinkLine::LEFT_FORK)
+                {
+                    self.segment(&mut out, i, top, LEFT, CENTER, dashed);
+                }
+                if cur
+                    .intersects(LinkLine::HORIZONTAL | LinkLine::RIGHT_MERGE | LinkLine::RIGHT_FORK)
+                {
+                    self.segment(&mut out, i, top, CENTER, RIGHT, dashed);
+                }
+            }
+            top += LINE_HEIGHT;
+        }
+
+        // Render the term line. Terminated columns end with a dotted stub.
+        if let Some(term_line) = &line.term_line {
+            for (i, term) in term_line.iter().enumerate() {
+                if *term {
+                    self.segment(&mut out, i, top, TOP, CENTER, true);
+                } else if line.pad_lines[i] != PadLine::Blank {
+                    let dashed = line.pad_lines[i] == PadLine::Ancestor;
+                    self.segment(&mut out, i, top, TOP, BOTTOM, dashed);
+                }
+            }
+            top += LINE_HEIGHT;
+        }
+
+        // Render the pad lines to the bottom of the row.
+        if top < row_bottom {
+            for (i, pad) in line.pad_lines.iter().enumerate() {
+                if *pad != PadLine::Blank {
+                    let x = (i * COLUMN_WIDTH + COLUMN_WIDTH / 2) as isize;
+                    let dashed = *pad == PadLine::Ancestor;
+                    self.line(
+                        &mut out,
+                        i,
+                        (x, top as isize),
+                        (x, row_bottom as isize),
+                        dashed,
+                    );
+                }
+            }
+        }
+
+        // Render the node.
+        let mut node = String::new();
+        let cx = node_column * COLUMN_WIDTH + COLUMN_WIDTH / 2;
+        let cy = row_top + LINE_HEIGHT / 2;
+        let radius = if line.glyph == "o" {
+            NODE_RADIUS
+        } else {
+            NODE_RADIUS + 2
+        };
+        let _ = writeln!(
+            node,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+            cx,
+            cy,
+            radius,
+            escape(&self.style.node_color)
+        );
+        if line.glyph != "o" {
+            let _ = writeln!(
+                node,
+                r#"<text x="{}" y="{}" fill="{}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                cx,
+                cy,
+                escape(&self.style.glyph_color),
+                NODE_RADIUS * 2,
+                escape(&line.glyph)
+            );
+        }
+
+        // Render the labels.
+        let label_x = line.node_line.len() * COLUMN_WIDTH + LABEL_PADDING;
+        let mut labels = String::new();
+        for (i, message_line) in message_lines.iter().enumerate() {
+            if message_line.is_empty() {
+                continue;
+            }
+            let _ = writeln!(
+                labels,
+                r#"<text x="{}" y="{}" fill="{}" xml:space="preserve">{}</text>"#,
+                label_x,
+                row_top + i * LINE_HEIGHT + BASELINE,
+                escape(&self.style.text_color),
+                escape(message_line)
+            );
+            let label_width = message_line.chars().count() * self.style.font_size * 3 / 5;
+            self.width = self.width.max(label_x + label_width + LABEL_PADDING);
+        }
+        self.width = self.width.max(label_x);
+
+        match link {
+            Some(link) => {
+                let _ = writeln!(out, r#"<a href="{}">"#, escape(&link));
+                out.push_str(&node);
+                out.push_str(&labels);
+                out.push_str("</a>\n");
+            }
+            None => {
+                out.push_str(&node);
+                out.push_str(&labels);
+            }
+        }
+
+        self.height = row_bottom;
+        self.last_pad_line = line.pad_lines;
+        self.body.push_str(&out);
+        out
+    }
+}
+
+/// Escape text for use in XML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::GraphRowRenderer;
+
+    fn render(
+        rows: &[(&'static str, Vec<Ancestor<&'static str>>)],
+    ) -> SvgRenderer<&'static str, GraphRowRenderer<&'static str>> {
+        let mut renderer = GraphRowRenderer::new()
+            .output()
+            .build_svg()
+            .with_link(|node: &&str| Some(format!("https://example.com/?n={}&x=1", node)));
+        for (node, parents) in rows.iter() {
+            let parents = parents
+                .iter()
+                .map(|p| match p {
+                    Ancestor::Parent(n) => Ancestor::Parent(*n),
+                    Ancestor::Ancestor(n) => Ancestor::Ancestor(*n),
+                    Ancestor::Anonymous => Ancestor::Anonymous,
+                })
+                .collect();
+            renderer.next_row(*node, parents, "o".to_string(), format!("{} <msg>", node));
+        }
+        renderer
+    }
+
+    #[test]
+    fn test_linear() {
+        let renderer = render(&[("B", vec![Ancestor::Parent("A")]), ("A", vec![])]);
+        assert_eq!(
+            renderer.finish_svg(),
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="82" height="80" viewBox="0 0 82 80" font-family="monospace" font-size="12">
+<line x1="8" y1="10" x2="8" y2="20" stroke="#1f77b4" stroke-width="2"/>
+<line x1="8" y1="20" x2="8" y2="40" stroke="#1f77b4" stroke-width="2"/>
+<a href="https://example.com/?n=B&amp;x=1">
+<circle cx="8" cy="10" r="5" fill="#444444"/>
+<text x="24" y="14" fill="#222222" xml:space="preserve">B &lt;msg&gt;</text>
+</a>
+<line x1="8" y1="40" x2="8" y2="50" stroke="#1f77b4" stroke-width="2"/>
+<a href="https://example.com/?n=A&amp;x=1">
+<circle cx="8" cy="50" r="5" fill="#444444"/>
+<text x="24" y="54" fill="#222222" xml:space="preserve">A &lt;msg&gt;</text>
+</a>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn test_merge_and_ancestor() {
+        let renderer = render(&[
+            ("D", vec![Ancestor::Parent("B"), Ancestor::Ancestor("C")]),
+            ("C", vec![Ancestor::Parent("A")]),
+            ("B", vec![Ancestor::Parent("A")]),
+            ("A", vec![]),
+        ]);
+        let svg = renderer.finish_svg();
+        // The merge row has a link line connecting both columns.
+        assert!(svg.contains(
+            r##"<line x1="8" y1="30" x2="16" y2="30" stroke="#1f77b4" stroke-width="2"/>"##
+        ));
+        // The edge to the ancestor is dashed.
+        assert!(svg.contains(r##"<line x1="16" y1="30" x2="24" y2="30" stroke="#ff7f0e" stroke-width="2" stroke-dasharray="2,3"/>"##));
+        assert_eq!(svg.matches("<circle").count(), 4);
+    }
+
+    #[test]
+    fn test_html() {
+        let renderer = render(&[("A", vec![])]).with_style(SvgStyle {
+            background: Some("white".to_string()),
+            ..Default::default()
+        });
+        let html = renderer.finish_html();
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<body>\n<svg "));
+        assert!(html.contains(r#"<rect width="100%" height="100%" fill="white"/>"#));
+        assert!(html.ends_with("</svg>\n</body>\n</html>\n"));
+    }
+}
@@ -98,7 +98,7 @@ pub(crate) fn render_string_with_order(
             .next()
             .expect("name should be in the output");
         assert_eq!(
-            row_indent.width() as u64,
+            strip_ansi(row_indent).width() as u64,
             width,
             "indent '{}' for row for {} is the wrong width",
             row_indent,
@@ -117,3 +117,20 @@ pub(crate) fn render_string_with_order(
             .join("\n")
     )
 }
+
+/// Remove ANSI color codes.
+pub(crate) fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_escape = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_escape {
+            in_escape = c != 'm';
+        } else if c == '\x1B' && chars.peek() == Some(&'[') {
+            in_escape = true;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
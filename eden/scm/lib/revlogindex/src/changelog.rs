@@ -0,0 +1,236 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Minimal reader of commit texts stored in a non-inlined revlog changelog.
+//! See "# index ng" in revlog.py for the index format.
+
+use crate::RevlogIndex;
+use anyhow::{format_err, Result};
+use flate2::read::ZlibDecoder;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const FLAG_INLINE_DATA: u32 = 1 << 16;
+const FLAG_GENERALDELTA: u32 = 1 << 17;
+
+/// The revlog uses a feature this reader does not support, like inlined
+/// data or compression other than zlib.
+#[derive(Debug)]
+pub struct UnsupportedRevlog(pub String);
+
+impl fmt::Display for UnsupportedRevlog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported revlog: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedRevlog {}
+
+/// `00changelog.i` and `00changelog.d` in a store.
+pub struct Changelog {
+    pub index: RevlogIndex,
+    data_path: PathBuf,
+    generaldelta: bool,
+}
+
+impl Changelog {
+    /// Open the changelog in `store_path`.
+    pub fn open(store_path: &Path) -> Result<Self> {
+        let index_path = store_path.join("00changelog.i");
+        let mut header = [0u8; 4];
+        if let Ok(mut file) = fs::File::open(&index_path) {
+            let _ = file.read_exact(&mut header);
+        }
+        let flags = u32::from_be_bytes(header);
+        if flags & FLAG_INLINE_DATA != 0 {
+            return Err(UnsupportedRevlog("inlined data".to_string()).into());
+        }
+        let index = RevlogIndex::new(&index_path, &store_path.join("00changelog.nodemap"))?;
+        Ok(Changelog {
+            index,
+            data_path: store_path.join("00changelog.d"),
+            generaldelta: flags & FLAG_GENERALDELTA != 0,
+        })
+    }
+
+    /// Node of the last revision, or `None` if the changelog is empty.
+    pub fn tip(&self) -> Option<[u8; 20]> {
+        self.index.data().last().map(|entry| entry.node)
+    }
+
+    /// Revision number of `node`.
+    pub fn rev(&self, node: &[u8]) -> Result<Option<u32>> {
+        self.index.nodemap.node_to_rev(node)
+    }
+
+    /// Full text of a revision.
+    pub fn revision(&self, rev: u32) -> Result<Vec<u8>> {
+        let entries = self.index.data();
+        let mut data = fs::File::open(&self.data_path)?;
+        let mut chunks = Vec::new();
+        let mut current = rev;
+        loop {
+            let entry = entries
+                .get(current as usize)
+                .ok_or_else(|| format_err!("revision {} is out of range", current))?;
+            let base = i32::from_be(entry.base) as u32;
+            // The first 4 bytes of rev 0 are the revlog header.
+            let offset = if current == 0 {
+                0
+            } else {
+                u64::from_be(entry.offset_flags) >> 16
+            };
+            let mut chunk = vec![0u8; i32::from_be(entry.compressed) as usize];
+            data.seek(SeekFrom::Start(offset))?;
+            data.read_exact(&mut chunk)?;
+            chunks.push(decompress(chunk)?);
+            if base == current {
+                break;
+            }
+            current = if self.generaldelta { base } else { current - 1 };
+        }
+        let base = chunks.pop().unwrap_or_default();
+        let deltas: Vec<&[u8]> = chunks.iter().rev().map(|c| c.as_slice()).collect();
+        mpatch::mpatch::get_full_text(&base, &deltas).map_err(|e| format_err!("{}", e))
+    }
+}
+
+fn decompress(chunk: Vec<u8>) -> Result<Vec<u8>> {
+    match chunk.first().cloned() {
+        None | Some(b'\0') => Ok(chunk),
+        Some(b'u') => Ok(chunk[1..].to_vec()),
+        Some(b'x') => {
+            let mut result = Vec::new();
+            ZlibDecoder::new(&chunk[..]).read_to_end(&mut result)?;
+            Ok(result)
+        }
+        Some(kind) => Err(UnsupportedRevlog(format!("compression {:?}", kind as char)).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// Write a changelog with `(base, p1, chunk)` entries. Node `i` is
+    /// `[i + 1; 20]`.
+    fn write_changelog(dir: &Path, flags: u32, entries: &[(i32, i32, Vec<u8>)]) {
+        let mut index = Vec::new();
+        let mut data = Vec::new();
+        for (rev, (base, p1, chunk)) in entries.iter().enumerate() {
+            let offset_flags = if rev == 0 {
+                u64::from(flags | 1) << 32
+            } else {
+                (data.len() as u64) << 16
+            };
+            index.extend_from_slice(&offset_flags.to_be_bytes());
+            index.extend_from_slice(&(chunk.len() as i32).to_be_bytes());
+            index.extend_from_slice(&0i32.to_be_bytes());
+            index.extend_from_slice(&base.to_be_bytes());
+            index.extend_from_slice(&(rev as i32).to_be_bytes());
+            index.extend_from_slice(&p1.to_be_bytes());
+            index.extend_from_slice(&(-1i32).to_be_bytes());
+            index.extend_from_slice(&[rev as u8 + 1; 20]);
+            index.extend_from_slice(&[0; 12]);
+            data.extend_from_slice(chunk);
+        }
+        fs::write(dir.join("00changelog.i"), index).unwrap();
+        fs::write(dir.join("00changelog.d"), data).unwrap();
+    }
+
+    /// A delta replacing `text[start..end]` with `data`.
+    fn delta(start: u32, end: u32, data: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&start.to_be_bytes());
+        result.extend_from_slice(&end.to_be_bytes());
+        result.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        result.extend_from_slice(data);
+        result
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_revision() -> Result<()> {
+        let dir = TempDir::new()?;
+        write_changelog(
+            dir.path(),
+            FLAG_GENERALDELTA,
+            &[
+                (0, -1, b"uabc\n".to_vec()),
+                (0, 0, zlib(&delta(0, 3, b"xyz"))),
+                // Delta against rev 0, not rev 1.
+                (0, 1, [&b"u"[..], &delta(3, 4, b"!\n")].concat()),
+                (3, 2, zlib(b"full text")),
+            ],
+        );
+
+        let changelog = Changelog::open(dir.path())?;
+        assert_eq!(changelog.revision(0)?, b"abc\n");
+        assert_eq!(changelog.revision(1)?, b"xyz\n");
+        assert_eq!(changelog.revision(2)?, b"abc!\n");
+        assert_eq!(changelog.revision(3)?, b"full text");
+        assert!(changelog.revision(4).is_err());
+
+        assert_eq!(changelog.tip(), Some([4; 20]));
+        assert_eq!(changelog.rev(&[2; 20])?, Some(1));
+        assert_eq!(changelog.rev(&[9; 20])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_revision_without_generaldelta() -> Result<()> {
+        let dir = TempDir::new()?;
+        write_changelog(
+            dir.path(),
+            0,
+            &[
+                (0, -1, b"uabc".to_vec()),
+                (0, 0, zlib(&delta(0, 1, b"x"))),
+                // Chained to rev 1.
+                (0, 1, zlib(&delta(2, 3, b"z"))),
+            ],
+        );
+
+        let changelog = Changelog::open(dir.path())?;
+        assert_eq!(changelog.revision(2)?, b"xbz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported() -> Result<()> {
+        let dir = TempDir::new()?;
+        write_changelog(dir.path(), FLAG_INLINE_DATA, &[(0, -1, b"uabc".to_vec())]);
+        let err = Changelog::open(dir.path()).err().unwrap();
+        assert!(err.is::<UnsupportedRevlog>());
+
+        write_changelog(dir.path(), 0, &[(0, -1, b"\x28\xb5\x2f\xfd".to_vec())]);
+        let err = Changelog::open(dir.path())?.revision(0).err().unwrap();
+        assert!(err.is::<UnsupportedRevlog>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty() -> Result<()> {
+        let dir = TempDir::new()?;
+        let changelog = Changelog::open(dir.path())?;
+        assert_eq!(changelog.tip(), None);
+        assert_eq!(changelog.rev(&[1; 20])?, None);
+        Ok(())
+    }
+}
@@ -7,9 +7,11 @@
 
 #![allow(dead_code)]
 
+pub mod changelog;
 pub mod nodemap;
 mod revlogindex;
 
+pub use crate::changelog::{Changelog, UnsupportedRevlog};
 pub use crate::nodemap::NodeRevMap;
 pub use crate::revlogindex::RevlogEntry;
 pub use crate::revlogindex::RevlogIndex;
@@ -354,10 +354,10 @@ impl ParentRevs {
 #[repr(packed)]
 #[derive(Copy, Clone)]
 pub struct RevlogEntry {
-    offset_flags: u64,
-    compressed: i32,
+    pub(crate) offset_flags: u64,
+    pub(crate) compressed: i32,
     len: i32,
-    base: i32,
+    pub(crate) base: i32,
     link: i32,
     p1: i32,
     p2: i32,
@@ -433,7 +433,7 @@ impl RevlogIndex {
     }
 
     #[inline]
-    fn data(&self) -> &[RevlogEntry] {
+    pub(crate) fn data(&self) -> &[RevlogEntry] {
         self.nodemap.changelogi.as_ref()
     }
 
@@ -1670,6 +1670,259 @@ impl<S: Eq + Hash> TreeSpan<S> {
     }
 }
 
+// -------- OpenTelemetry (OTLP/JSON) output --------
+
+/// Top-level object of the OTLP "ExportTraceServiceRequest" message.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpTrace<'a> {
+    resource_spans: Vec<OtlpResourceSpans<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpResourceSpans<'a> {
+    resource: OtlpResource<'a>,
+    scope_spans: Vec<OtlpScopeSpans<'a>>,
+}
+
+#[derive(Serialize)]
+struct OtlpResource<'a> {
+    attributes: Vec<OtlpKeyValue<'a>>,
+}
+
+#[derive(Serialize)]
+struct OtlpScopeSpans<'a> {
+    scope: OtlpScope,
+    spans: Vec<OtlpSpan<'a>>,
+}
+
+#[derive(Serialize)]
+struct OtlpScope {
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpSpan<'a> {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    name: &'a str,
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<OtlpKeyValue<'a>>,
+    events: Vec<OtlpEvent<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpEvent<'a> {
+    time_unix_nano: String,
+    name: &'a str,
+    attributes: Vec<OtlpKeyValue<'a>>,
+}
+
+#[derive(Serialize)]
+struct OtlpKeyValue<'a> {
+    key: &'a str,
+    value: OtlpAnyValue<'a>,
+}
+
+/// 64-bit integers are strings in the JSON encoding of protobuf.
+#[derive(Serialize)]
+enum OtlpAnyValue<'a> {
+    #[serde(rename = "stringValue")]
+    Str(&'a str),
+    #[serde(rename = "intValue")]
+    Int(String),
+}
+
+/// `SPAN_KIND_INTERNAL` in the OTLP protocol.
+const OTLP_SPAN_KIND_INTERNAL: u8 = 1;
+
+impl TracingData {
+    /// Write OpenTelemetry "OTLP/JSON" spans that can be imported by Jaeger,
+    /// Tempo, or other OpenTelemetry compatible tools.
+    ///
+    /// See https://github.com/open-telemetry/opentelemetry-proto.
+    pub fn write_otlp_json(
+        &self,
+        out: &mut dyn io::Write,
+        service_name: &str,
+    ) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(out, &self.otlp(service_name))
+    }
+
+    /// Return a serializable object that if serialized in JSON, matches
+    /// the OTLP "ExportTraceServiceRequest" message.
+    ///
+    /// All spans share one trace ID, and spans are grouped by process.
+    /// Spans at the top level of a thread have an empty parent span ID.
+    /// Events become span events of their parent span. Incomplete spans
+    /// end at their start time.
+    pub fn otlp<'a>(&'a self, service_name: &'a str) -> impl serde::Serialize + 'a {
+        let start_nanos = self
+            .start
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let trace_id = format!("{:016x}{:016x}", self.default_process_id, start_nanos);
+        let nanos = |micros: u64| (start_nanos + micros * 1000).to_string();
+        let attributes = |span: &TreeSpan<&'a str>| -> Vec<OtlpKeyValue<'a>> {
+            span.meta
+                .iter()
+                .filter(|(k, _v)| **k != "name")
+                .map(|(k, v)| OtlpKeyValue {
+                    key: k,
+                    value: OtlpAnyValue::Str(v),
+                })
+                .collect()
+        };
+
+        let mut spans_by_pid = IndexMap::<u64, Vec<OtlpSpan>>::new();
+        for (thread_index, ((pid, tid), spans)) in self.tree_spans::<&str>().into_iter().enumerate()
+        {
+            // Span IDs are unique in the trace. Index 0 is the root span,
+            // which is not exported.
+            let span_id = |index: usize| match index {
+                0 => String::new(),
+                _ => format!("{:08x}{:08x}", thread_index + 1, index),
+            };
+            let mut parents = vec![0; spans.len()];
+            for (index, span) in spans.iter().enumerate() {
+                for &child in span.children.iter() {
+                    parents[child] = index;
+                }
+            }
+
+            let mut otlp_spans: Vec<Option<OtlpSpan>> = Vec::with_capacity(spans.len());
+            let mut events = Vec::new();
+            for (index, span) in spans.iter().enumerate() {
+                let parent = parents[index];
+                if index == 0 || (span.is_event && parent != 0) {
+                    otlp_spans.push(None);
+                    if index != 0 {
+                        events.push(index);
+                    }
+                    continue;
+                }
+                let mut span_attributes = attributes(span);
+                span_attributes.push(OtlpKeyValue {
+                    key: "thread.id",
+                    value: OtlpAnyValue::Int(tid.to_string()),
+                });
+                otlp_spans.push(Some(OtlpSpan {
+                    trace_id: trace_id.clone(),
+                    span_id: span_id(index),
+                    parent_span_id: span_id(parent),
+                    name: span.meta.get("name").cloned().unwrap_or("(unnamed)"),
+                    kind: OTLP_SPAN_KIND_INTERNAL,
+                    start_time_unix_nano: nanos(span.start),
+                    end_time_unix_nano: nanos(span.start + span.duration.unwrap_or(0)),
+                    attributes: span_attributes,
+                    events: Vec::new(),
+                }));
+            }
+
+            // Attach events to their parent spans.
+            for index in events {
+                let span = &spans[index];
+                if let Some(parent) = otlp_spans[parents[index]].as_mut() {
+                    parent.events.push(OtlpEvent {
+                        time_unix_nano: nanos(span.start),
+                        name: span.meta.get("name").cloned().unwrap_or("(unnamed)"),
+                        attributes: attributes(span),
+                    });
+                }
+            }
+
+            spans_by_pid
+                .entry(pid)
+                .or_default()
+                .extend(otlp_spans.into_iter().flatten());
+        }
+
+        let resource_spans = spans_by_pid
+            .into_iter()
+            .map(|(pid, spans)| OtlpResourceSpans {
+                resource: OtlpResource {
+                    attributes: vec![
+                        OtlpKeyValue {
+                            key: "service.name",
+                            value: OtlpAnyValue::Str(service_name),
+                        },
+                        OtlpKeyValue {
+                            key: "process.pid",
+                            value: OtlpAnyValue::Int(pid.to_string()),
+                        },
+                    ],
+                },
+                scope_spans: vec![OtlpScopeSpans {
+                    scope: OtlpScope {
+                        name: "tracing-collector",
+                    },
+                    spans,
+                }],
+            })
+            .collect();
+
+        OtlpTrace { resource_spans }
+    }
+}
+
+// -------- Folded stacks output --------
+
+impl TracingData {
+    /// Generate "folded stacks" that can be rendered by flamegraph tools.
+    ///
+    /// See https://github.com/brendangregg/FlameGraph.
+    ///
+    /// Each line is a `;`-separated list of span names, followed by the
+    /// microseconds spent in the last span but not in its child spans.
+    /// Identical stacks from different threads are summed up. Events are
+    /// ignored.
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks = IndexMap::<String, u64>::new();
+        for spans in self.tree_spans::<&str>().values() {
+            collect_folded_stacks(spans, 0, &mut Vec::new(), &mut stacks);
+        }
+        stacks
+            .into_iter()
+            .filter(|(_stack, micros)| *micros > 0)
+            .map(|(stack, micros)| format!("{} {}\n", stack, micros))
+            .collect()
+    }
+}
+
+/// Add self time of the `index`-th span and its descendants to `stacks`.
+/// `stack` contains names of the span and its ancestors.
+fn collect_folded_stacks(
+    spans: &TreeSpans<&str>,
+    index: usize,
+    stack: &mut Vec<String>,
+    stacks: &mut IndexMap<String, u64>,
+) {
+    let span = &spans[index];
+    let mut self_time = span.duration.unwrap_or(0);
+    for &child_index in span.children.iter() {
+        let child = &spans[child_index];
+        if child.is_event {
+            continue;
+        }
+        self_time = self_time.saturating_sub(child.duration.unwrap_or(0));
+        // ";" separates frames. A space separates the count.
+        let name = child.meta.get("name").cloned().unwrap_or("(unnamed)");
+        stack.push(name.replace(';', ",").replace('\n', " "));
+        collect_folded_stacks(spans, child_index, stack, stacks);
+        stack.pop();
+    }
+    if index > 0 {
+        *stacks.entry(stack.join(";")).or_default() += self_time;
+    }
+}
+
 // -------- Tests --------
 
 #[cfg(test)]
@@ -2060,4 +2313,68 @@ Start Dur.ms | Name
         assert_eq!(data1.ascii(&Default::default()), "");
         assert_eq!(data2.ascii(&Default::default()), "");
     }
+
+    fn data_with_event() -> TracingData {
+        let mut data = TracingData::new_for_test();
+        data.start = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        data.default_process_id = 10;
+        let span_id1 = data.add_espan(&meta("foo", "a.py", "10"), None);
+        let span_id2 = data.add_espan(&meta("bar; baz", "a.py", "20"), None);
+        let event_id = data.add_espan(&[("name", "hit"), ("key", "x")], None);
+        data.add_action(span_id1, Action::EnterSpan);
+        data.add_action(span_id2, Action::EnterSpan);
+        data.add_action(event_id, Action::Event);
+        data.add_action(span_id2, Action::ExitSpan);
+        data.add_action(span_id2, Action::EnterSpan);
+        data.add_action(span_id2, Action::ExitSpan);
+        data.add_action(span_id1, Action::ExitSpan);
+        data
+    }
+
+    #[test]
+    fn test_otlp() {
+        let data = data_with_event();
+        let json = serde_json::to_value(&data.otlp("hg")).unwrap();
+        let resource_spans = &json["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["resource"]["attributes"].to_string(),
+            r#"[{"key":"service.name","value":{"stringValue":"hg"}},{"key":"process.pid","value":{"intValue":"10"}}]"#
+        );
+        let spans = resource_spans["scopeSpans"][0]["spans"].as_array().unwrap();
+        let summary: Vec<String> = spans
+            .iter()
+            .map(|s| {
+                format!(
+                    "{} {} {} {}..{} {}",
+                    s["spanId"].as_str().unwrap(),
+                    s["parentSpanId"].as_str().unwrap(),
+                    s["name"].as_str().unwrap(),
+                    s["startTimeUnixNano"].as_str().unwrap(),
+                    s["endTimeUnixNano"].as_str().unwrap(),
+                    s["events"],
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                "0000000100000001  foo 1002000000..1014000000 []",
+                "0000000100000002 0000000100000001 bar; baz 1004000000..1008000000 [{\"attributes\":[{\"key\":\"key\",\"value\":{\"stringValue\":\"x\"}}],\"name\":\"hit\",\"timeUnixNano\":\"1006000000\"}]",
+                "0000000100000004 0000000100000001 bar; baz 1010000000..1012000000 []",
+            ]
+        );
+        assert_eq!(spans[0]["traceId"], "000000000000000a000000003b9aca00");
+        assert_eq!(
+            serde_json::to_string(&spans[0]["attributes"].as_array().unwrap()[..2]).unwrap(),
+            r#"[{"key":"module_path","value":{"stringValue":"a.py"}},{"key":"line","value":{"stringValue":"10"}}]"#
+        );
+        assert_eq!(spans[0]["attributes"][2]["key"], "thread.id");
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let data = data_with_event();
+        assert_eq!(data.folded_stacks(), "foo;bar, baz 6000\nfoo 6000\n");
+        assert_eq!(TracingData::new_for_test().folded_stacks(), "");
+    }
 }
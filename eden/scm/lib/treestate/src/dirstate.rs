@@ -6,7 +6,242 @@
  */
 
 //! Directory State.
+//!
+//! The `.hg/dirstate` file stores the working copy parents and points to the
+//! tree state file:
+//!
+//! ```plain,ignore
+//! dirstate := <p1: 20 bytes> <p2: 20 bytes> HEADER <metadata>
+//! metadata := key=value ("\0" key=value)*
+//! ```
+//!
+//! The metadata has `filename`, the name of the file in `.hg/treestate`,
+//! `rootid`, the root of the tree in that file, and optionally `threshold`,
+//! the root id that triggers a repack.
+
+use crate::errors::ErrorKind;
+use crate::store::BlockId;
+use crate::treestate::TreeState;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const HEADER: &[u8] = b"\ntreestate\n\0";
+const NULL_ID: [u8; 20] = [0; 20];
 
 /// A dirstate object. This maintains .hg/dirstate file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dirstate {
+    pub p1: [u8; 20],
+    pub p2: [u8; 20],
+
+    /// File name of the tree state, relative to `.hg/treestate`.
+    pub tree_filename: String,
+
+    /// Root of the tree in the tree state file.
+    pub tree_root_id: BlockId,
+
+    /// Root id that makes the next write repack the tree into a new file.
+    /// 0 means no repack is scheduled.
+    pub repack_threshold: u64,
+}
+
+impl Dirstate {
+    /// Read the dirstate from the `.hg` directory.
+    pub fn read(dot_hg: impl AsRef<Path>) -> Result<Self> {
+        let data = fs::read(dot_hg.as_ref().join("dirstate"))?;
+        Self::deserialize(&data)
+    }
+
+    /// Write the dirstate to the `.hg` directory atomically.
+    pub fn write(&self, dot_hg: impl AsRef<Path>) -> Result<()> {
+        let dot_hg = dot_hg.as_ref();
+        let tmp_path = dot_hg.join("dirstate.tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&self.serialize())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, dot_hg.join("dirstate"))?;
+        Ok(())
+    }
+
+    /// Open the tree state the dirstate points to.
+    pub fn open_tree(&self, dot_hg: impl AsRef<Path>) -> Result<TreeState> {
+        let path = dot_hg.as_ref().join("treestate").join(&self.tree_filename);
+        TreeState::open(path, Some(self.tree_root_id))
+    }
+
+    /// Flush `tree` and point the dirstate to its new root. The parents are
+    /// also recorded in the tree metadata.
+    pub fn flush_tree(&mut self, tree: &mut TreeState) -> Result<()> {
+        let mut metadata = unpack_metadata(tree.get_metadata());
+        for (name, id) in [("p1", self.p1), ("p2", self.p2)].iter() {
+            if *id == NULL_ID {
+                metadata.remove(*name);
+            } else {
+                metadata.insert(name.to_string(), to_hex(id));
+            }
+        }
+        tree.set_metadata(pack_metadata(&metadata)?);
+        self.tree_root_id = tree.flush()?;
+        Ok(())
+    }
+
+    /// Test if the tree should be repacked into a new file, like
+    /// `treestate.write` in Python. Repacking is not done natively.
+    pub fn needs_repack(&self) -> bool {
+        self.repack_threshold > 0 && self.tree_root_id.0 > self.repack_threshold
+    }
+
+    /// Schedule a repack when the tree file grows `factor` times larger, if
+    /// it is larger than `min_size` and no repack is scheduled.
+    pub fn update_repack_threshold(&mut self, min_size: u64, factor: u64) {
+        if self.repack_threshold == 0 && self.tree_root_id.0 > min_size {
+            self.repack_threshold = self.tree_root_id.0 * factor;
+        }
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        let mut p1 = NULL_ID;
+        let mut p2 = NULL_ID;
+        if data.len() < 40 + HEADER.len() || &data[40..40 + HEADER.len()] != HEADER {
+            return Err(ErrorKind::CorruptDirstate.into());
+        }
+        p1.copy_from_slice(&data[0..20]);
+        p2.copy_from_slice(&data[20..40]);
+        let metadata = unpack_metadata(&data[40 + HEADER.len()..]);
+        let parse = |name: &str| -> Result<u64> {
+            match metadata.get(name) {
+                Some(value) => Ok(value.parse().map_err(|_| ErrorKind::CorruptDirstate)?),
+                None => Ok(0),
+            }
+        };
+        let tree_filename = match metadata.get("filename") {
+            Some(name) => name.clone(),
+            None => return Err(ErrorKind::CorruptDirstate.into()),
+        };
+        Ok(Dirstate {
+            p1,
+            p2,
+            tree_filename,
+            tree_root_id: BlockId(parse("rootid")?),
+            repack_threshold: parse("threshold")?,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut metadata = BTreeMap::new();
+        metadata.insert("filename".to_string(), self.tree_filename.clone());
+        metadata.insert("rootid".to_string(), self.tree_root_id.0.to_string());
+        if self.repack_threshold > 0 {
+            metadata.insert("threshold".to_string(), self.repack_threshold.to_string());
+        }
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.p1);
+        data.extend_from_slice(&self.p2);
+        data.extend_from_slice(HEADER);
+        // The file name and numbers cannot contain "=" or "\0".
+        data.extend(pack_metadata(&metadata).unwrap());
+        data
+    }
+}
+
+/// Parse "\0"-separated `key=value` entries. Entries without "=" are ignored.
+pub fn unpack_metadata(data: &[u8]) -> BTreeMap<String, String> {
+    String::from_utf8_lossy(data)
+        .split('\0')
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.to_string(), value.to_string())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Serialize `key=value` entries separated by "\0". Empty values are skipped.
+pub fn pack_metadata(metadata: &BTreeMap<String, String>) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    for (key, value) in metadata {
+        if value.is_empty() {
+            continue;
+        }
+        if key.contains('=') || key.contains('\0') || value.contains('\0') {
+            return Err(ErrorKind::IllegalMetadata(format!("{:?}={:?}", key, value)).into());
+        }
+        entries.push(format!("{}={}", key, value));
+    }
+    Ok(entries.join("\0").into_bytes())
+}
+
+fn to_hex(id: &[u8; 20]) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filestate::{FileStateV2, StateFlags};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_metadata() {
+        let metadata = unpack_metadata(b"a=1\0b=x=y\0junk\0c=");
+        assert_eq!(
+            format!("{:?}", metadata),
+            r#"{"a": "1", "b": "x=y", "c": ""}"#
+        );
+        assert_eq!(pack_metadata(&metadata).unwrap(), b"a=1\0b=x=y");
+
+        let mut bad = BTreeMap::new();
+        bad.insert("a=b".to_string(), "c".to_string());
+        assert!(pack_metadata(&bad).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = TempDir::new("dirstate").unwrap();
+        let dot_hg = dir.path();
+        fs::create_dir(dot_hg.join("treestate")).unwrap();
+
+        let mut tree = TreeState::open(dot_hg.join("treestate").join("t"), None).unwrap();
+        let state = FileStateV2 {
+            mode: 0o644,
+            size: 1,
+            mtime: 2,
+            state: StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT,
+            copied: None,
+        };
+        tree.insert("a/b", &state).unwrap();
+
+        let mut dirstate = Dirstate {
+            p1: [1; 20],
+            p2: NULL_ID,
+            tree_filename: "t".to_string(),
+            tree_root_id: BlockId(0),
+            repack_threshold: 0,
+        };
+        dirstate.flush_tree(&mut tree).unwrap();
+        dirstate.write(dot_hg).unwrap();
+
+        let loaded = Dirstate::read(dot_hg).unwrap();
+        assert_eq!(loaded, dirstate);
+        let mut tree = loaded.open_tree(dot_hg).unwrap();
+        assert_eq!(tree.get("a/b").unwrap(), Some(&state));
+        assert_eq!(
+            tree.get_metadata(),
+            &b"p1=0101010101010101010101010101010101010101"[..]
+        );
+
+        assert!(!dirstate.needs_repack());
+        dirstate.update_repack_threshold(0, 3);
+        assert_eq!(dirstate.repack_threshold, dirstate.tree_root_id.0 * 3);
+        dirstate.tree_root_id = BlockId(dirstate.repack_threshold + 1);
+        assert!(dirstate.needs_repack());
 
-pub struct Dirstate {}
+        fs::write(dot_hg.join("dirstate"), b"bad").unwrap();
+        assert!(Dirstate::read(dot_hg).is_err());
+    }
+}
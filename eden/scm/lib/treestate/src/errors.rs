@@ -25,4 +25,8 @@ pub enum ErrorKind {
     CorruptTree,
     #[error("callback error: {0}")]
     CallbackError(String),
+    #[error("working directory state appears damaged")]
+    CorruptDirstate,
+    #[error("illegal metadata entry: {0}")]
+    IllegalMetadata(String),
 }
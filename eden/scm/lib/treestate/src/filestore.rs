@@ -137,6 +137,35 @@ impl FileStore {
     pub fn position(&self) -> u64 {
         self.position
     }
+
+    /// Returns the number of bytes used by the file header.
+    pub fn header_len(&self) -> u64 {
+        HEADER_LEN
+    }
+
+    /// Returns the number of bytes a block occupies in the file, including its length prefix.
+    pub fn block_len(&self, id: BlockId) -> Result<u64> {
+        if id.0 < HEADER_LEN || id.0 > self.position - 4 {
+            bail!(ErrorKind::InvalidStoreId(id.0));
+        }
+
+        let size = match self.cache {
+            Some(ref cache) if id.0 + 4 <= cache.len() as u64 => {
+                let header_start = id.0 as usize;
+                BigEndian::read_u32(&cache[header_start..header_start + 4])
+            }
+            _ => {
+                let mut file = self.file.borrow_mut();
+                file.seek(SeekFrom::Start(id.0))?;
+                *self.at_end.borrow_mut() = false;
+                file.get_mut().read_u32::<BigEndian>()?
+            }
+        };
+        if size as u64 > self.position - id.0 - 4 {
+            bail!(ErrorKind::InvalidStoreId(id.0));
+        }
+        Ok(4 + size as u64)
+    }
 }
 
 impl Store for FileStore {
@@ -363,6 +392,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn block_len() {
+        let dir = TempDir::new("filestore_test").expect("create temp dir");
+        let p = dir.path().join("store");
+        let mut s = FileStore::create(&p).expect("create store");
+        let id1 = s.append("data block 1".as_bytes()).expect("write block 1");
+        let id2 = s.append(&[]).expect("write block 2");
+        s.flush().expect("flush");
+        assert_eq!(s.block_len(id1).expect("len 1"), 16);
+        assert_eq!(s.block_len(id2).expect("len 2"), 4);
+        assert_eq!(s.header_len() + 16 + 4, s.position());
+        s.cache().expect("can cache");
+        assert_eq!(s.block_len(id1).expect("len 1"), 16);
+        assert_eq!(
+            s.block_len(BlockId(2)).unwrap_err().to_string(),
+            "invalid store id: 2"
+        );
+    }
+
     #[test]
     fn invalid_store_ids() {
         let dir = TempDir::new("filestore_test").expect("create temp dir");
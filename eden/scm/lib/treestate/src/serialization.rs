@@ -12,7 +12,7 @@ use crate::filestate::{FileState, FileStateV2, StateFlags};
 use crate::store::BlockId;
 use crate::tree::{AggregatedState, Key, Node, NodeEntry, NodeEntryMap};
 use crate::treedirstate::TreeDirstateRoot;
-use crate::treestate::TreeStateRoot;
+use crate::treestate::{TreeStateRoot, TREESTATE_ROOT_VERSION};
 use anyhow::{bail, Result};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::hash::Hasher;
@@ -257,7 +257,7 @@ impl Serializable for TreeStateRoot {
 
         let mut cur = Cursor::new(buf);
         let version = cur.read_vlq()?;
-        if version != 0 {
+        if version > TREESTATE_ROOT_VERSION {
             bail!(ErrorKind::UnsupportedTreeVersion(version));
         }
 
@@ -265,11 +265,19 @@ impl Serializable for TreeStateRoot {
         let file_count = cur.read_vlq()?;
         let metadata = Box::<[u8]>::deserialize(&mut cur)?;
 
+        // The size of the file right after it was last compacted, if recorded.
+        let compacted_size = if (cur.position() as usize) < cur.get_ref().len() {
+            cur.read_vlq()?
+        } else {
+            0
+        };
+
         Ok(TreeStateRoot {
             version,
             tree_block_id,
             file_count,
             metadata,
+            compacted_size,
         })
     }
 
@@ -279,6 +287,9 @@ impl Serializable for TreeStateRoot {
         buf.write_vlq(self.tree_block_id.0)?;
         buf.write_vlq(self.file_count)?;
         self.metadata.serialize(&mut buf)?;
+        if self.compacted_size != 0 {
+            buf.write_vlq(self.compacted_size)?;
+        }
         w.write_u64::<BigEndian>(xxhash(&buf))?;
         w.write_all(&buf)?;
         Ok(())
@@ -391,6 +391,25 @@ where
         }
     }
 
+    /// Collect the IDs of all blocks in the store that are still referenced by this node or its
+    /// children.  Nodes that have been modified in memory have no block yet, but their unchanged
+    /// children may still be referenced.
+    fn collect_block_ids(
+        &mut self,
+        store: &dyn StoreView,
+        block_ids: &mut Vec<BlockId>,
+    ) -> Result<()> {
+        if let Some(id) = self.id {
+            block_ids.push(id);
+        }
+        for (_name, entry) in self.load_entries(store)?.iter_mut() {
+            if let &mut NodeEntry::Directory(ref mut node) = entry {
+                node.collect_block_ids(store, block_ids)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Visit all of the files in under this node, by calling the visitor function on each one.
     ///
     /// `visit_dir` will be called to test if a directory is worth visiting or not.
@@ -926,6 +945,13 @@ where
         Ok(self.root.get(store, name)?)
     }
 
+    /// Return the IDs of all blocks in the store that are reachable from this tree.
+    pub fn block_ids(&mut self, store: &dyn StoreView) -> Result<Vec<BlockId>> {
+        let mut block_ids = Vec::new();
+        self.root.collect_block_ids(store, &mut block_ids)?;
+        Ok(block_ids)
+    }
+
     pub fn visit_advanced<F, VD, VF>(
         &mut self,
         store: &dyn StoreView,
@@ -22,15 +22,74 @@ pub struct TreeState {
     store: FileStore,
     tree: Tree<FileStateV2>,
     root: TreeStateRoot,
+    /// Block id of the last root written to, or read from, the store.
+    root_id: Option<BlockId>,
 }
 
+/// Format version of the root block written by this code. Root blocks with a newer version are
+/// rejected with `ErrorKind::UnsupportedTreeVersion`.
+///
+/// Version 0 has the tree block id, file count and metadata, optionally followed by the size of
+/// the file right after it was last compacted. Older readers ignore the trailing field, so adding
+/// it does not need a new version.
+pub(crate) const TREESTATE_ROOT_VERSION: u32 = 0;
+
 /// `TreeStateRoot` contains block id to the root `Tree`, and other metadata.
-#[derive(Default)]
 pub(crate) struct TreeStateRoot {
     pub version: u32,
     pub file_count: u32,
     pub tree_block_id: BlockId,
     pub metadata: Box<[u8]>,
+    /// Size of the file right after the last `write_as`. 0 if unknown.
+    pub compacted_size: u64,
+}
+
+impl Default for TreeStateRoot {
+    fn default() -> Self {
+        TreeStateRoot {
+            version: TREESTATE_ROOT_VERSION,
+            file_count: 0,
+            tree_block_id: BlockId::default(),
+            metadata: Box::default(),
+            compacted_size: 0,
+        }
+    }
+}
+
+/// Space usage of the file backing a `TreeState`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StoreStats {
+    /// Total size of the file.
+    pub total_size: u64,
+    /// Bytes used by the header, the last root, and the blocks reachable from the tree.
+    pub live_size: u64,
+}
+
+impl StoreStats {
+    /// Bytes used by blocks that are no longer reachable.
+    pub fn dead_size(&self) -> u64 {
+        self.total_size.saturating_sub(self.live_size)
+    }
+}
+
+/// Decide when `TreeState::compact_as` rewrites the file.
+///
+/// The file is rewritten when both thresholds are crossed.
+#[derive(Copy, Clone, Debug)]
+pub struct CompactionPolicy {
+    /// Minimal number of dead bytes.
+    pub min_dead_size: u64,
+    /// Minimal ratio of dead bytes to the total file size, between 0 and 1.
+    pub min_dead_ratio: f64,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        CompactionPolicy {
+            min_dead_size: 16 << 20,
+            min_dead_ratio: 0.5,
+        }
+    }
 }
 
 impl TreeState {
@@ -44,13 +103,23 @@ impl TreeState {
                     TreeStateRoot::deserialize(&mut root_buf)?
                 };
                 let tree = Tree::open(root.tree_block_id, root.file_count);
-                Ok(TreeState { store, tree, root })
+                Ok(TreeState {
+                    store,
+                    tree,
+                    root,
+                    root_id: Some(root_id),
+                })
             }
             None => {
                 let store = FileStore::create(path)?;
                 let root = TreeStateRoot::default();
                 let tree = Tree::new();
-                Ok(TreeState { store, tree, root })
+                Ok(TreeState {
+                    store,
+                    tree,
+                    root,
+                    root_id: None,
+                })
             }
         }
     }
@@ -66,11 +135,60 @@ impl TreeState {
         let mut new_store = FileStore::create(path)?;
         let tree_block_id = self.tree.write_full(&mut new_store, &self.store)?;
         self.store = new_store;
+        self.root.compacted_size = self.store.position();
         let root_id = self.write_root(tree_block_id)?;
         Ok(root_id)
     }
 
+    /// Measure how much of the file is used by the header, the last root block, and the tree
+    /// blocks that are still reachable.
+    ///
+    /// This loads all directories of the tree, which is about as expensive as `write_as`.
+    pub fn store_stats(&mut self) -> Result<StoreStats> {
+        let mut live_size = self.store.header_len();
+        if let Some(root_id) = self.root_id {
+            live_size += self.store.block_len(root_id)?;
+        }
+        for id in self.tree.block_ids(&self.store)? {
+            live_size += self.store.block_len(id)?;
+        }
+        Ok(StoreStats {
+            total_size: self.store.position(),
+            live_size,
+        })
+    }
+
+    /// Test if the file has enough dead bytes to be worth rewriting.
+    ///
+    /// If the file has not grown by `policy.min_dead_size` since it was last compacted, this
+    /// returns `false` without measuring the file.
+    pub fn needs_compaction(&mut self, policy: &CompactionPolicy) -> Result<bool> {
+        let total_size = self.store.position();
+        if total_size.saturating_sub(self.root.compacted_size) < policy.min_dead_size {
+            return Ok(false);
+        }
+        let stats = self.store_stats()?;
+        let dead_size = stats.dead_size();
+        Ok(dead_size >= policy.min_dead_size
+            && dead_size as f64 >= stats.total_size as f64 * policy.min_dead_ratio)
+    }
+
+    /// Save as a new file if `needs_compaction` says so. Return the new `root_id` if the file was
+    /// written, or `None` if the current file is still good enough.
+    pub fn compact_as<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        policy: &CompactionPolicy,
+    ) -> Result<Option<BlockId>> {
+        if self.needs_compaction(policy)? {
+            Ok(Some(self.write_as(path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
     fn write_root(&mut self, tree_block_id: BlockId) -> Result<BlockId> {
+        self.root.version = TREESTATE_ROOT_VERSION;
         self.root.tree_block_id = tree_block_id;
         self.root.file_count = self.len() as u32;
 
@@ -78,6 +196,7 @@ impl TreeState {
         self.root.serialize(&mut root_buf)?;
         let result = self.store.append(&root_buf)?;
         self.store.flush()?;
+        self.root_id = Some(result);
         Ok(result)
     }
 
@@ -209,6 +328,88 @@ mod tests {
         assert_eq!(state.get_metadata()[..], b"foobar"[..]);
     }
 
+    #[test]
+    fn test_compaction() {
+        let dir = TempDir::new("treestate").expect("tempdir");
+        let mut state = new_treestate(dir.path().join("1"));
+        state.flush().expect("flush");
+        let stats = state.store_stats().expect("stats");
+        assert_eq!(stats.dead_size(), 0);
+
+        // Rewriting existing entries leaves the old tree blocks behind.
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        for _ in 0..10 {
+            for path in &SAMPLE_PATHS {
+                let file: FileStateV2 = rng.gen();
+                state.insert(path, &file).expect("insert");
+            }
+            state.flush().expect("flush");
+        }
+        let stats = state.store_stats().expect("stats");
+        assert!(stats.dead_size() > stats.live_size);
+
+        let strict = CompactionPolicy {
+            min_dead_size: stats.total_size + 1,
+            min_dead_ratio: 0.0,
+        };
+        let path = dir.path().join("2");
+        assert_eq!(state.compact_as(&path, &strict).expect("compact"), None);
+        assert!(!path.exists());
+
+        let policy = CompactionPolicy {
+            min_dead_size: 1,
+            min_dead_ratio: 0.5,
+        };
+        let root_id = state.compact_as(&path, &policy).expect("compact").unwrap();
+        let mut state = TreeState::open(&path, Some(root_id)).expect("open");
+        assert_eq!(state.len(), SAMPLE_PATHS.len());
+        let stats = state.store_stats().expect("stats");
+        assert!(stats.total_size < stats.live_size * 2);
+        assert!(!state.needs_compaction(&policy).expect("needs_compaction"));
+    }
+
+    #[test]
+    fn test_root_version() {
+        let root = TreeStateRoot {
+            file_count: 3,
+            tree_block_id: BlockId(42),
+            metadata: Box::new(*b"meta"),
+            ..TreeStateRoot::default()
+        };
+        let mut buf = Vec::new();
+        root.serialize(&mut buf).expect("serialize");
+        let root = TreeStateRoot::deserialize(&mut Cursor::new(&buf)).expect("deserialize");
+        assert_eq!(root.version, 0);
+        assert_eq!(root.file_count, 3);
+        assert_eq!(root.compacted_size, 0);
+
+        // The compacted size is appended without changing the version, so
+        // older readers that stop after the metadata can still read it.
+        let root = TreeStateRoot {
+            compacted_size: 100,
+            ..TreeStateRoot::default()
+        };
+        let mut buf = Vec::new();
+        root.serialize(&mut buf).expect("serialize");
+        let root = TreeStateRoot::deserialize(&mut Cursor::new(&buf)).expect("deserialize");
+        assert_eq!(root.version, 0);
+        assert_eq!(root.compacted_size, 100);
+
+        let root = TreeStateRoot {
+            version: TREESTATE_ROOT_VERSION + 1,
+            ..TreeStateRoot::default()
+        };
+        let mut buf = Vec::new();
+        root.serialize(&mut buf).expect("serialize");
+        assert_eq!(
+            TreeStateRoot::deserialize(&mut Cursor::new(&buf))
+                .err()
+                .unwrap()
+                .to_string(),
+            format!("tree version not supported: {}", TREESTATE_ROOT_VERSION + 1)
+        );
+    }
+
     // Some random paths extracted from fb-hgext, plus some manually added entries, shuffled.
     const SAMPLE_PATHS: [&[u8]; 22] = [
         b".fbarcanist",
@@ -0,0 +1,858 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Apply a batch of working copy changes in parallel, with a journal so an
+//! interrupted batch can be resumed or rolled back.
+//!
+//! The journal directory (usually under `.hg`) contains:
+//! - `plan`: one line per action, written before any change is made.
+//! - `journal`: progress, appended as actions start and finish, and as
+//!   original files are backed up. The plan and "start" lines are synced
+//!   before the changes they describe are made. Written files are synced
+//!   before their "done" line is appended. Concurrent workers share one
+//!   journal sync.
+//! - `backup/<index>`: original files replaced or removed by the action at
+//!   `index`. They are moved (not copied) so backing up is cheap.
+//!
+//! Removals are applied before writes, so a file can be replaced by a
+//! directory and vice versa. Untracked files or directories in the way are
+//! not touched; the batch fails instead.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, create_dir_all, remove_dir, remove_dir_all, rename, symlink_metadata, File};
+use std::io::Write;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{bail, ensure, format_err, Context, Result};
+use bytes::Bytes;
+
+use types::{RepoPath, RepoPathBuf};
+use util::path::remove_file;
+
+use crate::vfs::{is_executable, UpdateFlag, VFS};
+
+const PLAN: &str = "plan";
+const JOURNAL: &str = "journal";
+const BACKUP: &str = "backup";
+
+/// A change to the working copy.
+#[derive(Clone)]
+pub enum Action {
+    /// Write a file. An existing file at `path` is replaced.
+    Write {
+        path: RepoPathBuf,
+        data: Bytes,
+        flag: Option<UpdateFlag>,
+    },
+
+    /// Remove a file. Parent directories that become empty are removed.
+    Remove { path: RepoPathBuf },
+
+    /// Set or clear the executable bit of an existing file. This is a no-op
+    /// if the filesystem does not support executables.
+    SetExecutable { path: RepoPathBuf, executable: bool },
+}
+
+/// Counts of changes made by a batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub written: usize,
+    pub removed: usize,
+    pub mode_changed: usize,
+
+    /// Actions that were completed before a resume.
+    pub skipped: usize,
+
+    /// Bytes written to the working copy.
+    pub bytes: usize,
+}
+
+/// Applies [`Action`]s to the working copy transactionally.
+///
+/// If [`BatchWriter::apply`] fails or the process gets killed, the journal
+/// is left in place. Use [`BatchWriter::resume`] to finish the batch, or
+/// [`BatchWriter::rollback`] to restore the original files.
+pub struct BatchWriter {
+    vfs: VFS,
+    journal_dir: PathBuf,
+    num_threads: usize,
+}
+
+/// Load the data of the `Write` actions at the given indexes.
+type LoadData<'a> = &'a mut dyn FnMut(&[usize]) -> Result<Vec<Bytes>>;
+
+/// State read from the journal directory.
+#[derive(Default)]
+struct JournalState {
+    plan: Vec<String>,
+
+    /// Started actions. For `SetExecutable`, the original executable bit.
+    started: HashMap<usize, Option<bool>>,
+
+    /// Actions whose original file was moved to `backup/<index>`.
+    backed_up: HashSet<usize>,
+
+    done: HashSet<usize>,
+}
+
+/// State shared by worker threads.
+struct Shared {
+    actions: Vec<Action>,
+    backup_dir: PathBuf,
+    journal: Journal,
+
+    /// Actions started or backed up before a resume. A file at their path
+    /// was written by the batch, not the original.
+    resumed: HashSet<usize>,
+}
+
+/// The journal file. Lines are appended by worker threads. A sync covers
+/// every line appended before it, so concurrent workers waiting for their
+/// lines share one sync.
+struct Journal {
+    file: Mutex<File>,
+
+    /// Number of lines appended.
+    appended: AtomicU64,
+
+    /// Number of lines known to be on disk. Held while syncing.
+    synced: Mutex<u64>,
+
+    /// Handle used for syncing, so appending is not blocked by a sync.
+    sync_file: File,
+}
+
+impl BatchWriter {
+    pub fn new(vfs: VFS, journal_dir: impl Into<PathBuf>, num_threads: usize) -> Self {
+        Self {
+            vfs,
+            journal_dir: journal_dir.into(),
+            num_threads: num_threads.max(1),
+        }
+    }
+
+    /// Test if there is an interrupted batch that needs to be resumed or
+    /// rolled back.
+    pub fn is_interrupted(&self) -> bool {
+        self.journal_dir.join(PLAN).exists()
+    }
+
+    /// Apply `actions` to the working copy.
+    ///
+    /// On success, the journal is removed. On error, the journal is kept.
+    pub fn apply(&self, actions: Vec<Action>) -> Result<BatchStats> {
+        self.write_plan(&actions)?;
+        self.run(actions, &JournalState::default(), None)
+    }
+
+    /// Like [`BatchWriter::apply`], but the data of `Write` actions is not
+    /// in `actions`. Instead, `load` is called with the indexes of up to
+    /// `chunk_size` writes at a time, and returns their data in the same
+    /// order. Only one chunk of data is kept in memory.
+    pub fn apply_chunked(
+        &self,
+        actions: Vec<Action>,
+        chunk_size: usize,
+        load: LoadData,
+    ) -> Result<BatchStats> {
+        self.write_plan(&actions)?;
+        self.run(
+            actions,
+            &JournalState::default(),
+            Some((chunk_size.max(1), load)),
+        )
+    }
+
+    /// Write the plan of a new batch.
+    fn write_plan(&self, actions: &[Action]) -> Result<()> {
+        ensure!(
+            !self.is_interrupted(),
+            "an interrupted batch exists at {:?}, resume or roll it back first",
+            &self.journal_dir
+        );
+
+        // Leftover from a batch interrupted before its plan was written.
+        if self.journal_dir.exists() {
+            remove_dir_all(&self.journal_dir)
+                .with_context(|| format!("Can't remove {:?}", &self.journal_dir))?;
+        }
+        create_dir_all(self.journal_dir.join(BACKUP))
+            .with_context(|| format!("Can't create {:?}", &self.journal_dir))?;
+
+        // Write the plan atomically. Its existence marks an active batch.
+        let plan: String = actions.iter().map(|a| describe(a) + "\n").collect();
+        let tmp_path = self.journal_dir.join(format!("{}.tmp", PLAN));
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(plan.as_bytes())?;
+        file.sync_all()?;
+        rename(&tmp_path, self.journal_dir.join(PLAN))?;
+        sync_dir(&self.journal_dir)
+    }
+
+    /// Finish an interrupted batch. `actions` must be the same as the ones
+    /// passed to the interrupted [`BatchWriter::apply`].
+    pub fn resume(&self, actions: Vec<Action>) -> Result<BatchStats> {
+        let state = self.read_journal()?;
+        let plan: Vec<String> = actions.iter().map(describe).collect();
+        ensure!(
+            state.plan == plan,
+            "actions do not match the interrupted batch at {:?}",
+            &self.journal_dir
+        );
+        self.run(actions, &state, None)
+    }
+
+    /// Undo an interrupted batch. Restore replaced or removed files, remove
+    /// written files, and revert mode changes.
+    pub fn rollback(&self) -> Result<()> {
+        if !self.journal_dir.exists() {
+            return Ok(());
+        }
+        if self.is_interrupted() {
+            let state = self.read_journal()?;
+            let backup_dir = self.journal_dir.join(BACKUP);
+            let mut entries = Vec::with_capacity(state.plan.len());
+            for (index, line) in state.plan.iter().enumerate() {
+                let (kind, _, path) = parse_plan_line(line)?;
+                entries.push((index, kind, path));
+            }
+            // Undo in the reverse order of `run`: writes first, then removals.
+            let (removes, others): (Vec<_>, Vec<_>) =
+                entries.into_iter().partition(|(_, kind, _)| *kind == "r");
+            for (index, kind, path) in others.into_iter().rev().chain(removes.into_iter().rev()) {
+                let filepath = self.vfs.audit(path)?;
+                match (kind, state.started.get(&index)) {
+                    ("w", Some(_)) => self.vfs.remove(path)?,
+                    ("x", Some(Some(executable))) => set_executable(&filepath, *executable)?,
+                    _ => {}
+                }
+
+                let backup = backup_dir.join(index.to_string());
+                if exists(&backup) {
+                    if let Some(parent) = filepath.parent() {
+                        create_dir_all(parent)
+                            .with_context(|| format!("Can't create directory {:?}", parent))?;
+                    }
+                    rename(&backup, &filepath)
+                        .with_context(|| format!("Can't restore {:?}", &filepath))?;
+                }
+            }
+        }
+        remove_dir_all(&self.journal_dir)
+            .with_context(|| format!("Can't remove {:?}", &self.journal_dir))?;
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        actions: Vec<Action>,
+        state: &JournalState,
+        mut chunked: Option<(usize, LoadData)>,
+    ) -> Result<BatchStats> {
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.journal_dir.join(JOURNAL))?;
+        let journal = Journal {
+            sync_file: file.try_clone()?,
+            file: Mutex::new(file),
+            appended: AtomicU64::new(0),
+            synced: Mutex::new(0),
+        };
+        let (removes, others): (Vec<usize>, Vec<usize>) = (0..actions.len())
+            .filter(|i| !state.done.contains(i))
+            .partition(|&i| matches!(actions[i], Action::Remove { .. }));
+        let resumed = state
+            .started
+            .keys()
+            .chain(state.backed_up.iter())
+            .cloned()
+            .collect();
+        let mut shared = Arc::new(Shared {
+            actions,
+            backup_dir: self.journal_dir.join(BACKUP),
+            journal,
+            resumed,
+        });
+
+        let mut stats = BatchStats {
+            skipped: state.done.len(),
+            ..Default::default()
+        };
+        self.run_parallel(&shared, removes, &mut stats)?;
+        match chunked.as_mut() {
+            None => self.run_parallel(&shared, others, &mut stats)?,
+            Some((chunk_size, load)) => {
+                for chunk in others.chunks(*chunk_size) {
+                    let writes: Vec<usize> = chunk
+                        .iter()
+                        .cloned()
+                        .filter(|&i| matches!(shared.actions[i], Action::Write { .. }))
+                        .collect();
+                    let contents = load(&writes)?;
+                    ensure!(
+                        contents.len() == writes.len(),
+                        "expected {} files, got {}",
+                        writes.len(),
+                        contents.len()
+                    );
+                    set_data(&mut shared, &writes, contents);
+                    self.run_parallel(&shared, chunk.to_vec(), &mut stats)?;
+                    set_data(&mut shared, &writes, iter::repeat(Bytes::new()));
+                }
+            }
+        }
+
+        // The batch is complete. Backups are no longer needed.
+        remove_dir_all(&self.journal_dir)
+            .with_context(|| format!("Can't remove {:?}", &self.journal_dir))?;
+        Ok(stats)
+    }
+
+    /// Run actions at `indexes` using a pool of threads. Stop picking new
+    /// actions after an error.
+    fn run_parallel(
+        &self,
+        shared: &Arc<Shared>,
+        indexes: Vec<usize>,
+        stats: &mut BatchStats,
+    ) -> Result<()> {
+        let indexes = Arc::new(indexes);
+        let next = Arc::new(AtomicUsize::new(0));
+        let failed = Arc::new(AtomicBool::new(false));
+
+        let threads: Vec<_> = (0..self.num_threads.min(indexes.len()))
+            .map(|_| {
+                // PathAuditor is per-thread. See its docstring.
+                let vfs = self.vfs.clone();
+                let shared = shared.clone();
+                let indexes = indexes.clone();
+                let next = next.clone();
+                let failed = failed.clone();
+                thread::spawn(move || -> Result<BatchStats> {
+                    let mut stats = BatchStats::default();
+                    while !failed.load(Ordering::Acquire) {
+                        let index = match indexes.get(next.fetch_add(1, Ordering::AcqRel)) {
+                            Some(&index) => index,
+                            None => break,
+                        };
+                        if let Err(e) = execute(&vfs, &shared, index, &mut stats) {
+                            failed.store(true, Ordering::Release);
+                            return Err(e);
+                        }
+                    }
+                    Ok(stats)
+                })
+            })
+            .collect();
+
+        let mut first_error = None;
+        for thread in threads {
+            let result = thread
+                .join()
+                .unwrap_or_else(|_| Err(format_err!("batch worker panicked")));
+            match result {
+                Ok(s) => {
+                    stats.written += s.written;
+                    stats.removed += s.removed;
+                    stats.mode_changed += s.mode_changed;
+                    stats.bytes += s.bytes;
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn read_journal(&self) -> Result<JournalState> {
+        let plan_path = self.journal_dir.join(PLAN);
+        let plan = fs::read_to_string(&plan_path)
+            .with_context(|| format!("Can't read {:?}", &plan_path))?;
+        let mut state = JournalState {
+            plan: plan.lines().map(|l| l.to_string()).collect(),
+            ..Default::default()
+        };
+
+        // A missing journal means no action was started.
+        let journal = fs::read_to_string(self.journal_dir.join(JOURNAL)).unwrap_or_default();
+        for line in journal.split_terminator('\n') {
+            let words: Vec<&str> = line.split(' ').collect();
+            let index = match words.get(1).and_then(|w| w.parse::<usize>().ok()) {
+                Some(index) if index < state.plan.len() => index,
+                // The last line might be incomplete.
+                _ => continue,
+            };
+            match (words[0], words.get(2)) {
+                ("start", old) => {
+                    state.started.insert(index, old.map(|&w| w == "x"));
+                }
+                ("backup", _) => {
+                    state.backed_up.insert(index);
+                }
+                ("done", _) => {
+                    state.done.insert(index);
+                }
+                _ => {}
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// Replace the data of the `Write` actions at `indexes`. Worker threads must
+/// have exited.
+fn set_data(
+    shared: &mut Arc<Shared>,
+    indexes: &[usize],
+    contents: impl IntoIterator<Item = Bytes>,
+) {
+    let actions = &mut Arc::get_mut(shared)
+        .expect("batch workers have exited")
+        .actions;
+    for (&index, content) in indexes.iter().zip(contents) {
+        if let Action::Write { data, .. } = &mut actions[index] {
+            *data = content;
+        }
+    }
+}
+
+/// Apply a single action. Record progress in the journal.
+fn execute(vfs: &VFS, shared: &Shared, index: usize, stats: &mut BatchStats) -> Result<()> {
+    let backup = shared.backup_dir.join(index.to_string());
+    match &shared.actions[index] {
+        Action::Write { path, data, flag } => {
+            let filepath = vfs.audit(path)?;
+            if let Ok(metadata) = symlink_metadata(&filepath) {
+                if metadata.is_dir() {
+                    // Directories left by removals are already gone unless
+                    // they contain untracked files.
+                    remove_dir(&filepath)
+                        .with_context(|| format!("Can't replace directory {:?}", &filepath))?;
+                } else if shared.resumed.contains(&index) || exists(&backup) {
+                    // Resumed. The file was (partially) written by this
+                    // action, and the original, if any, was backed up.
+                    remove_file(&filepath)?;
+                } else {
+                    rename(&filepath, &backup)
+                        .with_context(|| format!("Can't back up {:?}", &filepath))?;
+                    // Synced with the "start" line below. A backup that is
+                    // not recorded is still found by `exists(&backup)`.
+                    shared.journal.append(format!("backup {}", index))?;
+                }
+            }
+            shared.journal.record(format!("start {}", index))?;
+            if let Some(parent) = filepath.parent() {
+                create_dir_all(parent)
+                    .with_context(|| format!("Can't create directory {:?}", parent))?;
+            }
+            stats.bytes += vfs.write(path, data, *flag)?;
+            sync_file(&filepath)?;
+            stats.written += 1;
+        }
+        Action::Remove { path } => {
+            let filepath = vfs.audit(path)?;
+            if let Ok(metadata) = symlink_metadata(&filepath) {
+                if !metadata.is_dir() && !exists(&backup) {
+                    rename(&filepath, &backup)
+                        .with_context(|| format!("Can't remove file {:?}", &filepath))?;
+                    shared.journal.record(format!("backup {}", index))?;
+                }
+            }
+            // Remove empty parent directories.
+            vfs.remove(path)?;
+            stats.removed += 1;
+        }
+        Action::SetExecutable { path, executable } => {
+            if vfs.supports_executables() {
+                let filepath = vfs.audit(path)?;
+                let metadata = symlink_metadata(&filepath)
+                    .with_context(|| format!("Can't read metadata of {:?}", &filepath))?;
+                let old = if is_executable(&metadata) { "x" } else { "-" };
+                shared.journal.record(format!("start {} {}", index, old))?;
+                set_executable(&filepath, *executable)?;
+                stats.mode_changed += 1;
+            }
+        }
+    }
+    // A lost "done" line only makes a resume redo the action, so it is
+    // synced by later lines or not at all.
+    shared.journal.append(format!("done {}", index))?;
+    Ok(())
+}
+
+impl Journal {
+    /// Append a line without syncing it. Return its sequence number.
+    fn append(&self, line: String) -> Result<u64> {
+        let mut file = self.file.lock().unwrap();
+        file.write_all(format!("{}\n", line).as_bytes())?;
+        Ok(self.appended.fetch_add(1, Ordering::AcqRel) + 1)
+    }
+
+    /// Append a line and sync it, so it is on disk before the change it
+    /// describes.
+    fn record(&self, line: String) -> Result<()> {
+        let seq = self.append(line)?;
+        let mut synced = self.synced.lock().unwrap();
+        if *synced < seq {
+            // Lines appended up to now are covered by this sync.
+            let appended = self.appended.load(Ordering::Acquire);
+            self.sync_file.sync_data()?;
+            *synced = appended;
+        }
+        Ok(())
+    }
+}
+
+/// Sync a written file so it is on disk before its "done" line. Symlinks
+/// cannot be opened, so sync their directory instead.
+fn sync_file(filepath: &Path) -> Result<()> {
+    if symlink_metadata(filepath)?.file_type().is_symlink() {
+        match filepath.parent() {
+            Some(parent) => sync_dir(parent),
+            None => Ok(()),
+        }
+    } else {
+        // Windows needs write access to flush a file.
+        fs::OpenOptions::new()
+            .write(true)
+            .open(filepath)?
+            .sync_data()
+            .with_context(|| format!("Can't sync {:?}", filepath))
+    }
+}
+
+/// Sync a directory so renames in it are durable.
+fn sync_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?
+        .sync_all()
+        .with_context(|| format!("Can't sync {:?}", dir))?;
+
+    #[cfg(not(unix))]
+    let _ = dir;
+
+    Ok(())
+}
+
+/// Describe an action as a line in the plan: `<kind> <flag> <path>`.
+fn describe(action: &Action) -> String {
+    match action {
+        Action::Write { path, flag, .. } => {
+            let flag = match flag {
+                None => "-",
+                Some(UpdateFlag::Executable) => "x",
+                Some(UpdateFlag::Symlink) => "l",
+            };
+            format!("w {} {}", flag, path)
+        }
+        Action::Remove { path } => format!("r - {}", path),
+        Action::SetExecutable { path, executable } => {
+            format!("x {} {}", if *executable { "x" } else { "-" }, path)
+        }
+    }
+}
+
+fn parse_plan_line(line: &str) -> Result<(&str, &str, &RepoPath)> {
+    let mut split = line.splitn(3, ' ');
+    match (split.next(), split.next(), split.next()) {
+        (Some(kind), Some(flag), Some(path)) => Ok((kind, flag, RepoPath::from_str(path)?)),
+        _ => bail!("invalid plan entry: {:?}", line),
+    }
+}
+
+/// Like `Path::exists`, but does not follow symlinks.
+fn exists(path: &Path) -> bool {
+    symlink_metadata(path).is_ok()
+}
+
+/// Set or clear the executable bits. Only add execute permission where read
+/// permission is granted.
+fn set_executable(filepath: &Path, executable: bool) -> Result<()> {
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = symlink_metadata(filepath)?.permissions().mode();
+        let mode = if executable {
+            mode | ((mode & 0o444) >> 2)
+        } else {
+            mode & !0o111
+        };
+        fs::set_permissions(filepath, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Can't set mode of {:?}", filepath))?;
+    }
+
+    #[cfg(windows)]
+    let _ = (filepath, executable);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use tempfile::TempDir;
+
+    fn write(path: &str, data: &str) -> Action {
+        Action::Write {
+            path: RepoPathBuf::from_string(path.to_string()).unwrap(),
+            data: Bytes::from(data.to_string()),
+            flag: None,
+        }
+    }
+
+    fn remove(path: &str) -> Action {
+        Action::Remove {
+            path: RepoPathBuf::from_string(path.to_string()).unwrap(),
+        }
+    }
+
+    /// Create files from `(path, content)` pairs.
+    fn setup(root: &Path, files: &[(&str, &str)]) {
+        for (path, content) in files {
+            let path = root.join(path);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    /// List files as `(path, content)` pairs, excluding `.hg`.
+    fn list(root: &Path) -> Vec<(String, String)> {
+        fn walk(root: &Path, dir: &Path, result: &mut Vec<(String, String)>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let rel = path.strip_prefix(root).unwrap().to_str().unwrap();
+                if rel == ".hg" {
+                    continue;
+                }
+                if path.is_dir() {
+                    walk(root, &path, result);
+                } else {
+                    result.push((rel.replace('\\', "/"), fs::read_to_string(&path).unwrap()));
+                }
+            }
+        }
+        let mut result = Vec::new();
+        walk(root, root, &mut result);
+        result.sort();
+        result
+    }
+
+    fn writer(root: &Path) -> BatchWriter {
+        let vfs = VFS::new(root.to_path_buf()).unwrap();
+        BatchWriter::new(vfs, root.join(".hg").join("batch"), 4)
+    }
+
+    /// Actions that replace "b/c" with a file "b". The write to "u/v" fails
+    /// if "u" is an untracked file.
+    fn actions() -> Vec<Action> {
+        vec![
+            write("a", "2"),
+            write("b", "3"),
+            remove("b/c"),
+            write("d/e", "4"),
+            write("u/v", "5"),
+        ]
+    }
+
+    const ORIGINAL: &[(&str, &str)] = &[("a", "1"), ("b/c", "1"), ("f", "1")];
+
+    #[test]
+    fn test_apply() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        setup(root, ORIGINAL);
+
+        let writer = writer(root);
+        let stats = writer.apply(actions())?;
+        assert_eq!(stats.written, 4);
+        assert_eq!(stats.removed, 1);
+        assert!(!writer.is_interrupted());
+        assert!(!root.join(".hg").join("batch").exists());
+        assert_eq!(
+            list(root),
+            [
+                ("a", "2"),
+                ("b", "3"),
+                ("d/e", "4"),
+                ("f", "1"),
+                ("u/v", "5")
+            ]
+            .iter()
+            .map(|(p, c)| (p.to_string(), c.to_string()))
+            .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        setup(root, ORIGINAL);
+        setup(root, &[("u", "untracked")]);
+        let before = list(root);
+
+        let writer = writer(root);
+        assert!(writer.apply(actions()).is_err());
+        assert!(writer.is_interrupted());
+        assert!(writer.apply(actions()).is_err());
+
+        writer.rollback()?;
+        assert!(!writer.is_interrupted());
+        assert_eq!(list(root), before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        setup(root, ORIGINAL);
+        setup(root, &[("u", "untracked")]);
+
+        let writer = writer(root);
+        assert!(writer.apply(actions()).is_err());
+
+        // The plan must match.
+        assert!(writer.resume(vec![write("a", "2")]).is_err());
+
+        fs::remove_file(root.join("u"))?;
+        let stats = writer.resume(actions())?;
+        // Removals finished before the failure.
+        assert!(stats.skipped >= 1);
+        assert_eq!(stats.skipped + stats.written + stats.removed, 5);
+        assert!(!writer.is_interrupted());
+        assert_eq!(list(root).len(), 5);
+        assert_eq!(fs::read_to_string(root.join("u/v"))?, "5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_partial_write() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        setup(root, &[("u", "untracked")]);
+
+        let vfs = VFS::new(root.to_path_buf())?;
+        let writer = BatchWriter::new(vfs, root.join(".hg").join("batch"), 1);
+        let actions = || vec![write("n", "2"), write("u/v", "5")];
+        assert!(writer.apply(actions()).is_err());
+
+        // Pretend the process was killed while writing "n".
+        let journal_path = root.join(".hg").join("batch").join(JOURNAL);
+        let journal = fs::read_to_string(&journal_path)?.replace("done 0\n", "");
+        fs::write(&journal_path, journal)?;
+
+        // "n" is not backed up as an original file, so rollback removes it.
+        assert!(writer.resume(actions()).is_err());
+        writer.rollback()?;
+        assert_eq!(list(root), [("u".to_string(), "untracked".to_string())]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_chunked() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        setup(root, ORIGINAL);
+        setup(root, &[("u", "untracked")]);
+        let before = list(root);
+
+        // Data is loaded by chunks. Rollback works across chunks.
+        let writer = writer(root);
+        let full = actions();
+        let loaded = RefCell::new(Vec::new());
+        let mut load = |indexes: &[usize]| -> Result<Vec<Bytes>> {
+            loaded.borrow_mut().push(indexes.to_vec());
+            Ok(indexes
+                .iter()
+                .map(|&i| match &full[i] {
+                    Action::Write { data, .. } => data.clone(),
+                    _ => unreachable!(),
+                })
+                .collect())
+        };
+        let without_data = || {
+            actions()
+                .into_iter()
+                .map(|action| match action {
+                    Action::Write { path, flag, .. } => Action::Write {
+                        path,
+                        data: Bytes::new(),
+                        flag,
+                    },
+                    action => action,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(writer.apply_chunked(without_data(), 2, &mut load).is_err());
+        assert_eq!(*loaded.borrow(), vec![vec![0, 1], vec![3, 4]]);
+        writer.rollback()?;
+        assert_eq!(list(root), before);
+
+        fs::remove_file(root.join("u"))?;
+        let stats = writer.apply_chunked(without_data(), 3, &mut load)?;
+        assert_eq!(stats.written, 4);
+        assert_eq!(fs::read_to_string(root.join("u/v"))?, "5");
+        assert_eq!(fs::read_to_string(root.join("b"))?, "3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_many_threads() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        let vfs = VFS::new(root.to_path_buf())?;
+        let writer = BatchWriter::new(vfs, root.join(".hg").join("batch"), 8);
+        let actions = (0..200)
+            .map(|i| write(&format!("d{}/f{}", i % 7, i), &i.to_string()))
+            .collect();
+        let stats = writer.apply(actions)?;
+        assert_eq!(stats.written, 200);
+        assert_eq!(fs::read_to_string(root.join("d3/f199"))?, "199");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_executable() -> Result<()> {
+        let dir = TempDir::new()?;
+        let root = dir.path();
+        setup(root, &[("a", "1")]);
+        let writer = writer(root);
+        if !writer.vfs.supports_executables() {
+            return Ok(());
+        }
+
+        let path = RepoPathBuf::from_string("a".to_string())?;
+        let chmod = |executable| Action::SetExecutable {
+            path: path.clone(),
+            executable,
+        };
+        writer.apply(vec![chmod(true)])?;
+        assert!(is_executable(&symlink_metadata(root.join("a"))?));
+
+        // Interrupted by a failing write. Rollback reverts the mode change.
+        setup(root, &[("u", "untracked")]);
+        assert!(writer.apply(vec![chmod(false), write("u/v", "1")]).is_err());
+        writer.rollback()?;
+        assert!(is_executable(&symlink_metadata(root.join("a"))?));
+        Ok(())
+    }
+}
@@ -5,8 +5,10 @@
  * GNU General Public License version 2.
  */
 
+mod batch;
 mod pathauditor;
 mod vfs;
 
+pub use crate::batch::{Action, BatchStats, BatchWriter};
 pub use crate::pathauditor::PathAuditor;
 pub use crate::vfs::{is_executable, is_symlink, UpdateFlag, VFS};
@@ -66,6 +66,11 @@ impl VFS {
         self.root.join(path.as_str())
     }
 
+    /// Make sure that it is safe to write/remove `path`. Return the full path.
+    pub(crate) fn audit(&self, path: &RepoPath) -> Result<PathBuf> {
+        self.auditor.audit(path)
+    }
+
     pub fn metadata(&self, path: &RepoPath) -> Result<Metadata> {
         self.join(path).symlink_metadata().map_err(|e| e.into())
     }
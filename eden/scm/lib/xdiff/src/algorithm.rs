@@ -0,0 +1,582 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Diff algorithms and whitespace handling on top of xdiff.
+//!
+//! The bundled xdiff only implements Myers' algorithm. Patience and
+//! histogram diffs are implemented here on interned lines, and fall back to
+//! Myers for regions without good anchors.
+
+use crate::{xdl_diff_hunks, Hunk};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use xdiff_sys as ffi;
+
+/// Algorithm used to find the differences between lines.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiffAlgorithm {
+    /// Myers' algorithm with the indent heuristic. This is the default.
+    Myers,
+    /// Myers' algorithm, spending extra time to find the smallest diff.
+    Minimal,
+    /// Anchor on lines that are unique in both sides.
+    Patience,
+    /// Like patience, but anchor on the least frequent lines.
+    Histogram,
+}
+
+impl Default for DiffAlgorithm {
+    fn default() -> Self {
+        DiffAlgorithm::Myers
+    }
+}
+
+/// How whitespace is handled when comparing lines.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct WhitespaceOpts {
+    /// Ignore all whitespace.
+    pub ignore_all: bool,
+    /// Ignore changes in the amount of whitespace.
+    pub ignore_amount: bool,
+    /// Ignore changes whose lines are all blank.
+    pub ignore_blank_lines: bool,
+    /// Ignore a carriage return at the end of lines.
+    pub ignore_trailing_cr: bool,
+}
+
+impl WhitespaceOpts {
+    fn normalize<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut line = Cow::Borrowed(line);
+        if self.ignore_trailing_cr && line.last() == Some(&b'\r') {
+            line = Cow::Owned(line[..line.len() - 1].to_vec());
+        }
+        if self.ignore_all {
+            line = Cow::Owned(
+                line.iter()
+                    .cloned()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect(),
+            );
+        } else if self.ignore_amount {
+            let mut normalized = Vec::with_capacity(line.len());
+            for word in line
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|w| !w.is_empty())
+            {
+                if !normalized.is_empty() {
+                    normalized.push(b' ');
+                }
+                normalized.extend_from_slice(word);
+            }
+            // Leading whitespace is kept, but its amount is ignored.
+            if line.first().map(|b| b.is_ascii_whitespace()) == Some(true) {
+                normalized.insert(0, b' ');
+            }
+            line = Cow::Owned(normalized);
+        }
+        line
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Default::default()
+    }
+}
+
+/// Computes the hunks of differences between two texts, like
+/// [`diff_hunks`](crate::diff_hunks), using the given algorithm and
+/// whitespace handling.
+///
+/// # Example
+/// ```
+/// use xdiff::{diff_hunks_with_opts, DiffAlgorithm, WhitespaceOpts};
+/// let mut whitespace = WhitespaceOpts::default();
+/// whitespace.ignore_amount = true;
+/// let hunks = diff_hunks_with_opts("a  b\nc\n", "a b\nd\n", DiffAlgorithm::Histogram, &whitespace);
+/// assert_eq!(hunks.len(), 1);
+/// assert_eq!(hunks[0].remove, 1..2);
+/// ```
+pub fn diff_hunks_with_opts<T>(
+    old_text: T,
+    new_text: T,
+    algorithm: DiffAlgorithm,
+    whitespace: &WhitespaceOpts,
+) -> Vec<Hunk>
+where
+    T: AsRef<[u8]>,
+{
+    let old_text = old_text.as_ref();
+    let new_text = new_text.as_ref();
+    let mut hunks = match algorithm {
+        DiffAlgorithm::Myers | DiffAlgorithm::Minimal => {
+            let flags = match algorithm {
+                DiffAlgorithm::Minimal => ffi::XDF_NEED_MINIMAL | ffi::XDF_INDENT_HEURISTIC,
+                _ => ffi::XDF_INDENT_HEURISTIC,
+            };
+            if whitespace.is_default() {
+                xdl_diff_hunks(old_text, new_text, flags as u64)
+            } else {
+                let old = normalize_text(old_text, whitespace);
+                let new = normalize_text(new_text, whitespace);
+                xdl_diff_hunks(&old, &new, flags as u64)
+            }
+        }
+        DiffAlgorithm::Patience | DiffAlgorithm::Histogram => {
+            let mut ids = HashMap::new();
+            let old = line_ids(old_text, whitespace, &mut ids);
+            let new = line_ids(new_text, whitespace, &mut ids);
+            let mut hunks = Vec::new();
+            let mut diff = Diff {
+                old: &old,
+                new: &new,
+                hunks: &mut hunks,
+            };
+            match algorithm {
+                DiffAlgorithm::Patience => diff.patience(0..old.len(), 0..new.len(), 0),
+                _ => diff.histogram(0..old.len(), 0..new.len(), 0),
+            }
+            merge_adjacent(hunks)
+        }
+    };
+
+    if whitespace.ignore_blank_lines {
+        let old_lines = split_lines(old_text);
+        let new_lines = split_lines(new_text);
+        let is_blank = |line: &&[u8]| line.iter().all(|b| b.is_ascii_whitespace());
+        hunks.retain(|hunk| {
+            !(old_lines[hunk.remove.clone()].iter().all(is_blank)
+                && new_lines[hunk.add.clone()].iter().all(is_blank))
+        });
+    }
+
+    hunks
+}
+
+/// Split text into lines. Unlike other places, the "\n" is kept so a last
+/// line without "\n" differs from the same line with "\n", like in xdiff.
+pub(crate) fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, b) in text.iter().enumerate() {
+        if *b == b'\n' {
+            lines.push(&text[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// Normalize each line of `text`, keeping the number of lines.
+pub(crate) fn normalize_text(text: &[u8], whitespace: &WhitespaceOpts) -> Vec<u8> {
+    let mut result = Vec::with_capacity(text.len());
+    for line in split_lines(text) {
+        let (content, newline) = match line.last() {
+            Some(b'\n') => (&line[..line.len() - 1], true),
+            _ => (line, false),
+        };
+        result.extend_from_slice(&whitespace.normalize(content));
+        if newline {
+            result.push(b'\n');
+        }
+    }
+    result
+}
+
+/// Convert lines to ids. Equal lines after normalization get the same id.
+fn line_ids<'a>(
+    text: &'a [u8],
+    whitespace: &WhitespaceOpts,
+    ids: &mut HashMap<Cow<'a, [u8]>, usize>,
+) -> Vec<usize> {
+    split_lines(text)
+        .into_iter()
+        .map(|line| {
+            let (content, newline) = match line.last() {
+                Some(b'\n') => (&line[..line.len() - 1], true),
+                _ => (line, false),
+            };
+            let key = match whitespace.normalize(content) {
+                Cow::Borrowed(_) => Cow::Borrowed(line),
+                Cow::Owned(mut owned) => {
+                    if newline {
+                        owned.push(b'\n');
+                    }
+                    Cow::Owned(owned)
+                }
+            };
+            let next_id = ids.len();
+            *ids.entry(key).or_insert(next_id)
+        })
+        .collect()
+}
+
+/// Merge hunks that touch each other.
+fn merge_adjacent(hunks: Vec<Hunk>) -> Vec<Hunk> {
+    let mut result: Vec<Hunk> = Vec::with_capacity(hunks.len());
+    for hunk in hunks {
+        if hunk.remove.is_empty() && hunk.add.is_empty() {
+            continue;
+        }
+        match result.last_mut() {
+            Some(last)
+                if last.remove.end == hunk.remove.start && last.add.end == hunk.add.start =>
+            {
+                last.remove.end = hunk.remove.end;
+                last.add.end = hunk.add.end;
+            }
+            _ => result.push(hunk),
+        }
+    }
+    result
+}
+
+/// Lines occurring more often than this are not used as histogram anchors.
+const MAX_HISTOGRAM_CHAIN: usize = 64;
+
+/// Regions nested deeper than this are diffed with Myers' algorithm, so
+/// inputs splitting into many nested regions do not exhaust the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Occurrences of a line in a region, for patience diff.
+struct LineCount {
+    old_count: usize,
+    new_count: usize,
+    /// Index of the last occurrence in the old side.
+    old_index: usize,
+    /// Index of the last occurrence in the new side.
+    new_index: usize,
+}
+
+struct Diff<'a> {
+    old: &'a [usize],
+    new: &'a [usize],
+    hunks: &'a mut Vec<Hunk>,
+}
+
+type Range = std::ops::Range<usize>;
+
+impl<'a> Diff<'a> {
+    /// Strip common prefix and suffix. Return `None` if there is nothing
+    /// left to diff.
+    fn trim(&mut self, mut old: Range, mut new: Range) -> Option<(Range, Range)> {
+        while old.start < old.end
+            && new.start < new.end
+            && self.old[old.start] == self.new[new.start]
+        {
+            old.start += 1;
+            new.start += 1;
+        }
+        while old.start < old.end
+            && new.start < new.end
+            && self.old[old.end - 1] == self.new[new.end - 1]
+        {
+            old.end -= 1;
+            new.end -= 1;
+        }
+        if old.start == old.end || new.start == new.end {
+            if old.start < old.end || new.start < new.end {
+                self.hunks.push(Hunk {
+                    remove: old,
+                    add: new,
+                });
+            }
+            return None;
+        }
+        Some((old, new))
+    }
+
+    /// Diff a region using Myers' algorithm.
+    fn myers(&mut self, old: Range, new: Range) {
+        let to_text =
+            |ids: &[usize]| -> String { ids.iter().map(|id| format!("{}\n", id)).collect() };
+        let old_text = to_text(&self.old[old.clone()]);
+        let new_text = to_text(&self.new[new.clone()]);
+        for hunk in xdl_diff_hunks(old_text.as_bytes(), new_text.as_bytes(), 0) {
+            self.hunks.push(Hunk {
+                remove: hunk.remove.start + old.start..hunk.remove.end + old.start,
+                add: hunk.add.start + new.start..hunk.add.end + new.start,
+            });
+        }
+    }
+
+    fn patience(&mut self, old: Range, new: Range, depth: usize) {
+        let (old, new) = match self.trim(old, new) {
+            Some(ranges) => ranges,
+            None => return,
+        };
+        if depth > MAX_DEPTH {
+            return self.myers(old, new);
+        }
+
+        // Lines that appear exactly once in both sides.
+        let mut counts: HashMap<usize, LineCount> = HashMap::new();
+        for i in old.clone() {
+            let count = counts.entry(self.old[i]).or_insert(LineCount {
+                old_count: 0,
+                new_count: 0,
+                old_index: i,
+                new_index: 0,
+            });
+            count.old_count += 1;
+        }
+        for j in new.clone() {
+            if let Some(count) = counts.get_mut(&self.new[j]) {
+                count.new_count += 1;
+                count.new_index = j;
+            }
+        }
+        let mut unique: Vec<(usize, usize)> = counts
+            .values()
+            .filter(|count| count.old_count == 1 && count.new_count == 1)
+            .map(|count| (count.old_index, count.new_index))
+            .collect();
+        if unique.is_empty() {
+            return self.myers(old, new);
+        }
+        unique.sort_unstable_by_key(|&(_, j)| j);
+
+        // Longest increasing subsequence of old indexes.
+        let mut tails: Vec<usize> = Vec::new();
+        let mut prev: Vec<Option<usize>> = vec![None; unique.len()];
+        for (k, &(i, _)) in unique.iter().enumerate() {
+            let pos = tails.partition_point(|&t| unique[t].0 < i);
+            if pos > 0 {
+                prev[k] = Some(tails[pos - 1]);
+            }
+            if pos == tails.len() {
+                tails.push(k);
+            } else {
+                tails[pos] = k;
+            }
+        }
+        let mut anchors = Vec::with_capacity(tails.len());
+        let mut next = tails.last().cloned();
+        while let Some(k) = next {
+            anchors.push(unique[k]);
+            next = prev[k];
+        }
+        anchors.reverse();
+
+        // Diff the gaps between anchors.
+        let (mut i, mut j) = (old.start, new.start);
+        for (ai, aj) in anchors {
+            self.patience(i..ai, j..aj, depth + 1);
+            i = ai + 1;
+            j = aj + 1;
+        }
+        self.patience(i..old.end, j..new.end, depth + 1);
+    }
+
+    fn histogram(&mut self, old: Range, new: Range, depth: usize) {
+        let (old, new) = match self.trim(old, new) {
+            Some(ranges) => ranges,
+            None => return,
+        };
+        if depth > MAX_DEPTH {
+            return self.myers(old, new);
+        }
+
+        let mut positions: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in old.clone() {
+            positions.entry(self.old[i]).or_default().push(i);
+        }
+
+        // Find the common region whose rarest line is the least frequent,
+        // preferring longer regions.
+        // (lowest count, old start, new start, length)
+        let mut best: Option<(usize, usize, usize, usize)> = None;
+        let mut j = new.start;
+        while j < new.end {
+            let mut next_j = j + 1;
+            if let Some(list) = positions.get(&self.new[j]) {
+                if list.len() <= MAX_HISTOGRAM_CHAIN {
+                    for &i in list {
+                        let (mut si, mut sj) = (i, j);
+                        while si > old.start
+                            && sj > new.start
+                            && self.old[si - 1] == self.new[sj - 1]
+                        {
+                            si -= 1;
+                            sj -= 1;
+                        }
+                        let (mut ei, mut ej) = (i + 1, j + 1);
+                        while ei < old.end && ej < new.end && self.old[ei] == self.new[ej] {
+                            ei += 1;
+                            ej += 1;
+                        }
+                        let count = (si..ei)
+                            .map(|k| positions[&self.old[k]].len())
+                            .min()
+                            .unwrap_or(usize::MAX);
+                        let len = ei - si;
+                        let better = match best {
+                            None => true,
+                            Some((best_count, _, _, best_len)) => {
+                                count < best_count || (count == best_count && len > best_len)
+                            }
+                        };
+                        if better {
+                            best = Some((count, si, sj, len));
+                        }
+                        next_j = next_j.max(ej);
+                    }
+                }
+            }
+            j = next_j;
+        }
+
+        match best {
+            None => self.myers(old, new),
+            Some((_, i, j, len)) => {
+                self.histogram(old.start..i, new.start..j, depth + 1);
+                self.histogram(i + len..old.end, j + len..new.end, depth + 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str, algorithm: DiffAlgorithm) -> Vec<(Range, Range)> {
+        diff_hunks_with_opts(old, new, algorithm, &Default::default())
+            .into_iter()
+            .map(|h| (h.remove, h.add))
+            .collect()
+    }
+
+    #[test]
+    fn test_algorithms() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "a\nc\nd\nx\ne\nf\n";
+        let expected = [(1..2, 1..1), (4..4, 3..4), (5..5, 5..6)];
+        for algorithm in [
+            DiffAlgorithm::Myers,
+            DiffAlgorithm::Minimal,
+            DiffAlgorithm::Patience,
+            DiffAlgorithm::Histogram,
+        ]
+        .iter()
+        {
+            assert_eq!(diff(old, new, *algorithm), expected, "{:?}", algorithm);
+            assert!(diff(old, old, *algorithm).is_empty());
+            assert_eq!(diff("", old, *algorithm), [(0..0, 0..5)]);
+            assert_eq!(diff("a", "a\n", *algorithm), [(0..1, 0..1)]);
+        }
+    }
+
+    #[test]
+    fn test_hunks_apply() {
+        // Applying the hunks to the old lines should produce the new lines.
+        let words = ["{", "}", "a", "b", "c", ""];
+        let mut seed = 1u64;
+        let mut random = move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+        for _ in 0..500 {
+            let mut text = || -> Vec<&str> {
+                let len = random() % 10;
+                (0..len).map(|_| words[random() % words.len()]).collect()
+            };
+            let old = text();
+            let new = text();
+            let to_text =
+                |lines: &[&str]| -> String { lines.iter().map(|l| format!("{}\n", l)).collect() };
+            for algorithm in [DiffAlgorithm::Patience, DiffAlgorithm::Histogram].iter() {
+                let hunks = diff_hunks_with_opts(
+                    to_text(&old),
+                    to_text(&new),
+                    *algorithm,
+                    &Default::default(),
+                );
+                let mut applied = Vec::new();
+                let mut end = 0;
+                for hunk in hunks {
+                    assert!(hunk.remove.start >= end);
+                    applied.extend_from_slice(&old[end..hunk.remove.start]);
+                    applied.extend_from_slice(&new[hunk.add]);
+                    end = hunk.remove.end;
+                }
+                applied.extend_from_slice(&old[end..]);
+                assert_eq!(applied, new, "{:?} {:?}", algorithm, old);
+            }
+        }
+    }
+
+    #[test]
+    fn test_repeated_line() {
+        // A line repeated 65537 times on one side is not unique.
+        let old = format!("a\n{}", "x\n".repeat(65537));
+        for algorithm in [DiffAlgorithm::Patience, DiffAlgorithm::Histogram].iter() {
+            assert_eq!(diff(&old, "b\n", *algorithm), [(0..65538, 0..1)]);
+        }
+    }
+
+    #[test]
+    fn test_deep_regions() {
+        // Every other line changes, so each anchor splits off a small region.
+        let old: Vec<String> = (0..20000).map(|i| format!("{}", i)).collect();
+        let new: Vec<String> = (0..20000)
+            .map(|i| {
+                if i % 2 == 0 {
+                    format!("{}", i)
+                } else {
+                    format!("x{}", i)
+                }
+            })
+            .collect();
+        let to_text =
+            |lines: &[String]| -> String { lines.iter().map(|l| format!("{}\n", l)).collect() };
+        for algorithm in [DiffAlgorithm::Patience, DiffAlgorithm::Histogram].iter() {
+            let hunks = diff_hunks_with_opts(
+                to_text(&old),
+                to_text(&new),
+                *algorithm,
+                &Default::default(),
+            );
+            assert_eq!(hunks.len(), 10000, "{:?}", algorithm);
+            for (k, hunk) in hunks.iter().enumerate() {
+                assert_eq!(hunk.remove, 2 * k + 1..2 * k + 2);
+                assert_eq!(hunk.add, 2 * k + 1..2 * k + 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let diff = |old: &str, new: &str, f: fn(&mut WhitespaceOpts)| -> usize {
+            let mut whitespace = WhitespaceOpts::default();
+            f(&mut whitespace);
+            let myers = diff_hunks_with_opts(old, new, DiffAlgorithm::Myers, &whitespace);
+            let histogram = diff_hunks_with_opts(old, new, DiffAlgorithm::Histogram, &whitespace);
+            assert_eq!(myers, histogram);
+            myers.len()
+        };
+        assert_eq!(diff("a b\n", "a  b \n", |_| {}), 1);
+        assert_eq!(diff("a b\n", "a  b \n", |w| w.ignore_amount = true), 0);
+        assert_eq!(diff("a b\n", " ab\n", |w| w.ignore_amount = true), 1);
+        assert_eq!(diff("a b\n", " ab\n", |w| w.ignore_all = true), 0);
+        assert_eq!(
+            diff("a\r\nb\n", "a\nb\n", |w| w.ignore_trailing_cr = true),
+            0
+        );
+        assert_eq!(
+            diff("a\nb\n", "a\n\n  \nb\n", |w| w.ignore_blank_lines = true),
+            0
+        );
+        assert_eq!(
+            diff("a\nb\n", "a\n\nc\n", |w| w.ignore_blank_lines = true),
+            1
+        );
+    }
+}
@@ -12,7 +12,10 @@ use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
-use xdiff::{diff_unified, CopyInfo, DiffFile, DiffOpts, FileType};
+use xdiff::{
+    diff_unified, diff_unified_inline, CopyInfo, DiffAlgorithm, DiffFile, DiffOpts, FileType,
+    FunctionContext, Tokenizer, WhitespaceOpts,
+};
 
 const EXEC_BIT: u32 = 0o0000100;
 
@@ -43,6 +46,56 @@ struct Opt {
     /// Number of lines of unified context (default: 3)
     #[structopt(short = "U", long, default_value = "3")]
     unified: usize,
+
+    /// Highlight changed words within lines
+    #[structopt(long)]
+    word_diff: bool,
+
+    /// Highlight changed characters within lines
+    #[structopt(long)]
+    char_diff: bool,
+
+    /// Highlight changed words within lines, using the regex to find words
+    #[structopt(long)]
+    word_diff_regex: Option<regex::bytes::Regex>,
+
+    /// Diff algorithm (myers, minimal, patience, histogram)
+    #[structopt(long, default_value = "myers", parse(try_from_str = parse_algorithm))]
+    diff_algorithm: DiffAlgorithm,
+
+    /// Ignore all whitespace when comparing lines
+    #[structopt(short = "w", long)]
+    ignore_all_space: bool,
+
+    /// Ignore changes in the amount of whitespace
+    #[structopt(short = "b", long)]
+    ignore_space_change: bool,
+
+    /// Ignore changes whose lines are all blank
+    #[structopt(short = "B", long)]
+    ignore_blank_lines: bool,
+
+    /// Ignore carriage return at the end of lines
+    #[structopt(long)]
+    strip_trailing_cr: bool,
+
+    /// Show which function each change is in
+    #[structopt(short = "p", long)]
+    show_function: bool,
+
+    /// Regex to find function lines in files without a known extension (implies -p)
+    #[structopt(long)]
+    function_regex: Option<regex::bytes::Regex>,
+}
+
+fn parse_algorithm(name: &str) -> Result<DiffAlgorithm, String> {
+    match name {
+        "myers" => Ok(DiffAlgorithm::Myers),
+        "minimal" => Ok(DiffAlgorithm::Minimal),
+        "patience" => Ok(DiffAlgorithm::Patience),
+        "histogram" => Ok(DiffAlgorithm::Histogram),
+        _ => Err(format!("unknown diff algorithm: {}", name)),
+    }
 }
 
 fn main() -> Result<(), std::io::Error> {
@@ -97,14 +150,32 @@ fn main() -> Result<(), std::io::Error> {
         None
     };
 
-    let diff = diff_unified(
-        a,
-        b,
-        DiffOpts {
-            context: opt.unified,
-            copy_info,
+    let opts = DiffOpts {
+        context: opt.unified,
+        copy_info,
+        algorithm: opt.diff_algorithm,
+        whitespace: WhitespaceOpts {
+            ignore_all: opt.ignore_all_space,
+            ignore_amount: opt.ignore_space_change,
+            ignore_blank_lines: opt.ignore_blank_lines,
+            ignore_trailing_cr: opt.strip_trailing_cr,
         },
-    );
+        function_context: match (opt.show_function, opt.function_regex.clone()) {
+            (_, Some(regex)) => Some(FunctionContext::new().with_fallback(regex)),
+            (true, None) => Some(FunctionContext::new()),
+            (false, None) => None,
+        },
+    };
+    let tokenizer = match (opt.word_diff_regex.clone(), opt.char_diff, opt.word_diff) {
+        (Some(regex), _, _) => Some(Tokenizer::Regex(regex)),
+        (None, true, _) => Some(Tokenizer::Chars),
+        (None, false, true) => Some(Tokenizer::Words),
+        (None, false, false) => None,
+    };
+    let diff = match tokenizer {
+        Some(tokenizer) => diff_unified_inline(a, b, opts, &tokenizer),
+        None => diff_unified(a, b, opts),
+    };
 
     print!("{}", String::from_utf8_lossy(&diff));
     Ok(())
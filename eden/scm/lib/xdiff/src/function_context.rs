@@ -0,0 +1,204 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Function names shown after the "@@ ... @@" hunk headers.
+
+use regex::bytes::Regex;
+use std::collections::HashMap;
+
+/// Finds the enclosing function of a hunk by searching backwards for a line
+/// matching a regex. The regex is picked by the file extension.
+///
+/// # Example
+/// ```
+/// use xdiff::FunctionContext;
+/// let context = FunctionContext::new()
+///     .with_extension("sql", regex::bytes::Regex::new("^CREATE ").unwrap());
+/// assert_eq!(context.regex_for_path(b"a/b.sql").as_str(), "^CREATE ");
+/// assert_eq!(context.regex_for_path(b"a/b").as_str(), "^[[:alnum:]_$]");
+/// ```
+#[derive(Clone, Debug)]
+pub struct FunctionContext {
+    /// Regexes by file extension, without ".".
+    by_extension: HashMap<String, Regex>,
+    /// Regex used if the extension is not in `by_extension`.
+    fallback: Regex,
+}
+
+/// Maximum length of the function line shown in a hunk header, like mdiff.py.
+const MAX_FUNCTION_LEN: usize = 40;
+
+/// Default regexes by file extensions.
+const DEFAULT_PATTERNS: &[(&[&str], &str)] = &[
+    (
+        &["rs"],
+        r"^\s*(pub(\([^)]*\))?\s+)?((const|async|unsafe|extern\s+\S+)\s+)*(fn|struct|enum|union|trait|impl|mod|macro_rules!)\b",
+    ),
+    (&["py", "pyi"], r"^\s*(async\s+)?(def|class)\s"),
+    (
+        &["c", "h", "cc", "cpp", "cxx", "hpp", "m", "mm"],
+        r"^[[:alpha:]_][^;:]*(\(|$)",
+    ),
+    (&["go"], r"^(func|type)\s"),
+    (
+        &["js", "jsx", "ts", "tsx"],
+        r"^\s*(export\s+)?(default\s+)?(async\s+)?(function\b|class\b)",
+    ),
+    (
+        &["java", "kt", "scala"],
+        r"^\s*((public|protected|private|static|final|abstract|override)\s+)*(class|interface|enum|object|fun|def|[[:alnum:]_<>\[\]]+\s+[[:alnum:]_]+\s*\()",
+    ),
+    (&["sh", "bash"], r"^\s*(function\s+)?[[:alnum:]_]+\s*\(\)"),
+];
+
+/// Default regex for other files. Same as mdiff.py: a line starting with
+/// an identifier.
+const DEFAULT_FALLBACK: &str = r"^[[:alnum:]_$]";
+
+impl FunctionContext {
+    /// Create with default regexes for common languages.
+    pub fn new() -> Self {
+        let mut by_extension = HashMap::new();
+        for (extensions, pattern) in DEFAULT_PATTERNS {
+            let regex = Regex::new(pattern).expect("default pattern is valid");
+            for extension in extensions.iter() {
+                by_extension.insert(extension.to_string(), regex.clone());
+            }
+        }
+        Self {
+            by_extension,
+            fallback: Regex::new(DEFAULT_FALLBACK).expect("default pattern is valid"),
+        }
+    }
+
+    /// Use `regex` for files with the given extension, like "rs".
+    pub fn with_extension(mut self, extension: impl ToString, regex: Regex) -> Self {
+        self.by_extension.insert(extension.to_string(), regex);
+        self
+    }
+
+    /// Use `regex` for files without a configured extension.
+    pub fn with_fallback(mut self, regex: Regex) -> Self {
+        self.fallback = regex;
+        self
+    }
+
+    /// The regex used for the file at `path`.
+    pub fn regex_for_path(&self, path: &[u8]) -> &Regex {
+        let file_name = path.rsplit(|&b| b == b'/').next().unwrap_or(path);
+        let extension = match file_name.iter().rposition(|&b| b == b'.') {
+            Some(pos) if pos > 0 => &file_name[pos + 1..],
+            _ => return &self.fallback,
+        };
+        std::str::from_utf8(extension)
+            .ok()
+            .and_then(|extension| self.by_extension.get(extension))
+            .unwrap_or(&self.fallback)
+    }
+
+    /// The regex used for files without paths.
+    pub fn fallback(&self) -> &Regex {
+        &self.fallback
+    }
+}
+
+impl Default for FunctionContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for FunctionContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.fallback.as_str() == other.fallback.as_str()
+            && self.by_extension.len() == other.by_extension.len()
+            && self.by_extension.iter().all(|(extension, regex)| {
+                other.by_extension.get(extension).map(|r| r.as_str()) == Some(regex.as_str())
+            })
+    }
+}
+
+impl Eq for FunctionContext {}
+
+/// Search `lines[start..end]` backwards for a line matching `regex`.
+/// Return the line with trailing whitespace removed and shortened for
+/// hunk headers.
+pub(crate) fn find_function<'a>(
+    regex: &Regex,
+    lines: &[&'a [u8]],
+    start: usize,
+    end: usize,
+) -> Option<&'a [u8]> {
+    let line = lines[start..end]
+        .iter()
+        .rev()
+        .find(|line| regex.is_match(line))?;
+    let trimmed_len = line.len()
+        - line
+            .iter()
+            .rev()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count();
+    let mut len = trimmed_len.min(MAX_FUNCTION_LEN);
+    // Do not split UTF-8 characters. Continuation bytes are 0b10xxxxxx.
+    while len < trimmed_len && len > 0 && line[len] & 0xc0 == 0x80 {
+        len -= 1;
+    }
+    Some(&line[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_for_path() {
+        let context = FunctionContext::new();
+        let matches = |path: &str, line: &str| {
+            context
+                .regex_for_path(path.as_bytes())
+                .is_match(line.as_bytes())
+        };
+        assert!(matches("a/b.rs", "pub(crate) async fn foo() {"));
+        assert!(matches("a/b.rs", "impl<T> Foo for T {"));
+        assert!(!matches("a/b.rs", "    let x = 1;"));
+        assert!(matches("a.py", "    def foo(self):"));
+        assert!(!matches("a.py", "    return 1"));
+        assert!(matches("a.c", "static int main(int argc, char **argv)"));
+        assert!(!matches("a.c", "    return 0;"));
+        assert!(matches("README", "Title"));
+        assert!(!matches(".rs", "  x"));
+        assert!(matches(".rs", "x"));
+    }
+
+    #[test]
+    fn test_find_function() {
+        let lines: Vec<&[u8]> = vec![
+            b"fn foo() {",
+            b"    1",
+            b"}",
+            b"fn a_very_long_function_name_that_is_shortened() {  ",
+            b"    2",
+        ];
+        let regex = FunctionContext::new().regex_for_path(b"x.rs").clone();
+        assert_eq!(
+            find_function(&regex, &lines, 0, 2),
+            Some(&b"fn foo() {"[..])
+        );
+        assert_eq!(
+            find_function(&regex, &lines, 0, 5),
+            Some(&b"fn a_very_long_function_name_that_is_sho"[..])
+        );
+        assert_eq!(find_function(&regex, &lines, 1, 3), None);
+
+        let lines: Vec<&[u8]> = vec!["fn ééééééééééééééééééé() {".as_bytes()];
+        assert_eq!(
+            find_function(&regex, &lines, 0, 1).map(|l| l.len()),
+            Some(39)
+        );
+    }
+}
@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Refine line-based hunks into changed spans within lines.
+
+use crate::{diff_hunks, Hunk};
+use regex::bytes::Regex;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// How to split lines into tokens for an inline diff.
+#[derive(Clone, Debug)]
+pub enum Tokenizer {
+    /// Runs of word characters, runs of whitespace, and single punctuation
+    /// characters. Non-ASCII characters are word characters.
+    Words,
+    /// Individual UTF-8 characters.
+    Chars,
+    /// Matches of the regular expression. Text between matches is a token
+    /// too.
+    Regex(Regex),
+}
+
+/// A part of a line in an inline diff.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InlineSpan {
+    /// Byte range in the line, not including the "\n".
+    pub range: Range<usize>,
+    /// Whether the span is part of the change.
+    pub changed: bool,
+}
+
+/// A line hunk with its lines split into changed and unchanged spans.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InlineHunk {
+    pub hunk: Hunk,
+    /// Spans of each removed line.
+    pub remove: Vec<Vec<InlineSpan>>,
+    /// Spans of each added line.
+    pub add: Vec<Vec<InlineSpan>>,
+}
+
+impl Tokenizer {
+    /// Split `line` into tokens. Concatenating the tokens gives `line` back.
+    pub fn tokenize<'a>(&self, line: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut tokens = Vec::new();
+        match self {
+            Tokenizer::Words => {
+                // 0: word, 1: whitespace, 2: other.
+                let class = |b: u8| {
+                    if b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80 {
+                        0
+                    } else if b.is_ascii_whitespace() {
+                        1
+                    } else {
+                        2
+                    }
+                };
+                let mut start = 0;
+                for i in 1..=line.len() {
+                    if i == line.len()
+                        || class(line[i]) != class(line[start])
+                        || class(line[i]) == 2
+                    {
+                        tokens.push(&line[start..i]);
+                        start = i;
+                    }
+                }
+            }
+            Tokenizer::Chars => {
+                let mut start = 0;
+                for i in 1..=line.len() {
+                    // Continuation bytes of UTF-8 are 0b10xxxxxx.
+                    if i == line.len() || line[i] & 0xc0 != 0x80 {
+                        tokens.push(&line[start..i]);
+                        start = i;
+                    }
+                }
+            }
+            Tokenizer::Regex(regex) => {
+                let mut end = 0;
+                for m in regex.find_iter(line).filter(|m| !m.as_bytes().is_empty()) {
+                    if m.start() > end {
+                        tokens.push(&line[end..m.start()]);
+                    }
+                    tokens.push(m.as_bytes());
+                    end = m.end();
+                }
+                if end < line.len() {
+                    tokens.push(&line[end..]);
+                }
+            }
+        }
+        tokens
+    }
+}
+
+/// Computes line hunks like [`diff_hunks`], then finds the changed tokens
+/// within the lines of each hunk.
+///
+/// # Example
+/// ```
+/// use xdiff::{diff_inline, InlineSpan, Tokenizer};
+/// let hunks = diff_inline("let x = 1;\n", "let y = 1;\n", &Tokenizer::Words);
+/// let span = |range, changed| InlineSpan { range, changed };
+/// assert_eq!(
+///     hunks[0].remove,
+///     [vec![span(0..4, false), span(4..5, true), span(5..10, false)]]
+/// );
+/// ```
+pub fn diff_inline<T>(old_text: T, new_text: T, tokenizer: &Tokenizer) -> Vec<InlineHunk>
+where
+    T: AsRef<[u8]>,
+{
+    let old_text = old_text.as_ref();
+    let new_text = new_text.as_ref();
+    let old_lines = split_lines(old_text);
+    let new_lines = split_lines(new_text);
+    diff_hunks(old_text, new_text)
+        .into_iter()
+        .map(|hunk| {
+            let (remove, add) = refine_lines(
+                &old_lines[hunk.remove.clone()],
+                &new_lines[hunk.add.clone()],
+                tokenizer,
+            );
+            InlineHunk { hunk, remove, add }
+        })
+        .collect()
+}
+
+/// Split text into lines without "\n". A trailing "\n" does not start a
+/// new line.
+fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    let mut lines: Vec<_> = text.split(|c| c == &b'\n').collect();
+    if text.is_empty() || text.last() == Some(&b'\n') {
+        lines.pop();
+    }
+    lines
+}
+
+/// Find changed spans in `old_lines` and `new_lines` by diffing their
+/// tokens. Line boundaries are tokens too, so tokens can move between
+/// lines.
+pub(crate) fn refine_lines(
+    old_lines: &[&[u8]],
+    new_lines: &[&[u8]],
+    tokenizer: &Tokenizer,
+) -> (Vec<Vec<InlineSpan>>, Vec<Vec<InlineSpan>>) {
+    let mut ids: HashMap<&[u8], usize> = HashMap::new();
+    let old_tokens = line_tokens(old_lines, tokenizer, &mut ids);
+    let new_tokens = line_tokens(new_lines, tokenizer, &mut ids);
+
+    // xdiff compares lines. Write one token id per line to diff tokens.
+    let to_text =
+        |tokens: &[LineToken]| -> String { tokens.iter().map(|t| format!("{}\n", t.id)).collect() };
+    let mut old_changed = vec![false; old_tokens.len()];
+    let mut new_changed = vec![false; new_tokens.len()];
+    for hunk in diff_hunks(to_text(&old_tokens), to_text(&new_tokens)) {
+        old_changed[hunk.remove].iter_mut().for_each(|c| *c = true);
+        new_changed[hunk.add].iter_mut().for_each(|c| *c = true);
+    }
+
+    (
+        to_spans(old_lines.len(), &old_tokens, &old_changed),
+        to_spans(new_lines.len(), &new_tokens, &new_changed),
+    )
+}
+
+struct LineToken {
+    id: usize,
+    line: usize,
+    /// Byte range in the line. `None` for the end of a line.
+    range: Option<Range<usize>>,
+}
+
+fn line_tokens<'a>(
+    lines: &[&'a [u8]],
+    tokenizer: &Tokenizer,
+    ids: &mut HashMap<&'a [u8], usize>,
+) -> Vec<LineToken> {
+    let mut result = Vec::new();
+    for (line_index, line) in lines.iter().enumerate() {
+        let mut start = 0;
+        for token in tokenizer.tokenize(line) {
+            let next_id = ids.len();
+            result.push(LineToken {
+                id: *ids.entry(token).or_insert(next_id),
+                line: line_index,
+                range: Some(start..start + token.len()),
+            });
+            start += token.len();
+        }
+        let next_id = ids.len();
+        result.push(LineToken {
+            id: *ids.entry(b"\n").or_insert(next_id),
+            line: line_index,
+            range: None,
+        });
+    }
+    result
+}
+
+/// Merge adjacent tokens with the same `changed` state into spans.
+fn to_spans(line_count: usize, tokens: &[LineToken], changed: &[bool]) -> Vec<Vec<InlineSpan>> {
+    let mut result: Vec<Vec<InlineSpan>> = vec![Vec::new(); line_count];
+    for (token, &changed) in tokens.iter().zip(changed) {
+        if let Some(range) = &token.range {
+            let spans = &mut result[token.line];
+            match spans.last_mut() {
+                Some(last) if last.changed == changed => last.range.end = range.end,
+                _ => spans.push(InlineSpan {
+                    range: range.clone(),
+                    changed,
+                }),
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(tokenizer: &Tokenizer, line: &str) -> Vec<String> {
+        tokenizer
+            .tokenize(line.as_bytes())
+            .into_iter()
+            .map(|t| String::from_utf8_lossy(t).into_owned())
+            .collect()
+    }
+
+    /// Render changed spans as "[...]".
+    fn show(old: &str, new: &str, tokenizer: &Tokenizer) -> String {
+        let mut out = String::new();
+        for hunk in diff_inline(old, new, tokenizer) {
+            for (prefix, text, lines, spans) in [
+                ("-", old, hunk.hunk.remove, hunk.remove),
+                ("+", new, hunk.hunk.add, hunk.add),
+            ]
+            .iter()
+            {
+                let text_lines = split_lines(text.as_bytes());
+                for (line, spans) in text_lines[lines.clone()].iter().zip(spans) {
+                    out += prefix;
+                    for span in spans {
+                        let s = String::from_utf8_lossy(&line[span.range.clone()]);
+                        match span.changed {
+                            true => out += &format!("[{}]", s),
+                            false => out += &s,
+                        }
+                    }
+                    out += "\n";
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokens(&Tokenizer::Words, "foo_1(bar,  bäz)"),
+            ["foo_1", "(", "bar", ",", "  ", "bäz", ")"]
+        );
+        assert_eq!(tokens(&Tokenizer::Chars, "aé b"), ["a", "é", " ", "b"]);
+        let regex = Tokenizer::Regex(Regex::new("[0-9]+").unwrap());
+        assert_eq!(tokens(&regex, "a12b3"), ["a", "12", "b", "3"]);
+        assert!(tokens(&Tokenizer::Words, "").is_empty());
+    }
+
+    #[test]
+    fn test_diff_inline() {
+        assert_eq!(
+            show(
+                "a\nfoo(bar, baz)\nb\n",
+                "a\nfoo(bar, qux)\nb\n",
+                &Tokenizer::Words
+            ),
+            "-foo(bar, [baz])\n+foo(bar, [qux])\n"
+        );
+        assert_eq!(
+            show("color\n", "colour\n", &Tokenizer::Chars),
+            "-color\n+colo[u]r\n"
+        );
+
+        // Tokens can move between lines.
+        assert_eq!(
+            show("x = a +\n  b\n", "x = a + b\n", &Tokenizer::Words),
+            "-x = a +\n-[  ]b\n+x = a +[ ]b\n"
+        );
+
+        // Pure insertion.
+        assert_eq!(show("a\n", "a\nb c\n", &Tokenizer::Words), "+[b c]\n");
+    }
+}
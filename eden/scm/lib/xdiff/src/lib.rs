@@ -13,6 +13,17 @@ use std::ops::Range;
 use std::os::raw::{c_char, c_int, c_void};
 use xdiff_sys as ffi;
 
+mod algorithm;
+mod function_context;
+mod inline;
+mod merge;
+
+pub use algorithm::{diff_hunks_with_opts, DiffAlgorithm, WhitespaceOpts};
+pub use function_context::FunctionContext;
+pub use inline::{diff_inline, InlineHunk, InlineSpan, Tokenizer};
+pub use merge::{merge, Conflict, MergeOpts, MergeResult, MergeStyle};
+use regex::bytes::Regex;
+
 /// An individual difference between two texts. Consists of two
 /// line ranges that specify which parts of the texts differ.
 ///
@@ -64,6 +75,15 @@ pub fn diff_hunks<T>(old_text: T, new_text: T) -> Vec<Hunk>
 where
     T: AsRef<[u8]>,
 {
+    xdl_diff_hunks(
+        old_text.as_ref(),
+        new_text.as_ref(),
+        ffi::XDF_INDENT_HEURISTIC as u64,
+    )
+}
+
+/// Call xdiff with the given `XDF_*` flags.
+pub(crate) fn xdl_diff_hunks(old_text: &[u8], new_text: &[u8], flags: u64) -> Vec<Hunk> {
     extern "C" fn hunk_consumer(a1: i64, a2: i64, b1: i64, b2: i64, _priv: *mut c_void) -> c_int {
         let mut _priv = unsafe { (_priv as *mut Vec<Hunk>).as_mut() };
         let a1 = a1 as usize;
@@ -79,19 +99,15 @@ where
         return 0;
     }
 
-    let old_text = old_text.as_ref();
     let mut old_mmfile = ffi::mmfile_t {
         ptr: old_text.as_ptr() as *mut c_char,
         size: old_text.len() as i64,
     };
-    let new_text = new_text.as_ref();
     let mut new_mmfile = ffi::mmfile_t {
         ptr: new_text.as_ptr() as *mut c_char,
         size: new_text.len() as i64,
     };
-    let xpp = ffi::xpparam_t {
-        flags: ffi::XDF_INDENT_HEURISTIC as u64,
-    };
+    let xpp = ffi::xpparam_t { flags };
     let xecfg = ffi::xdemitconf_t {
         flags: 0,
         hunk_func: Some(hunk_consumer),
@@ -111,6 +127,22 @@ where
 pub struct HeaderlessDiffOpts {
     /// Number of context lines
     pub context: usize,
+    pub algorithm: DiffAlgorithm,
+    pub whitespace: WhitespaceOpts,
+    /// Show the enclosing function after "@@ ... @@" in hunk headers.
+    /// Without paths, the fallback regex is used.
+    pub function_context: Option<FunctionContext>,
+}
+
+impl Default for HeaderlessDiffOpts {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            algorithm: DiffAlgorithm::default(),
+            whitespace: WhitespaceOpts::default(),
+            function_context: None,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -128,15 +160,41 @@ pub struct DiffOpts {
     /// Number of context lines
     pub context: usize,
     pub copy_info: CopyInfo,
+    pub algorithm: DiffAlgorithm,
+    pub whitespace: WhitespaceOpts,
+    /// Show the enclosing function after "@@ ... @@" in hunk headers.
+    /// The regex is picked by the extension of the new file.
+    pub function_context: Option<FunctionContext>,
+}
+
+impl Default for DiffOpts {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            copy_info: CopyInfo::None,
+            algorithm: DiffAlgorithm::default(),
+            whitespace: WhitespaceOpts::default(),
+            function_context: None,
+        }
+    }
 }
 
 const MISSING_NEWLINE_MARKER: &[u8] = b"\\ No newline at end of file\n";
 
-struct DiffPayload<'a, 'b> {
+// ANSI colors matching "diff.deleted.*" and "diff.inserted.*" in color.py.
+const COLOR_DELETED: &[u8] = b"\x1b[31m";
+const COLOR_DELETED_CHANGED: &[u8] = b"\x1b[1;91m";
+const COLOR_INSERTED: &[u8] = b"\x1b[32m";
+const COLOR_INSERTED_CHANGED: &[u8] = b"\x1b[1;92m";
+const COLOR_RESET: &[u8] = b"\x1b[0m";
+
+struct DiffPayload<'a, 'b, 'c> {
     old_has_trailing_newline: bool,
     new_has_trailing_newline: bool,
     old_lines: Vec<&'a [u8]>,
     new_lines: Vec<&'b [u8]>,
+    /// Highlight changed tokens within lines.
+    inline: Option<&'c Tokenizer>,
 }
 
 struct DiffState<S, F>
@@ -157,6 +215,29 @@ where
         self.emit(b"\n");
     }
 
+    /// Emit a line with changed `spans` highlighted using ANSI colors.
+    fn emit_inline_line(&mut self, prefix: &[u8], line: &[u8], spans: &[InlineSpan]) {
+        let (color, color_changed) = if prefix == b"-" {
+            (COLOR_DELETED, COLOR_DELETED_CHANGED)
+        } else {
+            (COLOR_INSERTED, COLOR_INSERTED_CHANGED)
+        };
+        self.emit(color);
+        self.emit(prefix);
+        for span in spans {
+            if span.changed {
+                self.emit(color_changed);
+                self.emit(&line[span.range.clone()]);
+                self.emit(COLOR_RESET);
+                self.emit(color);
+            } else {
+                self.emit(&line[span.range.clone()]);
+            }
+        }
+        self.emit(COLOR_RESET);
+        self.emit(b"\n");
+    }
+
     fn emit(&mut self, text: &[u8]) {
         // This option can be unwrapped since it'll never be None: we always put something back in
         // its place.
@@ -169,11 +250,12 @@ where
         payload: &DiffPayload,
         cluster_bounds: Hunk,
         included_hunks: &[Hunk],
+        function: Option<&[u8]>,
     ) {
         // Emit the header.
         self.emit(
             format!(
-                "@@ -{},{} +{},{} @@\n",
+                "@@ -{},{} +{},{} @@",
                 // Of course line ranges in the diff format start from 1.
                 &cluster_bounds.remove.start + 1,
                 &cluster_bounds.remove.len(),
@@ -182,6 +264,11 @@ where
             )
             .as_bytes(),
         );
+        if let Some(function) = function {
+            self.emit(b" ");
+            self.emit(function);
+        }
+        self.emit(b"\n");
 
         let mut previous_hunk: Option<&Hunk> = None;
 
@@ -193,12 +280,22 @@ where
                 .iter()
                 .for_each(|line| self.emit_line(b" ", *line));
 
+            let old_lines = &payload.old_lines[hunk.remove.clone()];
+            let new_lines = &payload.new_lines[hunk.add.clone()];
+            let inline_spans = payload
+                .inline
+                .map(|tokenizer| inline::refine_lines(old_lines, new_lines, tokenizer));
+
             // Emit the lines from the old file preceded by '-' char.
-            payload.old_lines[hunk.remove.clone()]
-                .iter()
-                .for_each(|line| {
-                    self.emit_line(b"-", *line);
-                });
+            match &inline_spans {
+                Some((old_spans, _)) => old_lines
+                    .iter()
+                    .zip(old_spans)
+                    .for_each(|(line, spans)| self.emit_inline_line(b"-", line, spans)),
+                None => old_lines.iter().for_each(|line| {
+                    self.emit_line(b"-", line);
+                }),
+            }
             // In case file ends without newline we need to print a warning about it.
             if hunk.remove.end == payload.old_lines.len() {
                 if !payload.old_has_trailing_newline {
@@ -206,9 +303,15 @@ where
                 }
             }
             // Emit the lines from the new file preceded by '+' char.
-            payload.new_lines[hunk.add.clone()].iter().for_each(|line| {
-                self.emit_line(b"+", *line);
-            });
+            match &inline_spans {
+                Some((_, new_spans)) => new_lines
+                    .iter()
+                    .zip(new_spans)
+                    .for_each(|(line, spans)| self.emit_inline_line(b"+", line, spans)),
+                None => new_lines.iter().for_each(|line| {
+                    self.emit_line(b"+", line);
+                }),
+            }
             // In case file ends without newline we need to print a warning about it.
             if hunk.add.end == payload.new_lines.len() {
                 if !payload.new_has_trailing_newline {
@@ -268,7 +371,9 @@ where
 fn gen_diff_unified_headerless<T, F, S>(
     old_text: &T,
     new_text: &T,
-    opts: HeaderlessDiffOpts,
+    opts: &HeaderlessDiffOpts,
+    function_regex: Option<&Regex>,
+    inline: Option<&Tokenizer>,
     seed: S,
     reduce: F,
 ) -> S
@@ -292,7 +397,7 @@ where
     if new_has_trailing_newline {
         new_lines.pop();
     }
-    let hunks = diff_hunks(old_text, new_text);
+    let hunks = diff_hunks_with_opts(old_text, new_text, opts.algorithm, &opts.whitespace);
 
     if hunks.is_empty() {
         return seed;
@@ -303,6 +408,7 @@ where
         new_lines,
         old_has_trailing_newline,
         new_has_trailing_newline,
+        inline,
     };
 
     // TODO: Expose a constructor for this so that it cannot be constructed wiht seed: None.
@@ -316,6 +422,23 @@ where
     let sub_context = |n: usize| n.saturating_sub(opts.context);
     let add_context = |n: usize, max_l: usize| min(n + opts.context, max_l);
 
+    // Helper for finding the function shown in the header of a cluster
+    // starting at the given old line. Like mdiff.py, lines are searched
+    // backwards until the start of the previous cluster, and the previous
+    // function is reused if nothing matches.
+    let mut function_search_start = 0;
+    let mut last_function = None;
+    let mut find_function = |start: usize| {
+        let regex = function_regex?;
+        if let Some(function) =
+            function_context::find_function(regex, &payload.old_lines, function_search_start, start)
+        {
+            last_function = Some(function);
+        }
+        function_search_start = start;
+        last_function
+    };
+
     // Helper for emitting a single hunk cluster.
     // (a group of chunks with overlapping contexts and shared header)
 
@@ -346,7 +469,13 @@ where
             _ => {
                 // No overlap with previous hunk. Emit current cluster and start a new one.
                 if let Some((cluster_bounds, included_hunks_range)) = cluster {
-                    state.emit_hunk_cluster(&payload, cluster_bounds, &hunks[included_hunks_range]);
+                    let function = find_function(cluster_bounds.remove.start);
+                    state.emit_hunk_cluster(
+                        &payload,
+                        cluster_bounds,
+                        &hunks[included_hunks_range],
+                        function,
+                    );
                 }
                 Some((
                     Hunk {
@@ -362,7 +491,13 @@ where
     }
     // Emit the last cluster.
     if let Some((cluster_bounds, included_hunks_range)) = cluster {
-        state.emit_hunk_cluster(&payload, cluster_bounds, &hunks[included_hunks_range]);
+        let function = find_function(cluster_bounds.remove.start);
+        state.emit_hunk_cluster(
+            &payload,
+            cluster_bounds,
+            &hunks[included_hunks_range],
+            function,
+        );
     }
 
     state.collect()
@@ -422,10 +557,44 @@ pub fn diff_unified_headerless<T>(old_text: &T, new_text: &T, opts: HeaderlessDi
 where
     T: AsRef<[u8]>,
 {
-    gen_diff_unified_headerless(old_text, new_text, opts, Vec::new(), |mut v, part| {
-        v.extend(part);
-        v
-    })
+    gen_diff_unified_headerless(
+        old_text,
+        new_text,
+        &opts,
+        opts.function_context.as_ref().map(|c| c.fallback()),
+        None,
+        Vec::new(),
+        |mut v, part| {
+            v.extend(part);
+            v
+        },
+    )
+}
+
+/// Similar to [`diff_unified_headerless`], but changed lines are colored
+/// with ANSI escape sequences, and changed tokens within them are
+/// highlighted.
+pub fn diff_unified_headerless_inline<T>(
+    old_text: &T,
+    new_text: &T,
+    opts: HeaderlessDiffOpts,
+    tokenizer: &Tokenizer,
+) -> Vec<u8>
+where
+    T: AsRef<[u8]>,
+{
+    gen_diff_unified_headerless(
+        old_text,
+        new_text,
+        &opts,
+        opts.function_context.as_ref().map(|c| c.fallback()),
+        Some(tokenizer),
+        Vec::new(),
+        |mut v, part| {
+            v.extend(part);
+            v
+        },
+    )
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -527,6 +696,7 @@ fn gen_diff_unified<P, C, F, S>(
     old_file: Option<DiffFile<P, C>>,
     new_file: Option<DiffFile<P, C>>,
     diff_opts: DiffOpts,
+    inline: Option<&Tokenizer>,
     seed: S,
     reduce: F,
 ) -> S
@@ -658,6 +828,10 @@ where
     }
     // All headers emitted, now emit the actual diff.
     let (reduce, seed) = state.unwrap();
+    let function_regex = diff_opts
+        .function_context
+        .as_ref()
+        .map(|c| c.regex_for_path(new_name.as_ref()));
     match (
         &old_file.map(|file| file.contents),
         &new_file.map(|file| file.contents),
@@ -666,8 +840,19 @@ where
             // Typical case, we need to call actual diff function to get the diff.
             let opts = HeaderlessDiffOpts {
                 context: diff_opts.context,
+                algorithm: diff_opts.algorithm,
+                whitespace: diff_opts.whitespace,
+                function_context: None,
             };
-            gen_diff_unified_headerless(old_file, new_file, opts, seed, reduce)
+            gen_diff_unified_headerless(
+                old_file,
+                new_file,
+                &opts,
+                function_regex,
+                inline,
+                seed,
+                reduce,
+            )
         }
         (Some(FileContent::Inline(old_file)), None) => {
             // Degenerated case of all-minus diff.
@@ -697,12 +882,37 @@ where
     N: AsRef<[u8]> + Clone,
     C: AsRef<[u8]> + PartialEq + Eq,
 {
-    gen_diff_unified(old_file, new_file, opts, Vec::new(), |mut v, part| {
+    gen_diff_unified(old_file, new_file, opts, None, Vec::new(), |mut v, part| {
         v.extend(part);
         v
     })
 }
 
+/// Similar to [`diff_unified`], but changed lines are colored with ANSI
+/// escape sequences, and changed tokens within them are highlighted.
+pub fn diff_unified_inline<N, C>(
+    old_file: Option<DiffFile<N, C>>,
+    new_file: Option<DiffFile<N, C>>,
+    opts: DiffOpts,
+    tokenizer: &Tokenizer,
+) -> Vec<u8>
+where
+    N: AsRef<[u8]> + Clone,
+    C: AsRef<[u8]> + PartialEq + Eq,
+{
+    gen_diff_unified(
+        old_file,
+        new_file,
+        opts,
+        Some(tokenizer),
+        Vec::new(),
+        |mut v, part| {
+            v.extend(part);
+            v
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -750,7 +960,14 @@ d
 e
 z"#;
         assert_eq!(
-            diff_unified_headerless(&a, &b, HeaderlessDiffOpts { context: 10 }),
+            diff_unified_headerless(
+                &a,
+                &b,
+                HeaderlessDiffOpts {
+                    context: 10,
+                    ..Default::default()
+                }
+            ),
             r"@@ -1,4 +1,5 @@
  a
 -b
@@ -764,6 +981,30 @@ z"#;
         );
     }
 
+    #[test]
+    fn test_diff_unified_headerless_inline() {
+        let a = "a\nlet x = 1;\nb\n";
+        let b = "a\nlet y = 1;\nb\n";
+        let diff = diff_unified_headerless_inline(
+            &a,
+            &b,
+            HeaderlessDiffOpts {
+                context: 1,
+                ..Default::default()
+            },
+            &Tokenizer::Words,
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&diff).replace('\x1b', "~"),
+            r"@@ -1,3 +1,3 @@
+ a
+~[31m-let ~[1;91mx~[0m~[31m = 1;~[0m
+~[32m+let ~[1;92my~[0m~[32m = 1;~[0m
+ b
+"
+        );
+    }
+
     #[test]
     fn test_diff_unified() {
         let a = r#"a
@@ -794,6 +1035,7 @@ z"#;
                 DiffOpts {
                     context: 10,
                     copy_info: CopyInfo::None,
+                    ..Default::default()
                 }
             )),
             r"diff --git a/x b/y
@@ -815,6 +1057,42 @@ z"#;
         );
     }
 
+    #[test]
+    fn test_diff_unified_opts() {
+        let a = "class A:\n    def f(self):\n        return 1\n\n\n    def g(self):\n        x = 1\n        return x\n";
+        let b = "class A:\n    def f(self):\n        return 1\n\n\n    def g(self):\n        x  =  1\r\n        return x + 1\n";
+        let diff = |opts: DiffOpts| {
+            let diff = diff_unified(
+                Some(DiffFile::new("a.py", a, FileType::Regular)),
+                Some(DiffFile::new("a.py", b, FileType::Regular)),
+                opts,
+            );
+            String::from_utf8(diff).unwrap()
+        };
+        assert_eq!(
+            diff(DiffOpts {
+                context: 1,
+                algorithm: DiffAlgorithm::Histogram,
+                whitespace: WhitespaceOpts {
+                    ignore_amount: true,
+                    ignore_trailing_cr: true,
+                    ..Default::default()
+                },
+                function_context: Some(FunctionContext::new()),
+                ..Default::default()
+            }),
+            r"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -7,2 +7,2 @@     def g(self):
+         x = 1
+-        return x
++        return x + 1
+"
+        );
+        assert!(diff(DiffOpts::default()).contains("@@ -4,5 +4,5 @@\n"));
+    }
+
     #[test]
     fn test_diff_unified_file_removal() {
         let a = r#"a
@@ -833,6 +1111,7 @@ d
                 DiffOpts {
                     context: 10,
                     copy_info: CopyInfo::None,
+                    ..Default::default()
                 }
             )),
             r"diff --git a/x b/x
@@ -870,6 +1149,7 @@ d
                 DiffOpts {
                     context: 10,
                     copy_info: CopyInfo::None,
+                    ..Default::default()
                 }
             )),
             r"diff --git a/x b/x
@@ -909,6 +1189,7 @@ d
                 DiffOpts {
                     context: 10,
                     copy_info: CopyInfo::None,
+                    ..Default::default()
                 }
             )),
             r"diff --git a/x b/x
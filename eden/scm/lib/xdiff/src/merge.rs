@@ -0,0 +1,387 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Three-way merge of texts, similar to `simplemerge.py`.
+
+use crate::algorithm::{normalize_text, split_lines};
+use crate::{diff_hunks_with_opts, DiffAlgorithm, Hunk, WhitespaceOpts};
+use std::ops::Range;
+
+/// How conflicts are written in the merged text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MergeStyle {
+    /// Local and other sides. Lines common to both sides at the start or
+    /// end are moved out of the markers, if identical changes are resolved.
+    Merge,
+    /// Local, base and other sides.
+    Diff3,
+    /// Like `Diff3`, with common lines moved out of the markers like
+    /// `Merge`.
+    ZDiff3,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MergeOpts {
+    pub style: MergeStyle,
+    /// Label after the "<<<<<<<" marker. Omitted if empty.
+    pub local_label: String,
+    /// Label after the "|||||||" marker. Omitted if empty.
+    pub base_label: String,
+    /// Label after the ">>>>>>>" marker. Omitted if empty.
+    pub other_label: String,
+    pub algorithm: DiffAlgorithm,
+    /// Resolve conflicts where both sides made the same change.
+    pub resolve_identical: bool,
+    /// Resolve conflicts where the sides only differ in the amount of
+    /// whitespace, or one side only changed whitespace. The side with more
+    /// than whitespace changes wins, local wins otherwise.
+    pub resolve_whitespace: bool,
+}
+
+impl Default for MergeOpts {
+    fn default() -> Self {
+        Self {
+            style: MergeStyle::Merge,
+            local_label: "local".to_string(),
+            base_label: "base".to_string(),
+            other_label: "other".to_string(),
+            algorithm: DiffAlgorithm::default(),
+            resolve_identical: true,
+            resolve_whitespace: false,
+        }
+    }
+}
+
+/// A conflict region. Ranges are line numbers, starting from 0.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Conflict {
+    /// Lines of the base text in conflict.
+    pub base: Range<usize>,
+    /// Lines of the local text inside the markers.
+    pub local: Range<usize>,
+    /// Lines of the other text inside the markers.
+    pub other: Range<usize>,
+    /// Lines of the merged text, including the markers.
+    pub merged: Range<usize>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MergeResult {
+    /// The merged text with conflict markers.
+    pub merged: Vec<u8>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergeResult {
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+/// Merge changes from `base` to `local` and from `base` to `other`.
+///
+/// Changes touching each other are conflicts, unless they can be resolved
+/// as configured by `opts`.
+///
+/// # Example
+/// ```
+/// use xdiff::{merge, MergeOpts};
+/// let result = merge("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n", &MergeOpts::default());
+/// assert_eq!(result.merged, b"A\nb\nC\n");
+/// assert!(!result.has_conflicts());
+/// ```
+pub fn merge<T>(base: T, local: T, other: T, opts: &MergeOpts) -> MergeResult
+where
+    T: AsRef<[u8]>,
+{
+    let (base, local, other) = (base.as_ref(), local.as_ref(), other.as_ref());
+    let whitespace = WhitespaceOpts::default();
+    let local_hunks = diff_hunks_with_opts(base, local, opts.algorithm, &whitespace);
+    let other_hunks = diff_hunks_with_opts(base, other, opts.algorithm, &whitespace);
+    let base = split_lines(base);
+    let local = split_lines(local);
+    let other = split_lines(other);
+
+    // Like simplemerge.py, use the line ending of the local text in markers.
+    let newline: &[u8] = match local.first() {
+        Some(line) if line.ends_with(b"\r\n") => b"\r\n",
+        _ => b"\n",
+    };
+    let mut out = Output {
+        newline,
+        merged: Vec::new(),
+        line_count: 0,
+        conflicts: Vec::new(),
+    };
+
+    let mut local_hunks = local_hunks.iter().peekable();
+    let mut other_hunks = other_hunks.iter().peekable();
+    // Line count differences (side - base) of consumed hunks.
+    let (mut local_delta, mut other_delta) = (0isize, 0isize);
+    let mut base_pos = 0;
+    loop {
+        // Start a group with the first hunk, then add hunks touching it.
+        let start = match (local_hunks.peek(), other_hunks.peek()) {
+            (None, None) => break,
+            (Some(l), None) => l.remove.start,
+            (None, Some(o)) => o.remove.start,
+            (Some(l), Some(o)) => l.remove.start.min(o.remove.start),
+        };
+        let mut end = start;
+        let (local_delta_before, other_delta_before) = (local_delta, other_delta);
+        let (mut local_changed, mut other_changed) = (false, false);
+        loop {
+            if let Some(hunk) = local_hunks.peek().filter(|h| h.remove.start <= end) {
+                end = end.max(hunk.remove.end);
+                local_delta += delta(hunk);
+                local_changed = true;
+                local_hunks.next();
+            } else if let Some(hunk) = other_hunks.peek().filter(|h| h.remove.start <= end) {
+                end = end.max(hunk.remove.end);
+                other_delta += delta(hunk);
+                other_changed = true;
+                other_hunks.next();
+            } else {
+                break;
+            }
+        }
+
+        out.push_lines(&base[base_pos..start]);
+        let shift = |n: usize, delta: isize| (n as isize + delta) as usize;
+        let local_range = shift(start, local_delta_before)..shift(end, local_delta);
+        let other_range = shift(start, other_delta_before)..shift(end, other_delta);
+        match (local_changed, other_changed) {
+            (true, false) => out.push_lines(&local[local_range]),
+            (false, true) => out.push_lines(&other[other_range]),
+            _ => out.merge_group(
+                opts,
+                (&base, start..end),
+                (&local, local_range),
+                (&other, other_range),
+            ),
+        }
+        base_pos = end;
+    }
+    out.push_lines(&base[base_pos..]);
+
+    MergeResult {
+        merged: out.merged,
+        conflicts: out.conflicts,
+    }
+}
+
+fn delta(hunk: &Hunk) -> isize {
+    hunk.add.len() as isize - hunk.remove.len() as isize
+}
+
+struct Output {
+    newline: &'static [u8],
+    merged: Vec<u8>,
+    line_count: usize,
+    conflicts: Vec<Conflict>,
+}
+
+impl Output {
+    fn push_lines(&mut self, lines: &[&[u8]]) {
+        for line in lines {
+            self.merged.extend_from_slice(line);
+        }
+        self.line_count += lines.len();
+    }
+
+    /// Push lines followed by a marker. Add a newline if the last line
+    /// does not have one so the marker starts a new line.
+    fn push_lines_before_marker(&mut self, lines: &[&[u8]]) {
+        self.push_lines(lines);
+        if let Some(line) = lines.last() {
+            if !line.ends_with(b"\n") {
+                self.merged.extend_from_slice(self.newline);
+            }
+        }
+    }
+
+    fn push_marker(&mut self, marker: &str, label: &str) {
+        self.merged.extend_from_slice(marker.as_bytes());
+        if !label.is_empty() {
+            self.merged.push(b' ');
+            self.merged.extend_from_slice(label.as_bytes());
+        }
+        self.merged.extend_from_slice(self.newline);
+        self.line_count += 1;
+    }
+
+    /// Merge a group of lines changed by both sides.
+    fn merge_group(
+        &mut self,
+        opts: &MergeOpts,
+        (base, base_range): (&[&[u8]], Range<usize>),
+        (local, local_range): (&[&[u8]], Range<usize>),
+        (other, other_range): (&[&[u8]], Range<usize>),
+    ) {
+        let base_lines = &base[base_range.clone()];
+        let local_lines = &local[local_range.clone()];
+        let other_lines = &other[other_range.clone()];
+
+        if opts.resolve_identical && local_lines == other_lines {
+            return self.push_lines(local_lines);
+        }
+        if opts.resolve_whitespace {
+            let whitespace = WhitespaceOpts {
+                ignore_amount: true,
+                ignore_trailing_cr: true,
+                ..Default::default()
+            };
+            let normalize = |lines: &[&[u8]]| normalize_text(&lines.concat(), &whitespace);
+            let base_text = normalize(base_lines);
+            let local_text = normalize(local_lines);
+            let other_text = normalize(other_lines);
+            if local_text == other_text || other_text == base_text {
+                return self.push_lines(local_lines);
+            } else if local_text == base_text {
+                return self.push_lines(other_lines);
+            }
+        }
+
+        // Move lines common to both sides out of the markers.
+        let (prefix, suffix) = match opts.style {
+            MergeStyle::Merge | MergeStyle::ZDiff3 if opts.resolve_identical => {
+                let prefix = common_len(local_lines.iter(), other_lines.iter());
+                let suffix = common_len(
+                    local_lines[prefix..].iter().rev(),
+                    other_lines[prefix..].iter().rev(),
+                );
+                (prefix, suffix)
+            }
+            _ => (0, 0),
+        };
+        let local_range = local_range.start + prefix..local_range.end - suffix;
+        let other_range = other_range.start + prefix..other_range.end - suffix;
+
+        self.push_lines(&local_lines[..prefix]);
+        let merged_start = self.line_count;
+        self.push_marker("<<<<<<<", &opts.local_label);
+        self.push_lines_before_marker(&local[local_range.clone()]);
+        if opts.style != MergeStyle::Merge {
+            self.push_marker("|||||||", &opts.base_label);
+            self.push_lines_before_marker(base_lines);
+        }
+        self.push_marker("=======", "");
+        self.push_lines_before_marker(&other[other_range.clone()]);
+        self.push_marker(">>>>>>>", &opts.other_label);
+        self.conflicts.push(Conflict {
+            base: base_range,
+            local: local_range,
+            other: other_range,
+            merged: merged_start..self.line_count,
+        });
+        self.push_lines(&local_lines[local_lines.len() - suffix..]);
+    }
+}
+
+/// Number of equal lines at the start of both iterators.
+fn common_len<'a>(
+    a: impl Iterator<Item = &'a &'a [u8]>,
+    b: impl Iterator<Item = &'a &'a [u8]>,
+) -> usize {
+    a.zip(b).take_while(|(a, b)| a == b).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge_with(base: &str, local: &str, other: &str, f: fn(&mut MergeOpts)) -> String {
+        let mut opts = MergeOpts::default();
+        f(&mut opts);
+        let result = merge(base, local, other, &opts);
+        String::from_utf8(result.merged).unwrap()
+    }
+
+    #[test]
+    fn test_merge_clean() {
+        let base = "a\nb\nc\nd\ne\n";
+        assert_eq!(
+            merge_with(base, "a\nB\nc\nd\ne\n", "a\nb\nc\nD\ne\nf\n", |_| {}),
+            "a\nB\nc\nD\ne\nf\n"
+        );
+        // Identical changes.
+        assert_eq!(
+            merge_with(base, "a\nX\nc\nd\ne\n", "a\nX\nc\nd\ne\n", |_| {}),
+            "a\nX\nc\nd\ne\n"
+        );
+        // Deletions.
+        assert_eq!(
+            merge_with(base, "a\nc\nd\ne\n", "a\nb\nc\nd\n", |_| {}),
+            "a\nc\nd\n"
+        );
+        // Adjacent changes conflict.
+        assert!(merge_with(base, "a\ne\n", "a\nb\nc\nd\n", |_| {}).contains("<<<<<<<"));
+        assert_eq!(merge_with("", "", "a\n", |_| {}), "a\n");
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let base = "a\nb\nc\nd\n";
+        let local = "a\nx\nb2\ny\nd\n";
+        let other = "a\nx\nb3\ny\nd\n";
+        let opts = MergeOpts::default();
+        let result = merge(base, local, other, &opts);
+        assert_eq!(
+            String::from_utf8_lossy(&result.merged),
+            "a\nx\n<<<<<<< local\nb2\n=======\nb3\n>>>>>>> other\ny\nd\n"
+        );
+        assert_eq!(
+            result.conflicts,
+            [Conflict {
+                base: 1..3,
+                local: 2..3,
+                other: 2..3,
+                merged: 2..7,
+            }]
+        );
+
+        assert_eq!(
+            merge_with(base, local, other, |o| o.style = MergeStyle::Diff3),
+            "a\n<<<<<<< local\nx\nb2\ny\n||||||| base\nb\nc\n=======\nx\nb3\ny\n>>>>>>> other\nd\n"
+        );
+        assert_eq!(
+            merge_with(base, local, other, |o| {
+                o.style = MergeStyle::ZDiff3;
+                o.local_label = "dest".to_string();
+                o.base_label = String::new();
+            }),
+            "a\nx\n<<<<<<< dest\nb2\n|||||||\nb\nc\n=======\nb3\n>>>>>>> other\ny\nd\n"
+        );
+
+        // Identical changes are conflicts if not resolved.
+        assert_eq!(
+            merge_with("a\n", "b\n", "b\n", |o| o.resolve_identical = false),
+            "<<<<<<< local\nb\n=======\nb\n>>>>>>> other\n"
+        );
+
+        // Markers start new lines and use the line ending of local.
+        assert_eq!(
+            merge_with("a\r\n", "b\r\n", "c", |_| {}),
+            "<<<<<<< local\r\nb\r\n=======\r\nc\r\n>>>>>>> other\r\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_whitespace() {
+        let base = "if x:\n    y = 1\n";
+        let local = "if x:\n    y  =  1\n";
+        let other = "if x:\n    y = 2\n";
+        assert!(merge_with(base, local, other, |_| {}).contains("<<<<<<<"));
+        let resolve = |o: &mut MergeOpts| o.resolve_whitespace = true;
+        assert_eq!(merge_with(base, local, other, resolve), other);
+        assert_eq!(merge_with(base, other, local, resolve), other);
+        assert_eq!(
+            merge_with(base, local, "if x:\n  y = 1\r\n", resolve),
+            local
+        );
+    }
+}
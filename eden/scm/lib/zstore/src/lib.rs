@@ -10,6 +10,6 @@
 mod errors;
 mod zstore;
 
-pub use crate::zstore::{sha1, Id20, OpenOptions, Zstore};
+pub use crate::zstore::{sha1, GcOptions, GcStats, Id20, OpenOptions, Zstore};
 pub use errors::{Error, Result};
 pub use indexedlog::Repair;
@@ -9,6 +9,7 @@
 //!
 //! See [Zstore] for the main structure.
 
+use fs2::FileExt;
 use indexedlog::{log as ilog, DefaultOpenOptions};
 use lazy_static::lazy_static;
 use lru_cache::LruCache;
@@ -17,7 +18,9 @@ use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::{debug_span, info_span, trace_span};
 pub use types::Id20;
@@ -88,6 +91,8 @@ impl OpenOptions {
 
     /// Load or create [`Zstore`] at the given directory.
     pub fn open(&self, dir: &Path) -> crate::Result<Zstore> {
+        let _lock = GcLock::lock(dir, false)?;
+        recover_gc(dir)?;
         let log = Zstore::default_open_options().open(dir)?;
         // The cache_size should be greater than len(metalog.keys()))
         Ok(Zstore {
@@ -164,7 +169,11 @@ impl Zstore {
     /// See [indexedlog::Log::sync] for details.
     /// Return the size of the main log in bytes.
     pub fn flush(&mut self) -> crate::Result<u64> {
-        info_span!("Zstore::flush").in_scope(|| Ok(self.log.flush()?))
+        info_span!("Zstore::flush").in_scope(|| {
+            // Flushing reloads files from `dir`, which is replaced by GC.
+            let _lock = GcLock::lock(&self.dir, false)?;
+            Ok(self.log.flush()?)
+        })
     }
 
     /// Get the content of the specified blob.
@@ -435,6 +444,223 @@ impl fmt::Debug for DebugDeltaTree {
     }
 }
 
+// -------- Garbage Collection --------
+
+/// Options for [`Zstore::gc`].
+pub struct GcOptions {
+    /// Maximum delta chain length in the rebuilt store. A full text has
+    /// chain length 1. Objects that cannot be stored as a delta within this
+    /// limit are stored as full texts.
+    pub max_chain_len: usize,
+
+    /// Prevent constructing this struct.
+    _private: (),
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        GcOptions {
+            max_chain_len: 16,
+            _private: (),
+        }
+    }
+}
+
+/// Statistics of a [`Zstore::gc`] run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Number of entries in the log before GC.
+    pub objects_before: usize,
+
+    /// Number of objects kept.
+    pub objects_after: usize,
+
+    /// Size of the main log before GC.
+    pub bytes_before: u64,
+
+    /// Size of the main log after GC.
+    pub bytes_after: u64,
+}
+
+impl Zstore {
+    /// Remove objects that are not reachable from `roots`, and rebuild delta
+    /// chains of the remaining objects.
+    ///
+    /// `references` is called with the identity and content of every
+    /// reachable object, and returns identities of other objects it refers
+    /// to. Reachable objects must exist in the store.
+    ///
+    /// Reachable objects are copied to a new store next to the current one,
+    /// re-deltaed against their closest reachable delta base within
+    /// [`GcOptions::max_chain_len`]. Then the new store replaces the current
+    /// one by renaming directories, while holding a lock that
+    /// [`Zstore::open`] and [`Zstore::flush`] also take, so they never see
+    /// the store half replaced. If the process gets interrupted before the
+    /// new store is in place, [`Zstore::open`] moves the old store back.
+    ///
+    /// Pending changes are flushed first. The caller should prevent other
+    /// writers, for example, by holding a lock.
+    pub fn gc(
+        &mut self,
+        roots: &[Id20],
+        mut references: impl FnMut(Id20, &[u8]) -> crate::Result<Vec<Id20>>,
+        opts: &GcOptions,
+    ) -> crate::Result<GcStats> {
+        let bytes_before = self.flush()?;
+
+        // Find reachable objects.
+        let mut reachable = HashSet::new();
+        let mut to_visit = roots.to_vec();
+        while let Some(id) = to_visit.pop() {
+            if id == *EMPTY_ID20 || !reachable.insert(id) {
+                continue;
+            }
+            match self.get(id)? {
+                Some(data) => to_visit.extend(references(id, &data)?),
+                None => {
+                    return Err(self.error(format!("reachable {} is missing", id.to_hex())));
+                }
+            }
+        }
+
+        // Copy reachable objects to a new store, in the original order so
+        // delta bases are copied before deltas.
+        let new_dir = gc_path(&self.dir, "gc");
+        let old_dir = gc_path(&self.dir, "old");
+        for dir in [&new_dir, &old_dir].iter() {
+            if dir.exists() {
+                // Leftover from an earlier run.
+                fs::remove_dir_all(dir)?;
+            }
+        }
+        let mut new_store = Zstore {
+            dir: new_dir.clone(),
+            log: Self::default_open_options().fsync(true).open(&new_dir)?,
+            delta_opts: DeltaOptions {
+                max_depth: self.delta_opts.max_depth,
+                max_subchain_len: self.delta_opts.max_subchain_len,
+                max_chain_bytes: self.delta_opts.max_chain_bytes,
+                max_chain_factor_log: self.delta_opts.max_chain_factor_log,
+                _private: (),
+            },
+            cache: RefCell::new(LruCache::new(self.cache.borrow().capacity())),
+        };
+
+        // Chain lengths of objects in the new store.
+        let mut chain_lens: HashMap<Id20, usize> = HashMap::new();
+        let mut objects_before = 0;
+        for entry in self.log.iter() {
+            objects_before += 1;
+            let delta: Delta = mincode::deserialize(entry?)?;
+            let id = delta.id;
+            if !reachable.contains(&id) || chain_lens.contains_key(&id) {
+                continue;
+            }
+
+            // Use the closest reachable object in the old chain as delta base.
+            let mut base_id = delta.base_id;
+            while base_id != *EMPTY_ID20 && !chain_lens.contains_key(&base_id) {
+                base_id = match self.get_delta(base_id)? {
+                    Some(base_delta) => base_delta.base_id,
+                    None => *EMPTY_ID20,
+                };
+            }
+            let candidate_base_ids = match chain_lens.get(&base_id) {
+                Some(&len) if len < opts.max_chain_len => vec![base_id],
+                _ => Vec::new(),
+            };
+
+            let data = self.resolve(delta)?;
+            new_store.insert(&data, &candidate_base_ids)?;
+            let new_base_id = match new_store.get_delta(id)? {
+                Some(new_delta) => new_delta.base_id,
+                None => *EMPTY_ID20,
+            };
+            let len = chain_lens.get(&new_base_id).cloned().unwrap_or(0) + 1;
+            chain_lens.insert(id, len);
+        }
+        let bytes_after = new_store.log.flush()?;
+        drop(new_store);
+
+        // Swap the stores. Close the current log first so the directory can
+        // be renamed on Windows.
+        let lock = GcLock::lock(&self.dir, true)?;
+        self.log = Self::default_open_options().open(())?;
+        self.cache.borrow_mut().clear();
+        let result = fs::rename(&self.dir, &old_dir).and_then(|_| fs::rename(&new_dir, &self.dir));
+        recover_gc(&self.dir)?;
+        self.log = Self::default_open_options().open(&self.dir)?;
+        drop(lock);
+        result?;
+
+        // Other processes might still use the old files. If they cannot be
+        // removed now, the next GC will remove them.
+        let _ = fs::remove_dir_all(&old_dir);
+
+        Ok(GcStats {
+            objects_before,
+            objects_after: chain_lens.len(),
+            bytes_before,
+            bytes_after,
+        })
+    }
+}
+
+/// Lock serializing [`Zstore::gc`] replacing the store with other processes
+/// opening or reloading it. It is a file next to the store directory, since
+/// the directory itself gets replaced.
+struct GcLock(Option<fs::File>);
+
+impl GcLock {
+    /// Take the lock. The lock is skipped if the lock file cannot be
+    /// created, for example, when the store is read-only or not created yet.
+    fn lock(dir: &Path, exclusive: bool) -> crate::Result<Self> {
+        let file = match fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(gc_path(dir, "lock"))
+        {
+            Ok(file) => file,
+            Err(_) => return Ok(GcLock(None)),
+        };
+        if exclusive {
+            file.lock_exclusive()?;
+        } else {
+            file.lock_shared()?;
+        }
+        Ok(GcLock(Some(file)))
+    }
+}
+
+impl Drop for GcLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.0 {
+            let _ = file.unlock();
+        }
+    }
+}
+
+/// Recover from an interrupted [`Zstore::gc`]. If the store was moved away,
+/// but the new store was not moved in, move the old store back.
+///
+/// The caller should hold [`GcLock`], so a GC in progress is not rolled back.
+fn recover_gc(dir: &Path) -> crate::Result<()> {
+    let old_dir = gc_path(dir, "old");
+    if !dir.exists() && old_dir.exists() {
+        fs::rename(&old_dir, dir)?;
+    }
+    Ok(())
+}
+
+/// Path of a temporary directory next to `dir`, used by GC.
+fn gc_path(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    dir.with_file_name(name)
+}
+
 // -------- Utilities --------
 
 pub fn sha1(data: &[u8]) -> Id20 {
@@ -907,4 +1133,134 @@ Chain Len| Depth |Subchain Len| Bytes | Chain ID
 "#
         );
     }
+
+    #[test]
+    fn test_gc() {
+        let noise = generate_noise(4000);
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("store");
+        let mut zstore = Zstore::open(&path).unwrap();
+
+        // A linear chain of similar contents.
+        let mut ids = Vec::new();
+        let mut id = *EMPTY_ID20;
+        for i in 0..30 {
+            let content = format!("{}{}{}", noise, i, noise);
+            id = zstore.insert(content.as_bytes(), &[id]).unwrap();
+            ids.push(id);
+        }
+
+        // A root that references even items.
+        let even_ids: Vec<Id20> = ids.iter().step_by(2).cloned().collect();
+        let root_content: Vec<u8> = even_ids
+            .iter()
+            .flat_map(|id| id.as_ref().to_vec())
+            .collect();
+        let root_id = zstore.insert(&root_content, &[]).unwrap();
+        zstore.flush().unwrap();
+
+        let mut refs: HashMap<Id20, Vec<Id20>> = HashMap::new();
+        refs.insert(root_id, even_ids.clone());
+        let mut opts = GcOptions::default();
+        opts.max_chain_len = 3;
+        let stats = zstore
+            .gc(
+                &[root_id],
+                |id, _data| Ok(refs.get(&id).cloned().unwrap_or_default()),
+                &opts,
+            )
+            .unwrap();
+        assert_eq!(stats.objects_before, 31);
+        assert_eq!(stats.objects_after, 16);
+
+        let check = |zstore: &Zstore| {
+            for (i, id) in ids.iter().enumerate() {
+                let content = zstore.get(*id).unwrap();
+                if i % 2 == 0 {
+                    let expected = format!("{}{}{}", noise, i, noise);
+                    assert_eq!(content.unwrap(), expected.as_bytes());
+                } else {
+                    assert!(content.is_none());
+                }
+            }
+            assert_eq!(zstore.get(root_id).unwrap().unwrap(), &root_content[..]);
+        };
+        check(&zstore);
+        check(&Zstore::open(&path).unwrap());
+
+        // Delta chains are bounded.
+        fn max_chain_len(tree: &DebugDeltaTree) -> usize {
+            tree.children
+                .iter()
+                .map(max_chain_len)
+                .max()
+                .unwrap_or(0)
+                .max(tree.chain_len)
+        }
+        let tree = zstore.debug_delta_tree().unwrap();
+        assert!(max_chain_len(&tree) <= 3);
+
+        // Temporary directories are removed.
+        assert!(!gc_path(&path, "gc").exists());
+        assert!(!gc_path(&path, "old").exists());
+    }
+
+    #[test]
+    fn test_gc_missing_object() {
+        let dir = TempDir::new().unwrap();
+        let mut zstore = Zstore::open(&dir.path().join("store")).unwrap();
+        let id = zstore.insert(b"1", &[]).unwrap();
+        let missing = sha1(b"2");
+        let result = zstore.gc(&[id], |_, _| Ok(vec![missing]), &GcOptions::default());
+        assert!(result.is_err());
+        assert_eq!(zstore.get(id).unwrap().unwrap(), &b"1"[..]);
+    }
+
+    #[test]
+    fn test_gc_recover() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("store");
+        let mut zstore = Zstore::open(&path).unwrap();
+        let id = zstore.insert(b"123456", &[]).unwrap();
+        zstore.flush().unwrap();
+        drop(zstore);
+
+        // Simulate a GC interrupted after moving the store away.
+        fs::rename(&path, gc_path(&path, "old")).unwrap();
+        let zstore = Zstore::open(&path).unwrap();
+        assert_eq!(zstore.get(id).unwrap().unwrap(), &b"123456"[..]);
+        assert!(!gc_path(&path, "old").exists());
+    }
+
+    #[test]
+    fn test_gc_lock() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("store");
+        let mut zstore = Zstore::open(&path).unwrap();
+        let old_id = zstore.insert(b"old", &[]).unwrap();
+        zstore.flush().unwrap();
+        drop(zstore);
+        let new_path = gc_path(&path, "gc");
+        let mut zstore = Zstore::open(&new_path).unwrap();
+        let new_id = zstore.insert(b"new", &[]).unwrap();
+        zstore.flush().unwrap();
+        drop(zstore);
+
+        // Simulate a GC in the middle of replacing the store. Opening the
+        // store waits for it, instead of rolling it back.
+        let lock = GcLock::lock(&path, true).unwrap();
+        fs::rename(&path, gc_path(&path, "old")).unwrap();
+        let reader = {
+            let path = path.clone();
+            std::thread::spawn(move || Zstore::open(&path).unwrap().contains(new_id).unwrap())
+        };
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        fs::rename(&new_path, &path).unwrap();
+        drop(lock);
+
+        assert!(reader.join().unwrap());
+        let zstore = Zstore::open(&path).unwrap();
+        assert!(!zstore.contains(old_id).unwrap());
+        assert!(gc_path(&path, "old").exists());
+    }
 }
@@ -97,8 +97,11 @@ Show debug commands if there are no other candidates
   debugcheckstate
   debugcolor
   debugcommands
+  debugcommandserver
   debugcomplete
+  debugcompletion
   debugconfig
+  debugconfigdiff
   debugcreatestreamclonebundle
   debugdag
   debugdata
@@ -283,17 +286,17 @@ Show all commands + options
   archive: no-decode, prefix, rev, type, include, exclude
   backout: merge, no-commit, parent, rev, edit, tool, include, exclude, message, logfile, date, user
   bisect: reset, good, bad, skip, extend, command, noupdate, nosparseskip
-  blackbox: start, end, pattern, timestamp, sid
+  blackbox: start, end, pattern, timestamp, sid, session-id, type, command, exit-code, min-duration, format
   bookmarks: force, rev, delete, strip, rename, inactive, template
   branch: force, clean, new
   bundle: force, rev, base, all, type, ssh, remotecmd, insecure
   cat: output, rev, decode, include, exclude, template
   clone: noupdate, updaterev, rev, pull, uncompressed, stream, shallow, ssh, remotecmd, insecure
   commit: addremove, amend, edit, interactive, reuse-message, include, exclude, message, logfile, date, user
-  config: untrusted, edit, local, global, template
+  config: untrusted, edit, local, global, explain, template
   continue: 
   copy: after, force, include, exclude, dry-run
-  debug-args: 
+  debug-args: template
   debugancestor: 
   debugapplystreamclonebundle: 
   debugbindag: rev, output
@@ -305,7 +308,10 @@ Show all commands + options
   debugcheckstate: 
   debugcolor: style
   debugcommands: 
+  debugcommandserver: socket, idle-timeout
   debugcomplete: options
+  debugcompletion: shell, commands
+  debugconfigdiff: source, template
   debugcreatestreamclonebundle: 
   debugdag: bookmarks, branches, dots, spaces
   debugdata: changelog, manifest, dir
@@ -317,7 +323,7 @@ Show all commands + options
   debugdirstate: nodates, datesort, json
   debugdiscovery: old, nonheads, rev, ssh, remotecmd, insecure
   debugdrawdag: print
-  debugdynamicconfig: 
+  debugdynamicconfig: rules, validate, template
   debugedenimporthelper: in-fd, out-fd, manifest, get-manifest-node, cat-file, get-file-size, fetch-tree
   debugedenrunpostupdatehook: 
   debugexistingcasecollisions: rev
@@ -327,7 +333,7 @@ Show all commands + options
   debugformat: template
   debugfsinfo: 
   debuggetbundle: head, common, type
-  debughttp: 
+  debughttp: template
   debugignore: 
   debugindex: changelog, manifest, dir, format
   debugindexdot: changelog, manifest, dir
@@ -339,7 +345,7 @@ Show all commands + options
   debuglocks: force-lock, force-wlock, force-undolog-lock, set-lock, set-wlock
   debugmanifestdirs: rev
   debugmergestate: 
-  debugmetalog: time-range
+  debugmetalog: time-range, history, diff, restore, root, before, template
   debugmetalogroots: style, template
   debugmutation: rev, successors, time-range
   debugmutationfromobsmarkers: 
@@ -380,14 +386,14 @@ Show all commands + options
   debugwireargs: three, four, five, ssh, remotecmd, insecure
   diff: rev, change, text, git, binary, nodates, noprefix, show-function, reverse, ignore-all-space, ignore-space-change, ignore-blank-lines, ignore-space-at-eol, unified, stat, root, only-files-in-revs, include, exclude
   doctor: 
-  dump-trace: time-range, session-id, output-path
+  dump-trace: time-range, session-id, output-path, format
   export: output, switch-parent, rev, pattern, text, git, binary, nodates, include, exclude
   files: rev, print0, include, exclude, template
   forget: include, exclude
   fs: 
   githelp: 
   graft: rev, continue, abort, edit, log, force, currentdate, currentuser, date, user, tool, dry-run
-  grep: after-context, before-context, context, ignore-case, files-with-matches, line-number, invert-match, word-regexp, extended-regexp, fixed-strings, perl-regexp, include, exclude
+  grep: after-context, before-context, context, ignore-case, files-with-matches, line-number, invert-match, word-regexp, extended-regexp, fixed-strings, perl-regexp, rev, include, exclude
   heads: rev, topo, active, closed, style, template
   help: extension, command, keyword, system
   hint: ack
@@ -414,7 +420,7 @@ Show all commands + options
   resolve: all, list, mark, unmark, no-status, root-relative, tool, include, exclude, template, skip
   revert: all, date, rev, no-backup, interactive, include, exclude, dry-run
   rollback: dry-run, force
-  root: shared
+  root: shared, template
   serve: accesslog, daemon, daemon-postexec, errorlog, port, address, prefix, name, web-conf, webdir-conf, pid-file, port-file, stdio, cmdserver, templates, style, ipv6, certificate, read-only
   show: nodates, noprefix, stat, git, unified, ignore-all-space, ignore-space-change, ignore-blank-lines, ignore-space-at-eol, style, template, include, exclude
   status: all, modified, added, removed, deleted, clean, unknown, ignored, no-status, terse, copies, print0, rev, change, include, exclude, template
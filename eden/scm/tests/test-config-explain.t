@@ -0,0 +1,59 @@
+#chg-compatible
+
+  $ configure modern
+  $ setconfig configs.loaddynamicconfig=True
+  $ export HG_TEST_DYNAMICCONFIG="$TESTTMP/test_hgrc"
+  $ cat > test_hgrc <<EOF
+  > [section]
+  > dynamic=1
+  > both=dynamic
+  > [ui]
+  > verbose=false
+  > EOF
+
+  $ hg init repo
+  $ cd repo
+  $ hg debugdynamicconfig
+  $ cat >> .hg/hgrc <<EOF
+  > [section]
+  > both=repo
+  > EOF
+
+The dynamic config is overridden by the repo config, --config and global flags
+
+  $ hg config --explain section.both
+  section.both=repo
+      $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic): dynamic (overridden) (glob)
+      $TESTTMP/repo/.hg/hgrc:* (repository): repo (effective) (glob)
+  $ hg config --explain section.both --config section.both=cli
+  section.both=cli
+      $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic): dynamic (overridden) (glob)
+      $TESTTMP/repo/.hg/hgrc:* (repository): repo (overridden) (glob)
+      --config: cli (effective)
+  $ hg config --explain section
+  section.dynamic=1
+      $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic): 1 (effective) (glob)
+  section.both=repo
+      $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic): dynamic (overridden) (glob)
+      $TESTTMP/repo/.hg/hgrc:* (repository): repo (effective) (glob)
+  $ hg config --explain section.missing
+  [1]
+
+Compare with and without config sources
+
+  $ hg debugconfigdiff section
+  + section.dynamic=1  # $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic) (glob)
+  $ hg debugconfigdiff --source=--config section --config section.dynamic=2
+  - section.dynamic=1  # $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic) (glob)
+  + section.dynamic=2  # --config
+  $ hg debugconfigdiff --source=--verbose ui --verbose
+  - ui.verbose=false  # $TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic) (glob)
+  + ui.verbose=true  # --verbose
+  $ hg debugconfigdiff -T json section
+  [
+   {
+    "name": "section.dynamic",
+    "new": "1",
+    "newsource": "$TESTTMP/repo/.hg/hgrc.dynamic:* (dynamic)" (glob)
+   }
+  ]
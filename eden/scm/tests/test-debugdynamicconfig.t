@@ -184,3 +184,22 @@ Verify hgrc.dynamic is updated even if the original command is outside the repo
   [hooks]
   pretxnclose=printf "Hook ran!\n"
   
+
+Verify rules from configs.rulespath are applied before the repo exists
+  $ cd $TESTTMP
+  $ cat > test_hgrc <<EOF
+  > [section]
+  > key=value
+  > EOF
+  $ cat > rules.toml <<EOF
+  > [[rule]]
+  > name = "hook"
+  > [rule.config.hooks]
+  > pretxnclose = "echo rule hook ran"
+  > EOF
+  $ hg clone -q ssh://user@dummy/server client3 --config configs.rulespath=$TESTTMP/rules.toml
+  rule hook ran
+  rule hook ran
+  rule hook ran
+  $ grep pretxnclose client3/.hg/hgrc.dynamic
+  pretxnclose=echo rule hook ran
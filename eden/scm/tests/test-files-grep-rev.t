@@ -0,0 +1,145 @@
+#chg-compatible
+
+  $ configure modern
+
+Set up a server with a few files, and a client which has not checked them out
+
+  $ newserver server
+  $ mkdir -p a/b
+  $ printf 'one\nfoo\ntwo\nthree\nfour\nfive\nfoo bar\n' > a/x.txt
+  $ printf 'foo\n' > a/b/y.txt
+  $ printf 'bar\n' > z.txt
+  $ printf 'foo\000bin' > bin.dat
+  $ hg commit -Aqm first
+  $ printf 'changed\n' > z.txt
+  $ hg commit -qm second
+  $ hg bookmark master
+
+  $ clone server client
+  $ cd client
+  $ hg up -q null
+
+Bookmarks and remote names are resolved natively. Revsets fall back to Python,
+which prints the same output.
+
+  $ hg files -r master
+  a/b/y.txt
+  a/x.txt
+  bin.dat
+  z.txt
+  $ hg files -r 'master~1'
+  a/b/y.txt
+  a/x.txt
+  bin.dat
+  z.txt
+  $ hg files -r master -0 z.txt bin.dat | xargs -0 echo
+  bin.dat z.txt
+  $ hg files -r master -I '**.none'
+  [1]
+
+Patterns and paths are relative to the current directory
+
+  $ cd a
+  $ hg files -r master
+  b/y.txt
+  x.txt
+  ../bin.dat
+  ../z.txt
+  $ hg files -r master ../z.txt .
+  b/y.txt
+  x.txt
+  ../z.txt
+  $ hg files -r master -I 'b/**'
+  b/y.txt
+  $ hg files -r master -X b
+  x.txt
+  ../bin.dat
+  ../z.txt
+  $ hg files -r master 'path:z.txt'
+  ../z.txt
+  $ cd ..
+
+Sizes, flags and other template keywords are printed by Python
+
+  $ hg files -r master -v z.txt
+           8   z.txt
+  $ hg files -r master -T '{path}\n' z.txt
+  z.txt
+  $ hg files -r master -T json z.txt
+  [
+   {
+    "abspath": "z.txt",
+    "path": "z.txt"
+   }
+  ]
+
+Search a revision. Binary files are skipped.
+
+  $ hg grep -r master foo
+  a/b/y.txt:foo
+  a/x.txt:foo
+  a/x.txt:foo bar
+  $ hg grep -r 'master~1' foo
+  a/b/y.txt:foo
+  a/x.txt:foo
+  a/x.txt:foo bar
+  $ hg grep -r master -n foo a/x.txt
+  a/x.txt:2:foo
+  a/x.txt:7:foo bar
+  $ hg grep -r master -l foo
+  a/b/y.txt
+  a/x.txt
+  $ hg grep -r master -w fo
+  [1]
+  $ hg grep -r master -i CHANGED
+  z.txt:changed
+  $ hg grep -r 'master~1' -i CHANGED
+  [1]
+  $ cd a
+  $ hg grep -r master -V foo x.txt
+  x.txt:one
+  x.txt:two
+  x.txt:three
+  x.txt:four
+  x.txt:five
+  $ cd ..
+
+Context lines use "-", and groups of lines are separated by "--"
+
+  $ hg grep -r master -C 1 foo
+  a/b/y.txt:foo
+  --
+  a/x.txt-one
+  a/x.txt:foo
+  a/x.txt-two
+  --
+  a/x.txt-five
+  a/x.txt:foo bar
+  $ hg grep -r 'master~1' -C 1 foo
+  a/b/y.txt:foo
+  --
+  a/x.txt-one
+  a/x.txt:foo
+  a/x.txt-two
+  --
+  a/x.txt-five
+  a/x.txt:foo bar
+  $ hg grep -r master -n -B 1 -A 0 'foo bar'
+  a/x.txt-6-five
+  a/x.txt:7:foo bar
+  $ hg grep -r master -C x foo
+  abort: x: invalid context length argument
+  [255]
+
+Contents that are not local are fetched by Python. Nothing is printed twice.
+
+  $ cd $TESTTMP/server
+  $ printf 'foo new\n' > new.txt
+  $ hg commit -Aqm third
+  $ cd $TESTTMP/client
+  $ hg pull -q
+  $ hg grep -r master foo
+  a/b/y.txt:foo
+  a/x.txt:foo
+  a/x.txt:foo bar
+  new.txt:foo new
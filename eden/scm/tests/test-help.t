@@ -954,8 +954,14 @@ Test list of internal help commands
    debugcolor    show available color, effects or style
    debugcommands
                  list all available commands and options
+   debugcommandserver
+                 run a command server for native commands
    debugcomplete
                  returns the completion list associated with the given command
+   debugcompletion
+                 generate a shell completion script
+   debugconfigdiff
+                 show config items affected by given config sources
    debugcreatestreamclonebundle
                  create a stream clone bundle file
    debugdag      format the changelog or an index DAG as a concise textual
@@ -0,0 +1,93 @@
+#chg-compatible
+
+  $ configure modern
+  $ setconfig checkout.use-rust=true commands.update.check=noconflict
+
+Set up a server with two commits, and a client at the second one
+
+  $ newserver server
+  $ echo 1 > a
+  $ echo 1 > b
+  $ hg commit -Aqm A
+  $ echo 2 > a
+  $ hg rm -q b
+  $ echo 2 > c
+  $ hg commit -Aqm B
+  $ hg bookmark master
+
+  $ clone server client
+  $ cd client
+  $ A=$(hg log -r 'desc(A)' -T '{node}')
+  $ B=$(hg log -r 'desc(B)' -T '{node}')
+
+Update by hash
+
+  $ hg up $A
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  $ cat a b
+  1
+  1
+  $ test -f c
+  [1]
+  $ hg status
+  $ hg up .
+  0 files updated, 0 files merged, 0 files removed, 0 files unresolved
+
+Update by remote name, with and without the hoisted prefix
+
+  $ hg up remote/master
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  $ cat a c
+  2
+  2
+  $ hg up -q $A
+  $ hg up master
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  $ hg log -r . -T '{desc}\n'
+  B
+
+Update by bookmark activates it, and updating away leaves it
+
+  $ hg bookmark -r $A book
+  $ hg up book
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  (activating bookmark book)
+  $ hg up $B
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  (leaving bookmark book)
+  $ hg up --inactive book
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  $ hg bookmarks
+     book                      * (glob)
+  $ hg status
+
+Conflicting local changes abort the update, unless --clean discards them
+
+  $ echo 3 > b
+  $ hg up $B
+  abort: 1 conflicting file changes:
+   b
+  (commit, shelve, update --clean to discard them, or update --merge to merge them)
+  [255]
+  $ hg up --clean $B
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  $ hg status
+
+With metalog, bookmarks and remote names are read from it
+
+  $ cd $TESTTMP
+  $ clone server client2
+  $ cd client2
+  $ setconfig experimental.metalog=true
+  $ hg bookmark -r $A book2
+  $ hg up book2
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  (activating bookmark book2)
+  $ hg up remote/master
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  (leaving bookmark book2)
+  $ hg up $A
+  2 files updated, 0 files merged, 1 files removed, 0 files unresolved
+  $ hg log -r . -T '{desc}\n'
+  A
+  $ hg status